@@ -0,0 +1,287 @@
+//! Runtime backend management for autoscaled upstream pools. Changes are applied to
+//! the live `RouterState` only; a SIGHUP reload replaces them with the config file.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+
+use crate::{
+    capture::CapturedRequest,
+    config::{validate_backend, Backend},
+    error::ConfigError,
+    selector::SelectionContext,
+    state::{AppState, ClientOptions, RouterState, RuntimeBackend},
+};
+
+/// Most selections a single `/admin/simulate` request may run.
+const MAX_SIMULATED_SELECTIONS: u32 = 100_000;
+
+type AdminError = (StatusCode, String);
+
+/// Checks the `Authorization: Bearer <admin_token>` header. Without a configured
+/// token the backend management API is disabled and answers 404.
+fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), AdminError> {
+    let Some(token) = state.admin_token.as_deref() else {
+        return Err((StatusCode::NOT_FOUND, "Not Found".to_string()));
+    };
+    let presented = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // Compared in constant time so response timing doesn't reveal the token
+    let matches = presented.is_some_and(|p| bool::from(p.as_bytes().ct_eq(token.as_bytes())));
+    if !matches {
+        warn!("Rejected admin request with missing or invalid token");
+        return Err((StatusCode::UNAUTHORIZED, "Unauthorized".to_string()));
+    }
+    Ok(())
+}
+
+/// Deserializes a JSON request body, read as raw bytes so it is only parsed once the
+/// caller is authorized.
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, AdminError> {
+    serde_json::from_slice(body).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid request body: {}", e),
+        )
+    })
+}
+
+/// Applies `update` to the current `RouterState` and swaps in the result. On error the
+/// state is left untouched.
+fn update_router_state<F>(state: &AppState, update: F) -> Result<(), AdminError>
+where
+    F: Fn(&RouterState) -> Result<RouterState, AdminError>,
+{
+    let mut outcome = Ok(());
+    state.state.rcu(|current| match update(current) {
+        Ok(next) => {
+            outcome = Ok(());
+            Arc::new(next)
+        }
+        Err(e) => {
+            outcome = Err(e);
+            current.clone()
+        }
+    });
+    outcome
+}
+
+fn add_backend(
+    current: &RouterState,
+    backend: &Backend,
+    client_options: &ClientOptions,
+) -> Result<RouterState, AdminError> {
+    validate_backend(backend).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if backend.weight == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Backend '{}' has invalid weight 0", backend.label),
+        ));
+    }
+    if current
+        .backends
+        .iter()
+        .any(|b| b.config.label == backend.label)
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Backend '{}' already exists", backend.label),
+        ));
+    }
+    let min_shares: f64 = current
+        .backends
+        .iter()
+        .filter(|b| !b.is_fallback())
+        .map(|b| b.config.min_share)
+        .sum::<f64>()
+        + backend.min_share;
+    if min_shares > 1.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            ConfigError::MinSharesExceedTraffic(min_shares).to_string(),
+        ));
+    }
+
+    let runtime = RuntimeBackend::with_client_options(backend.clone(), client_options)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut next = current.clone();
+    // Keep the fallback backend, if any, at the end of the list
+    let position = next
+        .backends
+        .iter()
+        .position(|b| b.is_fallback())
+        .unwrap_or(next.backends.len());
+    next.backends.insert(position, runtime);
+    Ok(next)
+}
+
+fn remove_backend(current: &RouterState, label: &str) -> Result<RouterState, AdminError> {
+    let index = current
+        .backends
+        .iter()
+        .position(|b| b.config.label == label && !b.is_fallback())
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Backend '{}' not found", label),
+            )
+        })?;
+    if current.backends.iter().filter(|b| !b.is_fallback()).count() == 1 {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "Backend '{}' is the last backend and can't be removed",
+                label
+            ),
+        ));
+    }
+
+    let mut next = current.clone();
+    next.backends.remove(index);
+    Ok(next)
+}
+
+/// `POST /admin/backends`: adds a backend from a `Backend` JSON body. It starts
+/// healthy and is picked up by the next health check round.
+pub async fn create_backend(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(e) = authorize(&state, &headers) {
+        return e.into_response();
+    }
+    let backend: Backend = match parse_body(&body) {
+        Ok(backend) => backend,
+        Err(e) => return e.into_response(),
+    };
+
+    match update_router_state(&state, |current| {
+        add_backend(current, &backend, &state.client_options)
+    }) {
+        Ok(()) => {
+            state
+                .state
+                .load()
+                .health_state
+                .insert(backend.label.clone());
+            info!(
+                "Added backend label={} url={} via admin API",
+                backend.label, backend.url
+            );
+            StatusCode::CREATED.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `DELETE /admin/backends/{label}`: removes a regular backend. The fallback backend
+/// and the last remaining backend can't be removed.
+pub async fn delete_backend(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(label): Path<String>,
+) -> Response {
+    if let Err(e) = authorize(&state, &headers) {
+        return e.into_response();
+    }
+
+    match update_router_state(&state, |current| remove_backend(current, &label)) {
+        Ok(()) => {
+            state.state.load().health_state.remove(&label);
+            info!("Removed backend label={} via admin API", label);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SimulateRequest {
+    /// JSON-RPC method to select for, so method routes apply
+    method: Option<String>,
+    /// Number of selections to run; 1000 when omitted
+    count: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct SimulateResponse {
+    count: u32,
+    /// Times each backend was chosen, by label
+    backends: BTreeMap<String, u32>,
+    /// Selections that found no usable backend (the proxy would answer 503)
+    unavailable: u32,
+}
+
+/// `POST /admin/simulate`: runs backend selection `count` times for `method` against
+/// the live state and reports where the requests would have gone. Nothing is sent
+/// upstream, so it is safe for checking weights and routes after a reload.
+pub async fn simulate_routing(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(e) = authorize(&state, &headers) {
+        return e.into_response();
+    }
+    let request: SimulateRequest = match parse_body(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let count = request.count.unwrap_or(1000);
+    if count == 0 || count > MAX_SIMULATED_SELECTIONS {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("count must be between 1 and {}", MAX_SIMULATED_SELECTIONS),
+        )
+            .into_response();
+    }
+
+    let ctx = SelectionContext {
+        rpc_method: request.method.as_deref(),
+        ..Default::default()
+    };
+    let mut backends = BTreeMap::new();
+    let mut unavailable = 0;
+    for _ in 0..count {
+        match state.select_backend_for(&ctx, &[]) {
+            Some(selection) => *backends.entry(selection.label().to_string()).or_insert(0) += 1,
+            None => unavailable += 1,
+        }
+    }
+    Json(SimulateResponse {
+        count,
+        backends,
+        unavailable,
+    })
+    .into_response()
+}
+
+#[derive(Serialize)]
+pub struct CapturesResponse {
+    /// Most recent failed requests, newest first
+    captures: Vec<CapturedRequest>,
+}
+
+/// `GET /admin/captures`: the failed requests kept by `[capture]`, with credentials
+/// redacted. Empty when capture is disabled.
+pub async fn list_captures(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Err(e) = authorize(&state, &headers) {
+        return e.into_response();
+    }
+    Json(CapturesResponse {
+        captures: state.captures.entries(),
+    })
+    .into_response()
+}
@@ -0,0 +1,110 @@
+//! JSON-RPC batch validation and per-entry error responses.
+
+use std::{collections::HashSet, fmt};
+
+use serde_json::{json, Value};
+
+/// JSON-RPC 2.0 "Invalid Request" error code.
+pub const INVALID_REQUEST: i64 = -32600;
+/// JSON-RPC 2.0 "Internal error" code, used when the backend couldn't answer.
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// A validated batch. Entries without an `id` are notifications and get no response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    /// `id` of every entry that expects a response, in request order
+    pub ids: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchError {
+    Empty,
+    /// An entry that isn't a JSON object
+    InvalidEntry(usize),
+    DuplicateId(Value),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Empty => f.write_str("Empty batch"),
+            BatchError::InvalidEntry(index) => {
+                write!(f, "Batch entry {} is not a JSON-RPC request", index)
+            }
+            BatchError::DuplicateId(id) => write!(f, "Duplicate id {} in batch", id),
+        }
+    }
+}
+
+impl BatchError {
+    /// JSON-RPC error object for the whole batch. Its `id` is null since no single
+    /// request can be blamed.
+    pub fn to_json(&self) -> Value {
+        error_entry(&Value::Null, INVALID_REQUEST, &self.to_string())
+    }
+}
+
+/// Whether `body` is shaped like a batch: its first non-whitespace byte opens an array.
+/// Lets single requests skip batch validation without being parsed.
+pub fn is_batch(body: &[u8]) -> bool {
+    body.iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'[')
+}
+
+/// Validates `request` as a JSON-RPC batch. Returns `None` when it isn't a JSON array
+/// (a single request), which is left to the backend.
+pub fn parse_batch(request: &Value) -> Option<Result<Batch, BatchError>> {
+    let Value::Array(entries) = request else {
+        return None;
+    };
+    Some(validate(entries))
+}
+
+fn validate(entries: &[Value]) -> Result<Batch, BatchError> {
+    if entries.is_empty() {
+        return Err(BatchError::Empty);
+    }
+
+    let mut seen = HashSet::new();
+    let mut ids = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let Value::Object(entry) = entry else {
+            return Err(BatchError::InvalidEntry(index));
+        };
+        let Some(id) = entry.get("id") else {
+            continue; // notification
+        };
+        // Compare the serialized form so 1 and "1" stay distinct ids
+        if !seen.insert(id.to_string()) {
+            return Err(BatchError::DuplicateId(id.clone()));
+        }
+        ids.push(id.clone());
+    }
+    Ok(Batch { ids })
+}
+
+/// A JSON-RPC error response for request `id`.
+pub fn error_entry(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": id,
+    })
+}
+
+impl Batch {
+    /// One error per entry expecting a response, for when the batch as a whole got no
+    /// answer from its backend. `None` for a batch of only notifications.
+    pub fn failure_response(&self, message: &str) -> Option<Value> {
+        if self.ids.is_empty() {
+            return None;
+        }
+        Some(Value::Array(
+            self.ids
+                .iter()
+                .map(|id| error_entry(id, INTERNAL_ERROR, message))
+                .collect(),
+        ))
+    }
+}
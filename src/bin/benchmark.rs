@@ -2,7 +2,7 @@ use std::{
     collections::HashMap,
     net::SocketAddr,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicUsize, Ordering},
         Arc,
     },
     time::Instant,
@@ -22,10 +22,11 @@ use hyper_tls::HttpsConnector;
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
 use serde_json::{json, Value};
 use sol_rpc_router::{
-    config::Backend,
+    config::{Backend, HealthCheckConfig, ListenerConfig, ProxyConfig},
     handlers::{extract_rpc_method, health_endpoint, proxy, track_metrics},
     health::HealthState,
     mock::MockKeyStore,
+    server::serve_tcp,
     state::{AppState, RouterState, RuntimeBackend},
 };
 use tokio::sync::Barrier;
@@ -40,6 +41,10 @@ struct Args {
     /// Duration of the benchmark in seconds
     #[arg(short, long, default_value_t = 10)]
     duration: u64,
+
+    /// Set TCP_NODELAY on connections accepted by the router
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    tcp_nodelay: bool,
 }
 
 /// Spawn a mock upstream that returns a fixed JSON-RPC response.
@@ -66,7 +71,7 @@ async fn start_mock_upstream() -> SocketAddr {
 }
 
 /// Build and start the router in-process, returning the address it's listening on.
-async fn start_router(upstream_addr: SocketAddr) -> SocketAddr {
+async fn start_router(upstream_addr: SocketAddr, listener_config: ListenerConfig) -> SocketAddr {
     let https = HttpsConnector::new();
     let client = Client::builder(TokioExecutor::new()).build(https);
 
@@ -78,12 +83,10 @@ async fn start_router(upstream_addr: SocketAddr) -> SocketAddr {
         url: format!("http://{}", upstream_addr),
         ws_url: None,
         weight: 1,
+        ..Default::default()
     };
 
-    let runtime_backend = RuntimeBackend {
-        config: backend,
-        healthy: Arc::new(AtomicBool::new(true)),
-    };
+    let runtime_backend = RuntimeBackend::new(backend).unwrap();
 
     let health_state = Arc::new(HealthState::new(vec!["mock-upstream".to_string()]));
 
@@ -91,33 +94,33 @@ async fn start_router(upstream_addr: SocketAddr) -> SocketAddr {
         backends: vec![runtime_backend],
         method_routes: HashMap::new(),
         health_state: health_state.clone(),
-        proxy_timeout_secs: 30,
-        health_check_config: sol_rpc_router::config::HealthCheckConfig::default(),
+        proxy_config: ProxyConfig {
+            timeout_secs: 30,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
     };
 
-    let state = Arc::new(AppState {
+    let state = Arc::new(AppState::new(
         client,
         keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    });
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ));
 
     let app = Router::new()
         .route("/", post(proxy))
         .route("/health", get(health_endpoint))
-        .with_state(state)
-        .layer(middleware::from_fn(track_metrics))
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state, track_metrics))
         .layer(middleware::from_fn(extract_rpc_method));
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
 
     tokio::spawn(async move {
-        axum::serve(
-            listener,
-            app.into_make_service_with_connect_info::<SocketAddr>(),
-        )
-        .await
-        .unwrap();
+        serve_tcp(listener, app, listener_config, std::future::pending())
+            .await
+            .unwrap();
     });
 
     addr
@@ -132,8 +135,15 @@ async fn main() {
     println!("Mock upstream listening on {}", upstream_addr);
 
     // 2. Start router in-process (no Redis, no config file)
-    let router_addr = start_router(upstream_addr).await;
-    println!("Router listening on {}", router_addr);
+    let listener_config = ListenerConfig {
+        tcp_nodelay: args.tcp_nodelay,
+        ..Default::default()
+    };
+    let router_addr = start_router(upstream_addr, listener_config).await;
+    println!(
+        "Router listening on {} (tcp_nodelay={})",
+        router_addr, args.tcp_nodelay
+    );
 
     // Give servers a moment to be fully ready
     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
@@ -221,11 +231,7 @@ async fn main() {
         latencies.iter().sum::<u64>() as f64 / latencies.len() as f64 / 1000.0
     };
 
-    let p50 = latencies
-        .get(latencies.len() / 2)
-        .copied()
-        .unwrap_or(0) as f64
-        / 1000.0;
+    let p50 = latencies.get(latencies.len() / 2).copied().unwrap_or(0) as f64 / 1000.0;
 
     let p99_idx = ((latencies.len() as f64) * 0.99) as usize;
     let p99 = latencies.get(p99_idx).copied().unwrap_or(0) as f64 / 1000.0;
@@ -31,6 +31,12 @@ enum Commands {
         /// Custom API key value (auto-generated if omitted)
         #[arg(long)]
         key: Option<String>,
+        /// WebSocket messages per second per connection (default: router config)
+        #[arg(long)]
+        ws_message_rate: Option<u64>,
+        /// Requests the owner may have in flight at once (default: router config)
+        #[arg(long)]
+        max_concurrent_requests: Option<u64>,
     },
     /// Revoke an API key
     Revoke { key: String },
@@ -47,6 +53,12 @@ enum Commands {
         /// Activate (true) or deactivate (false)
         #[arg(long)]
         active: Option<bool>,
+        /// New WebSocket messages per second per connection
+        #[arg(long)]
+        ws_message_rate: Option<u64>,
+        /// New limit on requests in flight at once
+        #[arg(long)]
+        max_concurrent_requests: Option<u64>,
     },
     /// List all API keys
     List,
@@ -66,6 +78,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             rate_limit,
             expires_at,
             key: custom_key,
+            ws_message_rate,
+            max_concurrent_requests,
         } => {
             let key: String = custom_key.unwrap_or_else(|| {
                 rand::thread_rng()
@@ -90,6 +104,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 pipe.hset(&redis_key, "expires_at", exp);
             }
 
+            if let Some(rate) = ws_message_rate {
+                pipe.hset(&redis_key, "ws_message_rate", rate);
+            }
+
+            if let Some(limit) = max_concurrent_requests {
+                pipe.hset(&redis_key, "max_concurrent_requests", limit);
+            }
+
             let _: () = pipe.query_async(&mut con).await?;
 
             // Also store in a set for listing
@@ -121,6 +143,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             rate_limit,
             owner,
             active,
+            ws_message_rate,
+            max_concurrent_requests,
         } => {
             let redis_key = format!("api_key:{}", key);
             // Check existence first
@@ -153,6 +177,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 changes.push(format!("active -> {}", status));
             }
 
+            if let Some(rate) = ws_message_rate {
+                pipe.hset(&redis_key, "ws_message_rate", rate);
+                changes.push(format!("ws_message_rate -> {}", rate));
+            }
+
+            if let Some(limit) = max_concurrent_requests {
+                pipe.hset(&redis_key, "max_concurrent_requests", limit);
+                changes.push(format!("max_concurrent_requests -> {}", limit));
+            }
+
             if changes.is_empty() {
                 println!("No changes requested for key: {}", key);
             } else {
@@ -198,11 +232,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .await
                     .unwrap_or("true".to_string());
                 let created_at: u64 = con.hget(&redis_key, "created_at").await.unwrap_or(0);
+                let ws_message_rate: Option<u64> = con
+                    .hget(&redis_key, "ws_message_rate")
+                    .await
+                    .unwrap_or_default();
+                let max_concurrent_requests: Option<u64> = con
+                    .hget(&redis_key, "max_concurrent_requests")
+                    .await
+                    .unwrap_or_default();
 
                 println!("Key: {}", key);
                 println!("Owner: {}", owner);
                 println!("Active: {}", active);
                 println!("Rate Limit: {} RPS", rate_limit);
+                if let Some(rate) = ws_message_rate {
+                    println!("WebSocket Message Rate: {} per second", rate);
+                }
+                if let Some(limit) = max_concurrent_requests {
+                    println!("Max Concurrent Requests: {}", limit);
+                }
                 println!("Created At: {}", created_at);
             } else {
                 println!("Key not found");
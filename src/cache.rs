@@ -0,0 +1,293 @@
+//! In-memory cache of successful JSON-RPC responses for configured idempotent methods,
+//! and of `sendTransaction` results so resubmitted transactions aren't forwarded again.
+//! `warm::warm` primes it at startup.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::Bytes;
+use moka::{sync::Cache, Expiry};
+use serde_json::Value;
+use tokio::sync::watch;
+use tracing::debug;
+
+use crate::config::CacheConfig;
+
+const SEND_TRANSACTION: &str = "sendTransaction";
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// Full JSON-RPC response; its `id` is replaced with the requester's on a hit
+    response: Value,
+    /// Quoted hash of the `result`, served as the `ETag`
+    etag: String,
+    ttl: Duration,
+    slot_scoped: bool,
+}
+
+/// Expires each entry after its method's TTL, counted again when it is replaced.
+struct EntryTtl;
+
+impl Expiry<String, Arc<CacheEntry>> for EntryTtl {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        entry: &Arc<CacheEntry>,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Arc<CacheEntry>,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+/// A fresh cache entry, ready to serve.
+#[derive(Debug, Clone)]
+pub struct CacheHit {
+    /// Serialized response carrying the request's `id`
+    pub body: Bytes,
+    pub etag: String,
+}
+
+pub struct ResponseCache {
+    config: CacheConfig,
+    entries: Cache<String, Arc<CacheEntry>>,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("config", &self.config)
+            .field("entries", &self.entries.entry_count())
+            .finish()
+    }
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let entries = Cache::builder()
+            .max_capacity(config.max_entries as u64)
+            .expire_after(EntryTtl)
+            .build();
+        Self { config, entries }
+    }
+
+    fn ttl(&self, method: &str) -> Duration {
+        if method == SEND_TRANSACTION {
+            Duration::from_millis(self.config.send_transaction_dedup_ms)
+        } else {
+            Duration::from_millis(self.config.ttl_ms)
+        }
+    }
+
+    /// Whether responses to `method` are cached (or, for `sendTransaction`, deduplicated).
+    pub fn caches(&self, method: &str) -> bool {
+        !self.ttl(method).is_zero()
+            && (method == SEND_TRANSACTION || self.config.methods.iter().any(|m| m == method))
+    }
+
+    /// Methods fetched at startup to prime the cache.
+    pub fn warm_methods(&self) -> &[String] {
+        &self.config.warm_methods
+    }
+
+    /// Cache key for a parsed JSON-RPC request, or `None` when `method` isn't cached or
+    /// the request isn't a single one. `sendTransaction` is keyed by the transaction's
+    /// signature.
+    pub fn key(&self, method: &str, request: &Value) -> Option<String> {
+        if !self.caches(method) {
+            return None;
+        }
+        let dedup = method == SEND_TRANSACTION;
+        // Empty params are the same call as none
+        let params = match request.as_object()?.get("params") {
+            Some(Value::Array(params)) if params.is_empty() => &Value::Null,
+            params => params.unwrap_or(&Value::Null),
+        };
+        if dedup {
+            let signature = transaction_signature(params)?;
+            let hex: String = signature.iter().map(|b| format!("{:02x}", b)).collect();
+            return Some(format!("{}:{}", method, hex));
+        }
+        Some(format!("{}:{}", method, params))
+    }
+
+    /// Cached response for `key` carrying the request's `id`, unless the entry is
+    /// missing or older than its TTL.
+    pub fn get(&self, key: &str, id: Option<&Value>) -> Option<CacheHit> {
+        let entry = self.entries.get(key)?;
+        let mut response = entry.response.clone();
+        response["id"] = id.cloned().unwrap_or(Value::Null);
+        let body = serde_json::to_vec(&response).ok()?;
+        Some(CacheHit {
+            body: Bytes::from(body),
+            etag: entry.etag.clone(),
+        })
+    }
+
+    /// Stores `response` under `key` when it carries a `result`, returning its `ETag`.
+    /// Beyond `max_entries`, the least used entries are evicted to make room.
+    pub fn insert(&self, key: String, method: &str, response: Value) -> Option<String> {
+        let etag = etag(response.get("result")?);
+        let slot_scoped = self.config.slot_scoped_methods.iter().any(|m| m == method);
+        self.entries.insert(
+            key,
+            Arc::new(CacheEntry {
+                response,
+                etag: etag.clone(),
+                ttl: self.ttl(method),
+                slot_scoped,
+            }),
+        );
+        Some(etag)
+    }
+
+    /// Drops every entry for a slot-scoped method. Returns how many were removed.
+    pub fn evict_slot_scoped(&self) -> usize {
+        let mut evicted = 0;
+        for (key, entry) in &self.entries {
+            if entry.slot_scoped {
+                self.entries.invalidate(key.as_str());
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.run_pending_tasks();
+        self.entries.entry_count() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Evicts slot-scoped entries each time `slots` (see
+    /// `HealthState::subscribe_cluster_slot`) reports a new cluster slot. Runs until
+    /// the sender is dropped.
+    pub async fn follow_cluster_slot(self: Arc<Self>, mut slots: watch::Receiver<u64>) {
+        while slots.changed().await.is_ok() {
+            let slot = *slots.borrow_and_update();
+            let evicted = self.evict_slot_scoped();
+            if evicted > 0 {
+                debug!(
+                    "Cluster slot advanced to {}, evicted {} cached responses",
+                    slot, evicted
+                );
+            }
+        }
+    }
+}
+
+/// Strong `ETag` for a cached `result`: the same content always gets the same tag,
+/// whatever the request `id`, across processes and builds. The hash is 64-bit FNV-1a,
+/// which is fixed by its definition, unlike `DefaultHasher`.
+fn etag(result: &Value) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let hash = result.to_string().bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+/// First signature of the transaction in `sendTransaction` params, which identifies it:
+/// `[encoded_tx, {"encoding": "base58" | "base64"}]`, base58 when no encoding is given.
+fn transaction_signature(params: &Value) -> Option<[u8; 64]> {
+    let encoded = params.get(0)?.as_str()?;
+    let encoding = params
+        .get(1)
+        .and_then(|config| config.get("encoding"))
+        .and_then(Value::as_str)
+        .unwrap_or("base58");
+    let tx = match encoding {
+        "base58" => decode_base58(encoded)?,
+        "base64" => BASE64.decode(encoded).ok()?,
+        _ => return None,
+    };
+
+    // A compact-u16 signature count precedes the 64-byte signatures
+    let mut count = 0usize;
+    let mut offset = 0;
+    loop {
+        let byte = *tx.get(offset)?;
+        count |= usize::from(byte & 0x7f) << (7 * offset);
+        offset += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if offset == 3 {
+            return None;
+        }
+    }
+    if count == 0 {
+        return None;
+    }
+    tx.get(offset..offset + 64)?.try_into().ok()
+}
+
+/// Bitcoin-alphabet base58, as used by Solana; `None` on any character outside it.
+fn decode_base58(encoded: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    // Little-endian base-256 digits of the value decoded so far
+    let mut bytes: Vec<u8> = Vec::with_capacity(encoded.len());
+    for c in encoded.bytes() {
+        let mut carry = ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Each leading '1' is a leading zero byte
+    let zeros = encoded.bytes().take_while(|&c| c == b'1').count();
+    bytes.resize(bytes.len() + zeros, 0);
+    bytes.reverse();
+    Some(bytes)
+}
+
+/// Whether a request asked to skip cached answers, with `Cache-Control: no-cache` or
+/// `fresh=true` in the query string. Its upstream response still refreshes the cache.
+pub fn bypass_requested(cache_control: Option<&str>, query: Option<&str>) -> bool {
+    let no_cache = cache_control.is_some_and(|value| {
+        value
+            .split(',')
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+    });
+    let fresh = query.is_some_and(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .any(|(key, value)| key == "fresh" && value == "true")
+    });
+    no_cache || fresh
+}
+
+/// Whether an `If-None-Match` header value names `etag` (or is `*`). Weak validators
+/// match too, as the comparison for `If-None-Match` is weak.
+pub fn if_none_match(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
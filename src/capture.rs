@@ -0,0 +1,176 @@
+//! Capture of recent failed requests for debugging intermittent customer reports.
+//! `proxy` records each request answered with a 4xx or 5xx, together with the
+//! response, in a bounded ring buffer served by `GET /admin/captures`. Credentials
+//! are redacted before anything is stored.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::{request::Parts, HeaderMap, StatusCode};
+use serde::Serialize;
+
+use crate::handlers::{truncate_for_log, API_KEY_HEADER};
+
+/// Stands in for credentials in captured headers and query strings.
+pub const REDACTED: &str = "[redacted]";
+
+/// Request headers whose values are never captured.
+const SENSITIVE_HEADERS: [&str; 4] = [
+    API_KEY_HEADER,
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+];
+
+/// The client side of a request, taken before it is proxied.
+#[derive(Debug, Clone)]
+pub struct CaptureRequest {
+    rpc_method: Option<String>,
+    path: String,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl CaptureRequest {
+    /// Snapshot of a request with credentials redacted and the body cut to
+    /// `max_body_bytes`.
+    pub fn new(
+        parts: &Parts,
+        rpc_method: Option<String>,
+        body: &[u8],
+        max_body_bytes: usize,
+    ) -> Self {
+        let path = match parts.uri.query() {
+            Some(query) => format!("{}?{}", parts.uri.path(), redact_query(query)),
+            None => parts.uri.path().to_string(),
+        };
+        Self {
+            rpc_method,
+            path,
+            headers: redact_headers(&parts.headers),
+            body: truncate_for_log(body, max_body_bytes),
+        }
+    }
+}
+
+/// One failed request and the response the client got.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedRequest {
+    /// Milliseconds since the Unix epoch when the response was sent
+    pub timestamp_ms: u64,
+    pub rpc_method: Option<String>,
+    /// Request path and query, with any `api-key` value redacted
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub request_body: String,
+    pub status: u16,
+    /// Backend that answered, `null` when the router answered itself
+    pub backend: Option<String>,
+    /// `null` for responses streamed without a known size, which aren't buffered
+    pub response_body: Option<String>,
+}
+
+/// Ring buffer of the most recent failed requests. A capacity of 0 disables capture.
+#[derive(Debug)]
+pub struct RequestCapture {
+    entries: Mutex<VecDeque<CapturedRequest>>,
+    capacity: usize,
+    max_body_bytes: usize,
+}
+
+impl Default for RequestCapture {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl RequestCapture {
+    /// Keeps the last `capacity` failures, with request and response bodies cut to
+    /// `max_body_bytes` each.
+    pub fn new(capacity: usize, max_body_bytes: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            max_body_bytes,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// Stores a failed request, evicting the oldest entry once the buffer is full.
+    pub fn record(
+        &self,
+        request: CaptureRequest,
+        status: StatusCode,
+        backend: Option<String>,
+        response_body: Option<&[u8]>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let entry = CapturedRequest {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            rpc_method: request.rpc_method,
+            path: request.path,
+            headers: request.headers,
+            request_body: request.body,
+            status: status.as_u16(),
+            backend,
+            response_body: response_body.map(|body| truncate_for_log(body, self.max_body_bytes)),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Captured requests, newest first.
+    pub fn entries(&self) -> Vec<CapturedRequest> {
+        self.entries.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+/// Header values by name, with credentials replaced by `REDACTED`. Repeated headers
+/// are joined with `, `.
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut redacted = BTreeMap::new();
+    for (name, value) in headers {
+        let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        redacted
+            .entry(name.to_string())
+            .and_modify(|joined: &mut String| {
+                joined.push_str(", ");
+                joined.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    redacted
+}
+
+/// `query` with the value of every `api-key` parameter replaced by `REDACTED`.
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("api-key", _)) => format!("api-key={}", REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
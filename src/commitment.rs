@@ -0,0 +1,99 @@
+//! Default commitment for requests that don't name one. Providers disagree on the
+//! commitment they assume when it is omitted, so `proxy.default_commitment` fills it in
+//! before the request is forwarded, and every backend answers at the same level.
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+/// Methods that take a trailing config object with a `commitment`, and the position of
+/// that object in `params`. Methods that reject `processed` (`getBlock`,
+/// `getTransaction`, `getSignaturesForAddress`, ...) are left out so any default level
+/// is valid for every method listed.
+const COMMITMENT_PARAM: &[(&str, usize)] = &[
+    ("getAccountInfo", 1),
+    ("getBalance", 1),
+    ("getBlockHeight", 0),
+    ("getBlockProduction", 0),
+    ("getEpochInfo", 0),
+    ("getFeeForMessage", 1),
+    ("getInflationGovernor", 0),
+    ("getLargestAccounts", 0),
+    ("getLatestBlockhash", 0),
+    ("getMinimumBalanceForRentExemption", 1),
+    ("getMultipleAccounts", 1),
+    ("getProgramAccounts", 1),
+    ("getSlot", 0),
+    ("getSlotLeader", 0),
+    ("getStakeMinimumDelegation", 0),
+    ("getSupply", 0),
+    ("getTokenAccountBalance", 1),
+    ("getTokenAccountsByDelegate", 2),
+    ("getTokenAccountsByOwner", 2),
+    ("getTokenLargestAccounts", 1),
+    ("getTokenSupply", 1),
+    ("getTransactionCount", 0),
+    ("getVoteAccounts", 0),
+    ("isBlockhashValid", 1),
+    ("simulateTransaction", 1),
+];
+
+/// Position of `method`'s config object in `params`, if the method takes a commitment.
+pub fn config_position(method: &str) -> Option<usize> {
+    COMMITMENT_PARAM
+        .iter()
+        .find(|(m, _)| *m == method)
+        .map(|&(_, position)| position)
+}
+
+/// Rewrites the parsed request body `json` so every request to a method that takes a
+/// commitment names one, adding `level` to its config object (or appending the object)
+/// where it is missing. Works on single requests and batches. Returns the rewritten
+/// body, or `None` when nothing needed to change.
+pub fn inject_default(json: &mut Value, level: &str) -> Option<Bytes> {
+    let changed = match &mut *json {
+        Value::Array(entries) => {
+            let mut changed = false;
+            for entry in entries {
+                changed |= inject(entry, level);
+            }
+            changed
+        }
+        request => inject(request, level),
+    };
+    if !changed {
+        return None;
+    }
+    serde_json::to_vec(&*json).ok().map(Bytes::from)
+}
+
+/// Adds the commitment to one request. Requests whose params are too short to reach
+/// the config position, or hold something other than an object there, are left alone.
+fn inject(request: &mut Value, level: &str) -> bool {
+    let Some(request) = request.as_object_mut() else {
+        return false;
+    };
+    let Some(position) = request
+        .get("method")
+        .and_then(Value::as_str)
+        .and_then(config_position)
+    else {
+        return false;
+    };
+    let params = request
+        .entry("params")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    let Some(params) = params.as_array_mut() else {
+        return false;
+    };
+
+    if params.len() == position {
+        params.push(Value::Object(Map::new()));
+    }
+    match params.get_mut(position) {
+        Some(Value::Object(config)) if !config.contains_key("commitment") => {
+            config.insert("commitment".to_string(), level.into());
+            true
+        }
+        _ => false,
+    }
+}
@@ -1,30 +1,838 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    time::Duration,
+};
 
+use axum::http::{HeaderName, HeaderValue};
 use serde::Deserialize;
 
+use crate::{error::ConfigError, net::IpCidr};
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub port: u16,
     pub metrics_port: u16, // Required now
     pub redis_url: String, // Added Redis URL
+    /// Optional private listener for health/version/metrics and admin endpoints
+    pub admin_port: Option<u16>,
+    /// Bearer token for the `/admin/backends` API, which is disabled when unset
+    pub admin_token: Option<String>,
+    /// Optional Unix domain socket path serving the same routes as the HTTP port
+    pub listen_uds: Option<String>,
     pub backends: Vec<Backend>,
+    /// Used only when no regular backend is healthy (e.g. a public RPC endpoint)
+    pub fallback_backend: Option<Backend>,
+    /// Label of the backend that takes all traffic not claimed by a method route while
+    /// it is healthy; weighted selection applies only when it is down
+    pub default_backend: Option<String>,
     #[serde(default)]
-    pub method_routes: HashMap<String, String>,
+    pub method_routes: HashMap<String, MethodRoute>,
     #[serde(default)]
     pub health_check: HealthCheckConfig,
     #[serde(default)]
     pub proxy: ProxyConfig,
+    #[serde(default)]
+    pub startup_probe: StartupProbeConfig,
+    /// Seconds to report not-ready after SIGTERM before the listeners start draining
+    #[serde(default)]
+    pub shutdown_delay_secs: u64,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub listener: ListenerConfig,
+    /// Pooling of connections to backends; read at startup only
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+    /// Per-method rewrites applied to upstream responses, keyed by JSON-RPC method
+    #[serde(default)]
+    pub transform: HashMap<String, ResponseTransform>,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Recent failed requests kept for `GET /admin/captures`; read at startup only
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    /// How a backend is chosen among the healthy candidates; read at startup only
+    #[serde(default)]
+    pub selection: SelectionMode,
+    /// Where per-key request counts are kept; read at startup only
+    #[serde(default)]
+    pub rate_limiter: RateLimiterKind,
+    /// What happens to requests while the keystore can't validate keys (e.g. Redis is
+    /// down); read at startup only
+    #[serde(default)]
+    pub keystore_failure: KeystoreFailurePolicy,
+    /// Seconds a key's last successful validation is kept to answer for it while Redis
+    /// is unreachable (0 = off); read at startup only
+    #[serde(default)]
+    pub key_stale_ttl_secs: u64,
+    /// Method pattern (`getAccountInfo`, `get*`, `*Subscribe`) to the label recorded for
+    /// it in request metrics and `/stats` groups; read at startup only
+    #[serde(default)]
+    pub metric_groups: HashMap<String, String>,
+}
+
+/// Built-in `RateLimiter` used by the proxy.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimiterKind {
+    /// Counted in Redis and shared by every router instance (`RedisRateLimiter`)
+    #[default]
+    Redis,
+    /// Counted in process memory, per instance (`InMemoryRateLimiter`)
+    Memory,
+}
+
+/// Handling of keystore errors during key validation.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeystoreFailurePolicy {
+    /// Reject the request with 500
+    #[default]
+    FailClosed,
+    /// Accept the key provisionally (owner `unverified`, no rate limit) so traffic keeps
+    /// flowing while the keystore is down
+    FailOpen,
+}
+
+/// A `[method_routes]` entry: a backend label, an ordered list of labels to try in
+/// turn, or a table such as `{ backend = "archival", strict = true }` whose `backend`
+/// is either of those.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "MethodRouteEntry")]
+pub struct MethodRoute {
+    /// Backends in order of preference; the first usable one takes the request
+    pub backends: Vec<String>,
+    /// Return 503 while none of `backends` is usable instead of falling back to normal
+    /// selection, for methods other backends can't serve
+    pub strict: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RouteLabels {
+    One(String),
+    Ordered(Vec<String>),
+}
+
+impl From<RouteLabels> for Vec<String> {
+    fn from(labels: RouteLabels) -> Self {
+        match labels {
+            RouteLabels::One(label) => vec![label],
+            RouteLabels::Ordered(labels) => labels,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MethodRouteEntry {
+    Labels(RouteLabels),
+    Table {
+        backend: RouteLabels,
+        #[serde(default)]
+        strict: bool,
+    },
+}
+
+impl From<MethodRouteEntry> for MethodRoute {
+    fn from(entry: MethodRouteEntry) -> Self {
+        match entry {
+            MethodRouteEntry::Labels(labels) => Self {
+                backends: labels.into(),
+                strict: false,
+            },
+            MethodRouteEntry::Table { backend, strict } => Self {
+                backends: backend.into(),
+                strict,
+            },
+        }
+    }
+}
+
+impl MethodRoute {
+    /// A route that never falls back to a backend outside `backends`.
+    pub fn strict(backends: impl Into<MethodRoute>) -> Self {
+        Self {
+            strict: true,
+            ..backends.into()
+        }
+    }
+}
+
+impl From<&str> for MethodRoute {
+    fn from(backend: &str) -> Self {
+        [backend].into()
+    }
+}
+
+impl<const N: usize> From<[&str; N]> for MethodRoute {
+    fn from(backends: [&str; N]) -> Self {
+        Self {
+            backends: backends.iter().map(|b| b.to_string()).collect(),
+            strict: false,
+        }
+    }
+}
+
+impl std::fmt::Display for MethodRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.backends.join(" > "))?;
+        if self.strict {
+            f.write_str(" (strict)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Built-in `BackendSelector` used by the proxy.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// Weighted random choice (`WeightedRandomSelector`)
+    #[default]
+    Weighted,
+    /// Requests with the same first param go to the same backend
+    /// (`ConsistentHashSelector`)
+    ConsistentHash,
+    /// Lowest-`cost` backends first, pricier ones only when those are unavailable or
+    /// saturated (`CheapestFirstSelector`)
+    Cheapest,
+}
+
+/// Declarative rewrite of a JSON-RPC response, used to smooth over provider-specific
+/// response shapes.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ResponseTransform {
+    /// Fields removed from the `result` object
+    pub remove: Vec<String>,
+}
+
+impl ResponseTransform {
+    /// Applies the rewrite to a parsed response. Responses without an object `result`
+    /// (errors, scalar results) are left unchanged. Returns whether anything changed.
+    pub fn apply(&self, response: &mut serde_json::Value) -> bool {
+        let Some(result) = response.get_mut("result").and_then(|r| r.as_object_mut()) else {
+            return false;
+        };
+        let mut changed = false;
+        for field in &self.remove {
+            changed |= result.remove(field).is_some();
+        }
+        changed
+    }
+}
+
+/// Connection settings for the inbound HTTP and WebSocket listeners.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ListenerConfig {
+    /// Disable Nagle's algorithm on accepted connections
+    pub tcp_nodelay: bool,
+    /// Keep HTTP/1.1 client connections open between requests
+    pub http1_keep_alive: bool,
+    /// Interval between HTTP/2 keep-alive pings to idle clients; 0 disables them
+    pub http2_keep_alive_interval_secs: u64,
+    /// New connections accepted per client IP each second; more are closed right away
+    /// (0 = unlimited)
+    pub max_connections_per_ip_per_sec: u64,
+    /// Proxies (CIDRs) whose `X-Forwarded-For` is believed; their own connections
+    /// aren't limited
+    pub trusted_proxies: Vec<IpCidr>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            tcp_nodelay: true,
+            http1_keep_alive: true,
+            http2_keep_alive_interval_secs: 0,
+            max_connections_per_ip_per_sec: 0,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// Connection settings for the client that reaches backends.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    /// Seconds a pooled backend connection is reused before it is closed and replaced;
+    /// 0 keeps connections until they go idle or the backend closes them
+    pub max_connection_lifetime_secs: u64,
+    /// UNSAFE, for development against self-signed backends only: skip verification of
+    /// backend TLS certificates and host names. Also requires
+    /// `SOL_RPC_ROUTER_ALLOW_INVALID_CERTS=1` in the environment
+    pub danger_accept_invalid_certs: bool,
+}
+
+/// Environment variable that must be `1` for `upstream.danger_accept_invalid_certs` to
+/// be accepted, so a config file alone can't turn certificate checks off.
+pub const ALLOW_INVALID_CERTS_ENV: &str = "SOL_RPC_ROUTER_ALLOW_INVALID_CERTS";
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Requests slower than this are logged at warn level; 0 disables slow-request warnings
+    pub slow_request_threshold_ms: u64,
+    /// Fraction (0.0-1.0) of faster requests logged at info level; the rest log at debug
+    pub fast_request_sample_rate: f64,
+    /// Fraction (0.0-1.0) of proxied requests whose request and response bodies are logged
+    pub body_sample_rate: f64,
+    /// Logged bodies are truncated to this many bytes
+    pub max_logged_body_bytes: usize,
+    /// Key owners whose every request is logged at info with owner, status and bodies
+    pub traced_owners: Vec<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            slow_request_threshold_ms: 1000,
+            fast_request_sample_rate: 1.0,
+            body_sample_rate: 0.0,
+            max_logged_body_bytes: 1024,
+            traced_owners: Vec::new(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn is_slow(&self, duration: Duration) -> bool {
+        self.slow_request_threshold_ms > 0
+            && duration >= Duration::from_millis(self.slow_request_threshold_ms)
+    }
+
+    /// Whether a request under the slow threshold should be logged at info level.
+    pub fn sample_fast_request(&self) -> bool {
+        self.fast_request_sample_rate >= 1.0
+            || (self.fast_request_sample_rate > 0.0
+                && rand::random::<f64>() < self.fast_request_sample_rate)
+    }
+
+    pub fn is_traced(&self, owner: &str) -> bool {
+        self.traced_owners.iter().any(|o| o == owner)
+    }
+
+    /// Whether a proxied request's bodies should be logged.
+    pub fn sample_body(&self) -> bool {
+        self.body_sample_rate >= 1.0
+            || (self.body_sample_rate > 0.0 && rand::random::<f64>() < self.body_sample_rate)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// How long a cached response is served; 0 disables the cache
+    pub ttl_ms: u64,
+    /// Responses kept; the least used are evicted beyond this
+    pub max_entries: usize,
+    /// Idempotent methods whose successful responses are cached, keyed by params
+    pub methods: Vec<String>,
+    /// Cached methods whose answer changes every slot; their entries are also evicted
+    /// whenever the health check sees the cluster slot advance
+    pub slot_scoped_methods: Vec<String>,
+    /// How long a successful `sendTransaction` answers resubmissions of the same
+    /// transaction (matched by signature) without forwarding them; 0 disables it
+    pub send_transaction_dedup_ms: u64,
+    /// Cached methods called once, without params, at startup so the first clients
+    /// after a deploy hit a primed cache
+    pub warm_methods: Vec<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_ms: 0,
+            max_entries: 10_000,
+            methods: Vec::new(),
+            slot_scoped_methods: Vec::new(),
+            send_transaction_dedup_ms: 0,
+            warm_methods: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Failed (4xx/5xx) requests kept, oldest evicted first; 0 disables capture
+    pub max_entries: usize,
+    /// Request and response bodies are cut to this many bytes each
+    pub max_body_bytes: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 0,
+            max_body_bytes: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StartupProbeConfig {
+    /// Probe every backend once before the listeners are bound
+    pub enabled: bool,
+    /// Refuse to start when the probe finds zero reachable backends
+    pub require_reachable: bool,
+}
+
+impl Default for StartupProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            require_reachable: true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(default)]
 pub struct ProxyConfig {
     pub timeout_secs: u64,
+    /// Verify that single JSON-RPC responses echo the request `id`
+    pub validate_response_id: bool,
+    /// Treat a 2xx response with an empty body, a body cut off mid-stream, or (up to
+    /// `max_validated_json_bytes`) one that isn't JSON as a backend failure, retried like
+    /// a failure status. Buffers every successful response
+    pub validate_response_body: bool,
+    pub max_validated_json_bytes: usize,
+    pub id_mismatch_action: IdMismatchAction,
+    /// Upstream statuses treated as a backend failure by the proxy and the health check
+    pub failure_status_codes: FailureStatusCodes,
+    /// Accept the API key from the `api-key` query param; when false only the
+    /// `x-api-key` header is accepted
+    pub allow_query_api_key: bool,
+    /// JSON-RPC methods that are safe to send more than once (retry, hedging)
+    pub idempotent_methods: IdempotentMethods,
+    /// Extra attempts for idempotent requests after a failure that `retry_policy`
+    /// treats as retryable
+    pub max_retries: u32,
+    /// Which failures are retried
+    pub retry_policy: RetryPolicy,
+    /// Backoff before the first retry; doubles on each further retry
+    pub retry_base_delay_ms: u64,
+    /// Upper bound for a single backoff delay
+    pub retry_max_delay_ms: u64,
+    /// No retry starts once a request has been running this long
+    pub retry_budget_ms: u64,
+    /// Retry token bucket shared by all requests: each retry spends a token, each
+    /// successful response refills `retry_throttle_token_ratio` of one
+    pub retry_throttle_max_tokens: u32,
+    pub retry_throttle_token_ratio: f64,
+    /// Idempotent methods that get a second, hedged request to another backend when
+    /// the first hasn't answered within `hedge_delay_ms`
+    pub hedge_methods: Vec<String>,
+    pub hedge_delay_ms: u64,
+    /// Requests with more headers than this are rejected with 431
+    pub max_header_count: usize,
+    /// Requests whose header names and values add up to more bytes are rejected with 431
+    pub max_header_bytes: usize,
+    /// Re-compress bodies that clients sent gzip/deflate-encoded before forwarding them,
+    /// instead of sending the decompressed body
+    pub recompress_request_bodies: bool,
+    /// Upstream responses larger than this are rejected with 502, or cut off if the
+    /// limit is crossed mid-stream (0 = unlimited; buffered responses are still capped
+    /// at 10 MB)
+    pub max_response_bytes: usize,
+    /// Inbound messages per second allowed on one WebSocket connection, with bursts of
+    /// one second's worth; exceeding it closes the connection (0 = unlimited). API keys
+    /// can override it with their own `ws_message_rate`
+    pub ws_message_rate: u64,
+    /// Extra attempts to open the backend WebSocket before the client's upgrade is
+    /// refused, each against a freshly selected backend
+    pub ws_connect_retries: u32,
+    /// Backoff before the first WebSocket connect retry; doubles on each further retry
+    pub ws_connect_retry_base_delay_ms: u64,
+    /// Upper bound for a single WebSocket connect backoff
+    pub ws_connect_retry_max_delay_ms: u64,
+    /// Requests one owner may have in flight at once across all its keys; more are
+    /// answered with 429 (0 = unlimited). API keys can override it with their own
+    /// `max_concurrent_requests`
+    pub max_concurrent_requests_per_owner: u64,
+    /// Per-method overrides of `timeout_secs`, plus a soft threshold past which a still
+    /// running request is reported as slow
+    pub method_timeouts: HashMap<String, MethodTimeout>,
+    /// Answer `GET {path_prefix}{method}` as a JSON-RPC call to `method`, for clients
+    /// that don't speak JSON-RPC (off when unset)
+    pub rest_gateway: Option<RestGatewayConfig>,
+    /// Answer `GET /?method=...&params=...` as a JSON-RPC call, for methods listed in
+    /// `idempotent_methods` only, so a GET can't have side effects
+    pub allow_get_requests: bool,
+    /// Send write methods (e.g. `sendTransaction`) to one group of backends and reads
+    /// to another, each weighted on its own (off when unset)
+    pub read_write_split: Option<ReadWriteSplit>,
+    /// `User-Agent` sent upstream in place of the client's, e.g. `sol-rpc-router/1.2.3`
+    pub upstream_user_agent: Option<String>,
+    /// When `upstream_user_agent` replaces the client's `User-Agent`, keep the original
+    /// in `X-Original-User-Agent`
+    pub forward_original_user_agent: bool,
+    /// Headers set on every upstream request, replacing any the client sent, so
+    /// providers can identify the gateway
+    pub upstream_headers: HashMap<String, String>,
+    /// Upstream response trailers relayed to clients (e.g. `grpc-status`); all others
+    /// are dropped. Only responses streamed through unchanged carry trailers
+    pub forward_trailers: Vec<String>,
+    /// Availability zone this router runs in: selection stays among healthy backends
+    /// with this `zone` in the chosen tier, and only spills to other zones when none
+    /// is left (no preference when unset)
+    pub local_zone: Option<String>,
+    /// Tell clients which backend served each proxied request in `X-Served-By`, and
+    /// whether cached methods were cache hits in `X-Cache`. Off by default, since it
+    /// reveals the backend topology
+    pub expose_backend_header: bool,
+    /// Object added to every successful JSON-RPC response, e.g. the region that served
+    /// it (off when unset). Buffers all successful responses
+    pub response_metadata: Option<ResponseMetadata>,
+    /// Halve a backend's selection weight each time it answers `429`, restoring one
+    /// halving per `adaptive_weight_cooldown_secs` without another
+    pub adaptive_weights: bool,
+    pub adaptive_weight_cooldown_secs: u64,
+    /// Commitment level added to requests for methods that take one when the client
+    /// didn't name it, so backends with different defaults answer alike
+    pub default_commitment: Option<String>,
+}
+
+/// Routing mode for deployments where transactions must reach nodes with good leader
+/// connections while reads can go to replicas.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ReadWriteSplit {
+    /// Methods routed to `write_backends`; every other method is a read
+    pub write_methods: Vec<String>,
+    /// Labels of the backends that take write methods
+    pub write_backends: Vec<String>,
+    /// Labels of the backends that take reads; empty lets reads go to any backend
+    pub read_backends: Vec<String>,
+    /// Whether writes may go to any backend while no write backend is usable; off, they
+    /// fail with 503 instead of reaching read replicas
+    pub spill_writes: bool,
+}
+
+impl Default for ReadWriteSplit {
+    fn default() -> Self {
+        Self {
+            write_methods: vec!["sendTransaction".to_string()],
+            write_backends: Vec::new(),
+            read_backends: Vec::new(),
+            spill_writes: false,
+        }
+    }
+}
+
+impl ReadWriteSplit {
+    /// Whether `method` is a write. Requests without a single method (e.g. batches)
+    /// are reads.
+    pub fn is_write(&self, method: Option<&str>) -> bool {
+        method.is_some_and(|m| self.write_methods.iter().any(|w| w == m))
+    }
+
+    /// Labels of the backends that should serve `method`; empty when any backend may.
+    pub fn group_for(&self, method: Option<&str>) -> &[String] {
+        if self.is_write(method) {
+            &self.write_backends
+        } else {
+            &self.read_backends
+        }
+    }
+}
+
+/// Router identity injected into responses for multi-tenant transparency:
+/// `{"jsonrpc":"2.0","result":42,"id":1,"_router":{"region":"eu-west"}}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ResponseMetadata {
+    /// Top-level field the metadata is added under, replacing any the backend sent
+    pub field: String,
+    pub value: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for ResponseMetadata {
+    fn default() -> Self {
+        Self {
+            field: "_router".to_string(),
+            value: serde_json::Map::new(),
+        }
+    }
+}
+
+impl ResponseMetadata {
+    /// Adds the metadata to a parsed response, or to each response of a batch.
+    /// Returns whether anything changed; bodies that aren't JSON-RPC responses (scalars,
+    /// arrays of scalars) are left alone.
+    pub fn apply(&self, response: &mut serde_json::Value) -> bool {
+        let objects: Vec<_> = match response {
+            serde_json::Value::Object(object) => vec![object],
+            serde_json::Value::Array(entries) => entries
+                .iter_mut()
+                .filter_map(|e| e.as_object_mut())
+                .collect(),
+            _ => return false,
+        };
+        let changed = !objects.is_empty();
+        for object in objects {
+            object.insert(
+                self.field.clone(),
+                serde_json::Value::Object(self.value.clone()),
+            );
+        }
+        changed
+    }
+}
+
+/// REST-style access to the proxy: `GET /v1/getSlot` is forwarded as
+/// `{"jsonrpc":"2.0","id":1,"method":"getSlot"}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RestGatewayConfig {
+    /// Path in front of the method name; must start and end with `/`
+    pub path_prefix: String,
+    /// Reply with the bare `result` instead of the whole JSON-RPC response; error
+    /// responses are passed through unchanged
+    pub unwrap_result: bool,
+}
+
+impl Default for RestGatewayConfig {
+    fn default() -> Self {
+        Self {
+            path_prefix: "/".to_string(),
+            unwrap_result: false,
+        }
+    }
+}
+
+impl RestGatewayConfig {
+    /// The method named by `path`, if it is the prefix followed by a single segment.
+    pub fn method_for<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let method = path.strip_prefix(&self.path_prefix)?;
+        let valid = !method.is_empty()
+            && method
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        valid.then_some(method)
+    }
+}
+
+/// Timeouts for one JSON-RPC method, e.g.
+/// `getProgramAccounts = { timeout_secs = 120, soft_timeout_secs = 20 }`.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct MethodTimeout {
+    /// Hard timeout answered with 504; `proxy.timeout_secs` when unset
+    pub timeout_secs: Option<u64>,
+    /// Requests still running after this long are logged and counted as slow, but
+    /// not cut off
+    pub soft_timeout_secs: Option<u64>,
 }
 
 impl Default for ProxyConfig {
     fn default() -> Self {
-        Self { timeout_secs: 30 }
+        Self {
+            timeout_secs: 30,
+            validate_response_id: false,
+            validate_response_body: false,
+            max_validated_json_bytes: 1024 * 1024,
+            id_mismatch_action: IdMismatchAction::Pass,
+            failure_status_codes: FailureStatusCodes::default(),
+            allow_query_api_key: true,
+            idempotent_methods: IdempotentMethods::default(),
+            max_retries: 0,
+            retry_policy: RetryPolicy::default(),
+            retry_base_delay_ms: 50,
+            retry_max_delay_ms: 1000,
+            retry_budget_ms: 3000,
+            retry_throttle_max_tokens: 10,
+            retry_throttle_token_ratio: 0.1,
+            hedge_methods: Vec::new(),
+            hedge_delay_ms: 50,
+            max_header_count: 100,
+            max_header_bytes: 16 * 1024,
+            recompress_request_bodies: false,
+            max_response_bytes: 0,
+            ws_message_rate: 0,
+            ws_connect_retries: 0,
+            ws_connect_retry_base_delay_ms: 100,
+            ws_connect_retry_max_delay_ms: 2000,
+            max_concurrent_requests_per_owner: 0,
+            method_timeouts: HashMap::new(),
+            rest_gateway: None,
+            allow_get_requests: false,
+            read_write_split: None,
+            upstream_user_agent: None,
+            forward_original_user_agent: false,
+            upstream_headers: HashMap::new(),
+            forward_trailers: Vec::new(),
+            local_zone: None,
+            expose_backend_header: false,
+            response_metadata: None,
+            adaptive_weights: false,
+            adaptive_weight_cooldown_secs: 30,
+            default_commitment: None,
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Hard timeout for requests calling `method`.
+    pub fn timeout_for(&self, method: Option<&str>) -> Duration {
+        let secs = method
+            .and_then(|m| self.method_timeouts.get(m))
+            .and_then(|t| t.timeout_secs)
+            .unwrap_or(self.timeout_secs);
+        Duration::from_secs(secs)
+    }
+
+    /// Soft timeout for requests calling `method`, if one is configured.
+    pub fn soft_timeout_for(&self, method: Option<&str>) -> Option<Duration> {
+        let secs = self.method_timeouts.get(method?)?.soft_timeout_secs?;
+        Some(Duration::from_secs(secs))
+    }
+}
+
+/// Upstream HTTP statuses that mean "this backend is failing" rather than "the client
+/// sent a bad request". Defaults to every 5xx status.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct FailureStatusCodes(pub Vec<u16>);
+
+impl Default for FailureStatusCodes {
+    fn default() -> Self {
+        Self((500..=599).collect())
+    }
+}
+
+impl FailureStatusCodes {
+    pub fn is_failure(&self, status: u16) -> bool {
+        self.0.contains(&status)
+    }
+}
+
+/// Read-only Solana RPC methods that are safe to send more than once. Listing
+/// `sendTransaction` here opts in to resending it, at the risk of double submission.
+const DEFAULT_IDEMPOTENT_METHODS: &[&str] = &[
+    "getAccountInfo",
+    "getBalance",
+    "getBlock",
+    "getBlockCommitment",
+    "getBlockHeight",
+    "getBlockProduction",
+    "getBlockTime",
+    "getBlocks",
+    "getBlocksWithLimit",
+    "getClusterNodes",
+    "getEpochInfo",
+    "getEpochSchedule",
+    "getFeeForMessage",
+    "getFirstAvailableBlock",
+    "getGenesisHash",
+    "getHealth",
+    "getHighestSnapshotSlot",
+    "getIdentity",
+    "getInflationGovernor",
+    "getInflationRate",
+    "getInflationReward",
+    "getLargestAccounts",
+    "getLatestBlockhash",
+    "getLeaderSchedule",
+    "getMaxRetransmitSlot",
+    "getMaxShredInsertSlot",
+    "getMinimumBalanceForRentExemption",
+    "getMultipleAccounts",
+    "getProgramAccounts",
+    "getRecentPerformanceSamples",
+    "getRecentPrioritizationFees",
+    "getSignatureStatuses",
+    "getSignaturesForAddress",
+    "getSlot",
+    "getSlotLeader",
+    "getSlotLeaders",
+    "getStakeMinimumDelegation",
+    "getSupply",
+    "getTokenAccountBalance",
+    "getTokenAccountsByDelegate",
+    "getTokenAccountsByOwner",
+    "getTokenLargestAccounts",
+    "getTokenSupply",
+    "getTransaction",
+    "getTransactionCount",
+    "getVersion",
+    "getVoteAccounts",
+    "isBlockhashValid",
+    "minimumLedgerSlot",
+    "simulateTransaction",
+];
+
+/// JSON-RPC methods that can be retried or sent to several backends without side
+/// effects. Defaults to Solana's read-only methods; `sendTransaction` and
+/// `requestAirdrop` are excluded unless listed explicitly.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct IdempotentMethods(pub HashSet<String>);
+
+impl Default for IdempotentMethods {
+    fn default() -> Self {
+        Self(
+            DEFAULT_IDEMPOTENT_METHODS
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl IdempotentMethods {
+    pub fn contains(&self, method: &str) -> bool {
+        self.0.contains(method)
+    }
+}
+
+/// What `proxy` does when an upstream response `id` doesn't match the request.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdMismatchAction {
+    /// Log and count the mismatch, then return the response unchanged
+    Pass,
+    /// Retry once against a different healthy backend
+    Retry,
+}
+
+/// Which failed attempts `proxy` retries on another backend, for idempotent methods
+/// and within `max_retries`. Each level adds to the one before it.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Never retry
+    #[default]
+    #[serde(rename = "never")]
+    Never,
+    /// Retry only when the backend couldn't be reached, so no request was processed
+    #[serde(rename = "connect_errors_only")]
+    ConnectErrorsOnly,
+    /// Also retry responses with a `failure_status_codes` status
+    #[serde(rename = "connect_and_5xx")]
+    ConnectAnd5xx,
+    /// Also retry successful responses carrying a JSON-RPC error object
+    #[serde(rename = "connect_and_5xx_and_jsonrpc")]
+    ConnectAnd5xxAndJsonRpc,
+}
+
+impl RetryPolicy {
+    pub fn retries_connect_errors(self) -> bool {
+        self != Self::Never
+    }
+
+    pub fn retries_failure_status(self) -> bool {
+        matches!(self, Self::ConnectAnd5xx | Self::ConnectAnd5xxAndJsonRpc)
+    }
+
+    pub fn retries_jsonrpc_errors(self) -> bool {
+        self == Self::ConnectAnd5xxAndJsonRpc
     }
 }
 
@@ -34,9 +842,53 @@ pub struct HealthCheckConfig {
     pub interval_secs: u64,
     pub timeout_secs: u64,
     pub method: String,
+    /// JSON-RPC `params` sent with `method`: an array or an object
+    pub params: serde_json::Value,
     pub consecutive_failures_threshold: u32,
     pub consecutive_successes_threshold: u32,
     pub max_slot_lag: u64,
+    /// Seconds after startup during which failed checks are logged but not counted
+    pub warmup_secs: u64,
+    /// Maximum number of backends probed at the same time
+    pub concurrency: usize,
+    /// Spread the first round's probes evenly across `interval_secs` instead of
+    /// starting them all at startup
+    pub stagger_first_check: bool,
+    /// TCP connect timeout of the health check client; 0 leaves connects bounded only
+    /// by `timeout_secs`
+    pub connect_timeout_ms: u64,
+    /// How long the health check client keeps idle connections to backends open
+    pub pool_idle_timeout_secs: u64,
+    /// URL that receives a JSON POST whenever a backend turns healthy or unhealthy
+    pub webhook_url: Option<String>,
+    /// Minimum time between two notifications about the same backend; flips inside it
+    /// are folded into the next notification
+    pub webhook_min_interval_secs: u64,
+    /// While a backend keeps failing with the same error, log it again only after this
+    /// many seconds (0 logs every failure)
+    pub repeated_error_log_interval_secs: u64,
+    /// Representative read probed after a passing `method` check; a backend is only
+    /// healthy while both pass
+    pub secondary_check: Option<SecondaryCheck>,
+}
+
+/// Second health check method, e.g. `getLatestBlockhash`, that catches backends which
+/// are live but can't serve reads. It passes only on a JSON-RPC `result`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SecondaryCheck {
+    pub method: String,
+    /// JSON-RPC `params` sent with `method`: an array or an object
+    pub params: serde_json::Value,
+}
+
+impl Default for SecondaryCheck {
+    fn default() -> Self {
+        Self {
+            method: "getLatestBlockhash".to_string(),
+            params: serde_json::Value::Array(Vec::new()),
+        }
+    }
 }
 
 impl Default for HealthCheckConfig {
@@ -45,34 +897,178 @@ impl Default for HealthCheckConfig {
             interval_secs: 30,
             timeout_secs: 5,
             method: "getSlot".to_string(),
+            params: serde_json::Value::Array(Vec::new()),
             consecutive_failures_threshold: 3,
             consecutive_successes_threshold: 2,
             max_slot_lag: 50,
+            warmup_secs: 0,
+            concurrency: 16,
+            stagger_first_check: false,
+            connect_timeout_ms: 0,
+            pool_idle_timeout_secs: 90,
+            webhook_url: None,
+            webhook_min_interval_secs: 60,
+            repeated_error_log_interval_secs: 60,
+            secondary_check: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct Backend {
     pub label: String,
     pub url: String,
+    /// Relative selection weight; required for regular backends, unused by the fallback.
+    /// Any `u32` is accepted, but small values (1-1000) keep shares easy to reason about
+    #[serde(default)]
     pub weight: u32,
     pub ws_url: Option<String>,
+    /// Selection tier: backends in a higher tier only serve traffic while every
+    /// backend in the lower tiers is unhealthy
+    #[serde(default)]
+    pub tier: u32,
+    /// Relative cost of one request, e.g. a provider's price per million requests.
+    /// `selection = "cheapest"` prefers lower costs, and `/stats` sums it per backend
+    #[serde(default)]
+    pub cost: u32,
+    /// PEM client certificate presented to this backend (mutual TLS)
+    pub tls_client_cert: Option<String>,
+    /// PEM PKCS#8 private key for `tls_client_cert`
+    pub tls_client_key: Option<String>,
+    /// Forward the client's `Host` header instead of the backend's own host
+    #[serde(default)]
+    pub preserve_host: bool,
+    /// Commitment levels this backend serves preferentially: requests whose params
+    /// name one of them are routed within the group of backends listing it
+    #[serde(default)]
+    pub commitments: Vec<String>,
+    /// Most WebSocket connections the router keeps open to this backend at once, for
+    /// providers that cap them per account (0 = unlimited)
+    #[serde(default)]
+    pub max_ws_connections: usize,
+    /// Most HTTP requests the router has in flight to this backend at once; a
+    /// saturated backend is skipped by selection (0 = unlimited)
+    #[serde(default)]
+    pub max_concurrent_requests: usize,
+    /// Send this backend integer JSON-RPC ids, for nodes that reject string or null
+    /// ids; the client's id is restored in the response
+    #[serde(default)]
+    pub normalize_id: bool,
+    /// `http` or `https`: the scheme used to reach this backend (proxied requests and
+    /// health checks), whatever `url` says
+    pub force_scheme: Option<String>,
+    /// Availability zone the backend runs in; with `proxy.local_zone`, backends in
+    /// the same zone are preferred
+    pub zone: Option<String>,
+    /// Least fraction (0-1) of weighted selections this backend gets while it is a
+    /// candidate, whatever its weight, e.g. to keep a standby warm (0 = no floor)
+    #[serde(default)]
+    pub min_share: f64,
+}
+
+impl Backend {
+    /// `url` with its scheme replaced by `force_scheme`, if set.
+    pub fn effective_url(&self) -> Cow<'_, str> {
+        match (&self.force_scheme, self.url.split_once("://")) {
+            (Some(scheme), Some((_, rest))) => Cow::Owned(format!("{}://{}", scheme, rest)),
+            _ => Cow::Borrowed(&self.url),
+        }
+    }
+}
+
+/// Schemes accepted in `Backend::force_scheme`.
+pub const FORCE_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// Commitment levels accepted in `Backend::commitments`.
+pub const COMMITMENT_LEVELS: [&str; 3] = ["processed", "confirmed", "finalized"];
+
+/// Checks the fields shared by regular and fallback backends.
+pub fn validate_backend(backend: &Backend) -> Result<(), ConfigError> {
+    let invalid = |reason: String| ConfigError::InvalidBackend {
+        label: backend.label.clone(),
+        reason,
+    };
+    if backend.label.is_empty() {
+        return Err(invalid(format!(
+            "with URL '{}' has empty label",
+            backend.url
+        )));
+    }
+    match backend.url.parse::<axum::http::Uri>() {
+        Ok(uri) if uri.scheme().is_some() && uri.authority().is_some() => {}
+        _ => return Err(invalid(format!("has invalid URL '{}'", backend.url))),
+    }
+    if let Some(scheme) = backend
+        .force_scheme
+        .as_ref()
+        .filter(|s| !FORCE_SCHEMES.contains(&s.as_str()))
+    {
+        return Err(invalid(format!(
+            "has invalid force_scheme '{}' (expected http or https)",
+            scheme
+        )));
+    }
+    if backend.tls_client_cert.is_some() != backend.tls_client_key.is_some() {
+        return Err(invalid(
+            "must set both tls_client_cert and tls_client_key".to_string(),
+        ));
+    }
+    if !(0.0..1.0).contains(&backend.min_share) {
+        return Err(invalid(format!(
+            "has min_share {}, which must be at least 0 and below 1",
+            backend.min_share
+        )));
+    }
+    if let Some(level) = backend
+        .commitments
+        .iter()
+        .find(|c| !COMMITMENT_LEVELS.contains(&c.as_str()))
+    {
+        return Err(invalid(format!(
+            "has unknown commitment level '{}' (expected one of {})",
+            level,
+            COMMITMENT_LEVELS.join(", ")
+        )));
+    }
+    Ok(())
 }
 
-pub fn load_config(config_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+/// Checks `[metric_groups]` patterns: `*` may appear once, at the start or the end,
+/// and labels must not be empty.
+fn validate_metric_groups(patterns: &HashMap<String, String>) -> Result<(), ConfigError> {
+    for (pattern, label) in patterns {
+        let stars = pattern.matches('*').count();
+        let valid = match stars {
+            0 => !pattern.is_empty(),
+            1 => pattern.len() > 1 && (pattern.starts_with('*') || pattern.ends_with('*')),
+            _ => false,
+        };
+        if !valid {
+            return Err(ConfigError::invalid_value(
+                format!("metric_groups pattern '{}'", pattern),
+                "must be a method name, 'prefix*' or '*suffix'",
+            ));
+        }
+        if label.is_empty() {
+            return Err(ConfigError::empty(format!("metric_groups.'{}'", pattern)));
+        }
+    }
+    Ok(())
+}
+
+pub fn load_config(config_path: &str) -> Result<Config, ConfigError> {
     if !Path::new(config_path).exists() {
-        return Err(format!("Configuration file not found: {}", config_path).into());
+        return Err(ConfigError::NotFound(config_path.to_string()));
     }
 
     let contents = fs::read_to_string(config_path)?;
     let config: Config = toml::from_str(&contents)?;
 
     if config.redis_url.is_empty() {
-        return Err("Redis URL must be configured".into());
+        return Err(ConfigError::MissingRedisUrl);
     }
     if config.backends.is_empty() {
-        return Err("At least one backend must be configured".into());
+        return Err(ConfigError::NoBackends);
     }
 
     let backend_labels: HashMap<String, String> = config
@@ -82,40 +1078,353 @@ pub fn load_config(config_path: &str) -> Result<Config, Box<dyn std::error::Erro
         .collect();
 
     if backend_labels.len() != config.backends.len() {
-        return Err("Duplicate backend labels found in configuration".into());
+        return Err(ConfigError::DuplicateBackendLabels);
     }
 
     for backend in &config.backends {
         if backend.weight == 0 {
-            return Err(format!("Backend '{}' has invalid weight 0", backend.label).into());
+            return Err(ConfigError::InvalidBackend {
+                label: backend.label.clone(),
+                reason: "has invalid weight 0".to_string(),
+            });
+        }
+        validate_backend(backend)?;
+    }
+    let min_shares: f64 = config.backends.iter().map(|b| b.min_share).sum();
+    if min_shares > 1.0 {
+        return Err(ConfigError::MinSharesExceedTraffic(min_shares));
+    }
+
+    if let Some(fallback) = &config.fallback_backend {
+        validate_backend(fallback)?;
+        if backend_labels.contains_key(&fallback.label) {
+            return Err(ConfigError::InvalidBackend {
+                label: fallback.label.clone(),
+                reason: "is both the fallback backend and a configured backend".to_string(),
+            });
+        }
+    }
+
+    if !(config.health_check.params.is_array() || config.health_check.params.is_object()) {
+        return Err(ConfigError::invalid_value(
+            "health_check.params",
+            format!(
+                "must be a JSON array or object, got {}",
+                config.health_check.params
+            ),
+        ));
+    }
+
+    if let Some(check) = &config.health_check.secondary_check {
+        if check.method.is_empty() {
+            return Err(ConfigError::empty("health_check.secondary_check.method"));
         }
-        if backend.label.is_empty() {
-            return Err(format!("Backend with URL '{}' has empty label", backend.url).into());
+        if !(check.params.is_array() || check.params.is_object()) {
+            return Err(ConfigError::invalid_value(
+                "health_check.secondary_check.params",
+                format!("must be a JSON array or object, got {}", check.params),
+            ));
         }
     }
 
+    if config.upstream.danger_accept_invalid_certs
+        && std::env::var(ALLOW_INVALID_CERTS_ENV).as_deref() != Ok("1")
+    {
+        return Err(ConfigError::InvalidCertsNotAllowed);
+    }
+
+    validate_metric_groups(&config.metric_groups)?;
+
+    if config.health_check.concurrency == 0 {
+        return Err(ConfigError::out_of_range(
+            "health_check.concurrency",
+            0,
+            "> 0",
+        ));
+    }
+
     if config.proxy.timeout_secs == 0 {
-        return Err("Proxy timeout_secs must be > 0".into());
+        return Err(ConfigError::out_of_range("proxy.timeout_secs", 0, "> 0"));
+    }
+
+    for (method, timeouts) in &config.proxy.method_timeouts {
+        let hard = timeouts.timeout_secs.unwrap_or(config.proxy.timeout_secs);
+        if hard == 0 {
+            return Err(ConfigError::out_of_range(
+                format!("proxy.method_timeouts.{}.timeout_secs", method),
+                hard,
+                "> 0",
+            ));
+        }
+        if let Some(soft) = timeouts
+            .soft_timeout_secs
+            .filter(|&soft| soft == 0 || soft >= hard)
+        {
+            return Err(ConfigError::out_of_range(
+                format!("proxy.method_timeouts.{}.soft_timeout_secs", method),
+                soft,
+                format!("> 0 and below the hard timeout ({}s)", hard),
+            ));
+        }
+    }
+
+    if config.proxy.adaptive_weights && config.proxy.adaptive_weight_cooldown_secs == 0 {
+        return Err(ConfigError::out_of_range(
+            "proxy.adaptive_weight_cooldown_secs",
+            0,
+            "> 0",
+        ));
+    }
+
+    if let Some(level) = &config.proxy.default_commitment {
+        if !COMMITMENT_LEVELS.contains(&level.as_str()) {
+            return Err(ConfigError::invalid_value(
+                "proxy.default_commitment",
+                format!(
+                    "'{}' is not a commitment level (expected one of {})",
+                    level,
+                    COMMITMENT_LEVELS.join(", ")
+                ),
+            ));
+        }
+    }
+
+    if let Some(user_agent) = &config.proxy.upstream_user_agent {
+        if HeaderValue::from_str(user_agent).is_err() {
+            return Err(ConfigError::invalid_value(
+                "proxy.upstream_user_agent",
+                "is not a valid header value",
+            ));
+        }
+    }
+    for (name, value) in &config.proxy.upstream_headers {
+        if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err()
+        {
+            return Err(ConfigError::invalid_value(
+                format!("proxy.upstream_headers.{}", name),
+                "is not a valid header",
+            ));
+        }
+    }
+    for name in &config.proxy.forward_trailers {
+        if HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(ConfigError::invalid_value(
+                "proxy.forward_trailers",
+                format!("lists '{}', which is not a valid header name", name),
+            ));
+        }
+    }
+
+    if let Some(zone) = &config.proxy.local_zone {
+        if !config
+            .backends
+            .iter()
+            .any(|b| b.zone.as_ref() == Some(zone))
+        {
+            return Err(ConfigError::invalid_value(
+                "proxy.local_zone",
+                format!("'{}' matches no backend zone", zone),
+            ));
+        }
+    }
+
+    if let Some(split) = &config.proxy.read_write_split {
+        if split.write_backends.is_empty() {
+            return Err(ConfigError::empty("proxy.read_write_split.write_backends"));
+        }
+        for (group, labels) in [
+            ("write_backends", &split.write_backends),
+            ("read_backends", &split.read_backends),
+        ] {
+            if let Some(label) = labels.iter().find(|l| !backend_labels.contains_key(*l)) {
+                return Err(ConfigError::unknown_backend_label(
+                    format!("proxy.read_write_split.{}", group),
+                    label,
+                ));
+            }
+        }
+    }
+
+    if let Some(rest) = &config.proxy.rest_gateway {
+        if !rest.path_prefix.starts_with('/') || !rest.path_prefix.ends_with('/') {
+            return Err(ConfigError::invalid_value(
+                "proxy.rest_gateway.path_prefix",
+                "must start and end with '/'",
+            ));
+        }
+    }
+
+    if config
+        .proxy
+        .response_metadata
+        .as_ref()
+        .is_some_and(|metadata| metadata.field.is_empty())
+    {
+        return Err(ConfigError::empty("proxy.response_metadata.field"));
+    }
+
+    if config.proxy.retry_base_delay_ms > config.proxy.retry_max_delay_ms {
+        return Err(ConfigError::out_of_range(
+            "proxy.retry_base_delay_ms",
+            config.proxy.retry_base_delay_ms,
+            format!(
+                "at most retry_max_delay_ms ({})",
+                config.proxy.retry_max_delay_ms
+            ),
+        ));
+    }
+    if config.proxy.ws_connect_retry_base_delay_ms > config.proxy.ws_connect_retry_max_delay_ms {
+        return Err(ConfigError::out_of_range(
+            "proxy.ws_connect_retry_base_delay_ms",
+            config.proxy.ws_connect_retry_base_delay_ms,
+            format!(
+                "at most ws_connect_retry_max_delay_ms ({})",
+                config.proxy.ws_connect_retry_max_delay_ms
+            ),
+        ));
+    }
+
+    let unlisted =
+        |field: &str, methods: &[String], list: &'static str, allowed: &dyn Fn(&str) -> bool| {
+            methods
+                .iter()
+                .find(|method| !allowed(method))
+                .map(|method| ConfigError::UnlistedMethod {
+                    field: field.to_string(),
+                    method: method.clone(),
+                    list,
+                })
+        };
+    let idempotent = |method: &str| config.proxy.idempotent_methods.contains(method);
+    let cached = |method: &str| config.cache.methods.iter().any(|m| m == method);
+    if let Some(error) = [
+        unlisted(
+            "cache.methods",
+            &config.cache.methods,
+            "proxy.idempotent_methods",
+            &idempotent,
+        ),
+        unlisted(
+            "cache.slot_scoped_methods",
+            &config.cache.slot_scoped_methods,
+            "cache.methods",
+            &cached,
+        ),
+        unlisted(
+            "cache.warm_methods",
+            &config.cache.warm_methods,
+            "cache.methods",
+            &cached,
+        ),
+        unlisted(
+            "proxy.hedge_methods",
+            &config.proxy.hedge_methods,
+            "proxy.idempotent_methods",
+            &idempotent,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .next()
+    {
+        return Err(error);
+    }
+
+    for (field, value) in [
+        ("proxy.max_header_count", config.proxy.max_header_count),
+        ("proxy.max_header_bytes", config.proxy.max_header_bytes),
+    ] {
+        if value == 0 {
+            return Err(ConfigError::out_of_range(field, value, "> 0"));
+        }
+    }
+
+    for (field, ratio) in [
+        (
+            "proxy.retry_throttle_token_ratio",
+            config.proxy.retry_throttle_token_ratio,
+        ),
+        (
+            "logging.fast_request_sample_rate",
+            config.logging.fast_request_sample_rate,
+        ),
+        ("logging.body_sample_rate", config.logging.body_sample_rate),
+    ] {
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(ConfigError::out_of_range(
+                field,
+                ratio,
+                "between 0.0 and 1.0",
+            ));
+        }
+    }
+
+    if let Some(code) = config
+        .proxy
+        .failure_status_codes
+        .0
+        .iter()
+        .find(|code| !(100..=599).contains(*code))
+    {
+        return Err(ConfigError::out_of_range(
+            "proxy.failure_status_codes",
+            code,
+            "an HTTP status (100-599)",
+        ));
     }
 
-    for (method, label) in &config.method_routes {
+    if let Some(label) = &config.default_backend {
         if !backend_labels.contains_key(label) {
-            return Err(format!(
-                "Method route '{}' references unknown backend label '{}'",
-                method, label
-            )
-            .into());
+            return Err(ConfigError::unknown_backend_label("default_backend", label));
+        }
+    }
+
+    for (method, route) in &config.method_routes {
+        let field = format!("method_routes.{}", method);
+        if route.backends.is_empty() {
+            return Err(ConfigError::empty(format!("{}.backends", field)));
+        }
+        if let Some(label) = route
+            .backends
+            .iter()
+            .find(|l| !backend_labels.contains_key(*l))
+        {
+            return Err(ConfigError::unknown_backend_label(field, label));
         }
     }
 
     if config.port == config.metrics_port {
-        return Err("HTTP port and Metrics port must be different".into());
+        return Err(ConfigError::PortConflict {
+            listener: "Metrics",
+            port: config.metrics_port,
+            other: "HTTP",
+        });
     }
 
     // Check for WebSocket port conflict (port + 1)
-    let ws_port = config.port.checked_add(1).ok_or("Port overflow")?;
+    let ws_port = config.port.checked_add(1).ok_or_else(|| {
+        ConfigError::out_of_range("port", config.port, "below 65535 (WebSocket uses port + 1)")
+    })?;
     if ws_port == config.metrics_port {
-        return Err(format!("Metrics port {} conflicts with WebSocket port (HTTP port + 1)", config.metrics_port).into());
+        return Err(ConfigError::PortConflict {
+            listener: "Metrics",
+            port: config.metrics_port,
+            other: "WebSocket (HTTP port + 1)",
+        });
+    }
+
+    if config.admin_token.as_deref() == Some("") {
+        return Err(ConfigError::empty("admin_token"));
+    }
+
+    if let Some(admin_port) = config.admin_port {
+        if admin_port == config.port || admin_port == ws_port || admin_port == config.metrics_port {
+            return Err(ConfigError::PortConflict {
+                listener: "Admin",
+                port: admin_port,
+                other: "HTTP, WebSocket, or Metrics",
+            });
+        }
     }
 
     Ok(config)
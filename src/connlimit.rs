@@ -0,0 +1,144 @@
+//! Per-IP limit on new connections, enforced by `serve_tcp` before a connection is
+//! handed to hyper. A single address opening thousands of sockets (WebSockets in
+//! particular) can exhaust file descriptors long before any API key is checked, so
+//! connections over the rate are closed as soon as they are accepted.
+//!
+//! Peers inside `trusted_proxies` are exempt at accept time, since one proxy carries
+//! many clients. For them the client address comes from `X-Forwarded-For`, and each
+//! WebSocket upgrade, a new client connection, counts against that address instead.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::UPGRADE, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::counter;
+use tracing::debug;
+
+use crate::{config::ListenerConfig, net::IpCidr};
+
+/// Header a trusted proxy uses to pass on the address of the client it serves.
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Expired windows are swept, at most once per window, once the map grows past this
+/// many addresses.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// New connections per client address, counted in fixed one-second windows. Each
+/// listener keeps its own counts.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    per_second: u64,
+    trusted_proxies: Vec<IpCidr>,
+    windows: Mutex<Windows>,
+}
+
+#[derive(Debug)]
+struct Windows {
+    /// Address → start of its current window and the connections counted in it
+    counts: HashMap<IpAddr, (Instant, u64)>,
+    last_sweep: Instant,
+}
+
+impl Default for Windows {
+    fn default() -> Self {
+        Self {
+            counts: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+}
+
+impl ConnectionLimiter {
+    /// The limiter for `config`, or `None` when `max_connections_per_ip_per_sec` is 0.
+    pub fn from_config(config: &ListenerConfig) -> Option<Arc<Self>> {
+        (config.max_connections_per_ip_per_sec > 0).then(|| {
+            Arc::new(Self {
+                per_second: config.max_connections_per_ip_per_sec,
+                trusted_proxies: config.trusted_proxies.clone(),
+                windows: Mutex::default(),
+            })
+        })
+    }
+
+    pub fn is_trusted_proxy(&self, peer: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(peer))
+    }
+
+    /// Counts a connection accepted from `peer`, returning `false` when it should be
+    /// dropped. Trusted proxies are never limited here.
+    pub fn allow_connection(&self, peer: IpAddr) -> bool {
+        self.is_trusted_proxy(peer) || self.allow(peer)
+    }
+
+    /// The client behind a request: the peer itself, or when the peer is a trusted
+    /// proxy, the last `X-Forwarded-For` address that isn't one (falling back to the
+    /// peer when the header is missing or unparsable).
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted_proxy(peer) {
+            return peer;
+        }
+        headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+            .rev()
+            .find(|ip| !self.is_trusted_proxy(*ip))
+            .unwrap_or(peer)
+    }
+
+    fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(1);
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.counts.len() > SWEEP_THRESHOLD
+            && now.duration_since(windows.last_sweep) >= window
+        {
+            windows
+                .counts
+                .retain(|_, (start, _)| now.duration_since(*start) < window);
+            windows.last_sweep = now;
+        }
+
+        let (start, count) = windows.counts.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= window {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= self.per_second
+    }
+}
+
+/// Middleware for connections from trusted proxies: a WebSocket upgrade is a new
+/// client connection, so it counts against the forwarded client address and is
+/// answered `429` over the limit. Plain requests are left to the per-key limits.
+pub async fn limit_forwarded_connections(
+    State(limiter): State<Arc<ConnectionLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<std::net::SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(peer) = peer.filter(|_| req.headers().contains_key(UPGRADE)) {
+        let client = limiter.client_ip(peer, req.headers());
+        if !limiter.allow(client) {
+            counter!("rpc_connections_rejected_total", "reason" => "forwarded").increment(1);
+            debug!("Rejected WebSocket upgrade from {} via {}", client, peer);
+            return (StatusCode::TOO_MANY_REQUESTS, "Too many new connections").into_response();
+        }
+    }
+    next.run(req).await
+}
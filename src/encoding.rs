@@ -0,0 +1,107 @@
+//! `Content-Encoding: gzip` / `deflate` request bodies: decompression with a size limit,
+//! and re-compression for backends that should receive the original encoding.
+
+use std::io::{self, Read, Write};
+
+use axum::http::HeaderValue;
+use bytes::Bytes;
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+
+/// A request body compression the router decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    /// zlib-wrapped deflate, as `Content-Encoding: deflate` is specified
+    Deflate,
+}
+
+/// Request extension set by `extract_rpc_method` when it decompressed the body, so the
+/// proxy can restore the encoding for the backend.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedFrom(pub ContentEncoding);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The decompressed body is larger than the limit
+    TooLarge,
+    /// The body isn't valid for its declared encoding
+    Invalid,
+}
+
+impl ContentEncoding {
+    /// The encoding named by a `Content-Encoding` header, or `None` for encodings the
+    /// router leaves to the backend (including `identity`).
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        if value.eq_ignore_ascii_case("gzip") || value.eq_ignore_ascii_case("x-gzip") {
+            Some(Self::Gzip)
+        } else if value.eq_ignore_ascii_case("deflate") {
+            Some(Self::Deflate)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    pub fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(self.as_str())
+    }
+
+    /// Decompresses `body`, giving up once the output exceeds `limit` bytes so a small
+    /// compressed payload can't expand into an unbounded allocation. Runs on the
+    /// blocking pool, as bodies can be megabytes.
+    pub async fn decode(self, body: Bytes, limit: usize) -> Result<Bytes, DecodeError> {
+        tokio::task::spawn_blocking(move || self.decode_blocking(&body, limit))
+            .await
+            .map_err(|_| DecodeError::Invalid)?
+    }
+
+    fn decode_blocking(self, body: &[u8], limit: usize) -> Result<Bytes, DecodeError> {
+        let reader: Box<dyn Read + '_> = match self {
+            Self::Gzip => Box::new(GzDecoder::new(body)),
+            Self::Deflate => Box::new(ZlibDecoder::new(body)),
+        };
+        let mut decoded = Vec::new();
+        reader
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decoded)
+            .map_err(|_| DecodeError::Invalid)?;
+        if decoded.len() > limit {
+            return Err(DecodeError::TooLarge);
+        }
+        Ok(Bytes::from(decoded))
+    }
+
+    /// Compresses `body` on the blocking pool.
+    pub async fn encode(self, body: Bytes) -> io::Result<Bytes> {
+        tokio::task::spawn_blocking(move || self.encode_blocking(&body))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    fn encode_blocking(self, body: &[u8]) -> io::Result<Bytes> {
+        let encoded = match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(body)?;
+                encoder.finish()?
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(body)?;
+                encoder.finish()?
+            }
+        };
+        Ok(Bytes::from(encoded))
+    }
+}
@@ -0,0 +1,295 @@
+//! Error types shared across the crate: one enum per area, and `Error` wrapping them
+//! for callers that handle more than one. `status_code()` is the single mapping from
+//! an error to the HTTP status a client sees.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use thiserror::Error;
+
+use crate::batch::BatchError;
+
+/// Any error the router produces.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    KeyStore(#[from] KeyStoreError),
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+    #[error(transparent)]
+    HealthCheck(#[from] HealthCheckError),
+    #[error(transparent)]
+    RateLimit(#[from] RateLimitError),
+}
+
+impl Error {
+    /// Status for a client request that failed with this error. Only proxy and
+    /// keystore errors arise while serving requests; anything else is a server fault.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Proxy(e) => e.status_code(),
+            Error::KeyStore(e) => ProxyError::from(e.clone()).status_code(),
+            Error::Config(_) | Error::HealthCheck(_) | Error::RateLimit(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+/// Why `load_config` rejected a configuration file.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Configuration file not found: {0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Read(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error("Redis URL must be configured")]
+    MissingRedisUrl,
+    #[error("At least one backend must be configured")]
+    NoBackends,
+    #[error("Duplicate backend labels found in configuration")]
+    DuplicateBackendLabels,
+    /// A `[[backends]]` or `fallback_backend` entry is invalid on its own
+    #[error("Backend '{label}' {reason}")]
+    InvalidBackend { label: String, reason: String },
+    #[error("Backend min_share values add up to {0}, more than all traffic")]
+    MinSharesExceedTraffic(f64),
+    /// A setting names a backend label that isn't configured
+    #[error("{field} references unknown backend label '{label}'")]
+    UnknownBackendLabel { field: String, label: String },
+    /// A method list names a method missing from the list it must be a subset of
+    #[error("{field} lists '{method}', which is not in {list}")]
+    UnlistedMethod {
+        field: String,
+        method: String,
+        list: &'static str,
+    },
+    #[error("{field} is {value}, expected {expected}")]
+    OutOfRange {
+        field: String,
+        value: String,
+        expected: String,
+    },
+    #[error("{field} must not be empty")]
+    Empty { field: String },
+    /// A setting that doesn't parse as what it names, e.g. a header or commitment level
+    #[error("{field} {reason}")]
+    InvalidValue { field: String, reason: String },
+    #[error("{listener} port {port} conflicts with the {other} port")]
+    PortConflict {
+        listener: &'static str,
+        port: u16,
+        other: &'static str,
+    },
+    #[error(
+        "upstream.danger_accept_invalid_certs also requires {}=1 in the environment",
+        crate::config::ALLOW_INVALID_CERTS_ENV
+    )]
+    InvalidCertsNotAllowed,
+}
+
+impl ConfigError {
+    /// `OutOfRange` for `field`, whose `value` isn't `expected`.
+    pub fn out_of_range(
+        field: impl Into<String>,
+        value: impl std::fmt::Display,
+        expected: impl Into<String>,
+    ) -> Self {
+        ConfigError::OutOfRange {
+            field: field.into(),
+            value: value.to_string(),
+            expected: expected.into(),
+        }
+    }
+
+    pub fn invalid_value(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        ConfigError::InvalidValue {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unknown_backend_label(field: impl Into<String>, label: impl Into<String>) -> Self {
+        ConfigError::UnknownBackendLabel {
+            field: field.into(),
+            label: label.into(),
+        }
+    }
+
+    pub fn empty(field: impl Into<String>) -> Self {
+        ConfigError::Empty {
+            field: field.into(),
+        }
+    }
+}
+
+/// Why a `KeyStore` couldn't answer whether a key is valid.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum KeyStoreError {
+    /// The key is valid but over its rate limit
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    /// The store (or the rate limiter behind it) failed, e.g. Redis is unreachable
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+impl From<redis::RedisError> for KeyStoreError {
+    fn from(e: redis::RedisError) -> Self {
+        KeyStoreError::Unavailable(e.to_string())
+    }
+}
+
+/// Why a `RateLimiter` couldn't decide whether a request fits under its key's limit.
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("Rate limiter Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+/// A limiter failure is handled like the keystore being unavailable.
+impl From<RateLimitError> for KeyStoreError {
+    fn from(e: RateLimitError) -> Self {
+        KeyStoreError::Unavailable(e.to_string())
+    }
+}
+
+/// Why the proxy answered a request itself instead of relaying a backend response.
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("Unauthorized")]
+    MissingApiKey,
+    #[error("API keys in the query string are disabled; send the key in the x-api-key header")]
+    QueryApiKeyDisabled,
+    #[error("Unauthorized")]
+    InvalidApiKey,
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    #[error("Too many concurrent requests")]
+    TooManyConcurrentRequests,
+    /// The keystore failed and the failure policy doesn't admit the key anyway
+    #[error("Internal Server Error")]
+    KeyStoreUnavailable,
+    #[error("No healthy backends available")]
+    NoHealthyBackends,
+    #[error("No healthy WebSocket backends available")]
+    NoHealthyWsBackends,
+    #[error("WebSocket backends at capacity")]
+    WsBackendsAtCapacity,
+    #[error("WebSocket backend unavailable")]
+    WsBackendUnavailable,
+    /// The request couldn't be sent or the connection failed
+    #[error("Proxy error: {0}")]
+    Upstream(String),
+    #[error("Upstream request timed out after {0}s")]
+    Timeout(u64),
+    #[error("{0}")]
+    ResponseTooLarge(String),
+    #[error("Failed to read upstream response: {0}")]
+    ResponseRead(String),
+    /// The backend answered a success status with a body that isn't a usable response
+    #[error("Backend returned {0}")]
+    InvalidResponse(&'static str),
+    #[error("Request body too large")]
+    BodyTooLarge,
+    /// A `Content-Encoding` request body that doesn't decode, named by its encoding
+    #[error("Invalid {0} request body")]
+    InvalidEncoding(&'static str),
+    /// A request header (or all of them together) over a `proxy` header limit
+    #[error("{0}")]
+    HeadersTooLarge(String),
+    /// Answered with the batch's JSON-RPC error object rather than plain text
+    #[error("{0}")]
+    InvalidBatch(BatchError),
+    /// A request the router can't turn into a JSON-RPC call, e.g. a malformed GET query
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("Not a REST gateway method path")]
+    NotRestMethod,
+    #[error("{0} is not allowed over GET; send it as a POST")]
+    MethodNotAllowedOverGet(String),
+    /// The router itself failed before reaching a backend
+    #[error("{0}")]
+    Internal(&'static str),
+}
+
+impl ProxyError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ProxyError::MissingApiKey | ProxyError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            ProxyError::QueryApiKeyDisabled => StatusCode::BAD_REQUEST,
+            ProxyError::RateLimited | ProxyError::TooManyConcurrentRequests => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ProxyError::KeyStoreUnavailable => StatusCode::INTERNAL_SERVER_ERROR,
+            ProxyError::NoHealthyBackends
+            | ProxyError::NoHealthyWsBackends
+            | ProxyError::WsBackendsAtCapacity => StatusCode::SERVICE_UNAVAILABLE,
+            ProxyError::WsBackendUnavailable
+            | ProxyError::Upstream(_)
+            | ProxyError::ResponseTooLarge(_)
+            | ProxyError::ResponseRead(_)
+            | ProxyError::InvalidResponse(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ProxyError::InvalidEncoding(_)
+            | ProxyError::InvalidBatch(_)
+            | ProxyError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ProxyError::HeadersTooLarge(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ProxyError::NotRestMethod => StatusCode::NOT_FOUND,
+            ProxyError::MethodNotAllowedOverGet(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ProxyError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<KeyStoreError> for ProxyError {
+    fn from(e: KeyStoreError) -> Self {
+        match e {
+            KeyStoreError::RateLimited => ProxyError::RateLimited,
+            KeyStoreError::Unavailable(_) => ProxyError::KeyStoreUnavailable,
+        }
+    }
+}
+
+/// Plain-text body with the error's status; an invalid batch gets a JSON-RPC error.
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        match self {
+            ProxyError::InvalidBatch(e) => (status, Json(e.to_json())).into_response(),
+            e => (status, e.to_string()).into_response(),
+        }
+    }
+}
+
+/// Why a health check probe failed.
+#[derive(Debug, Error)]
+pub enum HealthCheckError {
+    #[error("Failed to serialize health check: {0}")]
+    SerializeRequest(serde_json::Error),
+    #[error("Failed to build request: {0}")]
+    BuildRequest(#[from] axum::http::Error),
+    #[error("Health check request failed: {0}")]
+    Request(String),
+    #[error("Health check timed out after {0}s")]
+    Timeout(u64),
+    #[error("Health check returned status: {0}")]
+    Status(StatusCode),
+    #[error("Failed to read response body: {0}")]
+    ReadBody(String),
+    #[error("Failed to parse response JSON: {0}")]
+    ParseBody(serde_json::Error),
+    #[error("Health check response missing numeric 'result' field for method {0}")]
+    MissingResult(String),
+    #[error("Secondary check {method} failed: {reason}")]
+    SecondaryCheck { method: String, reason: String },
+    #[error("Startup probe failed: none of the {0} configured backends are reachable")]
+    NoneReachable(usize),
+}
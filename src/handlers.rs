@@ -1,39 +1,194 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Instant,
+};
 
 use axum::{
-    body::{to_bytes, Body},
+    body::{to_bytes, Body, HttpBody},
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
         ConnectInfo, Query, State,
     },
-    http::{Request, StatusCode, Uri},
+    handler::Handler,
+    http::{
+        header::{
+            CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, HOST,
+            IF_NONE_MATCH, TE, TRAILER, UPGRADE, USER_AGENT,
+        },
+        request::Parts,
+        HeaderMap, HeaderName, HeaderValue, Request, StatusCode,
+    },
     middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
-use futures_util::{SinkExt, StreamExt};
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream, SinkExt, StreamExt};
+use http_body_util::{BodyExt, BodyStream, StreamBody};
+use hyper::body::Frame;
 use metrics::{counter, gauge, histogram};
 use serde::{Deserialize, Serialize};
-use tokio::time::{timeout, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::Message as TungsteniteMessage};
-use tracing::{error, info, warn};
+use tokio::{
+    net::TcpStream,
+    sync::OnceCell,
+    time::{timeout, Duration},
+};
+use tokio_tungstenite::{
+    connect_async, tungstenite::Message as TungsteniteMessage, MaybeTlsStream, WebSocketStream,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    batch::{is_batch, parse_batch, Batch},
+    cache::{self, ResponseCache},
+    capture::{CaptureRequest, RequestCapture},
+    commitment,
+    config::{
+        FailureStatusCodes, IdMismatchAction, KeystoreFailurePolicy, ProxyConfig, ResponseMetadata,
+        ResponseTransform,
+    },
+    encoding::{ContentEncoding, DecodeError, DecodedFrom},
+    error::{KeyStoreError, ProxyError},
+    keystore::KeyInfo,
+    probe::{self, Probe, Scan},
+    ratelimit::{Decision, TokenBucket},
+    retry,
+    selector::SelectionContext,
+    state::{
+        AppState, BackendSelection, InFlightRequest, RouterState, RuntimeBackend, WsConnection,
+    },
+    stats::{CostCounter, StatsReport},
+    subscriptions::SubscriptionTracker,
+    upstream::{HeldBody, QueuedBody},
+};
+
+pub(crate) const MAX_BODY_SIZE: usize = 10 * 1024 * 1024; // 10 MB
+const MAX_ID_CHECK_BODY_SIZE: usize = 1024 * 1024; // 1 MB
+
+/// Request header carrying the API key, as an alternative to the `api-key` query param.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Upstream header keeping the client's `User-Agent` when `upstream_user_agent` replaces it.
+pub const ORIGINAL_USER_AGENT_HEADER: &str = "x-original-user-agent";
 
-use crate::state::AppState;
+/// Response header naming the backend that served a request, with
+/// `proxy.expose_backend_header`.
+pub const SERVED_BY_HEADER: &str = "x-served-by";
 
-const MAX_BODY_SIZE: usize = 10 * 1024 * 1024; // 10 MB
+/// Response header saying whether a cacheable method was answered from the cache, with
+/// `proxy.expose_backend_header`.
+pub const CACHE_STATUS_HEADER: &str = "x-cache";
 
 #[derive(Clone)]
 pub struct RpcMethod(pub String);
 
+/// Why a request failed, set as a response extension by `proxy` and counted in
+/// `rpc_upstream_errors_total` by `track_metrics`. Separates backend faults (timeouts,
+/// connection errors, 5xx) from client faults (auth, rate limits).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Timeout,
+    ConnectError,
+    /// A final answer with a 4xx `failure_status_codes` status, e.g. a configured 429
+    Upstream4xx,
+    Upstream5xx,
+    /// A final answer with any other `failure_status_codes` status
+    UpstreamStatus,
+    /// A successful HTTP response carrying a JSON-RPC `error` object
+    JsonRpcError,
+    /// A successful HTTP response with an empty, truncated or non-JSON body
+    InvalidResponse,
+    Auth,
+    RateLimited,
+}
+
+impl ErrorCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::ConnectError => "connect_error",
+            ErrorCategory::Upstream4xx => "upstream_4xx",
+            ErrorCategory::Upstream5xx => "upstream_5xx",
+            ErrorCategory::UpstreamStatus => "upstream_status",
+            ErrorCategory::JsonRpcError => "jsonrpc_error",
+            ErrorCategory::InvalidResponse => "invalid_response",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::RateLimited => "rate_limited",
+        }
+    }
+
+    fn for_failure_status(status: StatusCode) -> Self {
+        if status.is_server_error() {
+            ErrorCategory::Upstream5xx
+        } else if status.is_client_error() {
+            ErrorCategory::Upstream4xx
+        } else {
+            ErrorCategory::UpstreamStatus
+        }
+    }
+}
+
+fn categorize(mut resp: Response, category: ErrorCategory) -> Response {
+    resp.extensions_mut().insert(category);
+    resp
+}
+
 #[derive(Clone)]
 pub struct SelectedBackend(pub String);
 
+/// Cost counter of the backend that answered, charged by `track_metrics`.
+#[derive(Clone)]
+struct ServedBy {
+    costs: Arc<CostCounter>,
+    cost: u32,
+}
+
+/// Whether a request for a cached method was answered from the response cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+        }
+    }
+}
+
+/// Time from sending the request upstream until the backend's response headers arrived,
+/// for the attempt whose response is returned.
+#[derive(Clone, Copy, Debug)]
+pub struct UpstreamDuration(pub Duration);
+
 #[derive(Clone)]
 pub struct ClientOwner(pub String);
 
+/// Resolved API key of a proxied request, stored in response extensions by `proxy`
+/// next to `ClientOwner` for middleware that needs more than the owner.
+#[derive(Clone)]
+pub struct ClientKey {
+    pub info: KeyInfo,
+    /// First characters of the key, safe to log
+    pub prefix: String,
+}
+
+/// JSON-RPC `id` of a single (non-batch) request, captured by `extract_rpc_method`.
+#[derive(Clone)]
+pub struct RpcId(pub serde_json::Value);
+
 #[derive(Deserialize)]
-struct MethodProbe<'a> {
-    method: Option<&'a str>,
+struct ResponseIdProbe {
+    id: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -42,261 +197,1837 @@ pub struct Params {
     pub api_key: Option<String>,
 }
 
-pub async fn extract_rpc_method(mut req: Request<Body>, next: Next) -> Response {
-    // Read body, extract "method" field, then reconstruct the request
-    let (parts, body) = req.into_parts();
-    let body_bytes = match to_bytes(body, MAX_BODY_SIZE).await {
+/// Removes every `api-key` parameter from a raw query string.
+/// Keys are compared after URL-decoding (so `api%2Dkey` is caught too), while the
+/// remaining pairs are kept byte-for-byte to preserve the client's encoding.
+pub fn strip_api_key(query: &str) -> String {
+    strip_query_params(query, |key, _| key == "api-key")
+}
+
+/// Removes the query parameters meant for the router rather than the backend: every
+/// `api-key`, and the `fresh=true` cache bypass. Compared like `strip_api_key`.
+pub fn strip_router_params(query: &str) -> String {
+    strip_query_params(query, |key, value| {
+        key == "api-key" || (key == "fresh" && value == "true")
+    })
+}
+
+fn strip_query_params(query: &str, strip: impl Fn(&str, &str) -> bool) -> String {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .is_none_or(|(key, value)| !strip(&key, &value))
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// First six characters of an API key, the most of it that is ever logged. Counted
+/// in characters, so a key with multibyte characters can't split one.
+pub fn key_prefix(key: &str) -> String {
+    key.chars().take(6).collect()
+}
+
+/// Why a request was turned away before its API key could be validated.
+enum ApiKeyRejection {
+    Missing,
+    QueryDisabled,
+}
+
+impl ApiKeyRejection {
+    fn into_response(self) -> Response {
+        match self {
+            ApiKeyRejection::Missing => ProxyError::MissingApiKey,
+            ApiKeyRejection::QueryDisabled => ProxyError::QueryApiKeyDisabled,
+        }
+        .into_response()
+    }
+}
+
+/// Returns the client's API key, preferring the `x-api-key` header over the query param.
+/// With `allow_query` off, any request carrying a query-string key is rejected, even if
+/// it also sends the header, so clients learn to keep keys out of URLs.
+fn extract_api_key(
+    headers: &HeaderMap,
+    params: Params,
+    allow_query: bool,
+) -> Result<String, ApiKeyRejection> {
+    if params.api_key.is_some() && !allow_query {
+        return Err(ApiKeyRejection::QueryDisabled);
+    }
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or(params.api_key)
+        .ok_or(ApiKeyRejection::Missing)
+}
+
+/// Validates `key` with the keystore, then counts the request against the key's
+/// `rate_limit` with `state.rate_limiter`. A limited key gives the same
+/// `KeyStoreError::RateLimited` a limiting keystore returns.
+///
+/// Under `KeystoreFailurePolicy::FailOpen` a keystore error (other than a rate limit)
+/// admits the key as `KeyInfo::unverified()` instead of failing the request.
+async fn authorize_key(state: &AppState, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
+    let info = match state.keystore.validate_key(key).await {
+        Ok(info) => info,
+        Err(e @ KeyStoreError::Unavailable(_))
+            if state.keystore_failure == KeystoreFailurePolicy::FailOpen =>
+        {
+            error!(
+                "Keystore unavailable, accepting API key unverified (prefix={}...): {}",
+                key_prefix(key),
+                e
+            );
+            counter!("rpc_keystore_fail_open_total").increment(1);
+            Some(KeyInfo::unverified())
+        }
+        Err(e) => return Err(e),
+    };
+    let Some(info) = info else {
+        return Ok(None);
+    };
+    let decision = state.rate_limiter.check(key, info.rate_limit, 1).await?;
+    if decision == Decision::Limited {
+        return Err(KeyStoreError::RateLimited);
+    }
+    Ok(Some(info))
+}
+
+/// Reads just enough of the body to find the JSON-RPC `method` and `id` (usually the
+/// first chunk) and records them as `RpcMethod` / `RpcId`. The rest of the body
+/// streams through without being buffered here.
+///
+/// Gzip and deflate bodies are decompressed first (up to `MAX_BODY_SIZE`), with
+/// `Content-Encoding` removed and `DecodedFrom` recorded for the proxy.
+pub async fn extract_rpc_method(mut req: Request<Body>, next: Next) -> Response {
+    if let Some(encoding) = req
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(ContentEncoding::from_header)
+    {
+        req = match decode_request(req, encoding).await {
+            Ok(req) => req,
+            Err(resp) => return resp,
+        };
+    }
+
+    let (parts, body) = req.into_parts();
+    let mut stream = body.into_data_stream();
+    let mut prefix = BytesMut::new();
+    let mut scanned = 0;
+    let mut ended = false;
+    let probe = loop {
+        // Rescan only once the prefix has doubled, so a method buried after large
+        // params costs linear rather than quadratic time
+        if ended || prefix.len() >= scanned * 2 {
+            scanned = prefix.len();
+            match probe::scan(&prefix) {
+                Scan::Done(probe) => break probe,
+                Scan::Incomplete if ended => break Probe::default(),
+                Scan::Incomplete => {}
+            }
+        }
+        if prefix.len() > MAX_BODY_SIZE {
+            // Leave the size limit to the handler
+            break Probe::default();
+        }
+        match stream.next().await {
+            Some(Ok(chunk)) => prefix.extend_from_slice(&chunk),
+            Some(Err(_)) => {
+                // If body read fails, pass empty body downstream
+                return next.run(Request::from_parts(parts, Body::empty())).await;
+            }
+            None => ended = true,
+        }
+    };
+
+    let prefix = prefix.freeze();
+    let body = if ended {
+        Body::from(prefix)
+    } else {
+        Body::from_stream(stream::once(async { Ok::<_, axum::Error>(prefix) }).chain(stream))
+    };
+    let mut req = Request::from_parts(parts, body);
+    if let Some(method) = probe.method {
+        req.extensions_mut().insert(RpcMethod(method));
+        if let Some(id) = probe.id {
+            req.extensions_mut().insert(RpcId(id));
+        }
+    }
+    next.run(req).await
+}
+
+async fn decode_request(
+    req: Request<Body>,
+    encoding: ContentEncoding,
+) -> Result<Request<Body>, Response> {
+    let (mut parts, body) = req.into_parts();
+    let compressed = to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|_| ProxyError::BodyTooLarge.into_response())?;
+    let decoded = encoding
+        .decode(compressed, MAX_BODY_SIZE)
+        .await
+        .map_err(|e| match e {
+            DecodeError::TooLarge => ProxyError::BodyTooLarge,
+            DecodeError::Invalid => ProxyError::InvalidEncoding(encoding.as_str()),
+        })
+        .map_err(IntoResponse::into_response)?;
+    counter!("rpc_decompressed_requests_total", "encoding" => encoding.as_str()).increment(1);
+
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.extensions.insert(DecodedFrom(encoding));
+    Ok(Request::from_parts(parts, Body::from(decoded)))
+}
+
+/// Client address for log lines. Connections without a peer IP (Unix domain sockets)
+/// have no `ConnectInfo` and are shown as `unix`.
+#[derive(Clone, Copy)]
+struct Peer(Option<SocketAddr>);
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(addr) => write!(f, "{}", addr),
+            None => f.write_str("unix"),
+        }
+    }
+}
+
+/// One access-log line: `METHOD path peer duration [rpc_method=..] [backend=..]`, plus
+/// `owner=.. status=..` for traced owners.
+struct RequestLine<'a> {
+    method: &'a axum::http::Method,
+    path: &'a str,
+    addr: Peer,
+    duration: Duration,
+    /// Part of `duration` spent waiting on the backend
+    upstream: Option<Duration>,
+    rpc_method: Option<&'a str>,
+    backend: Option<&'a str>,
+    /// Owner and status, only filled in for traced owners
+    traced: Option<(&'a str, StatusCode)>,
+}
+
+impl fmt::Display for RequestLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} total_ms={:.3}",
+            self.method,
+            self.path,
+            self.addr,
+            self.duration.as_secs_f64() * 1000.0
+        )?;
+        if let Some(upstream) = self.upstream {
+            write!(f, " upstream_ms={:.3}", upstream.as_secs_f64() * 1000.0)?;
+        }
+        if let Some(m) = self.rpc_method {
+            write!(f, " rpc_method={}", m)?;
+        }
+        if let Some(b) = self.backend {
+            write!(f, " backend={}", b)?;
+        }
+        if let Some((owner, status)) = self.traced {
+            write!(f, " owner={} status={}", owner, status.as_u16())?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn log_requests(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let addr = Peer(connect_info.map(|ConnectInfo(addr)| addr));
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let rpc_method = req.extensions().get::<RpcMethod>().cloned();
+
+    let start = std::time::Instant::now();
+    let response = next.run(req).await;
+    let duration = start.elapsed();
+    // REST gateway requests only learn their method in the handler
+    let rpc_method = rpc_method.or_else(|| response.extensions().get::<RpcMethod>().cloned());
+
+    // Extract backend from response extensions (set by proxy handler)
+    let backend = response.extensions().get::<SelectedBackend>();
+    // Owner is set by proxy once the API key is resolved
+    let logging = state.logging.load();
+    let traced_owner = response
+        .extensions()
+        .get::<ClientOwner>()
+        .map(|o| o.0.as_str())
+        .filter(|owner| logging.is_traced(owner));
+
+    let line = RequestLine {
+        method: &method,
+        path: &path,
+        addr,
+        duration,
+        upstream: response.extensions().get::<UpstreamDuration>().map(|u| u.0),
+        rpc_method: rpc_method.as_ref().map(|m| m.0.as_str()),
+        backend: backend.map(|b| b.0.as_str()),
+        traced: traced_owner.map(|owner| (owner, response.status())),
+    };
+
+    // Slow requests always log at warn; fast ones at info when sampled or traced,
+    // otherwise debug
+    if logging.is_slow(duration) {
+        warn!("Slow request: {}", line);
+    } else if traced_owner.is_some() || logging.sample_fast_request() {
+        info!("{}", line);
+    } else {
+        debug!("{}", line);
+    }
+
+    response
+}
+
+pub async fn track_metrics(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let start = std::time::Instant::now();
+    let method = req.method().to_string();
+
+    // Try to get RPC method if already extracted
+    let rpc_method = req.extensions().get::<RpcMethod>().cloned();
+
+    let mut response = next.run(req).await;
+
+    // REST gateway requests only learn their method in the handler
+    let rpc_method = rpc_method
+        .or_else(|| response.extensions().get::<RpcMethod>().cloned())
+        .map(|m| m.0)
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let elapsed = start.elapsed();
+    state.latency.record(elapsed);
+    state.metric_groups.record(&rpc_method, elapsed);
+    let rpc_method = state.metric_groups.label(&rpc_method).to_string();
+    let duration = elapsed.as_secs_f64();
+    let status = response.status().as_u16().to_string();
+
+    let backend = response
+        .extensions()
+        .get::<SelectedBackend>()
+        .map(|b| b.0.clone())
+        .unwrap_or_else(|| "none".to_string());
+    if let Some(served_by) = response.extensions().get::<ServedBy>() {
+        served_by.costs.record(served_by.cost);
+    }
+
+    let owner = response
+        .extensions()
+        .get::<ClientOwner>()
+        .map(|o| o.0.clone())
+        .unwrap_or_else(|| "none".to_string());
+
+    histogram!("rpc_request_duration_seconds", "rpc_method" => rpc_method.clone(), "backend" => backend.clone(), "owner" => owner.clone()).record(duration);
+    // Time spent in the router itself: auth, buffering, selection, retries' backoff
+    if let Some(UpstreamDuration(upstream)) = response.extensions().get::<UpstreamDuration>() {
+        histogram!("rpc_router_overhead_seconds", "rpc_method" => rpc_method.clone(), "backend" => backend.clone())
+            .record(elapsed.saturating_sub(*upstream).as_secs_f64());
+    }
+    match response.extensions().get::<ErrorCategory>() {
+        Some(category) => {
+            counter!("rpc_upstream_errors_total", "category" => category.as_str(), "backend" => backend.clone(), "owner" => owner.clone()).increment(1);
+        }
+        None if response.status().is_success() && backend != "none" => {
+            response = count_jsonrpc_errors(response, backend.clone(), owner.clone());
+        }
+        None => {}
+    }
+    counter!("rpc_requests_total", "method" => method, "status" => status, "rpc_method" => rpc_method, "backend" => backend, "owner" => owner).increment(1);
+
+    response
+}
+
+/// Counts a `jsonrpc_error` upstream error when the response's first chunk shows a
+/// JSON-RPC error object. The body still streams through unbuffered.
+fn count_jsonrpc_errors(response: Response, backend: String, owner: String) -> Response {
+    let (parts, body) = response.into_parts();
+    let mut first = true;
+    let body = body.into_data_stream().inspect(move |chunk| {
+        if !std::mem::take(&mut first) {
+            return;
+        }
+        if chunk.as_ref().is_ok_and(|c| probe::is_error_response(c)) {
+            counter!("rpc_upstream_errors_total", "category" => ErrorCategory::JsonRpcError.as_str(), "backend" => backend.clone(), "owner" => owner.clone()).increment(1);
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+pub async fn proxy(
+    State(state): State<Arc<AppState>>,
+    query: Query<Params>,
+    req: Request<Body>,
+) -> Response {
+    let router_state = state.state.load_full();
+    let proxy_config = &router_state.proxy_config;
+    let mut resp = if state.captures.is_enabled() {
+        forward_captured(state, query, req).await
+    } else {
+        forward(State(state), query, req).await
+    };
+    if let Some(metadata) = proxy_config
+        .response_metadata
+        .as_ref()
+        .filter(|_| resp.status().is_success())
+    {
+        resp = inject_metadata(resp, metadata, buffer_limit(proxy_config)).await;
+    }
+    if proxy_config.expose_backend_header {
+        expose_backend_headers(&mut resp);
+    }
+    resp
+}
+
+/// `forward`, recording the request in `state.captures` when it fails.
+async fn forward_captured(
+    state: Arc<AppState>,
+    query: Query<Params>,
+    req: Request<Body>,
+) -> Response {
+    // Capture needs the request body after `forward` has consumed it
+    let (parts, body) = req.into_parts();
+    let body = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return ProxyError::BodyTooLarge.into_response();
+        }
+    };
+    let rpc_method = parts.extensions.get::<RpcMethod>().map(|m| m.0.clone());
+    let captured = CaptureRequest::new(&parts, rpc_method, &body, state.captures.max_body_bytes());
+    let captures = state.captures.clone();
+    let resp = forward(
+        State(state),
+        query,
+        Request::from_parts(parts, Body::from(body)),
+    )
+    .await;
+    if resp.status().is_client_error() || resp.status().is_server_error() {
+        return capture_failure(&captures, captured, resp).await;
+    }
+    resp
+}
+
+/// Responses buffered for transforms, caching and metadata never exceed the
+/// configured response limit, or the default cap without one.
+pub(crate) fn buffer_limit(proxy_config: &ProxyConfig) -> usize {
+    match proxy_config.max_response_bytes {
+        0 => MAX_BODY_SIZE,
+        max => max,
+    }
+}
+
+/// Records a failed request and its response in `captures`. The response body is
+/// buffered only when its size is known and within `MAX_BODY_SIZE`, so a streamed
+/// error response isn't held back.
+async fn capture_failure(
+    captures: &RequestCapture,
+    request: CaptureRequest,
+    resp: Response,
+) -> Response {
+    let backend = resp
+        .extensions()
+        .get::<SelectedBackend>()
+        .map(|b| b.0.clone());
+    let content_length = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let sized = content_length
+        .or_else(|| resp.body().size_hint().exact())
+        .is_some_and(|len| len <= MAX_BODY_SIZE as u64);
+    if !sized {
+        captures.record(request, resp.status(), backend, None);
+        return resp;
+    }
+
+    let (parts, body) = resp.into_parts();
+    match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => {
+            captures.record(request, parts.status, backend, Some(&bytes));
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(e) => {
+            let resp = ProxyError::ResponseRead(e.to_string()).into_response();
+            captures.record(request, resp.status(), backend, None);
+            resp
+        }
+    }
+}
+
+/// Adds `X-Served-By` and, for cached methods, `X-Cache` from the response extensions.
+/// Cache hits name no backend.
+fn expose_backend_headers(resp: &mut Response) {
+    let cache_status = resp.extensions().get::<CacheStatus>().copied();
+    let served_by = resp
+        .extensions()
+        .get::<SelectedBackend>()
+        .filter(|_| cache_status != Some(CacheStatus::Hit))
+        .and_then(|b| HeaderValue::from_str(&b.0).ok());
+    if let Some(label) = served_by {
+        resp.headers_mut().insert(SERVED_BY_HEADER, label);
+    }
+    if let Some(status) = cache_status {
+        resp.headers_mut().insert(
+            CACHE_STATUS_HEADER,
+            HeaderValue::from_static(status.as_str()),
+        );
+    }
+}
+
+async fn forward(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<Params>,
+    req: Request<Body>,
+) -> Response {
+    let router_state = state.state.load_full();
+    let proxy_config = &router_state.proxy_config;
+
+    if let Err(reason) = check_header_limits(req.headers(), proxy_config) {
+        info!("Rejected request headers: {}", reason);
+        return ProxyError::HeadersTooLarge(reason).into_response();
+    }
+
+    let api_key = match extract_api_key(req.headers(), params, proxy_config.allow_query_api_key) {
+        Ok(k) => k,
+        Err(rejection) => {
+            match rejection {
+                ApiKeyRejection::Missing => info!("No API key provided"),
+                ApiKeyRejection::QueryDisabled => info!("Rejected API key sent in query string"),
+            }
+            return categorize(rejection.into_response(), ErrorCategory::Auth);
+        }
+    };
+
+    let key_info = match authorize_key(&state, &api_key).await {
+        Ok(Some(info)) => info,
+        Ok(None) => {
+            info!(
+                "Invalid API key presented (prefix={}...)",
+                key_prefix(&api_key)
+            );
+            let resp = ProxyError::InvalidApiKey.into_response();
+            return categorize(resp, ErrorCategory::Auth);
+        }
+        Err(KeyStoreError::RateLimited) => {
+            warn!("API key rate limited (prefix={}...)", key_prefix(&api_key));
+            let resp = ProxyError::RateLimited.into_response();
+            return categorize(resp, ErrorCategory::RateLimited);
+        }
+        Err(e) => {
+            error!("Key validation error: {}", e);
+            return ProxyError::from(e).into_response();
+        }
+    };
+
+    let client_key = ClientKey {
+        info: key_info,
+        prefix: key_prefix(&api_key),
+    };
+
+    // Slow requests held open count against their owner however few arrive per second;
+    // a key with its own limit is counted on its own
+    let slot = match client_key.info.max_concurrent_requests {
+        Some(0) => None,
+        Some(limit) => Some((
+            state.owner_concurrency.try_acquire_key(&api_key, limit),
+            limit,
+        )),
+        None => match proxy_config.max_concurrent_requests_per_owner {
+            0 => None,
+            limit => Some((
+                state
+                    .owner_concurrency
+                    .try_acquire(&client_key.info.owner, limit),
+                limit,
+            )),
+        },
+    };
+    let owner_slot = match slot {
+        None => None,
+        Some((Some(slot), _)) => Some(slot),
+        Some((None, limit)) => {
+            warn!(
+                "Owner {} (key prefix={}...) is at its limit of {} concurrent requests",
+                client_key.info.owner, client_key.prefix, limit
+            );
+            counter!("rpc_owner_concurrency_limited_total", "owner" => client_key.info.owner.clone())
+                .increment(1);
+            let resp = ProxyError::TooManyConcurrentRequests.into_response();
+            return categorize(resp, ErrorCategory::RateLimited);
+        }
+    };
+
+    let resp = forward_authorized(state, router_state, client_key, req).await;
+    // The slot stays taken until the response has streamed to the client
+    match owner_slot {
+        Some(slot) => HeldBody::hold(resp, slot),
+        None => resp,
+    }
+}
+
+/// The rest of `forward`, once the client's key is authorized and has its concurrency
+/// slot.
+async fn forward_authorized(
+    state: Arc<AppState>,
+    router_state: Arc<RouterState>,
+    client_key: ClientKey,
+    req: Request<Body>,
+) -> Response {
+    let proxy_config = &router_state.proxy_config;
+
+    // Get RPC method and id from extensions (set by extract_rpc_method middleware)
+    let rpc_method = req.extensions().get::<RpcMethod>().map(|m| m.0.clone());
+    let request_id = req.extensions().get::<RpcId>().map(|id| id.0.clone());
+    let client_addr = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+
+    let bypass_cache = cache::bypass_requested(
+        req.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|v| v.to_str().ok()),
+        req.uri().query(),
+    );
+
+    // Rebuild URI: strip the router's own query params while preserving others
+    let path = req.uri().path();
+    let cleaned_query = req
+        .uri()
+        .query()
+        .map(strip_router_params)
+        .unwrap_or_default();
+
+    let cleaned_request_path = if cleaned_query.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, cleaned_query)
+    };
+
+    // Buffer the body so the request can be re-sent if a retry is needed
+    let (mut parts, body) = req.into_parts();
+    let body_bytes = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return ProxyError::BodyTooLarge.into_response();
+        }
+    };
+    // Parsed once, when something below first needs to look inside the body
+    let mut request_json = LazyJson::default();
+    // Requests for methods that take a commitment get the configured default when
+    // they don't name one; single requests for other methods aren't parsed for it
+    let takes_commitment = is_batch(&body_bytes)
+        || rpc_method
+            .as_deref()
+            .and_then(commitment::config_position)
+            .is_some();
+    let body_bytes = match proxy_config
+        .default_commitment
+        .as_deref()
+        .filter(|_| takes_commitment)
+        .and_then(|level| commitment::inject_default(request_json.get_mut(&body_bytes)?, level))
+    {
+        Some(rewritten) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            rewritten
+        }
+        None => body_bytes,
+    };
+    // Bodies the client compressed go upstream decompressed unless configured otherwise
+    let recompress = match parts.extensions.get::<DecodedFrom>() {
+        Some(&DecodedFrom(encoding)) if proxy_config.recompress_request_bodies => {
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, encoding.header_value());
+            Some(encoding)
+        }
+        _ => None,
+    };
+
+    identify_upstream(&mut parts.headers, proxy_config);
+
+    // Sampled body logging only ever sees the body and the api-key-stripped path;
+    // traced owners have every body logged
+    let logging = state.logging.load_full();
+    let log_bodies = logging.is_traced(&client_key.info.owner) || logging.sample_body();
+    if log_bodies {
+        info!(
+            "Sampled request body: path={} body={}",
+            cleaned_request_path,
+            truncate_for_log(&body_bytes, logging.max_logged_body_bytes)
+        );
+    }
+
+    // Batches must have unique ids so every response can be matched to its request
+    let batch = match is_batch(&body_bytes)
+        .then(|| parse_batch(request_json.get(&body_bytes)?))
+        .flatten()
+    {
+        Some(Ok(batch)) => Some(batch),
+        Some(Err(e)) => {
+            info!("Rejected invalid batch: {}", e);
+            return ProxyError::InvalidBatch(e).into_response();
+        }
+        None => None,
+    };
+
+    // Serve cached methods from the response cache when a fresh entry exists, unless
+    // the client asked for a fresh answer; the upstream response is cached either way
+    let cache_key = rpc_method
+        .as_deref()
+        .filter(|m| state.cache.caches(m))
+        .and_then(|m| state.cache.key(m, request_json.get(&body_bytes)?));
+    if let (Some(key), Some(method)) = (&cache_key, &rpc_method) {
+        if bypass_cache {
+            counter!("rpc_cache_bypasses_total", "rpc_method" => method.clone()).increment(1);
+        } else if let Some(hit) = state.cache.get(key, request_id.as_ref()) {
+            counter!("rpc_cache_hits_total", "rpc_method" => method.clone()).increment(1);
+            // Clients that already hold this version get an empty 304. HTTP reserves
+            // 304 for GET and HEAD, but a cached JSON-RPC POST is a read too, and only
+            // clients that send `If-None-Match` see it
+            let not_modified = parts
+                .headers
+                .get(IF_NONE_MATCH)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| cache::if_none_match(v, &hit.etag));
+            let resp = if not_modified {
+                (StatusCode::NOT_MODIFIED, [(ETAG, hit.etag)]).into_response()
+            } else {
+                (
+                    [
+                        (CONTENT_TYPE, "application/json".to_string()),
+                        (ETAG, hit.etag),
+                    ],
+                    hit.body,
+                )
+                    .into_response()
+            };
+            let mut resp = tag_response(resp, "cache", &client_key);
+            resp.extensions_mut().insert(CacheStatus::Hit);
+            return resp;
+        } else {
+            counter!("rpc_cache_misses_total", "rpc_method" => method.clone()).increment(1);
+        }
+    }
+
+    let proxy_timeout = proxy_config.timeout_for(rpc_method.as_deref());
+    // All attempts, and the backoff between them, share one request deadline
+    let started = Instant::now();
+    let deadline = started + proxy_timeout;
+    // Reports the request as slow if it is still running at the method's soft timeout
+    let _slow_watch = proxy_config
+        .soft_timeout_for(rpc_method.as_deref())
+        .zip(rpc_method.clone())
+        .map(|(soft_timeout, method)| SlowWatch::start(soft_timeout, method));
+    let retry_safe = router_state.is_idempotent(rpc_method.as_deref());
+    let mut retries: u32 = 0;
+    let buffer_limit = buffer_limit(proxy_config);
+
+    let mut excluded: Vec<String> = Vec::new();
+    let mut mismatched_response: Option<Response> = None;
+
+    // Only look for a commitment when some backends are grouped by it
+    let commitment = router_state
+        .backends
+        .iter()
+        .any(|b| !b.config.commitments.is_empty())
+        .then(|| probe::commitment(request_json.get(&body_bytes)?))
+        .flatten();
+    let request_key = state
+        .selector
+        .uses_request_key()
+        .then(|| probe::first_param(request_json.get(&body_bytes)?))
+        .flatten();
+    let ctx = SelectionContext {
+        rpc_method: rpc_method.as_deref(),
+        client_addr,
+        owner: Some(&client_key.info.owner),
+        commitment: commitment.as_deref(),
+        request_key: request_key.as_deref(),
+    };
+
+    let upstream_body = match UpstreamBody::new(body_bytes, request_json, recompress).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to compress request body: {}", e);
+            return ProxyError::Internal("Failed to compress request body").into_response();
+        }
+    };
+
+    loop {
+        // Select backend based on method routing or the configured selector
+        let selection = state.select_backend_for(&ctx, &excluded).or_else(|| {
+            // A retry goes back to a backend that already failed when no other is left
+            if retries > 0 && mismatched_response.is_none() {
+                state.select_backend_for(&ctx, &[])
+            } else {
+                None
+            }
+        });
+        let selection = match selection {
+            Some(selection) => selection,
+            None => {
+                // A retry found no alternative backend; return what we already have
+                if let Some(resp) = mismatched_response {
+                    return resp;
+                }
+                tracing::error!("No healthy backends available for request");
+                return ProxyError::NoHealthyBackends.into_response();
+            }
+        };
+        // Selection skips saturated backends, but a concurrent request may have taken
+        // the last slot since
+        let Some(in_flight) = selection.backend().start_request() else {
+            counter!("rpc_backend_saturated_total", "backend" => selection.label().to_string())
+                .increment(1);
+            excluded.push(selection.label().to_string());
+            continue;
+        };
+        let upstream_req = match build_upstream_request(
+            &parts,
+            selection.backend(),
+            &cleaned_request_path,
+            upstream_body.for_backend(selection.backend()).await,
+        ) {
+            Ok(req) => req,
+            Err(e) => {
+                error!("{}", e);
+                return ProxyError::Internal("Invalid backend configuration").into_response();
+            }
+        };
+
+        // Forward request, over the backend's own client when it uses mutual TLS. A
+        // client disconnecting or resetting its HTTP/2 stream drops this future, which
+        // cancels the upstream request
+        let sent = Instant::now();
+        let remaining = deadline.saturating_duration_since(sent);
+        let abandoned = AbandonedRequest(Some(selection.label().to_string()));
+        let (selection, in_flight, result) = match router_state.hedge_delay(rpc_method.as_deref()) {
+            Some(hedge_delay) => {
+                let hedge = Hedge {
+                    state: &state,
+                    ctx: &ctx,
+                    excluded: &excluded,
+                    parts: &parts,
+                    path: &cleaned_request_path,
+                    body: &upstream_body,
+                    delay: hedge_delay,
+                    failure_status_codes: &proxy_config.failure_status_codes,
+                };
+                hedge
+                    .send(selection, in_flight, upstream_req, remaining)
+                    .await
+            }
+            None => {
+                let client = selection.backend().client().unwrap_or(&state.client);
+                let result = timeout(remaining, state.send_upstream(client, upstream_req)).await;
+                (selection, in_flight, result)
+            }
+        };
+        abandoned.disarm();
+        // The backend counts the request in flight until its response body is done
+        let mut result = result.map(|sent| sent.map(|resp| HeldBody::hold(resp, in_flight)));
+        if let Ok(Ok(resp)) = &mut result {
+            resp.extensions_mut()
+                .insert(UpstreamDuration(sent.elapsed()));
+        }
+        let backend_label = selection.label();
+
+        // Backoff before the next attempt, if this request may be retried and the
+        // shared retry budget has a token left
+        let retry_delay = |retries| {
+            if !retry_safe {
+                return None;
+            }
+            let delay = retry::retry_delay(proxy_config, retries, started, deadline)?;
+            if !state.retry_throttle.try_acquire() {
+                counter!("rpc_retries_throttled_total", "backend" => backend_label.to_string())
+                    .increment(1);
+                warn!(
+                    "Retry budget exhausted, returning failure from backend {}",
+                    backend_label
+                );
+                return None;
+            }
+            Some(delay)
+        };
+
+        // Upstream responses, error statuses included, are relayed with their own status
+        // and body; only transport failures and timeouts get a body written by the router
+        let resp = match result {
+            Ok(Ok(resp)) => {
+                let status = resp.status();
+                if status == StatusCode::TOO_MANY_REQUESTS && proxy_config.adaptive_weights {
+                    let throttle = &selection.backend().throttle;
+                    throttle.record_429(Duration::from_secs(
+                        proxy_config.adaptive_weight_cooldown_secs,
+                    ));
+                    counter!("rpc_backend_throttled_total", "backend" => backend_label.to_string())
+                        .increment(1);
+                    debug!(
+                        "Backend {} answered 429, weight now at {:.3} of configured",
+                        backend_label,
+                        throttle.factor()
+                    );
+                }
+                if proxy_config
+                    .failure_status_codes
+                    .is_failure(status.as_u16())
+                {
+                    counter!("rpc_backend_failures_total", "backend" => backend_label.to_string(), "status" => status.as_u16().to_string())
+                        .increment(1);
+                    warn!(
+                        "Backend {} returned failure status {}",
+                        backend_label, status
+                    );
+                    if let Some(delay) = proxy_config
+                        .retry_policy
+                        .retries_failure_status()
+                        .then(|| retry_delay(retries))
+                        .flatten()
+                    {
+                        backoff(backend_label, &mut retries, &mut excluded, delay).await;
+                        continue;
+                    }
+                } else {
+                    state.retry_throttle.record_success();
+                }
+                let resp = filter_trailers(resp.into_response(), &proxy_config.forward_trailers);
+                let resp = match limit_response(resp, proxy_config.max_response_bytes) {
+                    Ok(resp) => resp,
+                    Err(message) => {
+                        counter!("rpc_response_too_large_total", "backend" => backend_label.to_string())
+                            .increment(1);
+                        warn!("Backend {}: {}", backend_label, message);
+                        let resp =
+                            failure_response(ProxyError::ResponseTooLarge(message), batch.as_ref());
+                        return tag_backend_response(resp, &selection, &client_key);
+                    }
+                };
+                let resp = if proxy_config.validate_response_body && status.is_success() {
+                    match check_response_body(
+                        resp,
+                        proxy_config.max_validated_json_bytes,
+                        buffer_limit,
+                    )
+                    .await
+                    {
+                        Ok(resp) => resp,
+                        Err(invalid) => {
+                            counter!("rpc_invalid_responses_total", "backend" => backend_label.to_string(), "reason" => invalid.as_str())
+                                .increment(1);
+                            warn!(
+                                "Backend {} returned status {} with {}",
+                                backend_label,
+                                status,
+                                invalid.describe()
+                            );
+                            if let Some(delay) = proxy_config
+                                .retry_policy
+                                .retries_failure_status()
+                                .then(|| retry_delay(retries))
+                                .flatten()
+                            {
+                                backoff(backend_label, &mut retries, &mut excluded, delay).await;
+                                continue;
+                            }
+                            let resp = failure_response(
+                                ProxyError::InvalidResponse(invalid.describe()),
+                                batch.as_ref(),
+                            );
+                            let resp = categorize(resp, ErrorCategory::InvalidResponse);
+                            return tag_backend_response(resp, &selection, &client_key);
+                        }
+                    }
+                } else {
+                    resp
+                };
+                if proxy_config
+                    .failure_status_codes
+                    .is_failure(status.as_u16())
+                {
+                    categorize(resp, ErrorCategory::for_failure_status(status))
+                } else if retry_safe
+                    && retries < proxy_config.max_retries
+                    && status.is_success()
+                    && proxy_config.retry_policy.retries_jsonrpc_errors()
+                {
+                    // Buffered to look for a JSON-RPC error before anything is relayed
+                    let (parts, body) = resp.into_parts();
+                    let body_bytes = match to_bytes(body, buffer_limit).await {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            let resp = failure_response(
+                                ProxyError::ResponseRead(e.to_string()),
+                                batch.as_ref(),
+                            );
+                            return tag_backend_response(resp, &selection, &client_key);
+                        }
+                    };
+                    if probe::is_error_response(&body_bytes) {
+                        if let Some(delay) = retry_delay(retries) {
+                            warn!("Backend {} returned a JSON-RPC error", backend_label);
+                            backoff(backend_label, &mut retries, &mut excluded, delay).await;
+                            continue;
+                        }
+                    }
+                    Response::from_parts(parts, Body::from(body_bytes))
+                } else {
+                    resp
+                }
+            }
+            Ok(Err(err)) => {
+                info!("Backend request failed: {} (error type: {:?})", err, err);
+                if let Some(delay) = proxy_config
+                    .retry_policy
+                    .retries_connect_errors()
+                    .then(|| retry_delay(retries))
+                    .flatten()
+                {
+                    backoff(backend_label, &mut retries, &mut excluded, delay).await;
+                    continue;
+                }
+                let resp = failure_response(ProxyError::Upstream(err.to_string()), batch.as_ref());
+                let resp = categorize(resp, ErrorCategory::ConnectError);
+                return tag_backend_response(resp, &selection, &client_key);
+            }
+            Err(_) => {
+                let resp =
+                    failure_response(ProxyError::Timeout(proxy_timeout.as_secs()), batch.as_ref());
+                let resp = categorize(resp, ErrorCategory::Timeout);
+                return tag_backend_response(resp, &selection, &client_key);
+            }
+        };
+
+        let resp = match upstream_body.normalized_id(selection.backend()) {
+            Some(normalized) => normalized.restore(resp, buffer_limit).await,
+            None => resp,
+        };
+
+        let resp = match (proxy_config.validate_response_id, &request_id) {
+            (true, Some(expected_id)) => match check_response_id(resp, expected_id).await {
+                (resp, Some(actual_id)) => {
+                    counter!("rpc_response_id_mismatch_total", "backend" => backend_label.to_string())
+                        .increment(1);
+                    warn!(
+                        "Backend {} returned response id {} for request id {}",
+                        backend_label, actual_id, expected_id
+                    );
+
+                    if proxy_config.id_mismatch_action == IdMismatchAction::Retry
+                        && mismatched_response.is_none()
+                        && router_state.is_idempotent(rpc_method.as_deref())
+                    {
+                        mismatched_response =
+                            Some(tag_backend_response(resp, &selection, &client_key));
+                        excluded.push(backend_label.to_string());
+                        continue;
+                    }
+                    resp
+                }
+                (resp, None) => resp,
+            },
+            _ => resp,
+        };
+
+        let transforms = state.transforms.load_full();
+        let resp = match rpc_method.as_deref().and_then(|m| transforms.get(m)) {
+            Some(transform) => transform_response(resp, transform, buffer_limit).await,
+            None => resp,
+        };
+
+        let mut resp = match (&cache_key, &rpc_method) {
+            (Some(key), Some(method)) if resp.status() == StatusCode::OK => {
+                cache_response(resp, &state.cache, key, method, buffer_limit).await
+            }
+            _ => resp,
+        };
+        if cache_key.is_some() {
+            resp.extensions_mut().insert(CacheStatus::Miss);
+        }
+
+        let resp = if log_bodies {
+            log_response_body(resp, backend_label, logging.max_logged_body_bytes).await
+        } else {
+            resp
+        };
+
+        return tag_backend_response(resp, &selection, &client_key);
+    }
+}
+
+/// A request body parsed as JSON on first use, so a request nothing needs to look into
+/// is never parsed and the rest are parsed once. Holds `None` for a body that isn't
+/// JSON.
+#[derive(Default)]
+struct LazyJson(OnceLock<Option<serde_json::Value>>);
+
+impl LazyJson {
+    fn get(&self, body: &[u8]) -> Option<&serde_json::Value> {
+        self.0
+            .get_or_init(|| serde_json::from_slice(body).ok())
+            .as_ref()
+    }
+
+    fn get_mut(&mut self, body: &[u8]) -> Option<&mut serde_json::Value> {
+        self.get(body);
+        self.0.get_mut()?.as_mut()
+    }
+}
+
+type UpstreamResult = Result<
+    Result<hyper::Response<hyper::body::Incoming>, hyper_util::client::legacy::Error>,
+    tokio::time::error::Elapsed,
+>;
+
+/// Request body as forwarded upstream. Backends with `normalize_id` are sent an integer
+/// id in place of the client's, in a variant built the first time one is selected.
+struct UpstreamBody {
+    /// The client's body, before `recompress`
+    source: Bytes,
+    json: LazyJson,
+    recompress: Option<ContentEncoding>,
+    body: Bytes,
+    normalized: OnceCell<Option<(NormalizedId, Bytes)>>,
+}
+
+impl UpstreamBody {
+    async fn new(
+        source: Bytes,
+        json: LazyJson,
+        recompress: Option<ContentEncoding>,
+    ) -> std::io::Result<Self> {
+        let body = match recompress {
+            Some(encoding) => encoding.encode(source.clone()).await?,
+            None => source.clone(),
+        };
+        Ok(Self {
+            source,
+            json,
+            recompress,
+            body,
+            normalized: OnceCell::new(),
+        })
+    }
+
+    async fn for_backend(&self, backend: &RuntimeBackend) -> Bytes {
+        if !backend.config.normalize_id {
+            return self.body.clone();
+        }
+        let normalized = self
+            .normalized
+            .get_or_init(|| async {
+                let (normalized, body) = NormalizedId::rewrite(self.json.get(&self.source)?)?;
+                let body = match self.recompress {
+                    Some(encoding) => match encoding.encode(body).await {
+                        Ok(body) => body,
+                        Err(e) => {
+                            // The backend gets the client's id instead
+                            warn!("Failed to compress normalized request body: {}", e);
+                            return None;
+                        }
+                    },
+                    None => body,
+                };
+                Some((normalized, body))
+            })
+            .await;
+        match normalized {
+            Some((_, body)) => body.clone(),
+            None => self.body.clone(),
+        }
+    }
+
+    /// The id swap made for `backend`, if it was sent one.
+    fn normalized_id(&self, backend: &RuntimeBackend) -> Option<&NormalizedId> {
+        if !backend.config.normalize_id {
+            return None;
+        }
+        self.normalized
+            .get()?
+            .as_ref()
+            .map(|(normalized, _)| normalized)
+    }
+}
+
+/// Source of the integer ids sent to `normalize_id` backends.
+static NEXT_UPSTREAM_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A client's request `id` swapped for an integer one on the way upstream.
+struct NormalizedId {
+    original: serde_json::Value,
+    upstream: u64,
+}
+
+impl NormalizedId {
+    /// Rewrites the `id` of a single parsed request to the next integer id, returning
+    /// the body to send. Requests whose id is already a non-negative integer,
+    /// notifications and batches are left alone.
+    fn rewrite(request: &serde_json::Value) -> Option<(Self, Bytes)> {
+        if request.get("id")?.is_u64() {
+            return None;
+        }
+        let mut json = request.clone();
+        let id = json.as_object_mut()?.get_mut("id")?;
+        let upstream = NEXT_UPSTREAM_ID.fetch_add(1, Ordering::Relaxed);
+        let original = std::mem::replace(id, upstream.into());
+        let body = serde_json::to_vec(&json).ok()?;
+        Some((Self { original, upstream }, Bytes::from(body)))
+    }
+
+    /// Puts the client's id back into a response that echoes the upstream id. Other
+    /// responses are returned unchanged, so id validation still sees a mismatch.
+    async fn restore(&self, resp: Response, limit: usize) -> Response {
+        let (mut parts, body) = resp.into_parts();
+        let body_bytes = match to_bytes(body, limit).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return ProxyError::ResponseRead(e.to_string()).into_response();
+            }
+        };
+        let mut json = match serde_json::from_slice::<serde_json::Value>(&body_bytes) {
+            Ok(json) => json,
+            Err(_) => return Response::from_parts(parts, Body::from(body_bytes)),
+        };
+        match json.get_mut("id") {
+            Some(id) if id.as_u64() == Some(self.upstream) => *id = self.original.clone(),
+            _ => return Response::from_parts(parts, Body::from(body_bytes)),
+        }
+        match serde_json::to_vec(&json) {
+            Ok(rewritten) => {
+                parts.headers.remove(CONTENT_LENGTH);
+                Response::from_parts(parts, Body::from(rewritten))
+            }
+            Err(_) => Response::from_parts(parts, Body::from(body_bytes)),
+        }
+    }
+}
+
+/// Counts an upstream request the client gave up on: dropped, because the client
+/// disconnected, before `disarm` was called.
+struct AbandonedRequest(Option<String>);
+
+impl AbandonedRequest {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for AbandonedRequest {
+    fn drop(&mut self) {
+        if let Some(backend) = self.0.take() {
+            counter!("rpc_client_disconnects_total", "backend" => backend.clone()).increment(1);
+            debug!(
+                "Client disconnected, cancelled upstream request to {}",
+                backend
+            );
+        }
+    }
+}
+
+/// What a hedged attempt needs to build and route its second request.
+struct Hedge<'a> {
+    state: &'a AppState,
+    ctx: &'a SelectionContext<'a>,
+    excluded: &'a [String],
+    parts: &'a Parts,
+    path: &'a str,
+    body: &'a UpstreamBody,
+    delay: Duration,
+    failure_status_codes: &'a FailureStatusCodes,
+}
+
+impl Hedge<'_> {
+    /// Sends `req` to `primary` and, if it hasn't answered within the hedge delay,
+    /// the same request to a second backend. The first response that isn't a transport
+    /// failure, timeout or `failure_status_codes` status wins and the other request is
+    /// dropped, which cancels it. The hedged request takes an
+    /// in-flight slot on its backend like any other; the winner's slot is returned.
+    async fn send(
+        &self,
+        primary: BackendSelection,
+        primary_in_flight: InFlightRequest,
+        req: Request<Body>,
+        remaining: Duration,
+    ) -> (BackendSelection, InFlightRequest, UpstreamResult) {
+        let client = primary
+            .backend()
+            .client()
+            .unwrap_or(&self.state.client)
+            .clone();
+        let primary_fut = timeout(remaining, self.state.send_upstream(&client, req));
+        tokio::pin!(primary_fut);
+
+        let remaining = match remaining.checked_sub(self.delay) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return (primary, primary_in_flight, primary_fut.await),
+        };
+        tokio::select! {
+            result = &mut primary_fut => return (primary, primary_in_flight, result),
+            _ = tokio::time::sleep(self.delay) => {}
+        }
+
+        let mut excluded = self.excluded.to_vec();
+        excluded.push(primary.label().to_string());
+        let hedge = match self.state.select_backend_for(self.ctx, &excluded) {
+            Some(hedge) => hedge,
+            None => return (primary, primary_in_flight, primary_fut.await),
+        };
+        let Some(hedge_in_flight) = hedge.backend().start_request() else {
+            return (primary, primary_in_flight, primary_fut.await);
+        };
+        let hedge_req = match build_upstream_request(
+            self.parts,
+            hedge.backend(),
+            self.path,
+            self.body.for_backend(hedge.backend()).await,
+        ) {
+            Ok(req) => req,
+            Err(e) => {
+                error!("{}", e);
+                return (primary, primary_in_flight, primary_fut.await);
+            }
+        };
+
+        counter!("rpc_hedged_requests_total", "backend" => hedge.label().to_string()).increment(1);
+        debug!(
+            "Backend {} slower than {:?}, hedging to {}",
+            primary.label(),
+            self.delay,
+            hedge.label()
+        );
+        let hedge_client = hedge
+            .backend()
+            .client()
+            .unwrap_or(&self.state.client)
+            .clone();
+        let hedge_fut = timeout(
+            remaining,
+            self.state.send_upstream(&hedge_client, hedge_req),
+        );
+        tokio::pin!(hedge_fut);
+
+        // A failed attempt doesn't win while the other may still succeed
+        let answered = |result: &UpstreamResult| match result {
+            Ok(Ok(resp)) => !self.failure_status_codes.is_failure(resp.status().as_u16()),
+            _ => false,
+        };
+        let (winner, in_flight, result, outcome) = tokio::select! {
+            result = &mut primary_fut => if answered(&result) {
+                (primary, primary_in_flight, result, "primary")
+            } else {
+                (hedge, hedge_in_flight, hedge_fut.await, "hedge")
+            },
+            result = &mut hedge_fut => if answered(&result) {
+                (hedge, hedge_in_flight, result, "hedge")
+            } else {
+                (primary, primary_in_flight, primary_fut.await, "primary")
+            },
+        };
+        counter!("rpc_hedge_wins_total", "backend" => winner.label().to_string(), "winner" => outcome)
+            .increment(1);
+        (winner, in_flight, result)
+    }
+}
+
+/// Background timer that logs a warning and counts `rpc_slow_requests_total` once a
+/// request has run past its soft timeout. Dropping it, when the request finishes,
+/// cancels the timer.
+struct SlowWatch(tokio::task::JoinHandle<()>);
+
+impl SlowWatch {
+    fn start(soft_timeout: Duration, rpc_method: String) -> Self {
+        Self(tokio::spawn(async move {
+            tokio::time::sleep(soft_timeout).await;
+            counter!("rpc_slow_requests_total", "rpc_method" => rpc_method.clone()).increment(1);
+            warn!(
+                "Request for {} still running after soft timeout of {}s",
+                rpc_method,
+                soft_timeout.as_secs()
+            );
+        }))
+    }
+}
+
+impl Drop for SlowWatch {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Enforces `max_header_count` and `max_header_bytes` on the client's headers before
+/// any of them reach a backend.
+fn check_header_limits(headers: &HeaderMap, config: &ProxyConfig) -> Result<(), String> {
+    if headers.len() > config.max_header_count {
+        return Err(format!(
+            "Too many request headers: {} exceeds the limit of {}",
+            headers.len(),
+            config.max_header_count
+        ));
+    }
+    let header_bytes: usize = headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    if header_bytes > config.max_header_bytes {
+        return Err(format!(
+            "Request headers too large: {} bytes exceeds the limit of {}",
+            header_bytes, config.max_header_bytes
+        ));
+    }
+    Ok(())
+}
+
+/// Router-written response for a request that got no answer from its backend. Batches
+/// get a JSON-RPC error for each entry that expects a response; everything else gets
+/// `message` as plain text.
+fn failure_response(error: ProxyError, batch: Option<&Batch>) -> Response {
+    match batch.and_then(|batch| batch.failure_response(&error.to_string())) {
+        Some(errors) => (error.status_code(), Json(errors)).into_response(),
+        None => error.into_response(),
+    }
+}
+
+/// Counts a retry caused by `backend_label`, sleeps for its backoff `delay`, and
+/// excludes the backend from the next attempt.
+async fn backoff(
+    backend_label: &str,
+    retries: &mut u32,
+    excluded: &mut Vec<String>,
+    delay: Duration,
+) {
+    counter!("rpc_retries_total", "backend" => backend_label.to_string()).increment(1);
+    info!(
+        "Retrying request failed by backend {} in {:?} (retry {})",
+        backend_label,
+        delay,
+        *retries + 1
+    );
+    tokio::time::sleep(delay).await;
+    excluded.push(backend_label.to_string());
+    *retries += 1;
+}
+
+/// Enforces `max_response_bytes` on an upstream response: one whose Content-Length
+/// exceeds it is rejected before any of it is read, and any other body is counted as
+/// it streams and fails once it crosses the limit, which aborts a passthrough response
+/// and makes buffering it fail. A `max_bytes` of 0 leaves the response unchanged.
+fn limit_response(resp: Response, max_bytes: usize) -> Result<Response, String> {
+    if max_bytes == 0 {
+        return Ok(resp);
+    }
+    let too_large = |len: usize| {
+        format!(
+            "Upstream response of {} bytes exceeds max_response_bytes ({})",
+            len, max_bytes
+        )
+    };
+    if let Some(len) = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|len| *len > max_bytes)
+    {
+        return Err(too_large(len));
+    }
+
+    let (parts, body) = resp.into_parts();
+    let mut read = 0;
+    let body = BodyStream::new(body).map(move |frame| {
+        let frame = frame?;
+        read += frame.data_ref().map_or(0, Bytes::len);
+        if read > max_bytes {
+            return Err(axum::Error::new(format!(
+                "Upstream response exceeds max_response_bytes ({})",
+                max_bytes
+            )));
+        }
+        Ok(frame)
+    });
+    Ok(Response::from_parts(
+        parts,
+        Body::new(StreamBody::new(body)),
+    ))
+}
+
+/// Keeps only the upstream response trailers named in `forward`, and declares them in
+/// the `Trailer` header, without which HTTP/1.1 clients never receive them. With none
+/// named, backends aren't asked for trailers (see `identify_upstream`) and the body is
+/// passed on as is.
+fn filter_trailers(resp: Response, forward: &[String]) -> Response {
+    let allowed: Vec<HeaderName> = forward
+        .iter()
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+        .collect();
+    let (mut parts, body) = resp.into_parts();
+    parts.headers.remove(TRAILER);
+    if allowed.is_empty() {
+        return Response::from_parts(parts, body);
+    }
+    let declared = allowed
+        .iter()
+        .map(HeaderName::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::from_str(&declared) {
+        parts.headers.insert(TRAILER, value);
+    }
+
+    let body = body.map_frame(move |frame| match frame.into_trailers() {
+        Ok(trailers) => {
+            let mut kept = HeaderMap::new();
+            for name in &allowed {
+                for value in trailers.get_all(name) {
+                    kept.append(name.clone(), value.clone());
+                }
+            }
+            Frame::trailers(kept)
+        }
+        Err(frame) => frame,
+    });
+    Response::from_parts(parts, Body::new(body))
+}
+
+/// Buffers an upstream response and applies `transform` to its JSON body. Non-JSON
+/// bodies and responses the transform doesn't touch are returned as received.
+async fn transform_response(
+    resp: Response,
+    transform: &ResponseTransform,
+    limit: usize,
+) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let body_bytes = match to_bytes(body, limit).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return ProxyError::ResponseRead(e.to_string()).into_response();
+        }
+    };
+
+    let mut json = match serde_json::from_slice::<serde_json::Value>(&body_bytes) {
+        Ok(json) => json,
+        Err(_) => return Response::from_parts(parts, Body::from(body_bytes)),
+    };
+    if !transform.apply(&mut json) {
+        return Response::from_parts(parts, Body::from(body_bytes));
+    }
+
+    match serde_json::to_vec(&json) {
+        Ok(rewritten) => {
+            // The body length changed; let hyper recompute it
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(rewritten))
+        }
+        Err(_) => Response::from_parts(parts, Body::from(body_bytes)),
+    }
+}
+
+/// Buffers a successful response and adds `metadata` to its JSON body, or to each
+/// entry of a batch. Non-JSON bodies are returned as received.
+async fn inject_metadata(resp: Response, metadata: &ResponseMetadata, limit: usize) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let body_bytes = match to_bytes(body, limit).await {
+        Ok(bytes) => bytes,
+        Err(e) => return ProxyError::ResponseRead(e.to_string()).into_response(),
+    };
+
+    let mut json = match serde_json::from_slice::<serde_json::Value>(&body_bytes) {
+        Ok(json) => json,
+        Err(_) => return Response::from_parts(parts, Body::from(body_bytes)),
+    };
+    if !metadata.apply(&mut json) {
+        return Response::from_parts(parts, Body::from(body_bytes));
+    }
+
+    match serde_json::to_vec(&json) {
+        Ok(rewritten) => {
+            // The body changed, so neither its length nor a cache ETag still holds.
+            // Without an ETag clients have nothing to revalidate with, so responses
+            // carrying metadata are never answered with 304.
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.remove(ETAG);
+            Response::from_parts(parts, Body::from(rewritten))
+        }
+        Err(_) => Response::from_parts(parts, Body::from(body_bytes)),
+    }
+}
+
+/// Buffers a successful upstream response and stores its JSON body in `cache`. The
+/// response is returned unchanged apart from the cache entry's `ETag`.
+async fn cache_response(
+    resp: Response,
+    cache: &ResponseCache,
+    key: &str,
+    method: &str,
+    limit: usize,
+) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let body_bytes = match to_bytes(body, limit).await {
         Ok(bytes) => bytes,
-        Err(_) => {
-            // If body read fails, pass empty body downstream
-            return next.run(Request::from_parts(parts, Body::empty())).await;
+        Err(e) => {
+            return ProxyError::ResponseRead(e.to_string()).into_response();
         }
     };
 
-    // Optimize: Partial Zero-Copy Deserialization
-    // Instead of parsing the full JSON (which allocates for params),
-    // we use a struct that only captures 'method' and borrows the string from the buffer.
-    if let Ok(probe) = serde_json::from_slice::<MethodProbe>(&body_bytes) {
-        if let Some(method) = probe.method {
-            req = Request::from_parts(parts, Body::from(body_bytes.clone()));
-            req.extensions_mut().insert(RpcMethod(method.to_string()));
-            return next.run(req).await;
-        }
+    let etag = serde_json::from_slice::<serde_json::Value>(&body_bytes)
+        .ok()
+        .and_then(|json| cache.insert(key.to_string(), method, json))
+        .and_then(|etag| HeaderValue::from_str(&etag).ok());
+    if let Some(etag) = etag {
+        parts.headers.insert(ETAG, etag);
     }
-
-    // If no method found, reconstruct request with original body
-    req = Request::from_parts(parts, Body::from(body_bytes));
-    next.run(req).await
+    Response::from_parts(parts, Body::from(body_bytes))
 }
 
-pub async fn log_requests(
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    req: Request<Body>,
-    next: Next,
-) -> Response {
-    let method = req.method().clone();
-    let path = req.uri().path().to_string();
-    let rpc_method = req.extensions().get::<RpcMethod>().cloned();
-
-    let start = std::time::Instant::now();
-    let response = next.run(req).await;
-    let duration = start.elapsed();
+/// Lossy UTF-8 rendering of at most `max_bytes` of `body`, noting the full size when cut.
+pub(crate) fn truncate_for_log(body: &[u8], max_bytes: usize) -> String {
+    if body.len() <= max_bytes {
+        return String::from_utf8_lossy(body).into_owned();
+    }
+    format!(
+        "{}... ({} bytes total)",
+        String::from_utf8_lossy(&body[..max_bytes]),
+        body.len()
+    )
+}
 
-    // Extract backend from response extensions (set by proxy handler)
-    let backend = response.extensions().get::<SelectedBackend>().cloned();
+/// Buffers a sampled upstream response, logs its (truncated) body, and rebuilds it.
+/// Responses without a known size, or larger than `MAX_BODY_SIZE`, are passed through
+/// unlogged so sampling never holds back a streaming response.
+async fn log_response_body(resp: Response, backend_label: &str, max_bytes: usize) -> Response {
+    let sized = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .is_some_and(|len| len <= MAX_BODY_SIZE);
+    if !sized {
+        info!(
+            "Sampled response body: backend={} status={} body=<unsized, not logged>",
+            backend_label,
+            resp.status()
+        );
+        return resp;
+    }
 
-    match (rpc_method, backend) {
-        (Some(RpcMethod(m)), Some(SelectedBackend(b))) => info!(
-            "{} {} {} {:?} rpc_method={} backend={}",
-            method, path, addr, duration, m, b
-        ),
-        (Some(RpcMethod(m)), None) => info!(
-            "{} {} {} {:?} rpc_method={}",
-            method, path, addr, duration, m
-        ),
-        (None, Some(SelectedBackend(b))) => {
-            info!("{} {} {} {:?} backend={}", method, path, addr, duration, b)
+    let (parts, body) = resp.into_parts();
+    match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => {
+            info!(
+                "Sampled response body: backend={} status={} body={}",
+                backend_label,
+                parts.status,
+                truncate_for_log(&bytes, max_bytes)
+            );
+            Response::from_parts(parts, Body::from(bytes))
         }
-        (None, None) => info!("{} {} {} {:?}", method, path, addr, duration),
+        Err(e) => ProxyError::ResponseRead(e.to_string()).into_response(),
     }
-
-    response
 }
 
-pub async fn track_metrics(req: Request<Body>, next: Next) -> Response {
-    let start = std::time::Instant::now();
-    let method = req.method().to_string();
-
-    // Try to get RPC method if already extracted
-    let rpc_method = req
-        .extensions()
-        .get::<RpcMethod>()
-        .map(|m| m.0.clone())
-        .unwrap_or_else(|| "unknown".to_string());
-
-    let response = next.run(req).await;
-
-    let duration = start.elapsed().as_secs_f64();
-    let status = response.status().as_u16().to_string();
+/// Applies `upstream_user_agent` and `upstream_headers` to the headers forwarded
+/// upstream, replacing whatever the client sent, and asks for trailers only when
+/// `forward_trailers` names any. Values were checked at config load.
+pub(crate) fn identify_upstream(headers: &mut HeaderMap, config: &ProxyConfig) {
+    if config.forward_trailers.is_empty() {
+        headers.remove(TE);
+    } else {
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+    }
+    if let Some(user_agent) = config
+        .upstream_user_agent
+        .as_deref()
+        .and_then(|ua| HeaderValue::from_str(ua).ok())
+    {
+        let original = headers.insert(USER_AGENT, user_agent);
+        if let Some(original) = original.filter(|_| config.forward_original_user_agent) {
+            headers.insert(ORIGINAL_USER_AGENT_HEADER, original);
+        }
+    }
+    for (name, value) in &config.upstream_headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
 
-    let backend = response
-        .extensions()
-        .get::<SelectedBackend>()
-        .map(|b| b.0.clone())
-        .unwrap_or_else(|| "none".to_string());
+/// Builds the upstream request for `backend`, copying method and headers from the
+/// client request and rewriting the Host header to match the backend. The HTTP version
+/// is left to the upstream client, so an HTTP/2 client can reach an HTTP/1.1 backend.
+pub(crate) fn build_upstream_request(
+    parts: &Parts,
+    backend: &RuntimeBackend,
+    request_path: &str,
+    body: Bytes,
+) -> Result<Request<Body>, String> {
+    let uri = backend.upstream_uri(request_path)?;
+
+    let body = QueuedBody::new(Body::from(body), backend.queue_request());
+    let mut req = Request::new(Body::new(body));
+    *req.method_mut() = parts.method.clone();
+    *req.headers_mut() = parts.headers.clone();
+
+    // Update Host header to match the backend unless it routes on the client's Host,
+    // and never forward the client's key
+    if !(backend.config.preserve_host && req.headers().contains_key(HOST)) {
+        req.headers_mut()
+            .insert(HOST, backend.host_header().clone());
+    }
+    req.headers_mut().remove(API_KEY_HEADER);
 
-    let owner = response
-        .extensions()
-        .get::<ClientOwner>()
-        .map(|o| o.0.clone())
-        .unwrap_or_else(|| "none".to_string());
+    *req.uri_mut() = uri;
+    Ok(req)
+}
 
-    histogram!("rpc_request_duration_seconds", "rpc_method" => rpc_method.clone(), "backend" => backend.clone(), "owner" => owner.clone()).record(duration);
-    counter!("rpc_requests_total", "method" => method, "status" => status, "rpc_method" => rpc_method, "backend" => backend, "owner" => owner).increment(1);
+/// `tag_response` for an answer from `selection`, whose cost `track_metrics` records.
+fn tag_backend_response(
+    resp: Response,
+    selection: &BackendSelection,
+    client: &ClientKey,
+) -> Response {
+    let mut resp = tag_response(resp, selection.label(), client);
+    let backend = selection.backend();
+    resp.extensions_mut().insert(ServedBy {
+        costs: backend.costs.clone(),
+        cost: backend.config.cost,
+    });
+    resp
+}
 
-    response
+/// Stores the selected backend label, owner and resolved key in response extensions for
+/// logging/metrics.
+fn tag_response(mut resp: Response, backend_label: &str, client: &ClientKey) -> Response {
+    resp.extensions_mut()
+        .insert(SelectedBackend(backend_label.to_string()));
+    resp.extensions_mut()
+        .insert(ClientOwner(client.info.owner.clone()));
+    resp.extensions_mut().insert(client.clone());
+    resp
 }
 
-pub async fn proxy(
-    State(state): State<Arc<AppState>>,
-    Query(params): Query<Params>,
-    mut req: Request<Body>,
-) -> impl IntoResponse {
-    let api_key = match params.api_key {
-        Some(k) => k,
-        None => {
-            info!("No API key provided");
-            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
-        }
-    };
+/// Why `check_response_body` rejected a successful upstream response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InvalidBody {
+    Empty,
+    /// The body couldn't be read to the end, e.g. the connection reset mid-stream
+    Truncated,
+    InvalidJson,
+}
 
-    let owner = match state.keystore.validate_key(&api_key).await {
-        Ok(Some(info)) => {
-            info.owner
+impl InvalidBody {
+    fn as_str(self) -> &'static str {
+        match self {
+            InvalidBody::Empty => "empty",
+            InvalidBody::Truncated => "truncated",
+            InvalidBody::InvalidJson => "invalid_json",
         }
-        Ok(None) => {
-            info!("Invalid API key presented (prefix={}...)", &api_key[..api_key.len().min(6)]);
-            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
-        }
-        Err(e) => {
-            if e == "Rate limit exceeded" {
-                warn!("API key rate limited (prefix={}...)", &api_key[..api_key.len().min(6)]);
-                return (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
-            } else {
-                error!("Key validation error: {}", e);
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
-                    .into_response();
-            }
-        }
-    };
-
-    // Store owner in request extensions for metrics middleware
-    req.extensions_mut().insert(ClientOwner(owner));
-
-    // Get RPC method from extension (set by extract_rpc_method middleware)
-    let rpc_method = req.extensions().get::<RpcMethod>().map(|m| m.0.as_str());
+    }
 
-    // Select backend based on method routing or weighted random
-    let (backend_label, backend_url) = match state.select_backend(rpc_method) {
-        Some(selection) => selection,
-        None => {
-            tracing::error!("No healthy backends available for request");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                "No healthy backends available",
-            )
-                .into_response();
+    fn describe(self) -> &'static str {
+        match self {
+            InvalidBody::Empty => "an empty body",
+            InvalidBody::Truncated => "a truncated body",
+            InvalidBody::InvalidJson => "a body that isn't JSON",
         }
-    };
-
-    // Rebuild URI: strip api-key from query params while preserving others
-    let path = req.uri().path();
-    let cleaned_query = req
-        .uri()
-        .query()
-        .map(|q| {
-            q.split('&')
-                .filter(|p| !p.starts_with("api-key="))
-                .collect::<Vec<_>>()
-                .join("&")
-        })
-        .unwrap_or_default();
+    }
+}
 
-    let cleaned_request_path = if cleaned_query.is_empty() {
-        path.to_string()
-    } else {
-        format!("{}?{}", path, cleaned_query)
-    };
+/// Buffers a successful upstream response in full and checks that its body is
+/// complete and not empty, and parses as JSON when it is at most `max_json_bytes`.
+/// Returns the rebuilt response when the body passes. At most `buffer_limit` bytes are
+/// buffered, and a response cut off by it counts as truncated.
+async fn check_response_body(
+    resp: Response,
+    max_json_bytes: usize,
+    buffer_limit: usize,
+) -> Result<Response, InvalidBody> {
+    let (parts, body) = resp.into_parts();
+    let body_bytes = to_bytes(body, buffer_limit)
+        .await
+        .map_err(|_| InvalidBody::Truncated)?;
+    if body_bytes.iter().all(u8::is_ascii_whitespace) {
+        return Err(InvalidBody::Empty);
+    }
+    if body_bytes.len() <= max_json_bytes
+        && serde_json::from_slice::<serde::de::IgnoredAny>(&body_bytes).is_err()
+    {
+        return Err(InvalidBody::InvalidJson);
+    }
+    Ok(Response::from_parts(parts, Body::from(body_bytes)))
+}
 
-    // Build URI with selected backend
-    let uri_string = if cleaned_request_path == "/" {
-        // For root path requests, don't add trailing slash
-        backend_url.trim_end_matches('/').to_string()
-    } else if backend_url.ends_with('/') && cleaned_request_path.starts_with('/') {
-        // Avoid double slashes
-        format!("{}{}", backend_url, &cleaned_request_path[1..])
-    } else {
-        format!("{}{}", backend_url, cleaned_request_path)
-    };
+/// Buffers a small upstream response and compares its JSON-RPC `id` with `expected_id`.
+/// Returns the rebuilt response plus the upstream id when it doesn't match. Large,
+/// unsized, batch, or non-JSON responses are passed through unchecked.
+async fn check_response_id(
+    resp: Response,
+    expected_id: &serde_json::Value,
+) -> (Response, Option<serde_json::Value>) {
+    let small_enough = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .is_some_and(|len| len <= MAX_ID_CHECK_BODY_SIZE);
+    if !small_enough {
+        return (resp, None);
+    }
 
-    // Ensure we have a valid URI
-    let parsed_uri = match uri_string.parse::<Uri>() {
-        Ok(uri) => uri,
+    let (parts, body) = resp.into_parts();
+    let body_bytes = match to_bytes(body, MAX_ID_CHECK_BODY_SIZE).await {
+        Ok(bytes) => bytes,
         Err(e) => {
-            error!("Failed to parse backend URI '{}': {}", uri_string, e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Invalid backend configuration",
-            )
-                .into_response();
+            let resp = ProxyError::ResponseRead(e.to_string()).into_response();
+            return (resp, None);
         }
     };
 
-    // Update Host header to match the backend
-    if let Some(host) = parsed_uri.host() {
-        let host_value = if let Some(port) = parsed_uri.port_u16() {
-            format!("{}:{}", host, port)
-        } else {
-            host.to_string()
-        };
-        req.headers_mut()
-            .insert("host", host_value.parse().unwrap());
-    }
-
-    *req.uri_mut() = parsed_uri;
+    let mismatch = serde_json::from_slice::<ResponseIdProbe>(&body_bytes)
+        .ok()
+        .map(|probe| probe.id.unwrap_or(serde_json::Value::Null))
+        .filter(|actual_id| actual_id != expected_id);
 
-    // Capture owner before request is consumed
-    let client_owner = req.extensions().get::<ClientOwner>().cloned();
-
-    // Forward request
-    let proxy_timeout = state.state.load().proxy_timeout_secs;
-    let result = timeout(
-        Duration::from_secs(proxy_timeout),
-        state.client.request(req),
+    (
+        Response::from_parts(parts, Body::from(body_bytes)),
+        mismatch,
     )
-    .await;
-
-    match result {
-        Ok(Ok(mut resp)) => {
-            // Store selected backend label and owner in response extensions for logging/metrics
-            resp.extensions_mut()
-                .insert(SelectedBackend(backend_label.to_string()));
-            if let Some(owner) = client_owner {
-                resp.extensions_mut().insert(owner);
-            }
-            resp.into_response()
-        }
-        Ok(Err(err)) => {
-            info!("Backend request failed: {} (error type: {:?})", err, err);
-            let mut resp =
-                (StatusCode::BAD_GATEWAY, format!("Proxy error: {}", err)).into_response();
-            resp.extensions_mut()
-                .insert(SelectedBackend(backend_label.to_string()));
-            if let Some(owner) = client_owner {
-                resp.extensions_mut().insert(owner);
-            }
-            resp
-        }
-        Err(_) => {
-            let mut resp = (
-                StatusCode::GATEWAY_TIMEOUT,
-                format!("Upstream request timed out after {}s", proxy_timeout),
-            )
-                .into_response();
-            resp.extensions_mut()
-                .insert(SelectedBackend(backend_label.to_string()));
-            if let Some(owner) = client_owner {
-                resp.extensions_mut().insert(owner);
-            }
-            resp
-        }
-    }
 }
 
 #[derive(Serialize)]
 pub struct HealthResponse {
     pub overall_status: String,
     pub backends: Vec<BackendHealth>,
+    /// Backend health per `zone`, omitted when no backend has one
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub zones: BTreeMap<String, ZoneHealth>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ZoneHealth {
+    pub healthy_backends: usize,
+    pub total_backends: usize,
+    /// Whether this is `proxy.local_zone`, preferred by selection
+    pub local: bool,
 }
 
 #[derive(Serialize)]
@@ -304,9 +2035,181 @@ pub struct BackendHealth {
     pub label: String,
     pub healthy: bool,
     pub last_check: Option<String>,
+    /// `null` until a check has passed
+    pub seconds_since_last_success: Option<f64>,
     pub consecutive_failures: u32,
     pub consecutive_successes: u32,
     pub last_error: Option<String>,
+    /// Configured weight, and the weight in effect after `429` throttling
+    pub weight: u32,
+    pub effective_weight: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+}
+
+/// Serves `GET {path_prefix}{method}` when `proxy.rest_gateway` is configured: the
+/// request is turned into a params-less JSON-RPC call to `method` (id `1`) and proxied
+/// like any other, with the `api-key` query param or header still required. With
+/// `unwrap_result` a successful response is reduced to its `result` value.
+pub async fn rest_proxy(
+    State(state): State<Arc<AppState>>,
+    query: Query<Params>,
+    req: Request<Body>,
+) -> Response {
+    let router_state = state.state.load_full();
+    let Some(rest) = router_state.proxy_config.rest_gateway.clone() else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    let Some(method) = rest.method_for(req.uri().path()).map(str::to_string) else {
+        return ProxyError::NotRestMethod.into_response();
+    };
+
+    let (parts, _) = req.into_parts();
+    let query_string = parts.uri.query().map(str::to_string);
+    let id = serde_json::Value::from(1);
+    let mut resp = proxy_call(state, parts, query, query_string, method, id, None).await;
+    if rest.unwrap_result && resp.status().is_success() {
+        resp = unwrap_result(resp, buffer_limit(&router_state.proxy_config)).await;
+    }
+    resp
+}
+
+/// `GET /`: WebSocket upgrades go to `ws_proxy`, and with `proxy.allow_get_requests`
+/// any other request is a JSON-RPC call encoded in the query string.
+pub async fn root_get(State(state): State<Arc<AppState>>, req: Request<Body>) -> Response {
+    let allow_get = state.state.load().proxy_config.allow_get_requests;
+    if !allow_get || req.headers().contains_key(UPGRADE) {
+        return ws_proxy.call(req, state).await;
+    }
+    query_call(state, req).await
+}
+
+/// Serves `GET /?method=getSlot&params=[...]&id=7` as the JSON-RPC call it encodes.
+/// Only idempotent methods are accepted.
+async fn query_call(state: Arc<AppState>, req: Request<Body>) -> Response {
+    let (parts, _) = req.into_parts();
+    let call = match QueryCall::parse(parts.uri.query().unwrap_or_default()) {
+        Ok(call) => call,
+        Err(message) => return ProxyError::BadRequest(message.to_string()).into_response(),
+    };
+    if !state.state.load().is_idempotent(Some(&call.method)) {
+        return ProxyError::MethodNotAllowedOverGet(call.method).into_response();
+    }
+
+    let query = match Query::<Params>::try_from_uri(&parts.uri) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let QueryCall {
+        method,
+        params,
+        id,
+        query_string,
+    } = call;
+    proxy_call(state, parts, query, query_string, method, id, params).await
+}
+
+/// A JSON-RPC call encoded in a query string. `params` and `id` are JSON and
+/// optional (`id` defaults to `1`); other parameters, `api-key` included, are kept
+/// in `query_string` for the proxied request.
+struct QueryCall {
+    method: String,
+    params: Option<serde_json::Value>,
+    id: serde_json::Value,
+    query_string: Option<String>,
+}
+
+impl QueryCall {
+    fn parse(query: &str) -> Result<Self, &'static str> {
+        let mut method = None;
+        let mut params = None;
+        let mut id = serde_json::Value::from(1);
+        let mut kept = form_urlencoded::Serializer::new(String::new());
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "method" => method = Some(value.into_owned()),
+                "params" => match serde_json::from_str(&value) {
+                    Ok(value @ (serde_json::Value::Array(_) | serde_json::Value::Object(_))) => {
+                        params = Some(value)
+                    }
+                    _ => return Err("params must be a JSON array or object"),
+                },
+                // A bare string id doesn't need JSON quotes
+                "id" => id = serde_json::from_str(&value).unwrap_or(value.into_owned().into()),
+                _ => {
+                    kept.append_pair(&key, &value);
+                }
+            }
+        }
+        Ok(Self {
+            method: method.ok_or("GET requests need a method query parameter")?,
+            params,
+            id,
+            query_string: Some(kept.finish()).filter(|q| !q.is_empty()),
+        })
+    }
+}
+
+/// Proxies a JSON-RPC call built by the router (REST gateway, GET calls) as if the
+/// client had POSTed it to `/?{query_string}`.
+async fn proxy_call(
+    state: Arc<AppState>,
+    mut parts: Parts,
+    query: Query<Params>,
+    query_string: Option<String>,
+    method: String,
+    id: serde_json::Value,
+    params: Option<serde_json::Value>,
+) -> Response {
+    // JSON-RPC backends are served from the root path; only the query is kept
+    let path_and_query = match query_string {
+        Some(query) => format!("/?{}", query),
+        None => "/".to_string(),
+    };
+    parts.uri = match path_and_query.parse() {
+        Ok(uri) => uri,
+        Err(_) => return ProxyError::BadRequest("Invalid request URI".into()).into_response(),
+    };
+    parts.method = axum::http::Method::POST;
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    parts.extensions.insert(RpcMethod(method.clone()));
+    parts.extensions.insert(RpcId(id.clone()));
+    let mut body = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method });
+    if let Some(params) = params {
+        body["params"] = params;
+    }
+    let req = Request::from_parts(parts, Body::from(body.to_string()));
+
+    let mut resp = proxy(State(state), query, req).await.into_response();
+    // Middleware ran before the method was known, so it reads it from the response
+    resp.extensions_mut().insert(RpcMethod(method));
+    resp
+}
+
+/// Replaces a JSON-RPC response body with its `result` value. Bodies without one
+/// (errors, or anything that isn't a JSON object) are passed through unchanged.
+async fn unwrap_result(resp: Response, limit: usize) -> Response {
+    let (mut parts, body) = resp.into_parts();
+    let body_bytes = match to_bytes(body, limit).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to read response body for result unwrapping: {}", e);
+            return ProxyError::ResponseRead(e.to_string()).into_response();
+        }
+    };
+    let result = serde_json::from_slice::<serde_json::Value>(&body_bytes)
+        .ok()
+        .and_then(|mut json| json.get_mut("result").map(serde_json::Value::take));
+    match result {
+        Some(result) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(result.to_string()))
+        }
+        None => Response::from_parts(parts, Body::from(body_bytes)),
+    }
 }
 
 pub async fn health_endpoint(State(state): State<Arc<AppState>>) -> impl IntoResponse {
@@ -314,6 +2217,7 @@ pub async fn health_endpoint(State(state): State<Arc<AppState>>) -> impl IntoRes
     let all_statuses = current_state.health_state.get_all_statuses();
 
     let mut backends = Vec::new();
+    let mut zones: BTreeMap<String, ZoneHealth> = BTreeMap::new();
     let mut any_healthy = false;
 
     for backend in &current_state.backends {
@@ -325,83 +2229,268 @@ pub async fn health_endpoint(State(state): State<Arc<AppState>>) -> impl IntoRes
         if status.healthy {
             any_healthy = true;
         }
+        if let Some(zone) = &backend.config.zone {
+            let zone_health = zones.entry(zone.clone()).or_default();
+            zone_health.total_backends += 1;
+            zone_health.healthy_backends += usize::from(status.healthy);
+            zone_health.local = current_state.is_local_zone(backend);
+        }
 
         backends.push(BackendHealth {
             label: backend.config.label.clone(),
             healthy: status.healthy,
             last_check: status.last_check_time.map(|t| format!("{:?}", t)),
+            seconds_since_last_success: status
+                .time_since_last_success()
+                .map(|elapsed| elapsed.as_secs_f64()),
             consecutive_failures: status.consecutive_failures,
             consecutive_successes: status.consecutive_successes,
             last_error: status.last_error,
+            weight: backend.config.weight,
+            effective_weight: f64::from(backend.config.weight) * backend.throttle.factor(),
+            zone: backend.config.zone.clone(),
         });
     }
 
-    let overall_status = if any_healthy { "healthy" } else { "unhealthy" };
+    let draining = !state.ready.load(Ordering::Relaxed);
+    let overall_status = if draining {
+        "draining"
+    } else if any_healthy {
+        "healthy"
+    } else {
+        "unhealthy"
+    };
 
     let response = HealthResponse {
         overall_status: overall_status.to_string(),
         backends,
+        zones,
+    };
+
+    // Fail the check while draining so load balancers deregister this instance
+    let status = if draining {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
     };
 
-    Json(response)
+    (status, Json(response))
+}
+
+/// What a method route does when none of its backends can take a request.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteFallback {
+    /// Normal weighted selection among the other backends
+    Selection,
+    /// `strict` routes answer 503
+    Reject,
+}
+
+#[derive(Serialize)]
+pub struct RoutesResponse {
+    pub routes: BTreeMap<String, RouteStatus>,
+}
+
+#[derive(Serialize)]
+pub struct RouteStatus {
+    /// Route targets in order of preference
+    pub backends: Vec<RouteTarget>,
+    /// The target new requests go to right now, `null` while none is usable
+    pub active_backend: Option<String>,
+    pub fallback: RouteFallback,
+    /// Whether requests are currently getting the `fallback` behavior
+    pub falling_back: bool,
+}
+
+#[derive(Serialize)]
+pub struct RouteTarget {
+    pub label: String,
+    /// `false` for labels no backend has, e.g. after an admin removal
+    pub exists: bool,
+    pub healthy: bool,
+    /// Below its `max_concurrent_requests`
+    pub has_capacity: bool,
+}
+
+/// Every `[method_routes]` entry with the live health of its targets and what happens
+/// to its requests right now, so routing can be checked without cross-referencing
+/// the config and `/health`.
+pub async fn routes_endpoint(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let current_state = state.state.load();
+    let routes = current_state
+        .method_routes
+        .iter()
+        .map(|(method, route)| {
+            let backends: Vec<RouteTarget> = route
+                .backends
+                .iter()
+                .map(|label| {
+                    let backend = current_state
+                        .backends
+                        .iter()
+                        .find(|b| b.config.label == *label);
+                    RouteTarget {
+                        label: label.clone(),
+                        exists: backend.is_some(),
+                        healthy: backend.is_some_and(|b| b.healthy.load(Ordering::Relaxed)),
+                        has_capacity: backend.is_some_and(RuntimeBackend::has_request_capacity),
+                    }
+                })
+                .collect();
+            let active_backend = backends
+                .iter()
+                .find(|t| t.healthy && t.has_capacity)
+                .map(|t| t.label.clone());
+            let status = RouteStatus {
+                falling_back: active_backend.is_none(),
+                active_backend,
+                backends,
+                fallback: if route.strict {
+                    RouteFallback::Reject
+                } else {
+                    RouteFallback::Selection
+                },
+            };
+            (method.clone(), status)
+        })
+        .collect();
+
+    Json(RoutesResponse { routes })
+}
+
+/// Readiness probe: 200 while serving, 503 once shutdown has begun.
+pub async fn readyz_endpoint(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if state.ready.load(Ordering::Relaxed) {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    }
+}
+
+#[derive(Serialize)]
+pub struct VersionResponse {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_time: &'static str,
+    pub backend_count: usize,
+    pub uptime_secs: u64,
+}
+
+pub async fn version_endpoint(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        build_time: env!("BUILD_TIME"),
+        backend_count: state.state.load().backends.len(),
+        uptime_secs: state.start_time.elapsed().as_secs(),
+    })
+}
+
+/// Request count and latency percentiles recorded by `track_metrics` since startup.
+pub async fn stats_endpoint(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(StatsReport {
+        overall: state.latency.snapshot(),
+        groups: state.metric_groups.snapshots(),
+        costs: state
+            .state
+            .load()
+            .backends
+            .iter()
+            .map(|b| (b.config.label.clone(), b.costs.snapshot()))
+            .collect(),
+        keystore_cache: state.keystore.cache_stats(),
+        upstream_queue: state
+            .state
+            .load()
+            .backends
+            .iter()
+            .map(|b| (b.config.label.clone(), b.queued.load(Ordering::Relaxed)))
+            .collect(),
+    })
 }
 
 pub async fn ws_proxy(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
     Query(params): Query<Params>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
 ) -> impl IntoResponse {
-    let api_key = match params.api_key {
-        Some(k) => k,
-        None => {
-            info!("WebSocket: No API key provided from {}", addr);
+    let client_addr = connect_info.map(|ConnectInfo(addr)| addr);
+    let addr = Peer(client_addr);
+    let router_state = state.state.load_full();
+    if let Err(reason) = check_header_limits(&headers, &router_state.proxy_config) {
+        info!(
+            "WebSocket: Rejected request headers from {}: {}",
+            addr, reason
+        );
+        counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "headers_too_large").increment(1);
+        return ProxyError::HeadersTooLarge(reason).into_response();
+    }
+    let allow_query = router_state.proxy_config.allow_query_api_key;
+    let api_key = match extract_api_key(&headers, params, allow_query) {
+        Ok(k) => k,
+        Err(rejection) => {
+            match rejection {
+                ApiKeyRejection::Missing => {
+                    info!("WebSocket: No API key provided from {}", addr)
+                }
+                ApiKeyRejection::QueryDisabled => {
+                    info!("WebSocket: Rejected query-string API key from {}", addr)
+                }
+            }
             counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "auth_failed").increment(1);
-            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+            return rejection.into_response();
         }
     };
 
     // Validate API key
-    let owner = match state.keystore.validate_key(&api_key).await {
-        Ok(Some(info)) => {
-            info.owner
-        }
+    let info = match authorize_key(&state, &api_key).await {
+        Ok(Some(info)) => info,
         Ok(None) => {
-            info!("WebSocket: Invalid API key from {} (prefix={}...)", addr, &api_key[..api_key.len().min(6)]);
+            info!(
+                "WebSocket: Invalid API key from {} (prefix={}...)",
+                addr,
+                key_prefix(&api_key)
+            );
             counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "auth_failed").increment(1);
-            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+            return ProxyError::InvalidApiKey.into_response();
+        }
+        Err(KeyStoreError::RateLimited) => {
+            warn!(
+                "WebSocket: API key rate limited from {} (prefix={}...)",
+                addr,
+                key_prefix(&api_key)
+            );
+            counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "rate_limited").increment(1);
+            return ProxyError::RateLimited.into_response();
         }
         Err(e) => {
-            if e == "Rate limit exceeded" {
-                warn!(
-                    "WebSocket: API key rate limited from {} (prefix={}...)",
-                    addr, &api_key[..api_key.len().min(6)]
-                );
-                counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "rate_limited").increment(1);
-                return (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
-            }
             error!("WebSocket: Key validation error: {}", e);
             counter!("ws_connections_total", "backend" => "none", "owner" => "none", "status" => "error").increment(1);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response();
+            return ProxyError::from(e).into_response();
         }
     };
+    let message_rate = info
+        .ws_message_rate
+        .unwrap_or(state.state.load().proxy_config.ws_message_rate);
+    let limiter = TokenBucket::per_second(message_rate);
+    let owner = info.owner;
 
     // Select a backend with WebSocket support
-    let (backend_label, backend_ws_url) = match state.select_ws_backend() {
-        Some(selection) => selection,
-        None => {
-            error!("No healthy WebSocket backends available");
-            counter!("ws_connections_total", "backend" => "none", "owner" => owner.clone(), "status" => "no_backend").increment(1);
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                "No healthy WebSocket backends available",
-            )
-                .into_response();
-        }
+    let ctx = SelectionContext {
+        rpc_method: None,
+        client_addr,
+        owner: Some(&owner),
+        commitment: None,
+        request_key: None,
     };
-
-    let backend_label = backend_label.to_string();
-    let backend_ws_url = backend_ws_url.to_string();
+    let (backend_socket, backend_label, connection) =
+        match connect_ws_backend(&state, &ctx, &owner).await {
+            Ok(connected) => connected,
+            Err(resp) => return resp,
+        };
 
     info!(
         "WebSocket: {} upgrading connection, backend={}, owner={}",
@@ -409,33 +2498,96 @@ pub async fn ws_proxy(
     );
 
     ws.on_upgrade(move |client_socket| {
-        handle_ws_connection(client_socket, backend_ws_url, backend_label, owner, addr)
+        handle_ws_connection(
+            client_socket,
+            backend_socket,
+            backend_label,
+            owner,
+            addr,
+            limiter,
+            connection,
+        )
     })
     .into_response()
 }
 
+/// Backend side of a proxied WebSocket connection.
+type BackendSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Opens the backend WebSocket before the client's upgrade is accepted, backing off
+/// between attempts as configured by `ws_connect_retries`. Each attempt is bounded by
+/// `timeout_secs` and selects a backend afresh, skipping those that already failed
+/// unless no other is left. The error is the response refusing the upgrade.
+async fn connect_ws_backend(
+    state: &AppState,
+    ctx: &SelectionContext<'_>,
+    owner: &str,
+) -> Result<(BackendSocket, String, WsConnection), Response> {
+    let mut retries = 0;
+    let mut excluded: Vec<String> = Vec::new();
+    loop {
+        let selection = state
+            .select_ws_backend_for(ctx, &excluded)
+            .or_else(|| state.select_ws_backend_for(ctx, &[]));
+        let Some(selection) = selection else {
+            error!("No healthy WebSocket backends available");
+            counter!("ws_connections_total", "backend" => "none", "owner" => owner.to_string(), "status" => "no_backend").increment(1);
+            return Err(ProxyError::NoHealthyWsBackends.into_response());
+        };
+
+        let backend_label = selection.label().to_string();
+        let backend_url = selection.ws_url().unwrap_or_default().to_string();
+        // Held for the life of the connection; another upgrade may have taken the last
+        // slot since selection
+        let Some(connection) = selection.backend().open_ws_connection() else {
+            warn!(
+                "WebSocket: backend {} reached max_ws_connections",
+                backend_label
+            );
+            counter!("ws_connections_total", "backend" => backend_label, "owner" => owner.to_string(), "status" => "backend_full").increment(1);
+            return Err(ProxyError::WsBackendsAtCapacity.into_response());
+        };
+
+        let router_state = state.state.load_full();
+        let proxy_config = &router_state.proxy_config;
+        let connect_timeout = Duration::from_secs(proxy_config.timeout_secs);
+        let err = match timeout(connect_timeout, connect_async(&backend_url)).await {
+            Ok(Ok((socket, _))) => return Ok((socket, backend_label, connection)),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("timed out after {}s", proxy_config.timeout_secs),
+        };
+        drop(connection);
+        let Some(delay) = retry::ws_connect_delay(proxy_config, retries) else {
+            error!(
+                "WebSocket: Failed to connect to backend {} ({}): {}",
+                backend_label, backend_url, err
+            );
+            counter!("ws_connections_total", "backend" => backend_label, "owner" => owner.to_string(), "status" => "backend_connect_failed").increment(1);
+            return Err(ProxyError::WsBackendUnavailable.into_response());
+        };
+        warn!(
+            "WebSocket: Failed to connect to backend {} ({}), retrying in {:?}: {}",
+            backend_label, backend_url, delay, err
+        );
+        counter!("ws_connect_retries_total", "backend" => backend_label.clone()).increment(1);
+        excluded.push(backend_label);
+        tokio::time::sleep(delay).await;
+        retries += 1;
+    }
+}
+
 async fn handle_ws_connection(
     client_socket: WebSocket,
-    backend_url: String,
+    backend_socket: BackendSocket,
     backend_label: String,
     owner: String,
-    client_addr: SocketAddr,
+    client_addr: Peer,
+    mut limiter: Option<TokenBucket>,
+    _connection: WsConnection,
 ) {
-    // Connect to the backend WebSocket
-    let backend_socket = match connect_async(&backend_url).await {
-        Ok((socket, _)) => socket,
-        Err(e) => {
-            error!(
-                "WebSocket: Failed to connect to backend {} ({}): {}",
-                backend_label, backend_url, e
-            );
-            counter!("ws_connections_total", "backend" => backend_label, "owner" => owner, "status" => "backend_connect_failed").increment(1);
-            return;
-        }
-    };
-
     counter!("ws_connections_total", "backend" => backend_label.clone(), "owner" => owner.clone(), "status" => "connected").increment(1);
-    gauge!("ws_active_connections", "backend" => backend_label.clone(), "owner" => owner.clone()).increment(1.0);
+    gauge!("ws_active_connections", "backend" => backend_label.clone(), "owner" => owner.clone())
+        .increment(1.0);
     let connect_time = std::time::Instant::now();
 
     info!(
@@ -453,12 +2605,32 @@ async fn handle_ws_connection(
     let bl2 = backend_label.clone();
     let ow2 = owner.clone();
 
-    // Forward client -> backend
+    // Follow subscribe/unsubscribe traffic to report active subscriptions
+    let subscriptions = Mutex::new(SubscriptionTracker::new());
+    let track = |record: &dyn Fn(&mut SubscriptionTracker)| {
+        let mut tracker = subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        let before = tracker.active();
+        record(&mut tracker);
+        let after = tracker.active();
+        if after != before {
+            gauge!("ws_active_subscriptions", "backend" => backend_label.clone(), "owner" => owner.clone())
+                .increment(after as f64 - before as f64);
+        }
+    };
+
+    // Forward client -> backend. Resolves to true when the client exceeded its
+    // message rate.
     let client_to_backend = async {
         while let Some(msg) = client_read.next().await {
+            if matches!(msg, Ok(Message::Text(_) | Message::Binary(_)))
+                && limiter.as_mut().is_some_and(|l| !l.try_take())
+            {
+                return true;
+            }
             match msg {
                 Ok(Message::Text(text)) => {
                     counter!("ws_messages_total", "backend" => bl1.clone(), "owner" => ow1.clone(), "direction" => "client_to_backend").increment(1);
+                    track(&|t| t.client_frame(&text));
                     if backend_write
                         .send(TungsteniteMessage::Text(text))
                         .await
@@ -498,6 +2670,7 @@ async fn handle_ws_connection(
                 Ok(Message::Close(_)) | Err(_) => break,
             }
         }
+        false
     };
 
     // Forward backend -> client
@@ -506,6 +2679,7 @@ async fn handle_ws_connection(
             match msg {
                 Ok(TungsteniteMessage::Text(text)) => {
                     counter!("ws_messages_total", "backend" => bl2.clone(), "owner" => ow2.clone(), "direction" => "backend_to_client").increment(1);
+                    track(&|t| t.backend_frame(&text));
                     if client_write.send(Message::Text(text)).await.is_err() {
                         break;
                     }
@@ -535,7 +2709,19 @@ async fn handle_ws_connection(
 
     // Run both directions concurrently, stop when either ends
     tokio::select! {
-        _ = client_to_backend => {
+        rate_limited = client_to_backend => {
+            if rate_limited {
+                warn!(
+                    "WebSocket: {} exceeded its message rate, closing (owner={})",
+                    client_addr, owner
+                );
+                counter!("ws_connections_rate_limited_total", "backend" => backend_label.clone(), "owner" => owner.clone()).increment(1);
+                let close = CloseFrame {
+                    code: close_code::POLICY,
+                    reason: "Message rate limit exceeded".into(),
+                };
+                let _ = client_write.send(Message::Close(Some(close))).await;
+            }
             // Client side ended; send close to backend
             let _ = backend_write.send(TungsteniteMessage::Close(None)).await;
         },
@@ -546,7 +2732,14 @@ async fn handle_ws_connection(
     }
 
     let duration = connect_time.elapsed().as_secs_f64();
-    gauge!("ws_active_connections", "backend" => backend_label.clone(), "owner" => owner.clone()).decrement(1.0);
+    gauge!("ws_active_connections", "backend" => backend_label.clone(), "owner" => owner.clone())
+        .decrement(1.0);
+    let remaining = subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .active();
+    gauge!("ws_active_subscriptions", "backend" => backend_label.clone(), "owner" => owner.clone())
+        .decrement(remaining as f64);
     histogram!("ws_connection_duration_seconds", "backend" => backend_label.clone(), "owner" => owner.clone()).record(duration);
 
     info!(
@@ -1,36 +1,64 @@
 use std::{
     collections::HashMap,
-    sync::{atomic::Ordering, Arc, RwLock},
-    time::SystemTime,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use arc_swap::ArcSwap;
-use axum::{body::Body, http::Request};
+use axum::{
+    body::Body,
+    http::{Request, Response},
+};
 use futures_util::future;
+use hyper::body::Incoming;
 use hyper_tls::HttpsConnector;
 use hyper_util::client::legacy::{connect::HttpConnector, Client};
 use metrics::gauge;
-use tokio::time::{sleep, timeout, Duration};
+use serde::Serialize;
+use tokio::{
+    sync::{watch, Semaphore},
+    time::{sleep, timeout, Duration},
+};
 
 use crate::{
-    config::{Backend, HealthCheckConfig},
-    state::RouterState,
+    config::{Backend, FailureStatusCodes, HealthCheckConfig, SecondaryCheck},
+    error::HealthCheckError,
+    state::{tls_connector, HttpClient, RouterState},
 };
 
 #[derive(Debug, Clone)]
 pub struct BackendHealthStatus {
     pub healthy: bool,
     pub last_check_time: Option<SystemTime>,
+    /// When a check last passed (reachable and not lagging), whatever the thresholds
+    /// made of it
+    pub last_success_time: Option<SystemTime>,
     pub consecutive_failures: u32,
     pub consecutive_successes: u32,
     pub last_error: Option<String>,
 }
 
+impl BackendHealthStatus {
+    /// Time since the last passing check; `None` if none has passed yet.
+    pub fn time_since_last_success(&self) -> Option<Duration> {
+        let last_success = self.last_success_time?;
+        Some(
+            SystemTime::now()
+                .duration_since(last_success)
+                .unwrap_or_default(),
+        )
+    }
+}
+
 impl Default for BackendHealthStatus {
     fn default() -> Self {
         Self {
             healthy: true, // Start optimistic - assume backends are healthy
             last_check_time: None,
+            last_success_time: None,
             consecutive_failures: 0,
             consecutive_successes: 0,
             last_error: None,
@@ -41,6 +69,21 @@ impl Default for BackendHealthStatus {
 #[derive(Debug)]
 pub struct HealthState {
     statuses: RwLock<HashMap<String, BackendHealthStatus>>,
+    /// Highest slot reported by any backend's health check (the cluster tip)
+    cluster_slot: watch::Sender<u64>,
+    /// Health last sent to the webhook per backend, and when
+    last_notified: Mutex<HashMap<String, (bool, Instant)>>,
+    /// Whether the last round left every regular backend unhealthy
+    all_unhealthy: AtomicBool,
+    /// Failure last logged per backend, when, and how many repeats went unlogged since
+    logged_failures: Mutex<HashMap<String, LoggedFailure>>,
+}
+
+#[derive(Debug)]
+struct LoggedFailure {
+    error: String,
+    at: Instant,
+    suppressed: u32,
 }
 
 impl HealthState {
@@ -51,9 +94,117 @@ impl HealthState {
         }
         Self {
             statuses: RwLock::new(statuses),
+            cluster_slot: watch::Sender::new(0),
+            last_notified: Mutex::new(HashMap::new()),
+            all_unhealthy: AtomicBool::new(false),
+            logged_failures: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Whether the last health check round found no regular backend healthy.
+    pub fn all_unhealthy(&self) -> bool {
+        self.all_unhealthy.load(Ordering::Relaxed)
+    }
+
+    /// Records whether every regular backend is unhealthy, returning the previous value
+    /// so the caller reports only transitions.
+    fn set_all_unhealthy(&self, all_unhealthy: bool) -> bool {
+        self.all_unhealthy.swap(all_unhealthy, Ordering::Relaxed)
+    }
+
+    /// Whether the webhook should hear that `label` is now `healthy`. The first
+    /// notification goes out on a `transitioned` round; after that, one goes out
+    /// whenever the health differs from what was last sent and `min_interval` has
+    /// passed, so flapping produces at most one notification per interval and the
+    /// final state is always reported.
+    pub fn should_notify(
+        &self,
+        label: &str,
+        healthy: bool,
+        transitioned: bool,
+        min_interval: Duration,
+    ) -> bool {
+        let mut last_notified = self.last_notified.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let due = match last_notified.get(label) {
+            None => transitioned,
+            Some(&(sent_healthy, at)) => {
+                sent_healthy != healthy && now.duration_since(at) >= min_interval
+            }
+        };
+        if due {
+            last_notified.insert(label.to_string(), (healthy, now));
+        }
+        due
+    }
+
+    /// Whether a failed check of `label` with `error` should be logged. A new error, or
+    /// one that `transitioned` the backend's health, is always logged; the same error
+    /// again is logged only once `min_interval` has passed since it last was. Returns
+    /// the number of repeats skipped since the last log when it should be.
+    pub fn should_log_failure(
+        &self,
+        label: &str,
+        error: &str,
+        transitioned: bool,
+        min_interval: Duration,
+    ) -> Option<u32> {
+        let mut logged = self
+            .logged_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some(last) = logged.get_mut(label) {
+            if last.error == error && !transitioned && now.duration_since(last.at) < min_interval {
+                last.suppressed += 1;
+                return None;
+            }
+        }
+        let previous = logged.insert(
+            label.to_string(),
+            LoggedFailure {
+                error: error.to_string(),
+                at: now,
+                suppressed: 0,
+            },
+        );
+        Some(
+            previous
+                .filter(|p| p.error == error)
+                .map_or(0, |p| p.suppressed),
+        )
+    }
+
+    /// Forgets the failure last logged for `label` once a check passes, so the next
+    /// failure is logged in full.
+    pub fn clear_logged_failure(&self, label: &str) {
+        self.logged_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(label);
+    }
+
+    /// Records the cluster tip seen by a health check round. Subscribers are only
+    /// notified when the slot advances.
+    pub fn record_cluster_slot(&self, slot: u64) {
+        self.cluster_slot.send_if_modified(|current| {
+            let advanced = slot > *current;
+            if advanced {
+                *current = slot;
+            }
+            advanced
+        });
+    }
+
+    pub fn cluster_slot(&self) -> u64 {
+        *self.cluster_slot.borrow()
+    }
+
+    /// Notifies the receiver each time the cluster slot advances.
+    pub fn subscribe_cluster_slot(&self) -> watch::Receiver<u64> {
+        self.cluster_slot.subscribe()
+    }
+
     pub fn get_status(&self, label: &str) -> Option<BackendHealthStatus> {
         self.statuses
             .read()
@@ -72,6 +223,24 @@ impl HealthState {
         }
     }
 
+    /// Starts tracking a backend added at runtime, keeping any status it already has.
+    pub fn insert(&self, label: String) {
+        self.statuses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(label)
+            .or_default();
+    }
+
+    /// Stops tracking a backend removed at runtime.
+    pub fn remove(&self, label: &str) {
+        self.statuses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(label);
+        self.clear_logged_failure(label);
+    }
+
     pub fn get_all_statuses(&self) -> HashMap<String, BackendHealthStatus> {
         self.statuses
             .read()
@@ -80,232 +249,611 @@ impl HealthState {
     }
 }
 
+/// Builds the client shared by health checks of backends without their own mTLS
+/// client, with its connect and idle timeouts taken from `config` so probes can be
+/// tuned apart from proxied traffic. `accept_invalid_certs` follows
+/// `upstream.danger_accept_invalid_certs`.
+pub fn health_check_client(config: &HealthCheckConfig, accept_invalid_certs: bool) -> HttpClient {
+    build_health_check_client(config, accept_invalid_certs, None).expect("failed to initialize TLS")
+}
+
+/// `health_check_client`, also presenting `identity` to backends that ask for a client
+/// certificate.
+pub(crate) fn build_health_check_client(
+    config: &HealthCheckConfig,
+    accept_invalid_certs: bool,
+    identity: Option<&native_tls::Identity>,
+) -> Result<HttpClient, native_tls::Error> {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    if config.connect_timeout_ms > 0 {
+        http.set_connect_timeout(Some(Duration::from_millis(config.connect_timeout_ms)));
+    }
+    Ok(Client::builder(hyper_util::rt::TokioExecutor::new())
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .build(tls_connector(http, accept_invalid_certs, identity)?))
+}
+
 /// Performs a health check against a backend.
 /// Returns `Ok(Some(slot))` if the method is `getSlot` or `getBlockHeight` and the response
-/// contains a numeric result. Returns `Ok(None)` for other methods. Returns `Err` on failure.
-async fn perform_health_check(
+/// contains a numeric result. Returns `Ok(None)` for other methods, and for non-2xx statuses
+/// not listed in `failure_status_codes` (the backend answered, it just rejected the probe).
+/// Returns `Err` on failure.
+pub async fn perform_health_check(
     client: &Client<HttpsConnector<HttpConnector>, Body>,
     backend: &Backend,
     health_config: &HealthCheckConfig,
-) -> Result<Option<u64>, String> {
-    // Build health check request
+    failure_status_codes: &FailureStatusCodes,
+) -> Result<Option<u64>, HealthCheckError> {
+    let response = send_probe(
+        client,
+        backend,
+        &health_config.method,
+        &health_config.params,
+        health_config.timeout_secs,
+    )
+    .await?;
+
+    if failure_status_codes.is_failure(response.status().as_u16()) {
+        return Err(HealthCheckError::Status(response.status()));
+    }
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    // Parse the response body to extract slot/block height
+    let method = health_config.method.as_str();
+    if method == "getSlot" || method == "getBlockHeight" {
+        let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .map_err(|e| HealthCheckError::ReadBody(e.to_string()))?
+            .to_bytes();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&body_bytes).map_err(HealthCheckError::ParseBody)?;
+
+        if let Some(slot) = json.get("result").and_then(|v| v.as_u64()) {
+            Ok(Some(slot))
+        } else {
+            Err(HealthCheckError::MissingResult(method.to_string()))
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Runs `health_check.secondary_check` against a backend. Passes only when the backend
+/// answers `2xx` with a JSON-RPC `result`; anything else, a JSON-RPC error included,
+/// fails it.
+pub async fn perform_secondary_check(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    backend: &Backend,
+    check: &SecondaryCheck,
+    timeout_secs: u64,
+) -> Result<(), HealthCheckError> {
+    let fail = |reason: String| HealthCheckError::SecondaryCheck {
+        method: check.method.clone(),
+        reason,
+    };
+    let response = send_probe(client, backend, &check.method, &check.params, timeout_secs)
+        .await
+        .map_err(|e| fail(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(fail(format!("status {}", response.status())));
+    }
+    let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .map_err(|e| fail(format!("failed to read response body: {}", e)))?
+        .to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes)
+        .map_err(|e| fail(format!("failed to parse response JSON: {}", e)))?;
+    if let Some(error) = json.get("error") {
+        return Err(fail(format!("JSON-RPC error {}", error)));
+    }
+    if json.get("result").is_none() {
+        return Err(fail("response has no 'result'".to_string()));
+    }
+    Ok(())
+}
+
+/// POSTs a JSON-RPC probe for `method` to `backend`, bounded by `timeout_secs`.
+async fn send_probe(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    backend: &Backend,
+    method: &str,
+    params: &serde_json::Value,
+    timeout_secs: u64,
+) -> Result<Response<Incoming>, HealthCheckError> {
     let health_request = serde_json::json!({
         "jsonrpc": "2.0",
         "id": 1,
-        "method": health_config.method,
-        "params": []
+        "method": method,
+        "params": params
     });
 
-    let body_bytes = serde_json::to_vec(&health_request)
-        .map_err(|e| format!("Failed to serialize health check: {}", e))?;
+    let body_bytes =
+        serde_json::to_vec(&health_request).map_err(HealthCheckError::SerializeRequest)?;
 
     let req = Request::builder()
         .method("POST")
-        .uri(&backend.url)
+        .uri(backend.effective_url().as_ref())
         .header("content-type", "application/json")
-        .body(Body::from(body_bytes))
-        .map_err(|e| format!("Failed to build request: {}", e))?;
+        .body(Body::from(body_bytes))?;
 
-    // Perform request with timeout
-    let result = timeout(
-        Duration::from_secs(health_config.timeout_secs),
-        client.request(req),
-    )
-    .await;
-
-    match result {
-        Ok(Ok(response)) => {
-            if !response.status().is_success() {
-                return Err(format!(
-                    "Health check returned status: {}",
-                    response.status()
-                ));
+    match timeout(Duration::from_secs(timeout_secs), client.request(req)).await {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => Err(HealthCheckError::Request(e.to_string())),
+        Err(_) => Err(HealthCheckError::Timeout(timeout_secs)),
+    }
+}
+
+/// Probes every backend once before the router starts serving traffic.
+/// Unreachable backends are marked unhealthy so the first requests after a deploy
+/// avoid them. Returns the number of reachable backends, or `Err` if none are
+/// reachable and `require_reachable` is set.
+pub async fn startup_probe(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    router_state: &RouterState,
+    require_reachable: bool,
+) -> Result<usize, HealthCheckError> {
+    let health_config = &router_state.health_check_config;
+    let permits = Semaphore::new(health_config.concurrency);
+
+    let probe_futures: Vec<_> = router_state
+        .backends
+        .iter()
+        .map(|backend| {
+            let permits = &permits;
+            async move {
+                let _permit = permits.acquire().await;
+                perform_health_check(
+                    backend.health_client().unwrap_or(client),
+                    &backend.config,
+                    health_config,
+                    &router_state.proxy_config.failure_status_codes,
+                )
+                .await
+            }
+        })
+        .collect();
+
+    let results = future::join_all(probe_futures).await;
+
+    let mut reachable = 0;
+    for (backend, result) in router_state.backends.iter().zip(results) {
+        let mut status = router_state
+            .health_state
+            .get_status(&backend.config.label)
+            .unwrap_or_default();
+        status.last_check_time = Some(SystemTime::now());
+
+        match result {
+            Ok(_) => {
+                reachable += 1;
+                tracing::info!(
+                    "Startup probe: backend {} is reachable",
+                    backend.config.label
+                );
+                status.last_error = None;
+                status.last_success_time = status.last_check_time;
             }
+            Err(error) => {
+                tracing::warn!(
+                    "Startup probe: backend {} is unreachable: {}",
+                    backend.config.label,
+                    error
+                );
+                status.healthy = false;
+                status.last_error = Some(error.to_string());
+            }
+        }
 
-            // Parse the response body to extract slot/block height
-            let method = health_config.method.as_str();
-            if method == "getSlot" || method == "getBlockHeight" {
-                let body_bytes = http_body_util::BodyExt::collect(response.into_body())
-                    .await
-                    .map_err(|e| format!("Failed to read response body: {}", e))?
-                    .to_bytes();
+        gauge!("rpc_backend_health", "backend" => backend.config.label.clone())
+            .set(if status.healthy { 1.0 } else { 0.0 });
+        backend.healthy.store(status.healthy, Ordering::Relaxed);
+        router_state
+            .health_state
+            .update_status(&backend.config.label, status);
+    }
 
-                let json: serde_json::Value = serde_json::from_slice(&body_bytes)
-                    .map_err(|e| format!("Failed to parse response JSON: {}", e))?;
+    tracing::info!(
+        "Startup probe: {}/{} backends reachable",
+        reachable,
+        router_state.backends.len()
+    );
 
-                if let Some(slot) = json.get("result").and_then(|v| v.as_u64()) {
-                    Ok(Some(slot))
-                } else {
-                    Err(format!(
-                        "Health check response missing numeric 'result' field for method {}",
-                        method
-                    ))
-                }
-            } else {
-                Ok(None)
-            }
-        }
-        Ok(Err(e)) => Err(format!("Health check request failed: {}", e)),
-        Err(_) => Err(format!(
-            "Health check timed out after {}s",
-            health_config.timeout_secs
-        )),
+    if reachable == 0 && require_reachable {
+        return Err(HealthCheckError::NoneReachable(router_state.backends.len()));
     }
+
+    Ok(reachable)
 }
 
-pub async fn health_check_loop(
-    client: Client<HttpsConnector<HttpConnector>, Body>,
-    router_state: Arc<ArcSwap<RouterState>>,
+/// Runs one round of health checks against every backend in `current_state` and updates
+/// their status. During `in_warmup`, failures are logged and recorded as `last_error` but
+/// don't count toward `consecutive_failures_threshold`, so slow-starting backends aren't
+/// flapped out of rotation right after startup.
+pub async fn run_health_checks(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    current_state: &RouterState,
+    in_warmup: bool,
 ) {
-    loop {
-        // Load the current state for this iteration
-        let current_state = router_state.load();
+    run_staggered_health_checks(client, current_state, in_warmup, Duration::ZERO).await
+}
 
-        let health_config = &current_state.health_check_config;
-        let health_state = &current_state.health_state;
-        let check_interval = Duration::from_secs(health_config.interval_secs);
-
-        // Run all health checks concurrently so one slow backend doesn't block others
-        let check_futures: Vec<_> = current_state
-            .backends
-            .iter()
-            .map(|backend| {
-                let client = client.clone();
-                let config = backend.config.clone();
-                let hc = health_config.clone();
-                async move {
-                    let result = perform_health_check(&client, &config, &hc).await;
-                    (config.label.clone(), result)
+/// Same as `run_health_checks`, but starts the probes spread evenly over `spread`
+/// rather than all at once: of `n` backends, the `i`-th waits `spread * i / n` before
+/// probing. Statuses are updated once every probe has finished.
+pub async fn run_staggered_health_checks(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    current_state: &RouterState,
+    in_warmup: bool,
+    spread: Duration,
+) {
+    let health_config = &current_state.health_check_config;
+    let health_state = &current_state.health_state;
+    let backend_count = current_state.backends.len().max(1) as u32;
+
+    // Run health checks concurrently so one slow backend doesn't block others, but at
+    // most `concurrency` at a time so large fleets don't open a connection to every
+    // backend at once
+    let permits = Semaphore::new(health_config.concurrency);
+    let check_futures: Vec<_> = current_state
+        .backends
+        .iter()
+        .enumerate()
+        .map(|(i, backend)| {
+            let start_after = spread * i as u32 / backend_count;
+            let client = backend.health_client().unwrap_or(client).clone();
+            let config = backend.config.clone();
+            let hc = health_config.clone();
+            let failure_status_codes = &current_state.proxy_config.failure_status_codes;
+            let permits = &permits;
+            async move {
+                sleep(start_after).await;
+                let _permit = permits.acquire().await;
+                let mut result =
+                    perform_health_check(&client, &config, &hc, failure_status_codes).await;
+                if let (Ok(_), Some(check)) = (&result, &hc.secondary_check) {
+                    if let Err(e) =
+                        perform_secondary_check(&client, &config, check, hc.timeout_secs).await
+                    {
+                        result = Err(e);
+                    }
                 }
-            })
-            .collect();
-
-        let results = future::join_all(check_futures).await;
-
-        // Collect slot numbers from successful checks to determine the max (consensus tip)
-        let max_slot: Option<u64> = results
-            .iter()
-            .filter_map(|(_, result)| match result {
-                Ok(Some(slot)) => Some(*slot),
-                _ => None,
-            })
-            .max();
-
-        for (i, (label, check_result)) in results.into_iter().enumerate() {
-            let backend = &current_state.backends[i];
-
-            // Get current status from the detailed state
-            let mut current_status = health_state
-                .get_status(&label)
-                .unwrap_or_default();
-
-            let previous_healthy = current_status.healthy;
-
-            match check_result {
-                Ok(slot_opt) => {
-                    // Check for slot lag against consensus
-                    let lagging = match (slot_opt, max_slot) {
-                        (Some(slot), Some(max)) if max > slot && (max - slot) > health_config.max_slot_lag => {
-                            true
-                        }
-                        _ => false,
-                    };
-
-                    if lagging {
-                        let slot = slot_opt.unwrap();
-                        let max = max_slot.unwrap();
+                (config.label.clone(), result)
+            }
+        })
+        .collect();
+
+    let results = future::join_all(check_futures).await;
+
+    // Collect slot numbers from successful checks to determine the max (consensus tip)
+    let max_slot: Option<u64> = results
+        .iter()
+        .filter_map(|(_, result)| match result {
+            Ok(Some(slot)) => Some(*slot),
+            _ => None,
+        })
+        .max();
+    if let Some(slot) = max_slot {
+        health_state.record_cluster_slot(slot);
+    }
+
+    let repeat_log_interval = Duration::from_secs(health_config.repeated_error_log_interval_secs);
+    for (i, (label, check_result)) in results.into_iter().enumerate() {
+        let backend = &current_state.backends[i];
+
+        // Get current status from the detailed state
+        let mut current_status = health_state.get_status(&label).unwrap_or_default();
+
+        let previous_healthy = current_status.healthy;
+
+        match check_result {
+            Ok(slot_opt) => {
+                // Check for slot lag against consensus
+                let lagging = match (slot_opt, max_slot) {
+                    (Some(slot), Some(max))
+                        if max > slot && (max - slot) > health_config.max_slot_lag =>
+                    {
+                        Some((slot, max))
+                    }
+                    _ => None,
+                };
+
+                if let Some((slot, max)) = lagging {
+                    if !in_warmup {
                         current_status.consecutive_failures += 1;
-                        current_status.consecutive_successes = 0;
-                        current_status.last_error = Some(format!(
-                            "Backend lagging: slot {} is {} behind max {}",
-                            slot,
-                            max - slot,
-                            max
-                        ));
+                    }
+                    current_status.consecutive_successes = 0;
+                    current_status.last_error = Some(format!(
+                        "Backend lagging: slot {} is {} behind max {}",
+                        slot,
+                        max - slot,
+                        max
+                    ));
 
-                        if current_status.consecutive_failures
-                            >= health_config.consecutive_failures_threshold
-                        {
-                            current_status.healthy = false;
-                        }
+                    if current_status.consecutive_failures
+                        >= health_config.consecutive_failures_threshold
+                    {
+                        current_status.healthy = false;
+                    }
 
+                    // The lag changes every round, so any lag counts as the same failure
+                    if let Some(suppressed) = health_state.should_log_failure(
+                        &label,
+                        "lagging",
+                        previous_healthy != current_status.healthy,
+                        repeat_log_interval,
+                    ) {
                         tracing::warn!(
-                            "Backend {} is lagging: slot {} is {} behind consensus max {} (threshold: {})",
+                            "Backend {} is lagging: slot {} is {} behind consensus max {} (threshold: {}){}{}",
                             label,
                             slot,
                             max - slot,
                             max,
-                            health_config.max_slot_lag
+                            health_config.max_slot_lag,
+                            if in_warmup { " [warmup, not counted]" } else { "" },
+                            repeats_note(suppressed)
                         );
                     } else {
-                        current_status.consecutive_successes += 1;
-                        current_status.consecutive_failures = 0;
-                        current_status.last_error = None;
-
-                        // Mark healthy if threshold reached
-                        if current_status.consecutive_successes
-                            >= health_config.consecutive_successes_threshold
-                        {
-                            current_status.healthy = true;
-                        }
-
                         tracing::debug!(
-                            "Health check succeeded for backend {} (consecutive successes: {})",
+                            "Backend {} is still lagging ({} behind)",
                             label,
-                            current_status.consecutive_successes
+                            max - slot
                         );
                     }
-                }
-                Err(error) => {
-                    current_status.consecutive_failures += 1;
-                    current_status.consecutive_successes = 0;
-                    current_status.last_error = Some(error.clone());
-
-                    // Mark unhealthy if threshold reached
-                    if current_status.consecutive_failures
-                        >= health_config.consecutive_failures_threshold
+                } else {
+                    current_status.consecutive_successes += 1;
+                    current_status.consecutive_failures = 0;
+                    current_status.last_error = None;
+                    current_status.last_success_time = Some(SystemTime::now());
+                    health_state.clear_logged_failure(&label);
+
+                    // Mark healthy if threshold reached
+                    if current_status.consecutive_successes
+                        >= health_config.consecutive_successes_threshold
                     {
-                        current_status.healthy = false;
+                        current_status.healthy = true;
                     }
 
+                    tracing::debug!(
+                        "Health check succeeded for backend {} (consecutive successes: {})",
+                        label,
+                        current_status.consecutive_successes
+                    );
+                }
+            }
+            Err(error) => {
+                if !in_warmup {
+                    current_status.consecutive_failures += 1;
+                }
+                current_status.consecutive_successes = 0;
+                current_status.last_error = Some(error.to_string());
+
+                // Mark unhealthy if threshold reached
+                if current_status.consecutive_failures
+                    >= health_config.consecutive_failures_threshold
+                {
+                    current_status.healthy = false;
+                }
+
+                if let Some(suppressed) = health_state.should_log_failure(
+                    &label,
+                    &error.to_string(),
+                    previous_healthy != current_status.healthy,
+                    repeat_log_interval,
+                ) {
                     tracing::warn!(
-                        "Health check failed for backend {} (consecutive failures: {}): {}",
+                        "Health check failed for backend {} (consecutive failures: {}){}: {}{}",
+                        label,
+                        current_status.consecutive_failures,
+                        if in_warmup {
+                            " [warmup, not counted]"
+                        } else {
+                            ""
+                        },
+                        error,
+                        repeats_note(suppressed)
+                    );
+                } else {
+                    tracing::debug!(
+                        "Health check failed again for backend {} (consecutive failures: {}): {}",
                         label,
                         current_status.consecutive_failures,
                         error
                     );
                 }
             }
+        }
 
-            current_status.last_check_time = Some(SystemTime::now());
+        current_status.last_check_time = Some(SystemTime::now());
+
+        // Log state transitions
+        if previous_healthy && !current_status.healthy {
+            tracing::warn!(
+                "Backend {} marked as UNHEALTHY after {} consecutive failures",
+                label,
+                current_status.consecutive_failures
+            );
+        } else if !previous_healthy && current_status.healthy {
+            tracing::info!(
+                "Backend {} marked as HEALTHY after {} consecutive successes",
+                label,
+                current_status.consecutive_successes
+            );
+        }
 
-            // Log state transitions
-            if previous_healthy && !current_status.healthy {
-                tracing::warn!(
-                    "Backend {} marked as UNHEALTHY after {} consecutive failures",
-                    label,
-                    current_status.consecutive_failures
-                );
-            } else if !previous_healthy && current_status.healthy {
-                tracing::info!(
-                    "Backend {} marked as HEALTHY after {} consecutive successes",
-                    label,
-                    current_status.consecutive_successes
+        if let Some(url) = &health_config.webhook_url {
+            let min_interval = Duration::from_secs(health_config.webhook_min_interval_secs);
+            let transitioned = previous_healthy != current_status.healthy;
+            if health_state.should_notify(
+                &label,
+                current_status.healthy,
+                transitioned,
+                min_interval,
+            ) {
+                notify_webhook(
+                    client.clone(),
+                    url.clone(),
+                    HealthNotification::new(&label, &current_status),
+                    Duration::from_secs(health_config.timeout_secs),
                 );
             }
+        }
+
+        // Update metrics
+        gauge!("rpc_backend_health", "backend" => label.clone()).set(if current_status.healthy {
+            1.0
+        } else {
+            0.0
+        });
+
+        // Update detailed state (locked)
+        health_state.update_status(&label, current_status.clone());
+
+        // Update atomic boolean (lock-free)
+        backend
+            .healthy
+            .store(current_status.healthy, Ordering::Relaxed);
+    }
 
-            // Update metrics
-            gauge!("rpc_backend_health", "backend" => label.clone())
-                .set(if current_status.healthy { 1.0 } else { 0.0 });
+    report_outage(current_state);
+}
+
+/// Suffix for a failure log line counting the identical failures not logged before it.
+fn repeats_note(suppressed: u32) -> String {
+    match suppressed {
+        0 => String::new(),
+        n => format!(" ({} identical failures since last logged)", n),
+    }
+}
+
+/// Sets `rpc_all_backends_unhealthy` after a round, and logs once when the last regular
+/// backend goes unhealthy and once when one recovers, so a provider-wide outage pages
+/// as a single event rather than per backend. The fallback backend doesn't count.
+fn report_outage(current_state: &RouterState) {
+    let regular = || current_state.backends.iter().filter(|b| !b.is_fallback());
+    let total = regular().count();
+    let healthy = regular()
+        .filter(|b| b.healthy.load(Ordering::Relaxed))
+        .count();
+    let all_unhealthy = total > 0 && healthy == 0;
+    gauge!("rpc_all_backends_unhealthy").set(if all_unhealthy { 1.0 } else { 0.0 });
+
+    let was_all_unhealthy = current_state.health_state.set_all_unhealthy(all_unhealthy);
+    if all_unhealthy && !was_all_unhealthy {
+        tracing::error!(
+            "All {} backends are unhealthy; requests fail until one recovers",
+            total
+        );
+    } else if !all_unhealthy && was_all_unhealthy {
+        tracing::info!(
+            "{}/{} backends healthy again after all were down",
+            healthy,
+            total
+        );
+    }
+}
+
+/// JSON body POSTed to `health_check.webhook_url` when a backend changes state.
+#[derive(Debug, Serialize)]
+pub struct HealthNotification {
+    pub backend: String,
+    pub healthy: bool,
+    /// Consecutive failures when turning unhealthy, successes when turning healthy
+    pub consecutive: u32,
+    pub last_error: Option<String>,
+    /// Unix time of the check, in seconds
+    pub timestamp: u64,
+}
 
-            // Update detailed state (locked)
-            health_state.update_status(&label, current_status.clone());
+impl HealthNotification {
+    fn new(label: &str, status: &BackendHealthStatus) -> Self {
+        Self {
+            backend: label.to_string(),
+            healthy: status.healthy,
+            consecutive: if status.healthy {
+                status.consecutive_successes
+            } else {
+                status.consecutive_failures
+            },
+            last_error: status.last_error.clone(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+}
 
-            // Update atomic boolean (lock-free)
-            backend
-                .healthy
-                .store(current_status.healthy, Ordering::Relaxed);
+/// Sends `notification` in the background; a slow or failing webhook is logged and
+/// never holds up the health check round.
+fn notify_webhook(
+    client: HttpClient,
+    url: String,
+    notification: HealthNotification,
+    request_timeout: Duration,
+) {
+    tokio::spawn(async move {
+        let body = match serde_json::to_vec(&notification) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to serialize health notification: {}", e);
+                return;
+            }
+        };
+        let req = match Request::builder()
+            .method("POST")
+            .uri(&url)
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+        {
+            Ok(req) => req,
+            Err(e) => {
+                tracing::warn!("Invalid health webhook URL {}: {}", url, e);
+                return;
+            }
+        };
+        match timeout(request_timeout, client.request(req)).await {
+            Ok(Ok(resp)) if resp.status().is_success() => tracing::info!(
+                "Notified health webhook: backend {} is {}",
+                notification.backend,
+                if notification.healthy {
+                    "healthy"
+                } else {
+                    "unhealthy"
+                }
+            ),
+            Ok(Ok(resp)) => tracing::warn!("Health webhook returned status {}", resp.status()),
+            Ok(Err(e)) => tracing::warn!("Health webhook request failed: {}", e),
+            Err(_) => tracing::warn!("Health webhook timed out after {:?}", request_timeout),
         }
+    });
+}
+
+pub async fn health_check_loop(
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+    router_state: Arc<ArcSwap<RouterState>>,
+) {
+    let started = Instant::now();
+    let mut first_round = true;
+
+    loop {
+        // Load the current state for this iteration
+        let current_state = router_state.load();
+        let check_interval = Duration::from_secs(current_state.health_check_config.interval_secs);
+        let in_warmup =
+            started.elapsed() < Duration::from_secs(current_state.health_check_config.warmup_secs);
+
+        // Every backend is due at startup; spreading that first round avoids a burst of
+        // connections to providers shared by several backends
+        let spread = if first_round && current_state.health_check_config.stagger_first_check {
+            check_interval
+        } else {
+            Duration::ZERO
+        };
+        first_round = false;
+        run_staggered_health_checks(&client, &current_state, in_warmup, spread).await;
 
         // Release the guard before sleeping so we don't hold old state in memory if it gets swapped
         drop(current_state);
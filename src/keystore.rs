@@ -1,41 +1,147 @@
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use metrics::counter;
 use moka::future::Cache;
 use redis::{aio::ConnectionManager, Client};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::KeyStoreError;
 
 #[derive(Clone, Debug)]
 pub struct KeyInfo {
     pub owner: String,
     pub rate_limit: u64,
+    /// Inbound WebSocket messages per second per connection, overriding
+    /// `proxy.ws_message_rate` (0 = unlimited)
+    pub ws_message_rate: Option<u64>,
+    /// Requests the owner may have in flight at once, overriding
+    /// `proxy.max_concurrent_requests_per_owner` (0 = unlimited)
+    pub max_concurrent_requests: Option<u64>,
+}
+
+impl KeyInfo {
+    /// Owner recorded for keys accepted without validation.
+    pub const UNVERIFIED_OWNER: &'static str = "unverified";
+
+    /// Stand-in for a key that couldn't be validated because the keystore failed;
+    /// it has no rate limit, since the limit is unknown.
+    pub fn unverified() -> Self {
+        Self {
+            owner: Self::UNVERIFIED_OWNER.to_string(),
+            rate_limit: 0,
+            ws_message_rate: None,
+            max_concurrent_requests: None,
+        }
+    }
 }
 
 #[async_trait]
 pub trait KeyStore: Send + Sync {
-    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, String>;
+    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError>;
+
+    /// Hit and miss counts of the store's local key cache, for stores that have one.
+    fn cache_stats(&self) -> Option<KeyCacheStats> {
+        None
+    }
+}
+
+/// Local cache of key lookups (including keys found invalid) in front of a remote
+/// store. Every lookup counts as a hit or a miss, both in `rpc_keystore_cache_*_total`
+/// and in `stats()`.
+pub struct KeyCache {
+    cache: Cache<String, Option<KeyInfo>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Lookups a `KeyCache` answered, and the share of them it answered from memory.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct KeyCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// `None` until the first lookup
+    pub hit_ratio: Option<f64>,
 }
 
+impl KeyCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            cache: Cache::builder().time_to_live(ttl).build(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached lookup for `key`: `Some(None)` for a key cached as invalid, `None`
+    /// on a miss.
+    pub async fn get(&self, key: &str) -> Option<Option<KeyInfo>> {
+        let cached = self.cache.get(key).await;
+        if cached.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            counter!("rpc_keystore_cache_hits_total").increment(1);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            counter!("rpc_keystore_cache_misses_total").increment(1);
+        }
+        cached
+    }
+
+    pub async fn insert(&self, key: &str, info: Option<KeyInfo>) {
+        self.cache.insert(key.to_string(), info).await;
+    }
+
+    pub fn stats(&self) -> KeyCacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        KeyCacheStats {
+            hits,
+            misses,
+            hit_ratio: (lookups > 0).then(|| hits as f64 / lookups as f64),
+        }
+    }
+}
+
+/// Fields of an `api_key:{key}` hash, in the order `get_key_info` reads them.
+const KEY_FIELDS: [&str; 5] = [
+    "owner",
+    "active",
+    "rate_limit",
+    "ws_message_rate",
+    "max_concurrent_requests",
+];
+
+/// Values of `KEY_FIELDS`, each nil when unset. `ws_message_rate` is read as a
+/// string so a malformed value can't fail the whole lookup.
+type KeyFields = (
+    Option<String>,
+    Option<String>,
+    Option<u64>,
+    Option<String>,
+    Option<u64>,
+);
+
 pub struct RedisKeyStore {
     conn: ConnectionManager,
-    cache: Cache<String, Option<KeyInfo>>,
+    cache: KeyCache,
 }
 
 impl RedisKeyStore {
-    pub async fn new(redis_url: &str) -> Result<Self, String> {
-        let client = Client::open(redis_url).map_err(|e| e.to_string())?;
-        let conn = client
-            .get_connection_manager()
-            .await
-            .map_err(|e| e.to_string())?;
+    pub async fn new(redis_url: &str) -> Result<Self, KeyStoreError> {
+        let client = Client::open(redis_url)?;
+        let conn = client.get_connection_manager().await?;
 
-        let cache = Cache::builder()
-            .time_to_live(Duration::from_secs(60)) // Cache keys for 1 min
-            .build();
+        let cache = KeyCache::new(Duration::from_secs(60)); // Cache keys for 1 min
 
         Ok(Self { conn, cache })
     }
 
-    async fn get_key_info(&self, key: &str) -> Result<Option<KeyInfo>, String> {
+    async fn get_key_info(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
         // Check local cache
         if let Some(info) = self.cache.get(key).await {
             return Ok(info);
@@ -45,97 +151,103 @@ impl RedisKeyStore {
         let mut conn = self.conn.clone();
 
         let redis_key = format!("api_key:{}", key);
-        // Check if exists first to avoid errors on empty keys
-        let exists: bool = redis::cmd("EXISTS")
-            .arg(&redis_key)
-            .query_async(&mut conn)
-            .await
-            .map_err(|e| e.to_string())?;
-
-        if !exists {
-            self.cache.insert(key.to_string(), None).await;
+        // One round trip for every field; a missing key reads as all nil
+        let (owner, active, rate_limit, ws_message_rate, max_concurrent_requests): KeyFields =
+            redis::cmd("HMGET")
+                .arg(&redis_key)
+                .arg(&KEY_FIELDS)
+                .query_async(&mut conn)
+                .await?;
+
+        // A hash without an owner isn't a key
+        let Some(owner) = owner.filter(|_| active.as_deref() != Some("false")) else {
+            self.cache.insert(key, None).await;
             return Ok(None);
-        }
+        };
+        let rate_limit = rate_limit
+            .ok_or_else(|| KeyStoreError::Unavailable("API key has no rate_limit".to_string()))?;
+
+        // An optional limit that doesn't parse is ignored rather than failing the key
+        let ws_message_rate = ws_message_rate.and_then(|rate| match rate.parse() {
+            Ok(rate) => Some(rate),
+            Err(_) => {
+                warn!(
+                    "API key owned by {} has non-numeric ws_message_rate '{}', ignoring it",
+                    owner, rate
+                );
+                None
+            }
+        });
 
-        let owner: String = redis::cmd("HGET")
-            .arg(&redis_key)
-            .arg("owner")
-            .query_async(&mut conn)
-            .await
-            .map_err(|e| e.to_string())?;
-        let active: String = redis::cmd("HGET")
-            .arg(&redis_key)
-            .arg("active")
-            .query_async(&mut conn)
-            .await
-            .unwrap_or("true".to_string());
-
-        if active == "false" {
-            self.cache.insert(key.to_string(), None).await;
-            return Ok(None);
-        }
+        let info = KeyInfo {
+            owner,
+            rate_limit,
+            ws_message_rate,
+            max_concurrent_requests,
+        };
+        self.cache.insert(key, Some(info.clone())).await;
 
-        let rate_limit: u64 = redis::cmd("HGET")
-            .arg(&redis_key)
-            .arg("rate_limit")
-            .query_async(&mut conn)
-            .await
-            .map_err(|e| e.to_string())?;
+        Ok(Some(info))
+    }
+}
 
-        let info = KeyInfo { owner, rate_limit };
-        self.cache.insert(key.to_string(), Some(info.clone())).await;
+#[async_trait]
+impl KeyStore for RedisKeyStore {
+    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
+        // Cache -> Redis; rate limits are enforced by the `RateLimiter`
+        self.get_key_info(key).await
+    }
 
-        Ok(Some(info))
+    fn cache_stats(&self) -> Option<KeyCacheStats> {
+        Some(self.cache.stats())
     }
+}
 
-    async fn check_rate_limit(&self, key: &str, limit: u64) -> Result<bool, String> {
-        if limit == 0 {
-            return Ok(true); // No limit
-        }
+/// Keeps the last successful validation of each key for `ttl` (much longer than the
+/// keystore's own cache) and answers from it when the wrapped keystore errors, so
+/// keys seen before an outage keep working through it. Keys without a last-known-good
+/// entry still get the error; keys the keystore reports invalid are forgotten.
+pub struct StaleKeyCache<S> {
+    inner: S,
+    stale: Cache<String, KeyInfo>,
+}
 
-        let mut conn = self.conn.clone();
-        let redis_key = format!("rate_limit:{}", key);
-
-        // Atomic INCR and Expire if needed
-        // Script to ensure atomicity: INCR key; IF == 1 THEN EXPIRE key 1; END; RETURN val
-        let script = redis::Script::new(
-            r#"
-            local count = redis.call("INCR", KEYS[1])
-            if count == 1 then
-                redis.call("EXPIRE", KEYS[1], 1)
-            end
-            return count
-        "#,
-        );
-
-        let count: u64 = script
-            .key(&redis_key)
-            .invoke_async(&mut conn)
-            .await
-            .map_err(|e| e.to_string())?;
-
-        if count > limit {
-            return Ok(false);
+impl<S: KeyStore> StaleKeyCache<S> {
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        Self {
+            inner,
+            stale: Cache::builder()
+                .time_to_live(ttl)
+                .max_capacity(100_000)
+                .build(),
         }
-
-        Ok(true)
     }
 }
 
 #[async_trait]
-impl KeyStore for RedisKeyStore {
-    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, String> {
-        // 1. Get Key Info (Cache -> Redis)
-        let info_opt = self.get_key_info(key).await?;
-
-        if let Some(info) = info_opt {
-            // 2. Check Rate Limit
-            if !self.check_rate_limit(key, info.rate_limit).await? {
-                return Err("Rate limit exceeded".to_string());
+impl<S: KeyStore> KeyStore for StaleKeyCache<S> {
+    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
+        match self.inner.validate_key(key).await {
+            Ok(Some(info)) => {
+                self.stale.insert(key.to_string(), info.clone()).await;
+                Ok(Some(info))
             }
-            return Ok(Some(info));
+            Ok(None) => {
+                self.stale.invalidate(key).await;
+                Ok(None)
+            }
+            Err(KeyStoreError::RateLimited) => Err(KeyStoreError::RateLimited),
+            Err(e) => match self.stale.get(key).await {
+                Some(info) => {
+                    counter!("rpc_keystore_stale_validations_total").increment(1);
+                    Ok(Some(info))
+                }
+                None => Err(e),
+            },
         }
+    }
 
-        Ok(None)
+    fn cache_stats(&self) -> Option<KeyCacheStats> {
+        self.inner.cache_stats()
     }
 }
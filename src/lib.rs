@@ -1,6 +1,28 @@
+pub mod admin;
+pub mod batch;
+pub mod cache;
+pub mod capture;
+pub mod commitment;
 pub mod config;
+pub mod connlimit;
+pub mod encoding;
+pub mod error;
 pub mod handlers;
 pub mod health;
 pub mod keystore;
 pub mod mock;
+pub mod net;
+pub mod probe;
+pub mod ratelimit;
+pub mod retry;
+pub mod routes;
+pub mod selector;
+pub mod server;
+pub mod shutdown;
 pub mod state;
+pub mod stats;
+pub mod subscriptions;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod upstream;
+pub mod warm;
@@ -1,25 +1,32 @@
-use std::{net::SocketAddr, sync::{atomic::AtomicBool, Arc}};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use arc_swap::ArcSwap;
-use axum::{
-    middleware,
-    routing::{get, post},
-    Router,
-};
+use axum::{routing::get, Router};
 use clap::Parser;
-use hyper_tls::HttpsConnector;
-use hyper_util::client::legacy::Client;
 use metrics_exporter_prometheus::PrometheusBuilder;
 use sol_rpc_router::{
-    config::load_config,
-    handlers::{extract_rpc_method, health_endpoint, log_requests, proxy, track_metrics, ws_proxy},
-    health::{health_check_loop, HealthState},
-    keystore::RedisKeyStore,
-    state::{AppState, RouterState, RuntimeBackend},
+    cache::ResponseCache,
+    capture::RequestCapture,
+    config::{load_config, RateLimiterKind, SelectionMode},
+    health::{health_check_client, health_check_loop, startup_probe, HealthState},
+    keystore::{KeyStore, RedisKeyStore, StaleKeyCache},
+    ratelimit::{InMemoryRateLimiter, RateLimiter, RedisRateLimiter},
+    retry::RetryThrottle,
+    routes::{admin_router, public_router, ws_router},
+    selector::{
+        BackendSelector, CheapestFirstSelector, ConsistentHashSelector, WeightedRandomSelector,
+    },
+    server::{bind_tcp, bind_unix, serve_tcp, serve_unix},
+    shutdown::{begin_drain, shutdown_requested, wait_for_termination},
+    state::{upstream_client, AppState, ClientOptions, RouterState, RuntimeBackend},
+    stats::MetricGroups,
+    warm,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
 };
-use tokio::signal::unix::{signal, SignalKind};
-use tower_http::cors::CorsLayer;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Parser, Debug)]
 #[command(name = "rpc-router")]
@@ -38,7 +45,9 @@ async fn main() {
     // Using set_buckets makes the exporter emit true Prometheus histograms (_bucket/_sum/_count)
     // instead of summaries, which is required for histogram_quantile() in Grafana.
     let builder = PrometheusBuilder::new()
-        .set_buckets(&[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0])
+        .set_buckets(&[
+            0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+        ])
         .expect("failed to set histogram buckets");
     let handle = builder
         .install_recorder()
@@ -63,37 +72,104 @@ async fn main() {
 
     if !config.method_routes.is_empty() {
         info!("Method routing overrides:");
-        for (method, label) in &config.method_routes {
-            info!("  - {} -> {}", method, label);
+        for (method, route) in &config.method_routes {
+            info!("  - {} -> {}", method, route);
         }
     }
 
+    // Bind every listener before the startup probe and Redis setup, so a port that is
+    // already taken is reported right away
+    let http_addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    let ws_port = config
+        .port
+        .checked_add(1)
+        .expect("WebSocket port overflow: HTTP port cannot be 65535");
+    let ws_addr = SocketAddr::from(([0, 0, 0, 0], ws_port));
+    let metrics_addr = SocketAddr::from(([0, 0, 0, 0], config.metrics_port));
+    let admin_addr = config
+        .admin_port
+        .map(|port| SocketAddr::from(([0, 0, 0, 0], port)));
+    let http_listener = bind_or_exit(http_addr, "HTTP").await;
+    let ws_listener = bind_or_exit(ws_addr, "WebSocket").await;
+    let metrics_listener = bind_or_exit(metrics_addr, "Metrics").await;
+    let admin_listener = match admin_addr {
+        Some(addr) => Some(bind_or_exit(addr, "Admin").await),
+        None => None,
+    };
+
+    // mTLS backends build their own clients with the same settings as the shared ones
+    let client_options = ClientOptions {
+        accept_invalid_certs: config.upstream.danger_accept_invalid_certs,
+        health_check: config.health_check.clone(),
+    };
+
     // Initialize runtime backends with atomic health status
-    let runtime_backends: Vec<RuntimeBackend> = config
+    let mut runtime_backends: Vec<RuntimeBackend> = config
         .backends
         .iter()
-        .map(|b| RuntimeBackend {
-            config: b.clone(),
-            healthy: Arc::new(AtomicBool::new(true)), // Default to healthy
+        .map(|b| {
+            let backend = RuntimeBackend::with_client_options(b.clone(), &client_options)
+                .expect("Invalid backend configuration");
+            if config.default_backend.as_ref() == Some(&b.label) {
+                info!("  - [{}] is the default backend", b.label);
+                backend.into_primary()
+            } else {
+                backend
+            }
         })
         .collect();
 
+    if let Some(fallback) = &config.fallback_backend {
+        info!("  - [{}] {} (fallback)", fallback.label, fallback.url);
+        runtime_backends.push(
+            RuntimeBackend::with_client_options(fallback.clone(), &client_options)
+                .expect("Invalid fallback backend configuration")
+                .into_fallback(),
+        );
+    }
+
     // Initialize health state
-    let backend_labels: Vec<String> = config.backends.iter().map(|b| b.label.clone()).collect();
+    let backend_labels: Vec<String> = runtime_backends
+        .iter()
+        .map(|b| b.config.label.clone())
+        .collect();
     let health_state = Arc::new(HealthState::new(backend_labels));
 
     let initial_router_state = RouterState {
         backends: runtime_backends,
         method_routes: config.method_routes,
         health_state: health_state.clone(),
-        proxy_timeout_secs: config.proxy.timeout_secs,
+        proxy_config: config.proxy.clone(),
         health_check_config: config.health_check.clone(),
     };
 
-    let router_state = Arc::new(ArcSwap::from_pointee(initial_router_state));
+    let accept_invalid_certs = client_options.accept_invalid_certs;
+    if accept_invalid_certs {
+        warn!("==========================================================================");
+        warn!("upstream.danger_accept_invalid_certs is ON: backend TLS certificates and");
+        warn!("host names are NOT verified. Development only - never run this in production.");
+        warn!("==========================================================================");
+    }
+    let client = upstream_client(accept_invalid_certs);
+    // Probes get their own client so their timeouts and pool are tuned separately
+    let health_client = health_check_client(&config.health_check, accept_invalid_certs);
+
+    // Optionally probe every backend once so config typos surface at deploy time
+    if config.startup_probe.enabled {
+        info!("Running startup probe against all backends");
+        if let Err(e) = startup_probe(
+            &health_client,
+            &initial_router_state,
+            config.startup_probe.require_reachable,
+        )
+        .await
+        {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
-    let https = HttpsConnector::new();
-    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = Arc::new(ArcSwap::from_pointee(initial_router_state));
 
     // Initialize Redis KeyStore
     let keystore = match RedisKeyStore::new(&config.redis_url).await {
@@ -103,79 +179,173 @@ async fn main() {
             std::process::exit(1);
         }
     };
+    let keystore: Arc<dyn KeyStore> = if config.key_stale_ttl_secs > 0 {
+        Arc::new(StaleKeyCache::new(
+            keystore,
+            Duration::from_secs(config.key_stale_ttl_secs),
+        ))
+    } else {
+        Arc::new(keystore)
+    };
 
-    let state = Arc::new(AppState {
-        client: client.clone(),
-        keystore: Arc::new(keystore),
-        state: router_state.clone(),
-    });
+    // Slot-scoped cache entries are evicted whenever the health check sees a new cluster slot
+    let cache = Arc::new(ResponseCache::new(config.cache.clone()));
+    tokio::spawn(
+        cache
+            .clone()
+            .follow_cluster_slot(health_state.subscribe_cluster_slot()),
+    );
+
+    let rate_limiter: Arc<dyn RateLimiter> = match config.rate_limiter {
+        RateLimiterKind::Redis => match RedisRateLimiter::new(&config.redis_url).await {
+            Ok(limiter) => Arc::new(limiter),
+            Err(e) => {
+                error!("Failed to initialize Redis rate limiter: {}", e);
+                std::process::exit(1);
+            }
+        },
+        RateLimiterKind::Memory => Arc::new(InMemoryRateLimiter::new()),
+    };
+
+    let selector: Arc<dyn BackendSelector> = match config.selection {
+        SelectionMode::Weighted => Arc::new(WeightedRandomSelector),
+        SelectionMode::ConsistentHash => Arc::new(ConsistentHashSelector),
+        SelectionMode::Cheapest => Arc::new(CheapestFirstSelector),
+    };
+    let mut state = AppState::new(client.clone(), keystore, router_state.clone())
+        .with_logging(config.logging.clone())
+        .with_transforms(config.transform.clone())
+        .with_admin_token(config.admin_token.clone())
+        .with_selector(selector)
+        .with_client_options(client_options.clone())
+        .with_rate_limiter(rate_limiter)
+        .with_keystore_failure(config.keystore_failure)
+        .with_cache(cache)
+        .with_metric_groups(MetricGroups::new(&config.metric_groups))
+        .with_request_capture(RequestCapture::new(
+            config.capture.max_entries,
+            config.capture.max_body_bytes,
+        ))
+        .with_retry_throttle(Arc::new(RetryThrottle::new(
+            config.proxy.retry_throttle_max_tokens,
+            config.proxy.retry_throttle_token_ratio,
+        )));
+    if config.upstream.max_connection_lifetime_secs > 0 {
+        state = state.with_max_connection_lifetime(Duration::from_secs(
+            config.upstream.max_connection_lifetime_secs,
+        ));
+    }
+    let state = Arc::new(state);
+
+    // Prime the response cache before the listeners start serving
+    warm::warm(&state).await;
 
     // Spawn background health check task
-    let health_check_client = client.clone();
     let health_check_state = router_state.clone();
 
     tokio::spawn(async move {
         info!("Starting health check loop");
         // Loop will read config from state each iteration
-        health_check_loop(
-            health_check_client,
-            health_check_state,
-        )
-        .await;
+        health_check_loop(health_client, health_check_state).await;
     });
 
     // Spawn SIGHUP handler for hot reload
     let reload_state = router_state.clone();
+    let reload_app = state.clone();
+    let reload_client_options = client_options.clone();
     let config_path = args.config.clone();
     // We keep the original health_state to preserve history across reloads if backends match
-    let persistent_health_state = health_state.clone(); 
+    let persistent_health_state = health_state.clone();
 
     tokio::spawn(async move {
         let mut sighup = signal(SignalKind::hangup()).expect("Failed to register SIGHUP handler");
-        
+
         loop {
             sighup.recv().await;
-            info!("Received SIGHUP, reloading configuration from {}", config_path);
+            info!(
+                "Received SIGHUP, reloading configuration from {}",
+                config_path
+            );
 
             match load_config(&config_path) {
                 Ok(new_config) => {
                     info!("Configuration reloaded successfully");
                     info!("New backend count: {}", new_config.backends.len());
-                    
+
                     // Re-initialize runtime backends
                     // We attempt to preserve health status if backend label matches
-                    let new_runtime_backends: Vec<RuntimeBackend> = new_config
+                    let current = reload_state.load_full();
+                    let new_runtime_backends: Result<Vec<RuntimeBackend>, String> = new_config
                         .backends
                         .iter()
-                        .map(|b| {
+                        .map(|b| (b, false))
+                        .chain(new_config.fallback_backend.iter().map(|b| (b, true)))
+                        .map(|(b, is_fallback)| {
                             // Check if we have existing status for this label
-                            let is_healthy = if let Some(status) = persistent_health_state.get_status(&b.label) {
+                            let is_healthy = if let Some(status) =
+                                persistent_health_state.get_status(&b.label)
+                            {
                                 status.healthy
                             } else {
                                 true // Default new backends to healthy
                             };
 
-                            RuntimeBackend {
-                                config: b.clone(),
-                                healthy: Arc::new(AtomicBool::new(is_healthy)),
+                            let mut backend = RuntimeBackend::with_health(
+                                b.clone(),
+                                is_healthy,
+                                &reload_client_options,
+                            )?;
+                            // Connections, requests, throttling, shares and costs from before the reload still count
+                            if let Some(old) = current
+                                .backends
+                                .iter()
+                                .find(|old| old.config.label == b.label)
+                            {
+                                backend.ws_connections = old.ws_connections.clone();
+                                backend.in_flight = old.in_flight.clone();
+                                backend.queued = old.queued.clone();
+                                backend.throttle = old.throttle.clone();
+                                backend.selections = old.selections.clone();
+                                backend.costs = old.costs.clone();
                             }
+                            Ok(if is_fallback {
+                                backend.into_fallback()
+                            } else if new_config.default_backend.as_ref() == Some(&b.label) {
+                                backend.into_primary()
+                            } else {
+                                backend
+                            })
                         })
                         .collect();
-                    
+                    let new_runtime_backends = match new_runtime_backends {
+                        Ok(backends) => backends,
+                        Err(e) => {
+                            error!("Failed to reload configuration: {}", e);
+                            continue;
+                        }
+                    };
+
                     // Update method routes info
                     if !new_config.method_routes.is_empty() {
-                         info!("Updated method routing overrides:");
-                         for (method, label) in &new_config.method_routes {
-                             info!("  - {} -> {}", method, label);
-                         }
+                        info!("Updated method routing overrides:");
+                        for (method, route) in &new_config.method_routes {
+                            info!("  - {} -> {}", method, route);
+                        }
                     }
 
+                    reload_app.logging.store(Arc::new(new_config.logging));
+                    reload_app.transforms.store(Arc::new(new_config.transform));
+                    reload_app.retry_throttle.resize(
+                        new_config.proxy.retry_throttle_max_tokens,
+                        new_config.proxy.retry_throttle_token_ratio,
+                    );
+
                     // Create new router state
                     let new_router_state = RouterState {
                         backends: new_runtime_backends,
                         method_routes: new_config.method_routes,
                         health_state: persistent_health_state.clone(), // Reuse the persistent health state container
-                        proxy_timeout_secs: new_config.proxy.timeout_secs,
+                        proxy_config: new_config.proxy,
                         health_check_config: new_config.health_check,
                     };
 
@@ -190,59 +360,71 @@ async fn main() {
         }
     });
 
-    // HTTP server (JSON-RPC over HTTP + WebSocket on same port)
-    let http_app = Router::new()
-        .route("/", get(ws_proxy).post(proxy))
-        .route("/*path", post(proxy))
-        .route("/health", get(health_endpoint))
-        .with_state(state.clone())
-        .layer(middleware::from_fn(track_metrics))
-        .layer(middleware::from_fn(log_requests))
-        .layer(middleware::from_fn(extract_rpc_method))
-        .layer(CorsLayer::permissive());
+    // SIGTERM: report not-ready for shutdown_delay_secs, then stop accepting connections
+    // and let in-flight requests finish
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let drain_state = state.clone();
+    let shutdown_delay = Duration::from_secs(config.shutdown_delay_secs);
+
+    tokio::spawn(async move {
+        wait_for_termination().await;
+        begin_drain(&drain_state, shutdown_delay).await;
+        info!("Draining in-flight requests");
+        let _ = shutdown_tx.send(true);
+    });
+
+    // HTTP server (JSON-RPC over HTTP + WebSocket on same port).
+    // Operational endpoints move to the admin listener when admin_port is configured.
+    let http_app = public_router(state.clone(), config.admin_port.is_none());
 
     // WebSocket server (following Solana convention: WS port = HTTP port + 1)
-    let ws_app = Router::new()
-        .route("/", get(ws_proxy))
-        .with_state(state)
-        .layer(middleware::from_fn(log_requests))
-        .layer(CorsLayer::permissive());
+    let ws_app = ws_router(state.clone());
 
     // Metrics server (dedicated port)
-    let metrics_app = Router::new()
-        .route("/metrics", get(move || std::future::ready(handle.render())));
+    let metrics_handle = handle.clone();
+    let metrics_app = Router::new().route(
+        "/metrics",
+        get(move || std::future::ready(metrics_handle.render())),
+    );
 
-    let http_addr = SocketAddr::from(([0, 0, 0, 0], config.port));
-    let ws_port = config
-        .port
-        .checked_add(1)
-        .expect("WebSocket port overflow: HTTP port cannot be 65535");
-    let ws_addr = SocketAddr::from(([0, 0, 0, 0], ws_port));
-    let metrics_addr = SocketAddr::from(([0, 0, 0, 0], config.metrics_port));
+    // Admin server (optional private port): health, version, and metrics
+    let admin_app =
+        admin_router(state).route("/metrics", get(move || std::future::ready(handle.render())));
 
     info!("HTTP server listening on http://{}", http_addr);
     info!("WebSocket server listening on ws://{}", ws_addr);
     info!("Metrics server listening on http://{}", metrics_addr);
-    info!("Health monitoring endpoint: http://{}/health", http_addr);
+    match admin_addr {
+        Some(addr) => {
+            info!("Admin server listening on http://{}", addr);
+            info!("Health monitoring endpoint: http://{}/health", addr);
+        }
+        None => info!("Health monitoring endpoint: http://{}/health", http_addr),
+    }
+
+    if let Some(path) = &config.listen_uds {
+        info!("HTTP server listening on unix:{}", path);
+    }
 
     // Start all servers concurrently
+    // Client-facing listeners apply the [listener] TCP_NODELAY and keep-alive settings
     let http_server = async {
-        axum::serve(
-            tokio::net::TcpListener::bind(http_addr)
-                .await
-                .expect("Failed to bind HTTP server"),
-            http_app.into_make_service_with_connect_info::<SocketAddr>(),
+        serve_tcp(
+            http_listener,
+            http_app.clone(),
+            config.listener.clone(),
+            shutdown_requested(shutdown_rx.clone()),
         )
         .await
         .expect("HTTP server error");
     };
 
     let ws_server = async {
-        axum::serve(
-            tokio::net::TcpListener::bind(ws_addr)
-                .await
-                .expect("Failed to bind WebSocket server"),
-            ws_app.into_make_service_with_connect_info::<SocketAddr>(),
+        serve_tcp(
+            ws_listener,
+            ws_app,
+            config.listener.clone(),
+            shutdown_requested(shutdown_rx.clone()),
         )
         .await
         .expect("WebSocket server error");
@@ -250,14 +432,57 @@ async fn main() {
 
     let metrics_server = async {
         axum::serve(
-            tokio::net::TcpListener::bind(metrics_addr)
-                .await
-                .expect("Failed to bind Metrics server"),
+            metrics_listener,
             metrics_app.into_make_service_with_connect_info::<SocketAddr>(),
         )
+        .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()))
         .await
         .expect("Metrics server error");
     };
 
-    tokio::join!(http_server, ws_server, metrics_server);
+    let admin_server = async {
+        if let Some(listener) = admin_listener {
+            axum::serve(
+                listener,
+                admin_app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()))
+            .await
+            .expect("Admin server error");
+        }
+    };
+
+    // Unix domain socket (optional): same routes as the HTTP port, for sidecar clients
+    let uds_server = async {
+        if let Some(path) = &config.listen_uds {
+            let listener = bind_unix(path).expect("Failed to bind Unix domain socket");
+            serve_unix(
+                listener,
+                http_app.clone(),
+                shutdown_requested(shutdown_rx.clone()),
+            )
+            .await
+            .expect("Unix socket server error");
+        }
+    };
+
+    tokio::join!(
+        http_server,
+        ws_server,
+        metrics_server,
+        admin_server,
+        uds_server
+    );
+    info!("All listeners drained, exiting");
+}
+
+/// Binds a TCP listener, exiting with the reason instead of panicking when it fails.
+async fn bind_or_exit(addr: SocketAddr, name: &str) -> tokio::net::TcpListener {
+    match bind_tcp(addr, name).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
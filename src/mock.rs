@@ -5,7 +5,10 @@ use std::{
 
 use async_trait::async_trait;
 
-use crate::keystore::{KeyInfo, KeyStore};
+use crate::{
+    error::KeyStoreError,
+    keystore::{KeyInfo, KeyStore},
+};
 
 #[derive(Clone)]
 pub struct MockKeyStore {
@@ -39,10 +42,26 @@ impl MockKeyStore {
             KeyInfo {
                 owner: owner.to_string(),
                 rate_limit,
+                ws_message_rate: None,
+                max_concurrent_requests: None,
             },
         );
     }
 
+    /// Overrides the WebSocket message rate of an added key.
+    pub fn set_ws_message_rate(&self, key: &str, rate: u64) {
+        if let Some(info) = self.keys.lock().unwrap().get_mut(key) {
+            info.ws_message_rate = Some(rate);
+        }
+    }
+
+    /// Overrides the concurrent request limit of an added key.
+    pub fn set_max_concurrent_requests(&self, key: &str, limit: u64) {
+        if let Some(info) = self.keys.lock().unwrap().get_mut(key) {
+            info.max_concurrent_requests = Some(limit);
+        }
+    }
+
     pub fn set_inactive(&self, key: &str) {
         self.inactive_keys.lock().unwrap().push(key.to_string());
     }
@@ -61,14 +80,14 @@ impl MockKeyStore {
 
 #[async_trait]
 impl KeyStore for MockKeyStore {
-    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, String> {
+    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
         let mut counts = self.call_counts.lock().unwrap();
         *counts.entry(key.to_string()).or_insert(0) += 1;
         drop(counts);
 
         // Check for custom errors first
         if let Some(msg) = self.error_keys.lock().unwrap().get(key) {
-            return Err(msg.clone());
+            return Err(KeyStoreError::Unavailable(msg.clone()));
         }
 
         if self
@@ -87,7 +106,7 @@ impl KeyStore for MockKeyStore {
                 .unwrap()
                 .contains(&key.to_string())
             {
-                return Err("Rate limit exceeded".to_string());
+                return Err(KeyStoreError::RateLimited);
             }
 
             return Ok(Some(info.clone()));
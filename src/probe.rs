@@ -0,0 +1,230 @@
+//! Routing details probed from JSON-RPC request bodies: incremental extraction of
+//! `method` and `id` from the start of a body, so it doesn't have to be buffered in
+//! full, and the `commitment` level named in the params.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// What was found in the request body.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Probe {
+    pub method: Option<String>,
+    pub id: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scan {
+    /// The prefix ends before `method` and `id` could be found; read more of the body
+    Incomplete,
+    /// Scanning is finished. A body that isn't a single JSON-RPC object (a batch, or
+    /// not JSON) gives an empty probe.
+    Done(Probe),
+}
+
+/// Scans a prefix of a request body for the top-level `method` and `id` members.
+/// Stops as soon as both are seen, so the rest of the body (usually `params`) is never
+/// looked at when they come first. Call again with a longer prefix on `Incomplete`.
+pub fn scan(prefix: &[u8]) -> Scan {
+    let mut scanner = Scanner {
+        buf: prefix,
+        pos: 0,
+    };
+    let mut probe = Probe::default();
+    match scanner.members(&mut probe) {
+        Ok(()) => Scan::Done(probe),
+        Err(Stop::Incomplete) => Scan::Incomplete,
+        Err(Stop::Invalid) => Scan::Done(Probe::default()),
+    }
+}
+
+enum Stop {
+    Incomplete,
+    Invalid,
+}
+
+type Step<T> = Result<T, Stop>;
+
+struct Scanner<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    /// Next non-whitespace byte, without consuming it.
+    fn peek(&mut self) -> Step<u8> {
+        while let Some(b) = self.buf.get(self.pos) {
+            if !b.is_ascii_whitespace() {
+                return Ok(*b);
+            }
+            self.pos += 1;
+        }
+        Err(Stop::Incomplete)
+    }
+
+    fn expect(&mut self, byte: u8) -> Step<()> {
+        if self.peek()? != byte {
+            return Err(Stop::Invalid);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Index just past the string starting with the quote at `start`.
+    fn string_end(&self, start: usize) -> Step<usize> {
+        let mut i = start + 1;
+        while let Some(b) = self.buf.get(i) {
+            match b {
+                b'\\' => i += 2,
+                b'"' => return Ok(i + 1),
+                _ => i += 1,
+            }
+        }
+        Err(Stop::Incomplete)
+    }
+
+    /// Consumes one complete value and returns its raw bytes.
+    fn value(&mut self) -> Step<&'a [u8]> {
+        let start = self.pos;
+        let end = match self.peek()? {
+            b'"' => self.string_end(start)?,
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                let mut i = start;
+                loop {
+                    match self.buf.get(i) {
+                        None => return Err(Stop::Incomplete),
+                        Some(b'"') => {
+                            i = self.string_end(i)?;
+                            continue;
+                        }
+                        Some(b'{' | b'[') => depth += 1,
+                        Some(b'}' | b']') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break i + 1;
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                    i += 1;
+                }
+            }
+            _ => {
+                // Numbers and literals end at a delimiter, which must be in the prefix
+                // to know the value is complete
+                let len = self.buf[start..]
+                    .iter()
+                    .position(|b| matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace())
+                    .ok_or(Stop::Incomplete)?;
+                if len == 0 {
+                    return Err(Stop::Invalid);
+                }
+                start + len
+            }
+        };
+        self.pos = end;
+        Ok(&self.buf[start..end])
+    }
+
+    /// Consumes an object member's key and the following colon.
+    fn key(&mut self) -> Step<String> {
+        if self.peek()? != b'"' {
+            return Err(Stop::Invalid);
+        }
+        let key = parse(self.value()?)?;
+        self.expect(b':')?;
+        Ok(key)
+    }
+
+    /// Consumes the separator after a member. Returns false at the end of the object.
+    fn next_member(&mut self) -> Step<bool> {
+        match self.peek()? {
+            b',' => {
+                self.pos += 1;
+                Ok(true)
+            }
+            b'}' => Ok(false),
+            _ => Err(Stop::Invalid),
+        }
+    }
+
+    /// The first of `keys` among the top-level members, without reading its value.
+    fn first_of(&mut self, keys: &[&str]) -> Step<Option<String>> {
+        self.expect(b'{')?;
+        if self.peek()? == b'}' {
+            return Ok(None);
+        }
+        loop {
+            let key = self.key()?;
+            if keys.contains(&key.as_str()) {
+                return Ok(Some(key));
+            }
+            self.value()?;
+            if !self.next_member()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn members(&mut self, probe: &mut Probe) -> Step<()> {
+        self.expect(b'{')?;
+        if self.peek()? == b'}' {
+            return Ok(());
+        }
+        let (mut seen_method, mut seen_id) = (false, false);
+        loop {
+            let key = self.key()?;
+            let value = self.value()?;
+            match key.as_str() {
+                "method" => {
+                    probe.method = parse(value)?;
+                    seen_method = true;
+                }
+                "id" => {
+                    probe.id = parse(value)?;
+                    seen_id = true;
+                }
+                _ => {}
+            }
+            if (seen_method && seen_id) || !self.next_member()? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Whether a prefix of a JSON-RPC response body is an error response, i.e. its
+/// top-level `error` member comes before any `result`. Batches, and prefixes that end
+/// before either member, don't count as errors.
+pub fn is_error_response(prefix: &[u8]) -> bool {
+    let mut scanner = Scanner {
+        buf: prefix,
+        pos: 0,
+    };
+    matches!(scanner.first_of(&["result", "error"]), Ok(Some(key)) if key == "error")
+}
+
+/// The `commitment` of a single request whose last param is a config object, as in
+/// `{"params": [..., {"commitment": "finalized"}]}`.
+pub fn commitment(request: &Value) -> Option<String> {
+    request
+        .get("params")?
+        .as_array()?
+        .last()?
+        .get("commitment")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// The first param of a single request, as a string: a string param as is, anything
+/// else as its JSON text. `None` for requests without params.
+pub fn first_param(request: &Value) -> Option<String> {
+    match request.get("params")?.as_array()?.first()? {
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn parse<T: DeserializeOwned>(raw: &[u8]) -> Step<T> {
+    serde_json::from_slice(raw).map_err(|_| Stop::Invalid)
+}
@@ -0,0 +1,281 @@
+//! Rate limiting: per-key request limiters behind the `RateLimiter` trait, the
+//! per-owner cap on concurrent requests, and the token bucket used to cap the inbound
+//! message rate of a single WebSocket connection.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, Client};
+
+use crate::error::RateLimitError;
+
+/// Outcome of a rate limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// The key has used up its limit for the current window
+    Limited,
+}
+
+/// Counts requests per API key against the key's `rate_limit`, independently of how
+/// keys are validated. Limits are per one-second window; a `limit` of 0 is unlimited.
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    /// Records `cost` requests for `key` and decides whether they fit under `limit`.
+    async fn check(&self, key: &str, limit: u64, cost: u64) -> Result<Decision, RateLimitError>;
+}
+
+/// Fixed one-second windows counted in Redis, shared by every router instance.
+pub struct RedisRateLimiter {
+    conn: ConnectionManager,
+}
+
+impl RedisRateLimiter {
+    pub async fn new(redis_url: &str) -> Result<Self, RateLimitError> {
+        let client = Client::open(redis_url)?;
+        let conn = client.get_connection_manager().await?;
+        Ok(Self { conn })
+    }
+}
+
+#[async_trait]
+impl RateLimiter for RedisRateLimiter {
+    async fn check(&self, key: &str, limit: u64, cost: u64) -> Result<Decision, RateLimitError> {
+        if limit == 0 {
+            return Ok(Decision::Allow); // No limit
+        }
+
+        let mut conn = self.conn.clone();
+        let redis_key = format!("rate_limit:{}", key);
+
+        // Script to ensure atomicity: INCRBY key cost; start the window's expiry on the
+        // first increment; return the count
+        let script = redis::Script::new(
+            r#"
+            local count = redis.call("INCRBY", KEYS[1], ARGV[1])
+            if count == tonumber(ARGV[1]) then
+                redis.call("EXPIRE", KEYS[1], 1)
+            end
+            return count
+        "#,
+        );
+
+        let count: u64 = script
+            .key(&redis_key)
+            .arg(cost)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(if count > limit {
+            Decision::Limited
+        } else {
+            Decision::Allow
+        })
+    }
+}
+
+/// Fixed one-second windows counted in process memory. Each router instance limits
+/// on its own, so with several instances a key gets `limit` per instance.
+#[derive(Default)]
+pub struct InMemoryRateLimiter {
+    windows: Mutex<Windows>,
+}
+
+struct Windows {
+    /// Key → start of its current window and the requests counted in it
+    counts: HashMap<String, (Instant, u64)>,
+    last_sweep: Instant,
+}
+
+impl Default for Windows {
+    fn default() -> Self {
+        Self {
+            counts: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+}
+
+/// Expired windows are swept, at most once per window, once the map grows past this
+/// many keys.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+impl InMemoryRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimiter for InMemoryRateLimiter {
+    async fn check(&self, key: &str, limit: u64, cost: u64) -> Result<Decision, RateLimitError> {
+        if limit == 0 {
+            return Ok(Decision::Allow);
+        }
+
+        let now = Instant::now();
+        let window = Duration::from_secs(1);
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.counts.len() > SWEEP_THRESHOLD
+            && now.duration_since(windows.last_sweep) >= window
+        {
+            windows
+                .counts
+                .retain(|_, (start, _)| now.duration_since(*start) < window);
+            windows.last_sweep = now;
+        }
+
+        let (start, count) = windows.counts.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(*start) >= window {
+            *start = now;
+            *count = 0;
+        }
+        *count += cost;
+        Ok(if *count > limit {
+            Decision::Limited
+        } else {
+            Decision::Allow
+        })
+    }
+}
+
+/// Requests each owner has in flight, a counting semaphore per owner. Unlike the
+/// per-second limit this catches clients that hold many slow requests open at once.
+/// A key with its own `max_concurrent_requests` is counted on its own instead of with
+/// its owner's other keys.
+///
+/// Counters live in sharded maps, so concurrent requests from different owners rarely
+/// contend, and are never removed: each owner (or limited key) seen keeps one.
+#[derive(Debug, Default)]
+pub struct OwnerConcurrency {
+    owners: Counters,
+    keys: Counters,
+}
+
+impl OwnerConcurrency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes one of `owner`'s `limit` slots, or returns `None` when all are in use.
+    pub fn try_acquire(&self, owner: &str, limit: u64) -> Option<OwnerSlot> {
+        self.owners.try_acquire(owner, limit)
+    }
+
+    /// Takes one of `key`'s own `limit` slots, or returns `None` when all are in use.
+    pub fn try_acquire_key(&self, key: &str, limit: u64) -> Option<OwnerSlot> {
+        self.keys.try_acquire(key, limit)
+    }
+
+    /// Requests `owner` has in flight, not counting keys with their own limit.
+    pub fn in_flight(&self, owner: &str) -> usize {
+        self.owners.in_flight(owner)
+    }
+
+    /// Requests in flight on `key`, when it has its own limit.
+    pub fn key_in_flight(&self, key: &str) -> usize {
+        self.keys.in_flight(key)
+    }
+}
+
+const COUNTER_SHARDS: usize = 16;
+
+/// In-flight counters by name, spread over `COUNTER_SHARDS` locks.
+#[derive(Debug)]
+struct Counters {
+    shards: [Mutex<HashMap<String, Arc<AtomicUsize>>>; COUNTER_SHARDS],
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Mutex::default()),
+        }
+    }
+}
+
+impl Counters {
+    fn shard(&self, name: &str) -> MutexGuard<'_, HashMap<String, Arc<AtomicUsize>>> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        self.shards[hasher.finish() as usize % COUNTER_SHARDS]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn counter(&self, name: &str) -> Arc<AtomicUsize> {
+        let mut counters = self.shard(name);
+        match counters.get(name) {
+            Some(counter) => counter.clone(),
+            None => counters.entry(name.to_string()).or_default().clone(),
+        }
+    }
+
+    fn try_acquire(&self, name: &str, limit: u64) -> Option<OwnerSlot> {
+        let counter = self.counter(name);
+        counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |busy| {
+                (limit == 0 || (busy as u64) < limit).then_some(busy + 1)
+            })
+            .ok()?;
+        Some(OwnerSlot(counter))
+    }
+
+    fn in_flight(&self, name: &str) -> usize {
+        self.shard(name)
+            .get(name)
+            .map_or(0, |counter| counter.load(Ordering::Relaxed))
+    }
+}
+
+/// One request counted against its owner's (or key's) concurrency limit, released on
+/// drop however the request ends.
+#[derive(Debug)]
+pub struct OwnerSlot(Arc<AtomicUsize>);
+
+impl Drop for OwnerSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Allows `rate` messages per second on average, with bursts of up to one second's
+/// worth. Owned by one connection, so it needs no synchronization.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, or `None` for a `rate` of 0 (unlimited).
+    pub fn per_second(rate: u64) -> Option<Self> {
+        (rate > 0).then(|| Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        })
+    }
+
+    /// Takes a token for one message, or returns `false` when the rate is exceeded.
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
@@ -0,0 +1,131 @@
+//! Retry backoff, and token-bucket retry throttling modelled on gRPC's
+//! `retryThrottling`.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::config::ProxyConfig;
+
+/// Tokens are tracked in thousandths so fractional refills need no float atomics.
+const SCALE: u64 = 1000;
+
+/// Shared budget that caps retry amplification. Every retry spends one token and
+/// every successful upstream response refills `token_ratio` tokens, up to
+/// `max_tokens`, so during an outage retries stop once the bucket is empty and
+/// resume only as successes come back.
+#[derive(Debug)]
+pub struct RetryThrottle {
+    tokens: AtomicU64,
+    max_tokens: AtomicU64,
+    refill: AtomicU64,
+}
+
+impl RetryThrottle {
+    /// A full bucket of `max_tokens`.
+    pub fn new(max_tokens: u32, token_ratio: f64) -> Self {
+        let max_tokens = u64::from(max_tokens) * SCALE;
+        Self {
+            tokens: AtomicU64::new(max_tokens),
+            max_tokens: AtomicU64::new(max_tokens),
+            refill: AtomicU64::new(scaled(token_ratio)),
+        }
+    }
+
+    /// Applies new limits, as on SIGHUP reload. The tokens already in the bucket are
+    /// kept, down to the new `max_tokens`.
+    pub fn resize(&self, max_tokens: u32, token_ratio: f64) {
+        let max_tokens = u64::from(max_tokens) * SCALE;
+        self.max_tokens.store(max_tokens, Ordering::Release);
+        self.refill.store(scaled(token_ratio), Ordering::Release);
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                (t > max_tokens).then_some(max_tokens)
+            });
+    }
+
+    /// Spends a token for one retry. Returns `false`, spending nothing, when the
+    /// budget is exhausted.
+    pub fn try_acquire(&self) -> bool {
+        self.tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                t.checked_sub(SCALE)
+            })
+            .is_ok()
+    }
+
+    /// Refills the budget after a successful upstream response.
+    pub fn record_success(&self) {
+        let max_tokens = self.max_tokens.load(Ordering::Acquire);
+        let refill = self.refill.load(Ordering::Acquire);
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                (t < max_tokens).then(|| (t + refill).min(max_tokens))
+            });
+    }
+
+    /// Tokens currently available.
+    pub fn tokens(&self) -> f64 {
+        self.tokens.load(Ordering::Acquire) as f64 / SCALE as f64
+    }
+}
+
+/// `tokens` in thousandths.
+fn scaled(tokens: f64) -> u64 {
+    (tokens * SCALE as f64).round() as u64
+}
+
+/// Backoff before retry number `retries + 1`, or `None` when no retry is allowed:
+/// `max_retries` is used up, `retry_budget_ms` since `started` is spent, or the
+/// backoff would run past the request `deadline`.
+///
+/// The delay doubles from `retry_base_delay_ms` up to `retry_max_delay_ms`, and its
+/// upper half is randomized so concurrent retries don't arrive in lockstep.
+pub fn retry_delay(
+    config: &ProxyConfig,
+    retries: u32,
+    started: Instant,
+    deadline: Instant,
+) -> Option<Duration> {
+    if retries >= config.max_retries {
+        return None;
+    }
+    let now = Instant::now();
+    if now.duration_since(started) >= Duration::from_millis(config.retry_budget_ms) {
+        return None;
+    }
+
+    let delay = jittered_backoff(
+        config.retry_base_delay_ms,
+        config.retry_max_delay_ms,
+        retries,
+        &mut rand::thread_rng(),
+    );
+    (now + delay < deadline).then_some(delay)
+}
+
+/// Backoff before WebSocket connect retry number `retries + 1`, or `None` once
+/// `ws_connect_retries` is used up. Same doubling and jitter as `retry_delay`.
+pub fn ws_connect_delay(config: &ProxyConfig, retries: u32) -> Option<Duration> {
+    (retries < config.ws_connect_retries).then(|| {
+        jittered_backoff(
+            config.ws_connect_retry_base_delay_ms,
+            config.ws_connect_retry_max_delay_ms,
+            retries,
+            &mut rand::thread_rng(),
+        )
+    })
+}
+
+/// `base_ms` doubled `retries` times and capped at `max_ms`, with the upper half
+/// randomized by `rng`.
+pub fn jittered_backoff(base_ms: u64, max_ms: u64, retries: u32, rng: &mut impl Rng) -> Duration {
+    let exp_ms = base_ms.saturating_mul(1u64 << retries.min(32)).min(max_ms);
+    let half = exp_ms / 2;
+    Duration::from_millis(half + rng.gen_range(0..=exp_ms - half))
+}
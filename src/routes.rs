@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::{
+    middleware,
+    routing::{delete, get, post},
+    Router,
+};
+use tower_http::cors::CorsLayer;
+
+use crate::{
+    admin::{create_backend, delete_backend, list_captures, simulate_routing},
+    handlers::{
+        extract_rpc_method, health_endpoint, log_requests, proxy, readyz_endpoint, rest_proxy,
+        root_get, routes_endpoint, stats_endpoint, track_metrics, version_endpoint, ws_proxy,
+    },
+    state::AppState,
+};
+
+/// JSON-RPC proxy and WebSocket upgrade routes served to API clients.
+pub fn proxy_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(root_get).post(proxy))
+        .route("/*path", get(rest_proxy).post(proxy))
+}
+
+/// Operational endpoints that can be moved to a private admin listener.
+pub fn admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(health_endpoint))
+        .route("/readyz", get(readyz_endpoint))
+        .route("/version", get(version_endpoint))
+        .route("/stats", get(stats_endpoint))
+        .route("/routes", get(routes_endpoint))
+        .route("/admin/backends", post(create_backend))
+        .route("/admin/backends/:label", delete(delete_backend))
+        .route("/admin/simulate", post(simulate_routing))
+        .route("/admin/captures", get(list_captures))
+}
+
+/// Builds the router for the main HTTP port. When `include_admin` is false the admin
+/// endpoints are served only by the admin listener, so the public port exposes nothing
+/// but the proxy.
+pub fn public_router(state: Arc<AppState>, include_admin: bool) -> Router {
+    let routes = if include_admin {
+        proxy_routes().merge(admin_routes())
+    } else {
+        proxy_routes()
+    };
+
+    routes
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state.clone(), track_metrics))
+        .layer(middleware::from_fn_with_state(state, log_requests))
+        .layer(middleware::from_fn(extract_rpc_method))
+        .layer(CorsLayer::permissive())
+}
+
+/// Builds the router for the dedicated admin port.
+pub fn admin_router(state: Arc<AppState>) -> Router {
+    admin_routes()
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state, log_requests))
+}
+
+/// Builds the router for the dedicated WebSocket port (HTTP port + 1).
+pub fn ws_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(ws_proxy))
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state, log_requests))
+        .layer(CorsLayer::permissive())
+}
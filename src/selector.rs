@@ -0,0 +1,249 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    net::SocketAddr,
+    sync::atomic::Ordering,
+};
+
+use rand::Rng;
+
+use crate::state::RuntimeBackend;
+
+/// Request details available to a `BackendSelector`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SelectionContext<'a> {
+    /// JSON-RPC method, when the body was a single request
+    pub rpc_method: Option<&'a str>,
+    /// Peer address of the client connection, when known
+    pub client_addr: Option<SocketAddr>,
+    /// Owner of the API key that authenticated the request
+    pub owner: Option<&'a str>,
+    /// `commitment` named in the request params, when backends are grouped by it
+    pub commitment: Option<&'a str>,
+    /// First request param (e.g. an account pubkey or a slot), only extracted for
+    /// selectors whose `uses_request_key` is true
+    pub request_key: Option<&'a str>,
+}
+
+/// Healthy backends eligible for a request, as a filtered view over
+/// `RouterState::backends`. Iterating doesn't allocate, so selection stays off the heap
+/// on the hot path; each call to `iter` re-applies the filter.
+#[derive(Clone, Copy)]
+pub struct Candidates<'a> {
+    backends: &'a [RuntimeBackend],
+    filter: &'a dyn Fn(&RuntimeBackend) -> bool,
+}
+
+impl<'a> Candidates<'a> {
+    pub fn new(
+        backends: &'a [RuntimeBackend],
+        filter: &'a dyn Fn(&RuntimeBackend) -> bool,
+    ) -> Self {
+        Self { backends, filter }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a RuntimeBackend> + '_ {
+        self.backends.iter().filter(|b| (self.filter)(b))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Position of `backend` in the backends these candidates are drawn from, compared
+    /// by address, or `None` if it isn't one of the candidates.
+    pub fn position(&self, backend: &RuntimeBackend) -> Option<usize> {
+        self.backends
+            .iter()
+            .position(|b| std::ptr::eq(b, backend))
+            .filter(|&index| (self.filter)(&self.backends[index]))
+    }
+
+    pub fn first(&self) -> Option<&'a RuntimeBackend> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&'a RuntimeBackend> {
+        self.iter().last()
+    }
+}
+
+/// Strategy for choosing among healthy backends.
+///
+/// `AppState` applies method routes and health filtering first, then hands the remaining
+/// candidates to the selector. Implement this to plug in custom routing (e.g. geo-aware
+/// selection by client address) without forking the proxy.
+pub trait BackendSelector: Send + Sync {
+    /// Picks one of `candidates`, which are all healthy and never empty.
+    /// Returning `None` makes the request fail with "no healthy backends".
+    fn select<'a>(
+        &self,
+        candidates: &Candidates<'a>,
+        ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend>;
+
+    /// Whether `select` reads `SelectionContext::request_key`. The proxy only parses
+    /// the request params for it when this returns true.
+    fn uses_request_key(&self) -> bool {
+        false
+    }
+}
+
+/// Default strategy: weighted random selection using each backend's `weight`, lowered
+/// while it is throttled (see `RuntimeBackend::effective_weight`), with each backend's
+/// `min_share` as a floor on its share.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WeightedRandomSelector;
+
+impl BackendSelector for WeightedRandomSelector {
+    fn select<'a>(
+        &self,
+        candidates: &Candidates<'a>,
+        _ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend> {
+        weighted_pick(|| candidates.iter())
+    }
+}
+
+/// Weighted random choice among the backends `backends()` yields, by effective weight;
+/// the first one when all weights are zero. A backend with a `min_share` that has
+/// recently had less than that share of the picks is chosen outright instead. Called
+/// several times to walk the backends.
+fn weighted_pick<'a, I>(backends: impl Fn() -> I) -> Option<&'a RuntimeBackend>
+where
+    I: Iterator<Item = &'a RuntimeBackend>,
+{
+    let (counted, has_floor) = backends().fold((0, false), |(counted, has_floor), b| {
+        (
+            counted + b.selections.load(Ordering::Relaxed),
+            has_floor || b.config.min_share > 0.0,
+        )
+    });
+    if !has_floor {
+        return weighted_random(&backends);
+    }
+    // Counting the pick about to be made
+    let total = counted + 1;
+    let chosen = under_served(&backends, total).or_else(|| weighted_random(&backends))?;
+    chosen.selections.fetch_add(1, Ordering::Relaxed);
+    if total > SHARE_WINDOW {
+        for backend in backends() {
+            let _ = backend
+                .selections
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n / 2));
+        }
+    }
+    Some(chosen)
+}
+
+fn weighted_random<'a, I>(backends: &impl Fn() -> I) -> Option<&'a RuntimeBackend>
+where
+    I: Iterator<Item = &'a RuntimeBackend>,
+{
+    // Summed as u64 so any number of backends at large u32 weights can't overflow
+    let total_weight: u64 = backends().map(RuntimeBackend::effective_weight).sum();
+
+    if total_weight == 0 {
+        return backends().next();
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut random_weight = rng.gen_range(0..total_weight);
+
+    for backend in backends() {
+        let weight = backend.effective_weight();
+        if random_weight < weight {
+            return Some(backend);
+        }
+        random_weight -= weight;
+    }
+
+    // Fallback: health changed between the two passes
+    backends().next()
+}
+
+/// Selections among a set of candidates beyond which every count is halved, so shares
+/// reflect roughly the last this many picks.
+///
+/// Counts are kept per backend, not per candidate set. A backend that is a candidate
+/// in several sets (method routes, the read/write split, zones, commitment groups)
+/// has its picks from all of them counted toward its share in each, and halving only
+/// ages the counts of the set being picked from. Floors are exact when every request
+/// sees the same candidates and approximate when candidate sets overlap.
+const SHARE_WINDOW: u64 = 1000;
+
+/// The backend furthest below its `min_share` of `total` picks among these
+/// candidates; `None` when every floor is met.
+fn under_served<'a, I>(backends: &impl Fn() -> I, total: u64) -> Option<&'a RuntimeBackend>
+where
+    I: Iterator<Item = &'a RuntimeBackend>,
+{
+    backends()
+        .map(|b| {
+            let floor = b.config.min_share * total as f64;
+            (b, floor - b.selections.load(Ordering::Relaxed) as f64)
+        })
+        .filter(|(_, deficit)| *deficit > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(b, _)| b)
+}
+
+/// Billing-aware strategy: only the candidates with the lowest `cost` are used, by
+/// weight. Pricier backends take traffic once every cheaper one is unhealthy or at its
+/// `max_concurrent_requests`, which removes it from the candidates.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CheapestFirstSelector;
+
+impl BackendSelector for CheapestFirstSelector {
+    fn select<'a>(
+        &self,
+        candidates: &Candidates<'a>,
+        _ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend> {
+        let cheapest = candidates.iter().map(|b| b.config.cost).min()?;
+        weighted_pick(|| candidates.iter().filter(move |b| b.config.cost == cheapest))
+    }
+}
+
+/// Cache-affinity strategy: requests carrying the same `request_key` go to the same
+/// backend, so identical reads hit a warm upstream cache. Uses weighted rendezvous
+/// hashing, which keeps the load proportional to `weight` and, when a backend drops
+/// out, only moves the keys that backend was serving. Requests without a key use
+/// weighted random selection.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsistentHashSelector;
+
+impl ConsistentHashSelector {
+    /// Rendezvous score of `backend` for `key`; the highest score wins.
+    fn score(backend: &RuntimeBackend, key: &str) -> f64 {
+        let mut hasher = DefaultHasher::new();
+        backend.config.label.hash(&mut hasher);
+        key.hash(&mut hasher);
+        // Uniform in (0, 1) from the top 53 bits
+        let unit = ((hasher.finish() >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        backend.effective_weight() as f64 / -unit.ln()
+    }
+}
+
+impl BackendSelector for ConsistentHashSelector {
+    fn select<'a>(
+        &self,
+        candidates: &Candidates<'a>,
+        ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend> {
+        let Some(key) = ctx.request_key else {
+            return WeightedRandomSelector.select(candidates, ctx);
+        };
+        if candidates.iter().all(|b| b.config.weight == 0) {
+            return candidates.first();
+        }
+        candidates
+            .iter()
+            .map(|b| (b, Self::score(b, key)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(b, _)| b)
+    }
+
+    fn uses_request_key(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    future::Future, io, net::SocketAddr, os::unix::fs::FileTypeExt, path::Path, time::Duration,
+};
+
+use axum::{extract::ConnectInfo, http::Request, middleware, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use metrics::counter;
+use tokio::net::{TcpListener, UnixListener};
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+use crate::{
+    config::ListenerConfig,
+    connlimit::{limit_forwarded_connections, ConnectionLimiter},
+};
+
+/// Binds the `name` listener (e.g. "HTTP") to `addr`. A port that is already taken gets
+/// an error saying so, the usual cause being another instance still running.
+pub async fn bind_tcp(addr: SocketAddr, name: &str) -> Result<TcpListener, String> {
+    TcpListener::bind(addr).await.map_err(|e| match e.kind() {
+        io::ErrorKind::AddrInUse => format!(
+            "{} port {} is already in use; is another instance running?",
+            name,
+            addr.port()
+        ),
+        _ => format!("Failed to bind {} server on {}: {}", name, addr, e),
+    })
+}
+
+/// Binds a Unix domain socket at `path`, replacing a stale socket file left behind by a
+/// previous run. Anything else at `path` is left alone and fails the bind.
+pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path)?;
+            debug!("Removed stale Unix socket {}", path.display());
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a Unix socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
+
+/// Pause before accepting again after an accept error, doubling with each consecutive
+/// error. Errors such as running out of file descriptors last until connections close,
+/// so retrying straight away would spin.
+struct AcceptBackoff {
+    delay: Duration,
+}
+
+impl AcceptBackoff {
+    const MIN: Duration = Duration::from_millis(5);
+    const MAX: Duration = Duration::from_secs(1);
+
+    fn new() -> Self {
+        Self { delay: Self::MIN }
+    }
+
+    fn reset(&mut self) {
+        self.delay = Self::MIN;
+    }
+
+    async fn wait(&mut self) {
+        tokio::time::sleep(self.delay).await;
+        self.delay = (self.delay * 2).min(Self::MAX);
+    }
+}
+
+/// Serves `app` on a Unix domain socket until `shutdown` resolves, then waits for open
+/// connections to finish.
+///
+/// `axum::serve` only accepts TCP listeners, so this runs its own accept loop. Requests
+/// carry no `ConnectInfo` because a Unix socket peer has no IP address.
+pub async fn serve_unix(
+    listener: UnixListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let graceful = GracefulShutdown::new();
+    let mut backoff = AcceptBackoff::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("Failed to accept Unix socket connection: {}", e);
+                        backoff.wait().await;
+                        continue;
+                    }
+                };
+                backoff.reset();
+
+                let service = TowerToHyperService::new(app.clone());
+                let conn = Builder::new(TokioExecutor::new())
+                    .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                    .into_owned();
+                let conn = graceful.watch(conn);
+
+                tokio::spawn(async move {
+                    if let Err(e) = conn.await {
+                        debug!("Unix socket connection error: {}", e);
+                    }
+                });
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // Stop accepting before waiting on in-flight connections
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Serves `app` on a TCP listener with the socket and keep-alive settings from `config`,
+/// until `shutdown` resolves, then waits for open connections to finish.
+///
+/// `axum::serve` doesn't expose hyper's keep-alive knobs, so this runs its own accept
+/// loop. Each request gets `ConnectInfo<SocketAddr>`, as
+/// `into_make_service_with_connect_info` would provide. With
+/// `max_connections_per_ip_per_sec` set, connections over the rate are closed as soon
+/// as they are accepted (see `connlimit`).
+pub async fn serve_tcp(
+    listener: TcpListener,
+    app: Router,
+    config: ListenerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(config.http1_keep_alive);
+    if config.http2_keep_alive_interval_secs > 0 {
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(Duration::from_secs(config.http2_keep_alive_interval_secs));
+    }
+
+    let limiter = ConnectionLimiter::from_config(&config);
+    // Connections from trusted proxies are limited per forwarded client instead
+    let proxied_app = limiter.clone().map(|limiter| {
+        app.clone().layer(middleware::from_fn_with_state(
+            limiter,
+            limit_forwarded_connections,
+        ))
+    });
+
+    let graceful = GracefulShutdown::new();
+    let mut backoff = AcceptBackoff::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept TCP connection: {}", e);
+                        backoff.wait().await;
+                        continue;
+                    }
+                };
+                backoff.reset();
+                let app = match (&limiter, &proxied_app) {
+                    (Some(limiter), Some(proxied_app)) if limiter.is_trusted_proxy(addr.ip()) => {
+                        proxied_app.clone()
+                    }
+                    (Some(limiter), _) if !limiter.allow_connection(addr.ip()) => {
+                        counter!("rpc_connections_rejected_total", "reason" => "accept")
+                            .increment(1);
+                        debug!("Dropped connection from {}: over the per-IP rate", addr);
+                        continue;
+                    }
+                    _ => app.clone(),
+                };
+                if let Err(e) = stream.set_nodelay(config.tcp_nodelay) {
+                    debug!("Failed to set TCP_NODELAY for {}: {}", addr, e);
+                }
+
+                let app = app.map_request(move |mut req: Request<Incoming>| {
+                    req.extensions_mut().insert(ConnectInfo(addr));
+                    req
+                });
+                let conn = builder
+                    .serve_connection_with_upgrades(
+                        TokioIo::new(stream),
+                        TowerToHyperService::new(app),
+                    )
+                    .into_owned();
+                let conn = graceful.watch(conn);
+
+                tokio::spawn(async move {
+                    if let Err(e) = conn.await {
+                        debug!("Connection error from {}: {}", addr, e);
+                    }
+                });
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // Stop accepting before waiting on in-flight connections
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
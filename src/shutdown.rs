@@ -0,0 +1,37 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::info;
+
+use crate::state::AppState;
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn wait_for_termination() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to register SIGTERM handler");
+
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+    }
+}
+
+/// Pre-shutdown phase: marks the router not ready, then waits `delay` so load balancers
+/// see `/readyz` fail and stop sending traffic before the listeners begin draining.
+pub async fn begin_drain(state: &AppState, delay: Duration) {
+    state.ready.store(false, Ordering::Relaxed);
+    info!(
+        "Router marked not ready; waiting {}s before draining connections",
+        delay.as_secs()
+    );
+    tokio::time::sleep(delay).await;
+}
+
+/// Resolves once `true` has been sent on the shutdown channel. Passed to each listener's
+/// `with_graceful_shutdown`.
+pub async fn shutdown_requested(mut rx: watch::Receiver<bool>) {
+    // A dropped sender also counts as a shutdown request
+    let _ = rx.wait_for(|shutdown| *shutdown).await;
+}
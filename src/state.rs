@@ -1,147 +1,1048 @@
 use std::{
     collections::HashMap,
+    fs,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock,
     },
+    time::{Duration, Instant},
 };
 
 use arc_swap::ArcSwap;
-use axum::body::Body;
+use axum::{
+    body::Body,
+    http::{
+        uri::{Authority, PathAndQuery, Scheme},
+        HeaderValue, Request, Response, Uri,
+    },
+};
+use hyper::body::Incoming;
 use hyper_tls::HttpsConnector;
-use hyper_util::client::legacy::{connect::HttpConnector, Client};
-use rand::Rng;
-use tracing::{debug, info};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use metrics::{counter, gauge, Gauge};
+use tracing::{debug, debug_span, error, field, info, warn, Span};
 
 use crate::{
-    config::{Backend, HealthCheckConfig},
-    health::HealthState,
+    cache::ResponseCache,
+    capture::RequestCapture,
+    config::{
+        Backend, HealthCheckConfig, KeystoreFailurePolicy, LoggingConfig, MethodRoute, ProxyConfig,
+        ReadWriteSplit, ResponseTransform,
+    },
+    health::{build_health_check_client, HealthState},
     keystore::KeyStore,
+    ratelimit::{InMemoryRateLimiter, OwnerConcurrency, RateLimiter},
+    retry::RetryThrottle,
+    selector::{BackendSelector, Candidates, SelectionContext, WeightedRandomSelector},
+    stats::{CostCounter, LatencyHistogram, MetricGroups},
+    upstream::ConnectionLifetime,
 };
 
+/// HTTP(S) client used to reach backends.
+pub type HttpClient = Client<HttpsConnector<HttpConnector>, Body>;
+
 #[derive(Debug, Clone)]
 pub struct RuntimeBackend {
     pub config: Backend,
     pub healthy: Arc<AtomicBool>,
+    /// WebSocket connections currently open to this backend, each held by a
+    /// `WsConnection`
+    pub ws_connections: Arc<AtomicUsize>,
+    /// Upstream HTTP requests currently awaiting a response from this backend, each
+    /// held by an `InFlightRequest`
+    pub in_flight: Arc<AtomicUsize>,
+    /// Upstream HTTP requests waiting for a connection to this backend, each held by a
+    /// `QueuedRequest`
+    pub queued: Arc<AtomicUsize>,
+    /// `rpc_upstream_queue_depth` for this backend, registered once
+    queue_gauge: Gauge,
+    /// Weight reduction after `429` responses, with `proxy.adaptive_weights`
+    pub throttle: Arc<WeightThrottle>,
+    /// Recent weighted selections of this backend, kept while some candidate has a
+    /// `min_share` and halved as they grow so the count follows recent traffic
+    pub selections: Arc<AtomicU64>,
+    /// Requests this backend answered and their cost, for `/stats`
+    pub costs: Arc<CostCounter>,
+    /// `config.url` split into parts once, so requests only append their path
+    scheme: Scheme,
+    authority: Authority,
+    base_path: String,
+    /// Query string of `config.url` (e.g. a provider key), kept on every request
+    base_query: Option<String>,
+    host_header: HeaderValue,
+    /// Dedicated clients presenting this backend's TLS client identity, if configured:
+    /// one for proxied traffic and one for health checks
+    client: Option<HttpClient>,
+    health_client: Option<HttpClient>,
+    /// Only selected when no regular backend is healthy
+    fallback: bool,
+    /// The configured `default_backend`, preferred over weighted selection
+    primary: bool,
+}
+
+impl RuntimeBackend {
+    /// Parses `config.url` up front. Backends start out healthy. A backend with a TLS
+    /// client identity gets clients built with the default `ClientOptions`.
+    pub fn new(config: Backend) -> Result<Self, String> {
+        Self::with_client_options(config, &ClientOptions::default())
+    }
+
+    /// Same as `new`, with a TLS client identity's clients built from `options` like the
+    /// shared ones.
+    pub fn with_client_options(config: Backend, options: &ClientOptions) -> Result<Self, String> {
+        // force_scheme was checked by config::validate_backend
+        let uri = config.effective_url().parse::<Uri>().map_err(|e| {
+            format!(
+                "Backend '{}' has invalid URL '{}': {}",
+                config.label, config.url, e
+            )
+        })?;
+        let parts = uri.into_parts();
+        let (Some(scheme), Some(authority)) = (parts.scheme, parts.authority) else {
+            return Err(format!(
+                "Backend '{}' URL '{}' must include a scheme and host",
+                config.label, config.url
+            ));
+        };
+
+        // Host header carries the host and explicit port, without userinfo
+        let host = match authority.port_u16() {
+            Some(port) => format!("{}:{}", authority.host(), port),
+            None => authority.host().to_string(),
+        };
+        let host_header = HeaderValue::from_str(&host).map_err(|e| {
+            format!(
+                "Backend '{}' has invalid host '{}': {}",
+                config.label, host, e
+            )
+        })?;
+
+        let base_path = parts
+            .path_and_query
+            .as_ref()
+            .map(|pq| pq.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
+        let base_query = parts
+            .path_and_query
+            .as_ref()
+            .and_then(|pq| pq.query())
+            .filter(|q| !q.is_empty())
+            .map(str::to_string);
+
+        let (client, health_client) = match (&config.tls_client_cert, &config.tls_client_key) {
+            (Some(cert), Some(key)) => {
+                let (client, health_client) = mtls_clients(cert, key, options).map_err(|e| {
+                    format!(
+                        "Backend '{}' has invalid TLS client identity: {}",
+                        config.label, e
+                    )
+                })?;
+                (Some(client), Some(health_client))
+            }
+            _ => (None, None),
+        };
+
+        let queue_gauge = gauge!("rpc_upstream_queue_depth", "backend" => config.label.clone());
+
+        Ok(Self {
+            config,
+            healthy: Arc::new(AtomicBool::new(true)),
+            ws_connections: Arc::new(AtomicUsize::new(0)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            queue_gauge,
+            throttle: Arc::new(WeightThrottle::default()),
+            selections: Arc::new(AtomicU64::new(0)),
+            costs: Arc::new(CostCounter::new()),
+            scheme,
+            authority,
+            base_path,
+            base_query,
+            host_header,
+            client,
+            health_client,
+            fallback: false,
+            primary: false,
+        })
+    }
+
+    /// Marks this backend as the fallback, excluded from regular selection.
+    pub fn into_fallback(mut self) -> Self {
+        self.fallback = true;
+        self
+    }
+
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+
+    /// Marks this backend as the default, chosen ahead of weighted selection while healthy.
+    pub fn into_primary(mut self) -> Self {
+        self.primary = true;
+        self
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// Same as `with_client_options`, with the initial health status set explicitly
+    /// (used on reload).
+    pub fn with_health(
+        config: Backend,
+        healthy: bool,
+        options: &ClientOptions,
+    ) -> Result<Self, String> {
+        let backend = Self::with_client_options(config, options)?;
+        backend.healthy.store(healthy, Ordering::Relaxed);
+        Ok(backend)
+    }
+
+    /// Whether another WebSocket connection fits under `max_ws_connections`.
+    pub fn has_ws_capacity(&self) -> bool {
+        let max = self.config.max_ws_connections;
+        max == 0 || self.ws_connections.load(Ordering::Relaxed) < max
+    }
+
+    /// Counts a new WebSocket connection to this backend until the returned guard is
+    /// dropped, or returns `None` if the backend is at `max_ws_connections`.
+    pub fn open_ws_connection(&self) -> Option<WsConnection> {
+        let max = self.config.max_ws_connections;
+        self.ws_connections
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| {
+                (max == 0 || open < max).then_some(open + 1)
+            })
+            .ok()?;
+        Some(WsConnection(self.ws_connections.clone()))
+    }
+
+    /// `config.weight` scaled by `2^MAX_THROTTLE_LEVEL` and halved once per throttle
+    /// level, so selectors can compare throttled and unthrottled backends in integers.
+    pub fn effective_weight(&self) -> u64 {
+        (u64::from(self.config.weight) << MAX_THROTTLE_LEVEL) >> self.throttle.level()
+    }
+
+    /// Whether another upstream request fits under `max_concurrent_requests`.
+    pub fn has_request_capacity(&self) -> bool {
+        let max = self.config.max_concurrent_requests;
+        max == 0 || self.in_flight.load(Ordering::Relaxed) < max
+    }
+
+    /// Counts an upstream request to this backend until the returned guard is dropped,
+    /// or returns `None` if the backend is at `max_concurrent_requests`.
+    pub fn start_request(&self) -> Option<InFlightRequest> {
+        let max = self.config.max_concurrent_requests;
+        self.in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |busy| {
+                (max == 0 || busy < max).then_some(busy + 1)
+            })
+            .ok()?;
+        Some(InFlightRequest(self.in_flight.clone()))
+    }
+
+    /// Counts an upstream request as waiting for a connection to this backend until the
+    /// returned guard is released or dropped.
+    pub fn queue_request(&self) -> QueuedRequest {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.queue_gauge.increment(1.0);
+        QueuedRequest {
+            queued: self.queued.clone(),
+            gauge: self.queue_gauge.clone(),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    /// Upstream URI for a client request path (plus query), appended to the backend's
+    /// base path. A bare `/` maps to the base URL itself, without a trailing slash.
+    /// A query in the backend URL is kept, followed by the client's parameters that it
+    /// doesn't already set.
+    pub fn upstream_uri(&self, request_path: &str) -> Result<Uri, String> {
+        let (path, client_query) = match request_path.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (request_path, ""),
+        };
+        let mut joined = if request_path == "/" {
+            if self.base_path.is_empty() {
+                "/".to_string()
+            } else {
+                self.base_path.clone()
+            }
+        } else {
+            format!("{}{}", self.base_path, path)
+        };
+        let query = merge_query(self.base_query.as_deref(), client_query);
+        if !query.is_empty() {
+            joined.push('?');
+            joined.push_str(&query);
+        }
+        let path_and_query = PathAndQuery::try_from(joined)
+            .map_err(|e| format!("Invalid request path '{}': {}", request_path, e))?;
+
+        Uri::builder()
+            .scheme(self.scheme.clone())
+            .authority(self.authority.clone())
+            .path_and_query(path_and_query)
+            .build()
+            .map_err(|e| format!("Failed to build backend URI: {}", e))
+    }
+
+    /// Value for the Host header on requests to this backend.
+    pub fn host_header(&self) -> &HeaderValue {
+        &self.host_header
+    }
+
+    /// The backend's own client when it uses mutual TLS; other backends share the
+    /// `AppState` client.
+    pub fn client(&self) -> Option<&HttpClient> {
+        self.client.as_ref()
+    }
+
+    /// The backend's own health check client when it uses mutual TLS; other backends
+    /// share the one from `health_check_client`.
+    pub fn health_client(&self) -> Option<&HttpClient> {
+        self.health_client.as_ref()
+    }
+}
+
+/// Settings shared by every client the router builds for its backends: the shared
+/// ones and those of backends with a TLS client identity.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// `upstream.danger_accept_invalid_certs`
+    pub accept_invalid_certs: bool,
+    /// Connect and idle timeouts of health check clients
+    pub health_check: HealthCheckConfig,
+}
+
+/// Builds the client for proxied traffic. With `accept_invalid_certs` backend TLS
+/// certificates and host names aren't verified at all.
+pub fn upstream_client(accept_invalid_certs: bool) -> HttpClient {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    Client::builder(TokioExecutor::new()).build(https_connector(http, accept_invalid_certs))
+}
+
+/// Wraps `http` in TLS, verifying backend certificates unless `accept_invalid_certs`.
+pub fn https_connector(
+    http: HttpConnector,
+    accept_invalid_certs: bool,
+) -> HttpsConnector<HttpConnector> {
+    tls_connector(http, accept_invalid_certs, None).expect("failed to initialize TLS")
+}
+
+/// `https_connector`, also presenting `identity` to backends that ask for a client
+/// certificate.
+pub(crate) fn tls_connector(
+    http: HttpConnector,
+    accept_invalid_certs: bool,
+    identity: Option<&native_tls::Identity>,
+) -> Result<HttpsConnector<HttpConnector>, native_tls::Error> {
+    let mut tls = native_tls::TlsConnector::builder();
+    tls.danger_accept_invalid_certs(accept_invalid_certs);
+    if let Some(identity) = identity {
+        tls.identity(identity.clone());
+    }
+    Ok(HttpsConnector::from((
+        http,
+        tokio_native_tls::TlsConnector::from(tls.build()?),
+    )))
+}
+
+/// Builds the proxy and health check clients for a backend that presents the PEM
+/// certificate and PKCS#8 key at the given paths.
+fn mtls_clients(
+    cert_path: &str,
+    key_path: &str,
+    options: &ClientOptions,
+) -> Result<(HttpClient, HttpClient), String> {
+    let cert = fs::read(cert_path).map_err(|e| format!("failed to read {}: {}", cert_path, e))?;
+    let key = fs::read(key_path).map_err(|e| format!("failed to read {}: {}", key_path, e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(|e| e.to_string())?;
+
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let https = tls_connector(http, options.accept_invalid_certs, Some(&identity))
+        .map_err(|e| e.to_string())?;
+    let client = Client::builder(TokioExecutor::new()).build(https);
+    let health_client = build_health_check_client(
+        &options.health_check,
+        options.accept_invalid_certs,
+        Some(&identity),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok((client, health_client))
+}
+
+/// One open WebSocket connection counted against its backend's
+/// `max_ws_connections`; the count drops when this is dropped.
+#[derive(Debug)]
+pub struct WsConnection(Arc<AtomicUsize>);
+
+impl Drop for WsConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Most halvings of a throttled backend's weight (down to 1/64).
+pub const MAX_THROTTLE_LEVEL: u32 = 6;
+
+/// Milliseconds since the first call, as a clock for lock-free timestamps.
+fn now_ms() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Backs a backend off after it answers `429`: each one halves its effective weight,
+/// up to `MAX_THROTTLE_LEVEL` times, and every cooldown without another `429` undoes
+/// one halving.
+#[derive(Debug, Default)]
+pub struct WeightThrottle {
+    /// Halvings as of `last_429_ms`
+    level: AtomicU32,
+    last_429_ms: AtomicU64,
+    cooldown_ms: AtomicU64,
+}
+
+impl WeightThrottle {
+    /// Current number of halvings, after recovery since the last `429`.
+    pub fn level(&self) -> u32 {
+        let level = self.level.load(Ordering::Relaxed);
+        if level == 0 {
+            return 0;
+        }
+        let cooldown = self.cooldown_ms.load(Ordering::Relaxed).max(1);
+        let elapsed = now_ms().saturating_sub(self.last_429_ms.load(Ordering::Relaxed));
+        let recovered = u32::try_from(elapsed / cooldown).unwrap_or(u32::MAX);
+        level.saturating_sub(recovered)
+    }
+
+    /// Fraction of the configured weight currently in effect.
+    pub fn factor(&self) -> f64 {
+        0.5f64.powi(self.level() as i32)
+    }
+
+    /// Records a `429`, halving the weight once more; recovery restarts from now.
+    pub fn record_429(&self, cooldown: Duration) {
+        let level = (self.level() + 1).min(MAX_THROTTLE_LEVEL);
+        self.cooldown_ms
+            .store(cooldown.as_millis() as u64, Ordering::Relaxed);
+        self.last_429_ms.store(now_ms(), Ordering::Relaxed);
+        self.level.store(level, Ordering::Relaxed);
+    }
+}
+
+/// One upstream request counted against its backend's `max_concurrent_requests`;
+/// the count drops when this is dropped.
+#[derive(Debug)]
+pub struct InFlightRequest(Arc<AtomicUsize>);
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// One upstream request counted in its backend's queue depth until it gets a
+/// connection; the count drops on `release` or when this is dropped.
+#[derive(Debug)]
+pub struct QueuedRequest {
+    queued: Arc<AtomicUsize>,
+    gauge: Gauge,
+    released: AtomicBool,
+}
+
+impl QueuedRequest {
+    /// Stops counting the request; later calls do nothing.
+    pub fn release(&self) {
+        if !self.released.swap(true, Ordering::Relaxed) {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            self.gauge.decrement(1.0);
+        }
+    }
+}
+
+impl Drop for QueuedRequest {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RouterState {
     pub backends: Vec<RuntimeBackend>,
-    pub method_routes: HashMap<String, String>,
+    pub method_routes: HashMap<String, MethodRoute>,
     pub health_state: Arc<HealthState>,
-    pub proxy_timeout_secs: u64,
+    pub proxy_config: ProxyConfig,
     pub health_check_config: HealthCheckConfig,
 }
 
+impl RouterState {
+    /// Whether `method` may be sent more than once (retries, hedging). Unknown
+    /// methods, and requests without a method (e.g. batches), are never retry-safe.
+    pub fn is_idempotent(&self, method: Option<&str>) -> bool {
+        method.is_some_and(|m| self.proxy_config.idempotent_methods.contains(m))
+    }
+
+    /// Whether `backend` runs in `proxy.local_zone`; always `false` when it is unset.
+    pub fn is_local_zone(&self, backend: &RuntimeBackend) -> bool {
+        self.proxy_config
+            .local_zone
+            .as_ref()
+            .is_some_and(|zone| backend.config.zone.as_ref() == Some(zone))
+    }
+
+    /// Narrows `is_candidate` to backends in the local zone while any of them passes
+    /// it, so traffic only crosses zones once the local zone has nothing usable.
+    fn prefer_local_zone<'a>(
+        &'a self,
+        is_candidate: &'a dyn Fn(&RuntimeBackend) -> bool,
+    ) -> impl Fn(&RuntimeBackend) -> bool + 'a {
+        let is_local = |b: &RuntimeBackend| self.is_local_zone(b) && is_candidate(b);
+        let local_only = self.backends.iter().any(is_local);
+        move |b: &RuntimeBackend| is_candidate(b) && (!local_only || self.is_local_zone(b))
+    }
+
+    /// How long to wait on the first attempt before hedging `method` to a second
+    /// backend, or `None` when the method isn't configured for hedging.
+    pub fn hedge_delay(&self, method: Option<&str>) -> Option<Duration> {
+        let method = method?;
+        (self.is_idempotent(Some(method))
+            && self.proxy_config.hedge_methods.iter().any(|m| m == method))
+        .then(|| Duration::from_millis(self.proxy_config.hedge_delay_ms))
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
-    pub client: Client<HttpsConnector<HttpConnector>, Body>,
+    pub client: HttpClient,
     pub keystore: Arc<dyn KeyStore>,
     pub state: Arc<ArcSwap<RouterState>>,
+    pub start_time: Instant,
+    /// Cleared on SIGTERM so `/readyz` and `/health` fail while in-flight requests drain
+    pub ready: Arc<AtomicBool>,
+    pub selector: Arc<dyn BackendSelector>,
+    /// `[logging]`, replaced on SIGHUP reload
+    pub logging: Arc<ArcSwap<LoggingConfig>>,
+    /// Response rewrites keyed by JSON-RPC method, replaced on SIGHUP reload
+    pub transforms: Arc<ArcSwap<HashMap<String, ResponseTransform>>>,
+    /// Bearer token required by the admin backend API; `None` disables it
+    pub admin_token: Option<String>,
+    /// Successful responses for `[cache]` methods; disabled unless configured
+    pub cache: Arc<ResponseCache>,
+    /// Caps retries across all requests so a failing backend can't trigger a retry storm
+    pub retry_throttle: Arc<RetryThrottle>,
+    /// Per-key request limiting, checked after the key is validated; in process memory
+    /// unless replaced with `with_rate_limiter`
+    pub rate_limiter: Arc<dyn RateLimiter>,
+    /// Whether keys are rejected or provisionally accepted when the keystore errors
+    pub keystore_failure: KeystoreFailurePolicy,
+    /// Latency of every request seen by `track_metrics`, summarized by `/stats`
+    pub latency: Arc<LatencyHistogram>,
+    /// Retires upstream connections after `[upstream] max_connection_lifetime_secs`;
+    /// `None` keeps them for as long as the pool does
+    pub connection_lifetime: Option<Arc<ConnectionLifetime>>,
+    /// Labels recorded in place of grouped methods, with a latency summary per group
+    pub metric_groups: Arc<MetricGroups>,
+    /// Requests in flight per key owner, for `max_concurrent_requests_per_owner`
+    pub owner_concurrency: Arc<OwnerConcurrency>,
+    /// Recent failed requests for `/admin/captures`; disabled unless configured
+    pub captures: Arc<RequestCapture>,
+    /// How backends added through the admin API build their mTLS clients
+    pub client_options: ClientOptions,
+    /// Whether the read and the write group of `read_write_split` had no healthy
+    /// backend when last checked, so each change is logged once
+    split_groups_down: Arc<[AtomicBool; 2]>,
 }
 
 impl AppState {
-    pub fn select_backend(&self, rpc_method: Option<&str>) -> Option<(String, String)> {
-        let state = self.state.load();
-
-        // Check method-specific routing first
-        if let Some(method) = rpc_method {
-            if let Some(backend_label) = state.method_routes.get(method) {
-                // Find the backend by label to check its atomic health
-                if let Some(backend) = state
-                    .backends
-                    .iter()
-                    .find(|b| b.config.label == *backend_label)
-                {
-                    if backend.healthy.load(Ordering::Relaxed) {
-                        debug!("Method {} routed to label={}", method, backend_label);
-                        return Some((backend.config.label.clone(), backend.config.url.clone()));
-                    } else {
-                        info!(
-                            "Method {} target label={} is unhealthy, falling back to weighted selection",
-                            method, backend_label
-                        );
-                    }
-                }
-            }
+    pub fn new(
+        client: HttpClient,
+        keystore: Arc<dyn KeyStore>,
+        state: Arc<ArcSwap<RouterState>>,
+    ) -> Self {
+        let proxy_defaults = ProxyConfig::default();
+        Self {
+            client,
+            keystore,
+            state,
+            start_time: Instant::now(),
+            ready: Arc::new(AtomicBool::new(true)),
+            selector: Arc::new(WeightedRandomSelector),
+            logging: Arc::new(ArcSwap::from_pointee(LoggingConfig::default())),
+            transforms: Arc::new(ArcSwap::from_pointee(HashMap::new())),
+            admin_token: None,
+            cache: Arc::new(ResponseCache::default()),
+            retry_throttle: Arc::new(RetryThrottle::new(
+                proxy_defaults.retry_throttle_max_tokens,
+                proxy_defaults.retry_throttle_token_ratio,
+            )),
+            rate_limiter: Arc::new(InMemoryRateLimiter::new()),
+            keystore_failure: KeystoreFailurePolicy::default(),
+            latency: Arc::new(LatencyHistogram::new()),
+            connection_lifetime: None,
+            metric_groups: Arc::new(MetricGroups::default()),
+            owner_concurrency: Arc::new(OwnerConcurrency::new()),
+            captures: Arc::new(RequestCapture::default()),
+            client_options: ClientOptions::default(),
+            split_groups_down: Arc::default(),
         }
+    }
 
-        // Filter out unhealthy backends (lock-free)
-        let healthy_backends: Vec<&RuntimeBackend> = state
-            .backends
-            .iter()
-            .filter(|b| b.healthy.load(Ordering::Relaxed))
-            .collect();
+    /// Settings for the mTLS clients of backends added at runtime.
+    pub fn with_client_options(mut self, options: ClientOptions) -> Self {
+        self.client_options = options;
+        self
+    }
 
-        if healthy_backends.is_empty() {
-            return None; // No healthy backends available
-        }
+    /// Keeps recent failed requests for `/admin/captures`.
+    pub fn with_request_capture(mut self, captures: RequestCapture) -> Self {
+        self.captures = Arc::new(captures);
+        self
+    }
+
+    /// Records grouped methods under their `[metric_groups]` label.
+    pub fn with_metric_groups(mut self, metric_groups: MetricGroups) -> Self {
+        self.metric_groups = Arc::new(metric_groups);
+        self
+    }
 
-        // Calculate total weight of healthy backends
-        let healthy_total_weight: u32 = healthy_backends.iter().map(|b| b.config.weight).sum();
+    /// Retires each upstream connection once it has been in use for `max_lifetime`.
+    pub fn with_max_connection_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.connection_lifetime = Some(Arc::new(ConnectionLifetime::new(max_lifetime)));
+        self
+    }
 
-        if healthy_total_weight == 0 {
-            return healthy_backends
-                .first()
-                .map(|b| (b.config.label.clone(), b.config.url.clone()));
+    /// Sends `req` upstream over `client`, recycling connections past their lifetime.
+    pub async fn send_upstream(
+        &self,
+        client: &HttpClient,
+        req: Request<Body>,
+    ) -> Result<Response<Incoming>, hyper_util::client::legacy::Error> {
+        match &self.connection_lifetime {
+            Some(lifetime) => lifetime.request(client, req).await,
+            None => client.request(req).await,
         }
+    }
+
+    /// Limits each key to its `rate_limit` with `limiter`.
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn RateLimiter>) -> Self {
+        self.rate_limiter = limiter;
+        self
+    }
+
+    /// Sets how keystore errors are handled; fail closed by default.
+    pub fn with_keystore_failure(mut self, policy: KeystoreFailurePolicy) -> Self {
+        self.keystore_failure = policy;
+        self
+    }
+
+    /// Replaces the retry token bucket built from `ProxyConfig` defaults.
+    pub fn with_retry_throttle(mut self, retry_throttle: Arc<RetryThrottle>) -> Self {
+        self.retry_throttle = retry_throttle;
+        self
+    }
 
-        // Weighted random selection among healthy backends
-        let mut rng = rand::thread_rng();
-        let mut random_weight = rng.gen_range(0..healthy_total_weight);
+    /// Serves and stores responses for cached methods through `cache`.
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Enables the `/admin/backends` API, authenticated with `token`.
+    pub fn with_admin_token(mut self, token: Option<String>) -> Self {
+        self.admin_token = token;
+        self
+    }
 
-        for backend in &healthy_backends {
-            if random_weight < backend.config.weight {
-                return Some((backend.config.label.clone(), backend.config.url.clone()));
+    /// Sets the per-method response rewrites applied by `proxy`.
+    pub fn with_transforms(mut self, transforms: HashMap<String, ResponseTransform>) -> Self {
+        self.transforms = Arc::new(ArcSwap::from_pointee(transforms));
+        self
+    }
+
+    /// Sets the slow-request threshold and sampling used by `log_requests`.
+    pub fn with_logging(mut self, logging: LoggingConfig) -> Self {
+        self.logging = Arc::new(ArcSwap::from_pointee(logging));
+        self
+    }
+
+    /// Replaces the default weighted-random strategy with a custom `BackendSelector`.
+    pub fn with_selector(mut self, selector: Arc<dyn BackendSelector>) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    pub fn select_backend(&self, rpc_method: Option<&str>) -> Option<(String, String)> {
+        let ctx = SelectionContext {
+            rpc_method,
+            ..Default::default()
+        };
+        self.select_backend_for(&ctx, &[])
+            .map(|selection| (selection.label().to_string(), selection.url().to_string()))
+    }
+
+    /// Selects a backend for an HTTP request, never returning one whose label is in
+    /// `excluded` (used when retrying against a different backend). The returned
+    /// `BackendSelection` gives direct access to the chosen `RuntimeBackend`.
+    ///
+    /// The decision is recorded on a debug-level `select_backend` span: the candidate
+    /// set with health, the method route (if one applied), and the chosen label.
+    pub fn select_backend_for(
+        &self,
+        ctx: &SelectionContext<'_>,
+        excluded: &[String],
+    ) -> Option<BackendSelection> {
+        let state = self.state.load_full();
+        let span = debug_span!(
+            "select_backend",
+            rpc_method = ctx.rpc_method,
+            candidates = field::Empty,
+            method_route = field::Empty,
+            commitment_group = field::Empty,
+            chosen = field::Empty,
+        );
+        let _guard = span.enter();
+        record_candidates(&span, &state);
+
+        let selection = self.pick_backend(state, ctx, excluded);
+        span.record("chosen", selection.as_ref().map_or("none", |s| s.label()));
+        selection
+    }
+
+    fn pick_backend(
+        &self,
+        state: Arc<RouterState>,
+        ctx: &SelectionContext<'_>,
+        excluded: &[String],
+    ) -> Option<BackendSelection> {
+        // Check method-specific routing first: the route's backends in order
+        if let Some((method, route)) = ctx
+            .rpc_method
+            .and_then(|method| Some((method, state.method_routes.get(method)?)))
+        {
+            let routed = route.backends.iter().find_map(|label| {
+                // Find the backend by label to check its atomic health
+                let Some(index) = state.backends.iter().position(|b| b.config.label == *label)
+                else {
+                    // Validation rules this out for loaded configs, so the config and
+                    // the backend list have drifted apart (e.g. an admin removal)
+                    error!(
+                        "Method {} is routed to backend {}, which doesn't exist",
+                        method, label
+                    );
+                    counter!(
+                        "rpc_route_misconfigured_total",
+                        "rpc_method" => method.to_string(),
+                        "backend" => label.clone()
+                    )
+                    .increment(1);
+                    return None;
+                };
+                let backend = &state.backends[index];
+                (backend.healthy.load(Ordering::Relaxed)
+                    && backend.has_request_capacity()
+                    && !excluded.contains(label))
+                .then_some(index)
+            });
+            if let Some(index) = routed {
+                let backend_label = state.backends[index].config.label.as_str();
+                debug!("Method {} routed to label={}", method, backend_label);
+                Span::current().record("method_route", backend_label);
+                return Some(BackendSelection { state, index });
+            } else if route.strict {
+                info!(
+                    "Method {} is strictly pinned to {}, none of which is available",
+                    method, route
+                );
+                return None;
+            } else if route.backends.iter().any(|label| !excluded.contains(label)) {
+                info!(
+                    "Method {} targets {} are unhealthy, falling back to weighted selection",
+                    method, route
+                );
             }
-            random_weight -= backend.config.weight;
         }
 
-        // Fallback (should never reach here if weights are valid)
-        healthy_backends
-            .first()
-            .map(|b| (b.config.label.clone(), b.config.url.clone()))
+        // Filter out unhealthy and saturated backends (lock-free, no allocation)
+        let is_available = |b: &RuntimeBackend| {
+            !b.is_fallback()
+                && b.healthy.load(Ordering::Relaxed)
+                && b.has_request_capacity()
+                && !excluded.contains(&b.config.label)
+        };
+        // With a read/write split, writes stay within the write group and reads within
+        // the read group while any of it is usable. Reads then go to any backend, and
+        // writes only with `spill_writes`
+        let split = state.proxy_config.read_write_split.as_ref();
+        let split_group = split.map_or(&[][..], |split| split.group_for(ctx.rpc_method));
+        if let Some(split) = split.filter(|_| !split_group.is_empty()) {
+            self.note_split_group(&state, split, ctx.rpc_method);
+        }
+        let in_split_group =
+            |b: &RuntimeBackend| split_group.contains(&b.config.label) && is_available(b);
+        let is_healthy: &dyn Fn(&RuntimeBackend) -> bool =
+            if state.backends.iter().any(in_split_group) {
+                &in_split_group
+            } else if split.is_some_and(|split| {
+                !split_group.is_empty() && split.is_write(ctx.rpc_method) && !split.spill_writes
+            }) {
+                return None;
+            } else {
+                &is_available
+            };
+        // A request naming a commitment level stays within that level's group while
+        // any of it is usable
+        let in_group = |b: &RuntimeBackend| {
+            ctx.commitment
+                .is_some_and(|c| b.config.commitments.iter().any(|bc| bc == c))
+                && is_healthy(b)
+        };
+        let is_usable: &dyn Fn(&RuntimeBackend) -> bool = if state.backends.iter().any(in_group) {
+            Span::current().record("commitment_group", ctx.commitment);
+            &in_group
+        } else {
+            &is_healthy
+        };
+        // The default backend takes the request whenever it can
+        if let Some(index) = primary_index(&state.backends, &is_usable) {
+            return Some(BackendSelection { state, index });
+        }
+        let Some(tier) = lowest_tier(&state.backends, &is_usable) else {
+            // No healthy backends available; use the fallback if there is one
+            return fallback_selection(state, |b| {
+                b.has_request_capacity() && !excluded.contains(&b.config.label)
+            });
+        };
+        let in_tier = |b: &RuntimeBackend| b.config.tier == tier && is_usable(b);
+        let index = {
+            let is_candidate = state.prefer_local_zone(&in_tier);
+            let healthy_backends = Candidates::new(&state.backends, &is_candidate);
+            let chosen = self.selector.select(&healthy_backends, ctx)?;
+            candidate_index(&healthy_backends, chosen)?
+        };
+        Some(BackendSelection { state, index })
+    }
+
+    /// Logs when the read/write split group serving `method` loses its last healthy
+    /// backend, and when it has one again, rather than on every request meanwhile.
+    fn note_split_group(&self, state: &RouterState, split: &ReadWriteSplit, method: Option<&str>) {
+        let writes = split.is_write(method);
+        let group = split.group_for(method);
+        let down = !state
+            .backends
+            .iter()
+            .any(|b| group.contains(&b.config.label) && b.healthy.load(Ordering::Relaxed));
+        if self.split_groups_down[usize::from(writes)].swap(down, Ordering::Relaxed) == down {
+            return;
+        }
+        let name = if writes { "write" } else { "read" };
+        if !down {
+            info!(
+                "A {} backend of the read/write split is healthy again",
+                name
+            );
+        } else if writes && !split.spill_writes {
+            warn!("No write backend of the read/write split is healthy, failing writes");
+        } else {
+            warn!(
+                "No {} backend of the read/write split is healthy, sending {}s to any backend",
+                name, name
+            );
+        }
     }
 
     /// Select a healthy backend that has WebSocket support (ws_url configured)
     pub fn select_ws_backend(&self) -> Option<(String, String)> {
-        let state = self.state.load();
+        self.select_ws_backend_for(&SelectionContext::default(), &[])
+            .and_then(|selection| {
+                let ws_url = selection.ws_url()?.to_string();
+                Some((selection.label().to_string(), ws_url))
+            })
+    }
 
-        // Filter to backends with ws_url configured and healthy (lock-free)
-        let ws_backends: Vec<&RuntimeBackend> = state
-            .backends
-            .iter()
-            .filter(|b| b.config.ws_url.is_some() && b.healthy.load(Ordering::Relaxed))
-            .collect();
+    /// Same as `select_ws_backend`, passing request details through to the selector and
+    /// skipping the `excluded` labels. The chosen backend is guaranteed to have a
+    /// `ws_url`.
+    pub fn select_ws_backend_for(
+        &self,
+        ctx: &SelectionContext<'_>,
+        excluded: &[String],
+    ) -> Option<BackendSelection> {
+        let state = self.state.load_full();
+        let span = debug_span!(
+            "select_ws_backend",
+            candidates = field::Empty,
+            chosen = field::Empty,
+        );
+        let _guard = span.enter();
+        record_candidates(&span, &state);
+
+        let selection = self.pick_ws_backend(state, ctx, excluded);
+        span.record("chosen", selection.as_ref().map_or("none", |s| s.label()));
+        selection
+    }
 
-        if ws_backends.is_empty() {
-            return None;
+    fn pick_ws_backend(
+        &self,
+        state: Arc<RouterState>,
+        ctx: &SelectionContext<'_>,
+        excluded: &[String],
+    ) -> Option<BackendSelection> {
+        // Filter to backends with ws_url configured, healthy, below their connection
+        // cap and not excluded (lock-free, no allocation)
+        let is_usable = |b: &RuntimeBackend| {
+            !b.is_fallback()
+                && b.config.ws_url.is_some()
+                && b.healthy.load(Ordering::Relaxed)
+                && b.has_ws_capacity()
+                && !excluded.contains(&b.config.label)
+        };
+        if let Some(index) = primary_index(&state.backends, &is_usable) {
+            return Some(BackendSelection { state, index });
         }
+        let Some(tier) = lowest_tier(&state.backends, &is_usable) else {
+            return fallback_selection(state, |b| {
+                b.config.ws_url.is_some()
+                    && b.has_ws_capacity()
+                    && !excluded.contains(&b.config.label)
+            });
+        };
+        let in_tier = |b: &RuntimeBackend| b.config.tier == tier && is_usable(b);
+        let index = {
+            let is_candidate = state.prefer_local_zone(&in_tier);
+            let ws_backends = Candidates::new(&state.backends, &is_candidate);
+            let chosen = self.selector.select(&ws_backends, ctx)?;
+            candidate_index(&ws_backends, chosen)?
+        };
+        Some(BackendSelection { state, index })
+    }
+}
 
-        // Calculate total weight of WebSocket-capable backends
-        let total_weight: u32 = ws_backends.iter().map(|b| b.config.weight).sum();
+/// Backend query parameters followed by the client's, minus any the backend already
+/// sets so a provider key in the backend URL can't be overridden.
+fn merge_query(base: Option<&str>, client: &str) -> String {
+    let Some(base) = base else {
+        return client.to_string();
+    };
+    let name = |pair: &str| pair.split('=').next().unwrap_or_default().to_string();
+    let base_names: Vec<String> = base.split('&').map(name).collect();
+    let mut merged = base.to_string();
+    for pair in client.split('&').filter(|p| !p.is_empty()) {
+        if !base_names.contains(&name(pair)) {
+            merged.push('&');
+            merged.push_str(pair);
+        }
+    }
+    merged
+}
 
-        // Weighted random selection
-        let mut rng = rand::thread_rng();
-        let mut random_weight = rng.gen_range(0..total_weight);
+/// Position of the default backend, if there is one and it passes `is_usable`.
+fn primary_index(
+    backends: &[RuntimeBackend],
+    is_usable: &dyn Fn(&RuntimeBackend) -> bool,
+) -> Option<usize> {
+    backends.iter().position(|b| b.is_primary() && is_usable(b))
+}
 
-        for backend in &ws_backends {
-            if random_weight < backend.config.weight {
-                return Some((
-                    backend.config.label.clone(),
-                    backend.config.ws_url.as_ref().unwrap().clone(),
-                ));
-            }
-            random_weight -= backend.config.weight;
-        }
+/// Lowest tier that has a backend passing `is_usable`; selection stays within it.
+fn lowest_tier(
+    backends: &[RuntimeBackend],
+    is_usable: &dyn Fn(&RuntimeBackend) -> bool,
+) -> Option<u32> {
+    backends
+        .iter()
+        .filter(|b| is_usable(b))
+        .map(|b| b.config.tier)
+        .min()
+}
 
-        // Fallback
-        ws_backends.first().map(|b| {
-            (
-                b.config.label.clone(),
-                b.config.ws_url.as_ref().unwrap().clone(),
-            )
+/// Records every backend as `label=healthy|unhealthy` on a selection span. Skipped when
+/// the span is disabled, so selection doesn't format strings at normal log levels.
+fn record_candidates(span: &Span, state: &RouterState) {
+    if span.is_disabled() {
+        return;
+    }
+    let candidates = state
+        .backends
+        .iter()
+        .map(|b| {
+            let health = if b.healthy.load(Ordering::Relaxed) {
+                "healthy"
+            } else {
+                "unhealthy"
+            };
+            format!("{}={}", b.config.label, health)
         })
+        .collect::<Vec<_>>()
+        .join(",");
+    span.record("candidates", candidates.as_str());
+}
+
+/// Selects the fallback backend when it is configured, healthy, and accepted by `usable`.
+fn fallback_selection(
+    state: Arc<RouterState>,
+    usable: impl Fn(&RuntimeBackend) -> bool,
+) -> Option<BackendSelection> {
+    let index = state.backends.iter().position(|b| b.is_fallback())?;
+    let fallback = &state.backends[index];
+    if !fallback.healthy.load(Ordering::Relaxed) || !usable(fallback) {
+        return None;
+    }
+    warn!(
+        "No healthy backends, routing to fallback backend label={}",
+        fallback.config.label
+    );
+    Some(BackendSelection { state, index })
+}
+
+/// Position of `chosen` in `RouterState::backends`, compared by address so that
+/// selectors returning a candidate reference never require a second lookup by label.
+/// A selector returning anything else, such as a backend kept from an earlier
+/// `RouterState`, is a bug in the selector: it is logged and the request gets no backend.
+fn candidate_index(candidates: &Candidates<'_>, chosen: &RuntimeBackend) -> Option<usize> {
+    let index = candidates.position(chosen);
+    if index.is_none() {
+        error!(
+            "Backend selector returned {}, which is not one of the request's candidates",
+            chosen.config.label
+        );
+    }
+    index
+}
+
+/// A chosen backend, together with the `RouterState` snapshot it was selected from.
+///
+/// Holding the snapshot keeps `index` valid across a concurrent config reload, so the
+/// proxy can reach the backend's atomics for the whole lifetime of a request.
+#[derive(Debug, Clone)]
+pub struct BackendSelection {
+    state: Arc<RouterState>,
+    index: usize,
+}
+
+impl BackendSelection {
+    /// Index of the backend in `RouterState::backends`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn backend(&self) -> &RuntimeBackend {
+        &self.state.backends[self.index]
+    }
+
+    pub fn label(&self) -> &str {
+        &self.backend().config.label
+    }
+
+    pub fn url(&self) -> &str {
+        &self.backend().config.url
+    }
+
+    pub fn ws_url(&self) -> Option<&str> {
+        self.backend().config.ws_url.as_deref()
     }
 }
@@ -0,0 +1,225 @@
+//! In-process request latency summary served by `/stats`, for a quick look at a running
+//! router without Prometheus, the method groups that label request metrics, and the
+//! estimated spend per backend.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::keystore::KeyCacheStats;
+
+/// Upper bound of the first bucket, in microseconds.
+const FIRST_BOUND_US: f64 = 50.0;
+/// Each bucket is this much wider than the previous one, so a percentile is reported
+/// within 20% of the true value.
+const GROWTH: f64 = 1.2;
+/// Latencies above this land in a final overflow bucket.
+const MAX_BOUND_US: f64 = 120_000_000.0;
+
+/// Fixed-bucket latency histogram with exponentially growing buckets. Memory stays
+/// constant however many requests are recorded, and recording is a single atomic add.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    /// Inclusive upper bound of each bucket, in microseconds
+    bounds: Vec<u64>,
+    /// One count per bound, plus the overflow bucket
+    counts: Vec<AtomicU64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        let mut bounds = Vec::new();
+        let mut bound = FIRST_BOUND_US;
+        while bound < MAX_BOUND_US {
+            bounds.push(bound.round() as u64);
+            bound *= GROWTH;
+        }
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { bounds, counts }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let bucket = self.bounds.partition_point(|&bound| bound < micros);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Request count and P50/P95/P99 so far. Each percentile is the upper bound of the
+    /// bucket it falls in; the overflow bucket reports the largest bound.
+    pub fn snapshot(&self) -> LatencySnapshot {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        let requests = counts.iter().sum();
+        let percentile = |q: f64| {
+            if requests == 0 {
+                return None;
+            }
+            let rank = ((q * requests as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            let bucket = counts
+                .iter()
+                .position(|&count| {
+                    seen += count;
+                    seen >= rank
+                })
+                .unwrap_or(counts.len() - 1);
+            let bound = self.bounds[bucket.min(self.bounds.len() - 1)];
+            Some(bound as f64 / 1000.0)
+        };
+        LatencySnapshot {
+            requests,
+            p50_ms: percentile(0.50),
+            p95_ms: percentile(0.95),
+            p99_ms: percentile(0.99),
+        }
+    }
+}
+
+/// Request count and latency percentiles. Percentiles are `null` until a request has
+/// been recorded.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LatencySnapshot {
+    pub requests: u64,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+/// Semantic grouping of JSON-RPC methods for dashboards, from `[metric_groups]`: each
+/// pattern (an exact method, `prefix*` or `*suffix`) maps to the label recorded in place
+/// of the method. An exact match wins over patterns, and a longer pattern over a
+/// shorter one. Each group also gets its own latency summary in `/stats`.
+#[derive(Debug, Default)]
+pub struct MetricGroups {
+    exact: HashMap<String, String>,
+    /// `(prefix, suffix, label)`, longest pattern first
+    wildcards: Vec<(String, String, String)>,
+    latency: BTreeMap<String, LatencyHistogram>,
+}
+
+impl MetricGroups {
+    /// Builds the groups from pattern → label pairs, as checked when the config is
+    /// loaded.
+    pub fn new(patterns: &HashMap<String, String>) -> Self {
+        let mut groups = Self::default();
+        for (pattern, label) in patterns {
+            match pattern.split_once('*') {
+                Some((prefix, suffix)) => {
+                    groups
+                        .wildcards
+                        .push((prefix.to_string(), suffix.to_string(), label.clone()))
+                }
+                None => {
+                    groups.exact.insert(pattern.clone(), label.clone());
+                }
+            }
+            groups.latency.entry(label.clone()).or_default();
+        }
+        groups.wildcards.sort_by(|a, b| {
+            (b.0.len() + b.1.len())
+                .cmp(&(a.0.len() + a.1.len()))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        groups
+    }
+
+    /// The group `method` belongs to, if any.
+    pub fn group(&self, method: &str) -> Option<&str> {
+        if let Some(label) = self.exact.get(method) {
+            return Some(label);
+        }
+        self.wildcards
+            .iter()
+            .find(|(prefix, suffix, _)| {
+                method.len() >= prefix.len() + suffix.len()
+                    && method.starts_with(prefix.as_str())
+                    && method.ends_with(suffix.as_str())
+            })
+            .map(|(_, _, label)| label.as_str())
+    }
+
+    /// Label recorded for `method`: its group, or the method itself.
+    pub fn label<'a>(&'a self, method: &'a str) -> &'a str {
+        self.group(method).unwrap_or(method)
+    }
+
+    /// Adds `latency` to the summary of `method`'s group, if it has one.
+    pub fn record(&self, method: &str, latency: Duration) {
+        if let Some(histogram) = self.group(method).and_then(|g| self.latency.get(g)) {
+            histogram.record(latency);
+        }
+    }
+
+    /// Latency summary of every group, by label.
+    pub fn snapshots(&self) -> BTreeMap<String, LatencySnapshot> {
+        self.latency
+            .iter()
+            .map(|(label, histogram)| (label.clone(), histogram.snapshot()))
+            .collect()
+    }
+}
+
+/// Requests one backend has served and what they cost by its configured `cost`, so
+/// operators can see how traffic is spread across pricing tiers. Kept on the
+/// `RuntimeBackend` and read by `/stats`.
+#[derive(Debug, Default)]
+pub struct CostCounter {
+    requests: AtomicU64,
+    estimated_cost: AtomicU64,
+}
+
+/// One backend's share of the estimated spend.
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct BackendCost {
+    pub requests: u64,
+    /// Sum of the backend's `cost` over its requests
+    pub estimated_cost: u64,
+}
+
+impl CostCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one request served at `cost`.
+    pub fn record(&self, cost: u32) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.estimated_cost
+            .fetch_add(u64::from(cost), Ordering::Relaxed);
+    }
+
+    /// Spend so far.
+    pub fn snapshot(&self) -> BackendCost {
+        BackendCost {
+            requests: self.requests.load(Ordering::Relaxed),
+            estimated_cost: self.estimated_cost.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Body of `/stats`: the overall summary, plus one per `[metric_groups]` label, the
+/// estimated cost per backend, the keystore's cache hit rate when it has a cache, and
+/// the requests waiting for a connection to each backend.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    pub overall: LatencySnapshot,
+    pub groups: BTreeMap<String, LatencySnapshot>,
+    pub costs: BTreeMap<String, BackendCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keystore_cache: Option<KeyCacheStats>,
+    pub upstream_queue: BTreeMap<String, usize>,
+}
@@ -0,0 +1,112 @@
+//! Bookkeeping of Solana PubSub subscriptions on a proxied WebSocket connection.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A client frame, or the fields of a backend frame the tracker cares about.
+#[derive(Deserialize)]
+struct Frame {
+    method: Option<String>,
+    id: Option<Value>,
+    #[serde(default)]
+    params: Value,
+    result: Option<Value>,
+}
+
+#[derive(Debug)]
+enum Pending {
+    /// `*Subscribe` request, by method
+    Subscribe(String),
+    /// `*Unsubscribe` request for an upstream subscription id
+    Unsubscribe(u64),
+}
+
+/// Active subscriptions of one connection, learned by watching frames in both
+/// directions: a `*Subscribe` request becomes active once the backend answers it with
+/// a subscription id, and is dropped when a matching `*Unsubscribe` succeeds.
+///
+/// Frames that aren't single JSON-RPC messages are ignored; the tracker never changes
+/// what is forwarded.
+#[derive(Debug, Default)]
+pub struct SubscriptionTracker {
+    /// Requests awaiting a response, keyed by their serialized `id`
+    pending: HashMap<String, Pending>,
+    /// Upstream subscription id → subscribe method
+    active: HashMap<u64, String>,
+    notifications: u64,
+}
+
+impl SubscriptionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a text frame sent by the client.
+    pub fn client_frame(&mut self, text: &str) {
+        let Ok(frame) = serde_json::from_str::<Frame>(text) else {
+            return;
+        };
+        let (Some(method), Some(id)) = (frame.method, frame.id) else {
+            return;
+        };
+        let pending = if method.ends_with("Unsubscribe") {
+            match frame.params.get(0).and_then(Value::as_u64) {
+                Some(subscription) => Pending::Unsubscribe(subscription),
+                None => return,
+            }
+        } else if method.ends_with("Subscribe") {
+            Pending::Subscribe(method)
+        } else {
+            return;
+        };
+        self.pending.insert(id.to_string(), pending);
+    }
+
+    /// Records a text frame sent by the backend: responses to tracked requests and
+    /// subscription notifications.
+    pub fn backend_frame(&mut self, text: &str) {
+        let Ok(frame) = serde_json::from_str::<Frame>(text) else {
+            return;
+        };
+        if frame
+            .method
+            .as_deref()
+            .is_some_and(|m| m.ends_with("Notification"))
+        {
+            self.notifications += 1;
+            return;
+        }
+        let Some(pending) = frame.id.and_then(|id| self.pending.remove(&id.to_string())) else {
+            return;
+        };
+        match (pending, frame.result) {
+            (Pending::Subscribe(method), Some(Value::Number(n))) => {
+                if let Some(subscription) = n.as_u64() {
+                    self.active.insert(subscription, method);
+                }
+            }
+            (Pending::Unsubscribe(subscription), Some(Value::Bool(true))) => {
+                self.active.remove(&subscription);
+            }
+            // Errors leave the subscription set unchanged
+            _ => {}
+        }
+    }
+
+    /// Number of active subscriptions.
+    pub fn active(&self) -> usize {
+        self.active.len()
+    }
+
+    /// The `*Subscribe` method that created upstream subscription `subscription`.
+    pub fn method_of(&self, subscription: u64) -> Option<&str> {
+        self.active.get(&subscription).map(String::as_str)
+    }
+
+    /// Subscription notifications received from the backend.
+    pub fn notifications(&self) -> u64 {
+        self.notifications
+    }
+}
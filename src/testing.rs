@@ -0,0 +1,421 @@
+//! In-process upstream backends (HTTP and WebSocket) for tests. Only compiled with the
+//! `testing` feature.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{
+        header::{CONTENT_TYPE, HOST, TRAILER},
+        HeaderMap, HeaderName, HeaderValue, StatusCode, Uri,
+    },
+    response::{IntoResponse, Response},
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::StreamBody;
+use hyper::body::Frame;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+const DEFAULT_BODY: &str = r#"{"jsonrpc":"2.0","result":"ok","id":1}"#;
+
+/// Builder for a mock JSON-RPC backend that answers every request (any path or method)
+/// with a canned response.
+///
+/// ```ignore
+/// let backend = MockBackend::new().status(503).start().await;
+/// // point a Backend config at backend.url
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    status: StatusCode,
+    body: String,
+    delay: Option<Duration>,
+    fail_after: Option<usize>,
+    fail_first: usize,
+    echo_id: bool,
+    trailers: Vec<(String, String)>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    /// A backend returning `200` with `{"jsonrpc":"2.0","result":"ok","id":1}`.
+    pub fn new() -> Self {
+        Self {
+            status: StatusCode::OK,
+            body: DEFAULT_BODY.to_string(),
+            delay: None,
+            fail_after: None,
+            fail_first: 0,
+            echo_id: false,
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Respond with this HTTP status.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = StatusCode::from_u16(status).expect("invalid HTTP status");
+        self
+    }
+
+    /// Respond with this body instead of the default JSON-RPC result.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Respond with a JSON-RPC result carrying the given `id`.
+    pub fn result_with_id(self, id: u64) -> Self {
+        self.body(format!(r#"{{"jsonrpc":"2.0","result":"ok","id":{}}}"#, id))
+    }
+
+    /// Copy each request's `id` into the response body, like a real JSON-RPC server.
+    pub fn echo_id(mut self) -> Self {
+        self.echo_id = true;
+        self
+    }
+
+    /// Respond with a JSON-RPC error object (HTTP status is left unchanged).
+    pub fn rpc_error(self, code: i64, message: &str) -> Self {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "error": { "code": code, "message": message },
+            "id": 1
+        });
+        self.body(body.to_string())
+    }
+
+    /// Wait this long before responding, e.g. to trigger proxy timeouts.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Serve the configured response for the first `n` requests, then answer `503`.
+    pub fn fail_after(mut self, n: usize) -> Self {
+        self.fail_after = Some(n);
+        self
+    }
+
+    /// Answer `503` to the first `n` requests, then serve the configured response.
+    pub fn fail_first(mut self, n: usize) -> Self {
+        self.fail_first = n;
+        self
+    }
+
+    /// Send this trailer after the body, declared in the `Trailer` header. Like any
+    /// HTTP/1.1 server, it is only sent to requests carrying `TE: trailers`.
+    pub fn trailer(mut self, name: &str, value: &str) -> Self {
+        self.trailers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Binds a random loopback port and serves in a background task.
+    pub async fn start(self) -> RunningMockBackend {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock backend");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let times = Arc::new(Mutex::new(Vec::new()));
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let peers = Arc::new(Mutex::new(Vec::new()));
+
+        let config = Arc::new(self);
+        let counter = requests.clone();
+        let seen_paths = paths.clone();
+        let seen_times = times.clone();
+        let seen_headers = headers.clone();
+        let seen_bodies = bodies.clone();
+        let seen_peers = peers.clone();
+        let app = Router::new().fallback(
+            move |ConnectInfo(peer): ConnectInfo<SocketAddr>,
+                  uri: Uri,
+                  headers: HeaderMap,
+                  body: Bytes| {
+                let config = config.clone();
+                let counter = counter.clone();
+                let seen_paths = seen_paths.clone();
+                let seen_times = seen_times.clone();
+                let seen_headers = seen_headers.clone();
+                let seen_bodies = seen_bodies.clone();
+                let seen_peers = seen_peers.clone();
+                async move {
+                    seen_times.lock().unwrap().push(Instant::now());
+                    seen_peers.lock().unwrap().push(peer);
+                    let path = uri.path_and_query().map_or("/", |pq| pq.as_str());
+                    seen_paths.lock().unwrap().push(path.to_string());
+                    seen_headers.lock().unwrap().push(headers);
+                    seen_bodies
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(&body).into_owned());
+                    config
+                        .respond(counter.fetch_add(1, Ordering::SeqCst), &body)
+                        .await
+                }
+            },
+        );
+
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        RunningMockBackend {
+            url: format!("http://{}", addr),
+            addr,
+            requests,
+            paths,
+            times,
+            headers,
+            bodies,
+            peers,
+        }
+    }
+
+    async fn respond(&self, request_index: usize, request: &[u8]) -> Response {
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        if request_index < self.fail_first || self.fail_after.is_some_and(|n| request_index >= n) {
+            return (StatusCode::SERVICE_UNAVAILABLE, "mock backend failure").into_response();
+        }
+
+        let body = match self.echo_id.then(|| echo_request_id(&self.body, request)) {
+            Some(Some(body)) => body,
+            _ => self.body.clone(),
+        };
+        if self.trailers.is_empty() {
+            return (self.status, [(CONTENT_TYPE, "application/json")], body).into_response();
+        }
+
+        let mut trailers = HeaderMap::new();
+        for (name, value) in &self.trailers {
+            trailers.append(
+                HeaderName::from_bytes(name.as_bytes()).expect("invalid trailer name"),
+                HeaderValue::from_str(value).expect("invalid trailer value"),
+            );
+        }
+        let declared = self
+            .trailers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let frames = [Frame::data(Bytes::from(body)), Frame::trailers(trailers)];
+        let body = StreamBody::new(futures_util::stream::iter(
+            frames.map(Ok::<_, std::convert::Infallible>),
+        ));
+        (
+            self.status,
+            [
+                (CONTENT_TYPE, "application/json".to_string()),
+                (TRAILER, declared),
+            ],
+            Body::new(body),
+        )
+            .into_response()
+    }
+}
+
+/// `response` with its `id` replaced by the one in `request`.
+fn echo_request_id(response: &str, request: &[u8]) -> Option<String> {
+    let request: serde_json::Value = serde_json::from_slice(request).ok()?;
+    let mut response: serde_json::Value = serde_json::from_str(response).ok()?;
+    response["id"] = request.get("id")?.clone();
+    Some(response.to_string())
+}
+
+/// Handle to a started `MockBackend`. The server runs until the test's runtime shuts down.
+#[derive(Debug, Clone)]
+pub struct RunningMockBackend {
+    /// Base URL to use as a `Backend::url`
+    pub url: String,
+    pub addr: SocketAddr,
+    requests: Arc<AtomicUsize>,
+    paths: Arc<Mutex<Vec<String>>>,
+    times: Arc<Mutex<Vec<Instant>>>,
+    headers: Arc<Mutex<Vec<HeaderMap>>>,
+    bodies: Arc<Mutex<Vec<String>>>,
+    peers: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl RunningMockBackend {
+    /// Number of requests received so far.
+    pub fn request_count(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Path and query of every request received, in arrival order.
+    pub fn request_paths(&self) -> Vec<String> {
+        self.paths.lock().unwrap().clone()
+    }
+
+    /// Arrival time of every request received, in arrival order.
+    pub fn request_times(&self) -> Vec<Instant> {
+        self.times.lock().unwrap().clone()
+    }
+
+    /// `Host` header of every request received (empty when absent), in arrival order.
+    pub fn request_hosts(&self) -> Vec<String> {
+        self.request_headers()
+            .iter()
+            .map(|headers| {
+                let host = headers.get(HOST).and_then(|h| h.to_str().ok());
+                host.unwrap_or_default().to_string()
+            })
+            .collect()
+    }
+
+    /// Headers of every request received, in arrival order.
+    pub fn request_headers(&self) -> Vec<HeaderMap> {
+        self.headers.lock().unwrap().clone()
+    }
+
+    /// Body of every request received, in arrival order.
+    pub fn request_bodies(&self) -> Vec<String> {
+        self.bodies.lock().unwrap().clone()
+    }
+
+    /// Client address of every request received, in arrival order; requests from the
+    /// same address shared a connection.
+    pub fn request_peers(&self) -> Vec<SocketAddr> {
+        self.peers.lock().unwrap().clone()
+    }
+}
+
+/// Builder for a mock WebSocket upstream that echoes Text and Binary frames back to the
+/// sender. Pings are answered with pongs by the WebSocket implementation itself.
+///
+/// ```ignore
+/// let backend = MockWsBackend::new().close_after(1).start().await;
+/// // point a Backend config's ws_url at backend.url
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockWsBackend {
+    close_after: Option<usize>,
+    fail_first: usize,
+}
+
+impl MockWsBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Echo the first `n` data frames of each connection, then close it from the
+    /// backend side.
+    pub fn close_after(mut self, n: usize) -> Self {
+        self.close_after = Some(n);
+        self
+    }
+
+    /// Drop the first `n` connections before the WebSocket handshake.
+    pub fn fail_first(mut self, n: usize) -> Self {
+        self.fail_first = n;
+        self
+    }
+
+    /// Binds a random loopback port and serves in a background task.
+    pub async fn start(self) -> RunningMockWsBackend {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock WebSocket backend");
+        let addr = listener.local_addr().unwrap();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let closes = Arc::new(AtomicUsize::new(0));
+
+        let seen_frames = frames.clone();
+        let seen_closes = closes.clone();
+        tokio::spawn(async move {
+            let mut accepted = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted += 1;
+                if accepted <= self.fail_first {
+                    drop(stream);
+                    continue;
+                }
+                let seen_frames = seen_frames.clone();
+                let seen_closes = seen_closes.clone();
+                tokio::spawn(async move {
+                    let Ok(mut ws) = accept_async(stream).await else {
+                        return;
+                    };
+                    let mut echoed = 0;
+                    while let Some(Ok(msg)) = ws.next().await {
+                        seen_frames.lock().unwrap().push(msg.clone());
+                        match msg {
+                            Message::Text(_) | Message::Binary(_) => {
+                                if ws.send(msg).await.is_err() {
+                                    break;
+                                }
+                                echoed += 1;
+                                if self.close_after.is_some_and(|n| echoed >= n) {
+                                    let _ = ws.close(None).await;
+                                    // Drain until the peer acknowledges the close
+                                    while let Some(Ok(_)) = ws.next().await {}
+                                    break;
+                                }
+                            }
+                            Message::Close(_) => {
+                                seen_closes.fetch_add(1, Ordering::SeqCst);
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                });
+            }
+        });
+
+        RunningMockWsBackend {
+            url: format!("ws://{}", addr),
+            addr,
+            frames,
+            closes,
+        }
+    }
+}
+
+/// Handle to a started `MockWsBackend`.
+#[derive(Debug, Clone)]
+pub struct RunningMockWsBackend {
+    /// URL to use as a `Backend::ws_url`
+    pub url: String,
+    pub addr: SocketAddr,
+    frames: Arc<Mutex<Vec<Message>>>,
+    closes: Arc<AtomicUsize>,
+}
+
+impl RunningMockWsBackend {
+    /// Every frame received, across connections, in arrival order.
+    pub fn frames(&self) -> Vec<Message> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    /// Connections the peer (the router) closed with a Close frame.
+    pub fn close_count(&self) -> usize {
+        self.closes.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,182 @@
+//! Upstream connection recycling. Load-balanced providers can pin a long-lived pooled
+//! connection to one instance behind their own balancer, so connections are retired
+//! once they reach `[upstream] max_connection_lifetime_secs`.
+
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    http::Extensions,
+};
+use bytes::Bytes;
+use hyper::{
+    body::{Frame, Incoming, SizeHint},
+    Request, Response,
+};
+use hyper_util::client::legacy::connect::{capture_connection, CaptureConnection, HttpInfo};
+
+use crate::state::{HttpClient, QueuedRequest};
+
+/// Poisons each pooled connection once it has been open for `max_lifetime`, so the
+/// pool closes it instead of handing it out again. Requests already running on it
+/// finish normally.
+///
+/// A connection's age counts from when the client hands it to its first request,
+/// which for a new connection is as soon as it is connected.
+#[derive(Debug)]
+pub struct ConnectionLifetime {
+    max_lifetime: Duration,
+    /// Local and remote address of each connection awaiting retirement
+    tracked: Arc<Mutex<HashSet<(SocketAddr, SocketAddr)>>>,
+}
+
+impl ConnectionLifetime {
+    pub fn new(max_lifetime: Duration) -> Self {
+        Self {
+            max_lifetime,
+            tracked: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Sends `req` over `client`, scheduling the connection it used for retirement the
+    /// first time that connection is seen.
+    pub async fn request(
+        &self,
+        client: &HttpClient,
+        mut req: Request<Body>,
+    ) -> Result<Response<Incoming>, hyper_util::client::legacy::Error> {
+        let captured = capture_connection(&mut req);
+        let (result, ()) = tokio::join!(client.request(req), self.track(captured));
+        result
+    }
+
+    /// Waits for the request to be given a connection, then starts that connection's
+    /// lifetime clock unless it is already running. Returns early if the request never
+    /// gets a connection.
+    async fn track(&self, mut captured: CaptureConnection) {
+        captured.wait_for_connection_metadata().await;
+        let Some(key) = connection_addrs(&captured) else {
+            return;
+        };
+        if !self.tracked.lock().unwrap().insert(key) {
+            return;
+        }
+
+        // The captured handle keeps the connection's poison flag reachable
+        let tracked = self.tracked.clone();
+        let max_lifetime = self.max_lifetime;
+        tokio::spawn(async move {
+            tokio::time::sleep(max_lifetime).await;
+            if let Some(connected) = captured.connection_metadata().as_ref() {
+                connected.poison();
+            }
+            tracked.lock().unwrap().remove(&key);
+        });
+    }
+}
+
+/// Local and remote address of the connection a request went out on, which tell
+/// connections apart; `None` if no connection was made.
+fn connection_addrs(captured: &CaptureConnection) -> Option<(SocketAddr, SocketAddr)> {
+    let mut extras = Extensions::new();
+    captured
+        .connection_metadata()
+        .as_ref()?
+        .get_extras(&mut extras);
+    let info = extras.get::<HttpInfo>()?;
+    Some((info.local_addr(), info.remote_addr()))
+}
+
+/// Upstream request body that keeps its request in the backend's queue depth until the
+/// client starts sending it. The hyper client only polls a request body once it holds a
+/// connection for the request, so the time before the first poll is the time spent
+/// waiting for a pooled or new connection.
+pub struct QueuedBody {
+    inner: Body,
+    queued: QueuedRequest,
+}
+
+impl QueuedBody {
+    pub fn new(inner: Body, queued: QueuedRequest) -> Self {
+        Self { inner, queued }
+    }
+}
+
+impl HttpBody for QueuedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        this.queued.release();
+        Pin::new(&mut this.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // An empty body is never polled, so being asked for it is the first sign of a
+        // connection
+        let ended = self.inner.is_end_stream();
+        if ended {
+            self.queued.release();
+        }
+        ended
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Response body that keeps `guard` alive until the body is dropped, so whatever the
+/// guard counts (a backend's in-flight requests, an owner's concurrency slot) covers
+/// the time spent streaming the response, not just waiting for its headers.
+pub struct HeldBody<G> {
+    inner: Body,
+    _guard: G,
+}
+
+impl<G: Send + Unpin + 'static> HeldBody<G> {
+    /// Moves `guard` into the body of `resp`.
+    pub fn hold<B>(resp: Response<B>, guard: G) -> Response<Body>
+    where
+        B: HttpBody<Data = Bytes> + Send + 'static,
+        B::Error: Into<axum::BoxError>,
+    {
+        resp.map(|body| {
+            Body::new(Self {
+                inner: Body::new(body),
+                _guard: guard,
+            })
+        })
+    }
+}
+
+impl<G: Send + Unpin + 'static> HttpBody for HeldBody<G> {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.get_mut().inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
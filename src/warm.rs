@@ -0,0 +1,110 @@
+//! Startup cache warming: each of `cache.warm_methods` is fetched once, through the
+//! same upstream request path as client traffic, and its answer cached before the
+//! listeners start serving.
+
+use std::time::Duration;
+
+use axum::body::{to_bytes, Body};
+use bytes::Bytes;
+use futures_util::future::join_all;
+use hyper::{header::CONTENT_TYPE, Method, Request};
+use serde_json::Value;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, info, warn};
+
+use crate::{
+    handlers::{buffer_limit, build_upstream_request, identify_upstream},
+    selector::SelectionContext,
+    state::AppState,
+};
+
+/// Overall time allowed for warming, so a slow backend can't hold up startup.
+const WARM_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Calls each of `cache.warm_methods` once, without params, on the backend selection
+/// would pick for it, and caches the answer, so the first wave of clients after a
+/// deploy doesn't all miss. Methods are fetched concurrently within `WARM_DEADLINE`
+/// (or `timeout_secs` if shorter). Fails soft: a method that can't be fetched in time
+/// is logged and left to the first client. Returns how many methods were cached.
+pub async fn warm(state: &AppState) -> usize {
+    let methods = state.cache.warm_methods();
+    if methods.is_empty() {
+        return 0;
+    }
+    let timeout_secs = state.state.load().proxy_config.timeout_secs;
+    let deadline = Instant::now() + WARM_DEADLINE.min(Duration::from_secs(timeout_secs));
+
+    let results = join_all(methods.iter().map(|method| async move {
+        let result = timeout_at(deadline, warm_method(state, method))
+            .await
+            .unwrap_or_else(|_| Err("timed out".to_string()));
+        (method, result)
+    }))
+    .await;
+
+    let mut warmed = 0;
+    for (method, result) in results {
+        match result {
+            Ok(()) => warmed += 1,
+            Err(e) => warn!("Cache warming for {} failed: {}", method, e),
+        }
+    }
+    info!(
+        "Warmed the response cache with {}/{} methods",
+        warmed,
+        methods.len()
+    );
+    warmed
+}
+
+async fn warm_method(state: &AppState, method: &str) -> Result<(), String> {
+    let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method });
+    let key = state
+        .cache
+        .key(method, &request)
+        .ok_or("method is not cached")?;
+    let body = Bytes::from(serde_json::to_vec(&request).map_err(|e| e.to_string())?);
+    let ctx = SelectionContext {
+        rpc_method: Some(method),
+        client_addr: None,
+        owner: None,
+        commitment: None,
+        request_key: None,
+    };
+    let selection = state
+        .select_backend_for(&ctx, &[])
+        .ok_or("no healthy backend")?;
+    let backend = selection.backend();
+    let _in_flight = backend
+        .start_request()
+        .ok_or_else(|| format!("{} is at its concurrency limit", selection.label()))?;
+
+    let router_state = state.state.load_full();
+    let proxy_config = &router_state.proxy_config;
+    let (mut parts, ()) = Request::builder()
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(())
+        .map_err(|e| e.to_string())?
+        .into_parts();
+    identify_upstream(&mut parts.headers, proxy_config);
+    let req = build_upstream_request(&parts, backend, "/", body)?;
+
+    let client = backend.client().unwrap_or(&state.client);
+    let resp = state
+        .send_upstream(client, req)
+        .await
+        .map_err(|e| format!("{} request failed: {}", selection.label(), e))?;
+    if !resp.status().is_success() {
+        return Err(format!("{} returned {}", selection.label(), resp.status()));
+    }
+    let body = to_bytes(Body::new(resp.into_body()), buffer_limit(proxy_config))
+        .await
+        .map_err(|e| e.to_string())?;
+    let response: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    state
+        .cache
+        .insert(key, method, response)
+        .map(|_| debug!("Warmed cached {} from {}", method, selection.label()))
+        .ok_or_else(|| format!("{} answered without a result", selection.label()))
+}
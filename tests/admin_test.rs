@@ -0,0 +1,372 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc},
+};
+
+use arc_swap::ArcSwap;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    capture::RequestCapture,
+    config::{Backend, HealthCheckConfig, MethodRoute, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::{admin_router, public_router},
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockBackend,
+};
+use tower::ServiceExt; // for oneshot
+
+const TOKEN: &str = "admin-secret";
+
+fn make_state(admin_token: Option<&str>) -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+
+    let backend = RuntimeBackend::new(Backend {
+        label: "a".to_string(),
+        url: "http://a".to_string(),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let router_state = RouterState {
+        backends: vec![backend],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["a".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(
+        AppState::new(
+            client,
+            keystore,
+            Arc::new(ArcSwap::from_pointee(router_state)),
+        )
+        .with_admin_token(admin_token.map(str::to_string)),
+    )
+}
+
+async fn admin_request(
+    state: &Arc<AppState>,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &str,
+) -> StatusCode {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(path)
+        .header("content-type", "application/json");
+    if let Some(token) = token {
+        req = req.header("authorization", format!("Bearer {}", token));
+    }
+    let mut req = req.body(Body::from(body.to_string())).unwrap();
+    // log_requests expects the peer address that axum::serve normally provides
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    admin_router(state.clone())
+        .oneshot(req)
+        .await
+        .unwrap()
+        .status()
+}
+
+fn selected_labels(state: &AppState) -> Vec<String> {
+    (0..50)
+        .filter_map(|_| {
+            state
+                .select_backend(Some("getSlot"))
+                .map(|(label, _)| label)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_added_backend_becomes_selectable() {
+    let state = make_state(Some(TOKEN));
+
+    let body = r#"{"label":"b","url":"http://b","weight":1}"#;
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), body).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let router_state = state.state.load();
+    assert_eq!(router_state.backends.len(), 2);
+    assert!(router_state.health_state.get_status("b").is_some());
+    drop(router_state);
+
+    // Removing the original backend leaves only the new one to select
+    let status = admin_request(&state, "DELETE", "/admin/backends/a", Some(TOKEN), "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(selected_labels(&state).iter().all(|label| label == "b"));
+}
+
+#[tokio::test]
+async fn test_deleted_backend_stops_being_selected() {
+    let state = make_state(Some(TOKEN));
+    let body = r#"{"label":"b","url":"http://b","weight":1}"#;
+    admin_request(&state, "POST", "/admin/backends", Some(TOKEN), body).await;
+
+    let status = admin_request(&state, "DELETE", "/admin/backends/b", Some(TOKEN), "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(selected_labels(&state).iter().all(|label| label == "a"));
+    assert!(state.state.load().health_state.get_status("b").is_none());
+}
+
+#[tokio::test]
+async fn test_admin_backend_api_rejects_invalid_changes() {
+    let state = make_state(Some(TOKEN));
+
+    let duplicate = r#"{"label":"a","url":"http://other","weight":1}"#;
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), duplicate).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let bad_url = r#"{"label":"c","url":"not a url","weight":1}"#;
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), bad_url).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Floors may add up to all traffic, but no more
+    let floor = r#"{"label":"c","url":"http://c","weight":1,"min_share":0.6}"#;
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), floor).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let over = r#"{"label":"d","url":"http://d","weight":1,"min_share":0.5}"#;
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), over).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let status = admin_request(&state, "DELETE", "/admin/backends/c", Some(TOKEN), "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let status = admin_request(&state, "DELETE", "/admin/backends/missing", Some(TOKEN), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The last backend stays
+    let status = admin_request(&state, "DELETE", "/admin/backends/a", Some(TOKEN), "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(state.state.load().backends.len(), 1);
+}
+
+#[tokio::test]
+async fn test_admin_backend_api_requires_token() {
+    let state = make_state(Some(TOKEN));
+    let body = r#"{"label":"b","url":"http://b","weight":1}"#;
+
+    let status = admin_request(&state, "POST", "/admin/backends", None, body).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = admin_request(&state, "POST", "/admin/backends", Some("wrong"), body).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(state.state.load().backends.len(), 1);
+
+    // The token is checked before the body is parsed
+    let status = admin_request(&state, "POST", "/admin/backends", None, "{not json").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), "{not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Without a configured token the API is disabled
+    let state = make_state(None);
+    let status = admin_request(&state, "POST", "/admin/backends", Some(TOKEN), body).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn simulate(state: &Arc<AppState>, body: &str) -> (StatusCode, serde_json::Value) {
+    let mut req = Request::builder()
+        .method("POST")
+        .uri("/admin/simulate")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", TOKEN))
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let resp = admin_router(state.clone()).oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn test_simulated_routing_follows_weights() {
+    let state = make_state(Some(TOKEN));
+    let body = r#"{"label":"b","url":"http://b","weight":3}"#;
+    admin_request(&state, "POST", "/admin/backends", Some(TOKEN), body).await;
+
+    let (status, result) = simulate(&state, r#"{"method":"getSlot","count":4000}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["count"], 4000);
+    assert_eq!(result["unavailable"], 0);
+    // Weights 1:3, so about 1000 and 3000
+    let a = result["backends"]["a"].as_u64().unwrap();
+    let b = result["backends"]["b"].as_u64().unwrap();
+    assert_eq!(a + b, 4000);
+    assert!((800..1200).contains(&a), "a chosen {} times", a);
+
+    // Nothing was sent or changed
+    assert_eq!(state.state.load().backends.len(), 2);
+}
+
+#[tokio::test]
+async fn test_simulate_rejects_bad_count_and_requires_token() {
+    let state = make_state(Some(TOKEN));
+    let (status, _) = simulate(&state, r#"{"count":0}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let status = admin_request(&state, "POST", "/admin/simulate", None, "{}").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = admin_request(&state, "POST", "/admin/simulate", None, "{not json").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = simulate(&state, "{not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+async fn get_routes(state: &Arc<AppState>) -> serde_json::Value {
+    let mut req = Request::builder()
+        .uri("/routes")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let resp = admin_router(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_routes_report_unhealthy_target_and_fallback() {
+    let state = make_state(None);
+    let loaded = state.state.load_full();
+    state.state.store(Arc::new(RouterState {
+        method_routes: HashMap::from([
+            ("getBlock".to_string(), "a".into()),
+            (
+                "getConfirmedBlock".to_string(),
+                MethodRoute {
+                    backends: vec!["a".to_string(), "gone".to_string()],
+                    strict: true,
+                },
+            ),
+        ]),
+        ..(*loaded).clone()
+    }));
+
+    let routes = get_routes(&state).await;
+    assert_eq!(routes["routes"]["getBlock"]["active_backend"], "a");
+    assert_eq!(routes["routes"]["getBlock"]["falling_back"], false);
+
+    state.state.load().backends[0]
+        .healthy
+        .store(false, Ordering::Relaxed);
+    let routes = get_routes(&state).await;
+    let get_block = &routes["routes"]["getBlock"];
+    assert_eq!(get_block["backends"][0]["label"], "a");
+    assert_eq!(get_block["backends"][0]["healthy"], false);
+    assert!(get_block["active_backend"].is_null());
+    assert_eq!(get_block["falling_back"], true);
+    assert_eq!(get_block["fallback"], "selection");
+
+    let strict = &routes["routes"]["getConfirmedBlock"];
+    assert_eq!(strict["fallback"], "reject");
+    assert_eq!(strict["backends"][1]["exists"], false);
+    assert_eq!(strict["falling_back"], true);
+}
+
+#[tokio::test]
+async fn test_failed_request_captured_with_api_key_redacted() {
+    let backend = MockBackend::new()
+        .status(500)
+        .body("backend exploded")
+        .start()
+        .await;
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("secret-key-123", "tester", 100);
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "a".to_string(),
+            url: backend.url.clone(),
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["a".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let state = Arc::new(
+        AppState::new(
+            client,
+            keystore,
+            Arc::new(ArcSwap::from_pointee(router_state)),
+        )
+        .with_admin_token(Some(TOKEN.to_string()))
+        .with_request_capture(RequestCapture::new(10, 1024)),
+    );
+
+    let mut req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=secret-key-123&trace=1")
+        .header("content-type", "application/json")
+        .header("x-api-key", "secret-key-123")
+        .body(Body::from(
+            r#"{"jsonrpc":"2.0","id":1,"method":"getBalance","params":["abc"]}"#,
+        ))
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let resp = public_router(state.clone(), false)
+        .oneshot(req)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    // The client still gets the backend's body
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"backend exploded");
+
+    let mut req = Request::builder()
+        .uri("/admin/captures")
+        .header("authorization", format!("Bearer {}", TOKEN))
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let resp = admin_router(state).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert!(
+        !String::from_utf8_lossy(&body).contains("secret-key-123"),
+        "api key leaked into captures"
+    );
+
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let captures = json["captures"].as_array().unwrap();
+    assert_eq!(captures.len(), 1);
+    let capture = &captures[0];
+    assert_eq!(capture["rpc_method"], "getBalance");
+    assert_eq!(capture["status"], 500);
+    assert_eq!(capture["backend"], "a");
+    assert_eq!(capture["path"], "/?api-key=[redacted]&trace=1");
+    assert_eq!(capture["headers"]["x-api-key"], "[redacted]");
+    assert_eq!(capture["headers"]["content-type"], "application/json");
+    assert!(capture["request_body"]
+        .as_str()
+        .unwrap()
+        .contains(r#""params":["abc"]"#));
+    assert_eq!(capture["response_body"], "backend exploded");
+}
@@ -0,0 +1,609 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware,
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use http_body_util::BodyExt;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use serde_json::{json, Value};
+use sol_rpc_router::{
+    cache::ResponseCache,
+    config::{Backend, CacheConfig, HealthCheckConfig, ProxyConfig},
+    handlers::{extract_rpc_method, proxy},
+    health::HealthState,
+    mock::MockKeyStore,
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockBackend,
+    warm,
+};
+use tower::ServiceExt; // for oneshot
+
+fn cache_config() -> CacheConfig {
+    CacheConfig {
+        ttl_ms: 60_000,
+        methods: vec!["getSlot".to_string(), "getGenesisHash".to_string()],
+        slot_scoped_methods: vec!["getSlot".to_string()],
+        ..Default::default()
+    }
+}
+
+fn make_state(backend_url: String, cache: Arc<ResponseCache>) -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "mock-backend".to_string(),
+            url: backend_url,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["mock-backend".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(
+        AppState::new(
+            client,
+            keystore,
+            Arc::new(ArcSwap::from_pointee(router_state)),
+        )
+        .with_cache(cache),
+    )
+}
+
+async fn send(state: Arc<AppState>, body: &str) -> (StatusCode, serde_json::Value) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_cached_response_served_with_request_id() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"genesis","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(cache_config())),
+    );
+
+    let (status, first) = send(
+        state.clone(),
+        r#"{"jsonrpc":"2.0","method":"getGenesisHash","id":1}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["result"], "genesis");
+
+    let (status, second) = send(
+        state,
+        r#"{"jsonrpc":"2.0","method":"getGenesisHash","id":"abc"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["result"], "genesis");
+    assert_eq!(second["id"], "abc");
+    assert_eq!(backend.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_uncached_method_always_proxied() {
+    let backend = MockBackend::new().start().await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(cache_config())),
+    );
+
+    for _ in 0..2 {
+        let (status, _) = send(
+            state.clone(),
+            r#"{"jsonrpc":"2.0","method":"getBalance","params":["abc"],"id":1}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    assert_eq!(backend.request_count(), 2);
+}
+
+#[test]
+fn test_full_cache_evicts_to_stay_within_max_entries() {
+    let cache = ResponseCache::new(CacheConfig {
+        max_entries: 2,
+        ..cache_config()
+    });
+    for slot in 0..10 {
+        let request = json!({"method": "getSlot", "params": [{"minContextSlot": slot}]});
+        let key = cache.key("getSlot", &request).unwrap();
+        let response = json!({"jsonrpc": "2.0", "result": slot, "id": 1});
+        assert!(cache.insert(key, "getSlot", response).is_some());
+    }
+    assert!(cache.len() <= 2);
+}
+
+#[tokio::test]
+async fn test_slot_advance_evicts_slot_scoped_entries() {
+    let cache = Arc::new(ResponseCache::new(cache_config()));
+    let slot_key = cache.key("getSlot", &json!({"method": "getSlot"})).unwrap();
+    let genesis_key = cache
+        .key("getGenesisHash", &json!({"method": "getGenesisHash"}))
+        .unwrap();
+    cache.insert(
+        slot_key.clone(),
+        "getSlot",
+        json!({"jsonrpc": "2.0", "result": 100, "id": 1}),
+    );
+    cache.insert(
+        genesis_key.clone(),
+        "getGenesisHash",
+        json!({"jsonrpc": "2.0", "result": "genesis", "id": 1}),
+    );
+
+    let health_state = HealthState::new(vec![]);
+    tokio::spawn(
+        cache
+            .clone()
+            .follow_cluster_slot(health_state.subscribe_cluster_slot()),
+    );
+
+    // A slot that doesn't advance leaves the cache alone
+    health_state.record_cluster_slot(0);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(cache.len(), 2);
+
+    health_state.record_cluster_slot(101);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while cache.len() != 1 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("slot-scoped entry was not evicted");
+
+    assert!(cache.get(&slot_key, None).is_none());
+    assert!(cache.get(&genesis_key, None).is_some());
+    assert_eq!(health_state.cluster_slot(), 101);
+}
+
+async fn send_conditional(
+    state: Arc<AppState>,
+    if_none_match: Option<&str>,
+) -> (StatusCode, Option<String>, Vec<u8>) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let mut req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json");
+    if let Some(etag) = if_none_match {
+        req = req.header("if-none-match", etag);
+    }
+    let req = req
+        .body(Body::from(
+            r#"{"jsonrpc":"2.0","id":1,"method":"getGenesisHash"}"#,
+        ))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let etag = response
+        .headers()
+        .get("etag")
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, etag, body.to_vec())
+}
+
+#[tokio::test]
+async fn test_matching_if_none_match_gets_not_modified() {
+    let backend = MockBackend::new().start().await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(cache_config())),
+    );
+
+    // The response that fills the cache already carries the ETag
+    let (status, etag, _) = send_conditional(state.clone(), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = etag.unwrap();
+    // The tag depends only on the result, the same in every process
+    assert_eq!(etag, "\"78219c83c3db0e97\"");
+
+    let (status, cached_etag, body) = send_conditional(state.clone(), Some(&etag)).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(cached_etag.as_deref(), Some(etag.as_str()));
+    assert!(body.is_empty());
+
+    // A stale validator gets the full cached response
+    let (status, _, body) = send_conditional(state, Some("\"0000000000000000\"")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.is_empty());
+    assert_eq!(backend.request_count(), 1);
+}
+
+/// Wire-format transaction with one signature made of `fill` bytes.
+fn transaction(fill: u8) -> Vec<u8> {
+    let mut tx = vec![1];
+    tx.extend([fill; 64]);
+    tx.extend([1, 0, 1, 2, 3]);
+    tx
+}
+
+fn base58(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    let mut digits: Vec<u32> = Vec::new();
+    for &byte in bytes {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut() {
+            carry += *digit << 8;
+            *digit = carry % 58;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push(carry % 58);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(digits.iter().rev().map(|&d| ALPHABET[d as usize] as char))
+        .collect()
+}
+
+fn send_transaction_request(encoded: &str, encoding: &str, id: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "sendTransaction",
+        "params": [encoded, {"encoding": encoding}],
+        "id": id,
+    })
+}
+
+fn send_transaction_body(encoded: &str, encoding: &str, id: u64) -> String {
+    send_transaction_request(encoded, encoding, id).to_string()
+}
+
+#[test]
+fn test_send_transaction_keyed_by_signature() {
+    let dedup = ResponseCache::new(CacheConfig {
+        send_transaction_dedup_ms: 5_000,
+        ..Default::default()
+    });
+    let tx = transaction(7);
+    let b64 = BASE64.encode(&tx);
+    let b58 = base58(&tx);
+
+    let key = dedup
+        .key(
+            "sendTransaction",
+            &send_transaction_request(&b64, "base64", 1),
+        )
+        .unwrap();
+    assert_eq!(
+        dedup.key(
+            "sendTransaction",
+            &send_transaction_request(&b58, "base58", 2)
+        ),
+        Some(key.clone())
+    );
+    let unspecified = json!({"method": "sendTransaction", "params": [b58]});
+    assert_eq!(
+        dedup.key("sendTransaction", &unspecified),
+        Some(key.clone())
+    );
+
+    let other = BASE64.encode(transaction(8));
+    assert_ne!(
+        dedup.key(
+            "sendTransaction",
+            &send_transaction_request(&other, "base64", 1)
+        ),
+        Some(key)
+    );
+    assert!(dedup
+        .key(
+            "sendTransaction",
+            &send_transaction_request("not base58!", "base58", 1)
+        )
+        .is_none());
+
+    // Off unless configured, even with other methods cached
+    let off = ResponseCache::new(cache_config());
+    assert!(off
+        .key(
+            "sendTransaction",
+            &send_transaction_request(&b64, "base64", 1)
+        )
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_duplicate_send_transaction_forwarded_once() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"5sig","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(CacheConfig {
+            send_transaction_dedup_ms: 60_000,
+            ..Default::default()
+        })),
+    );
+    let tx = BASE64.encode(transaction(7));
+
+    let (status, first) = send(state.clone(), &send_transaction_body(&tx, "base64", 1)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["result"], "5sig");
+
+    let (status, second) = send(state.clone(), &send_transaction_body(&tx, "base64", 2)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["result"], "5sig");
+    assert_eq!(second["id"], 2);
+    assert_eq!(backend.request_count(), 1);
+
+    // A different transaction is still sent
+    let other = BASE64.encode(transaction(8));
+    send(state, &send_transaction_body(&other, "base64", 3)).await;
+    assert_eq!(backend.request_count(), 2);
+}
+
+async fn send_genesis_request(
+    state: Arc<AppState>,
+    uri: &str,
+    cache_control: Option<&str>,
+) -> String {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let mut req = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(value) = cache_control {
+        req = req.header("cache-control", value);
+    }
+    let req = req
+        .body(Body::from(
+            r#"{"jsonrpc":"2.0","id":1,"method":"getGenesisHash"}"#,
+        ))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    json["result"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_no_cache_header_bypasses_and_refreshes_entry() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"upstream","id":1}"#)
+        .start()
+        .await;
+    let cache = Arc::new(ResponseCache::new(cache_config()));
+    let key = cache
+        .key("getGenesisHash", &json!({"method": "getGenesisHash"}))
+        .unwrap();
+    cache.insert(
+        key,
+        "getGenesisHash",
+        json!({"jsonrpc": "2.0", "result": "cached", "id": 1}),
+    );
+    let state = make_state(backend.url.clone(), cache);
+
+    let result = send_genesis_request(state.clone(), "/?api-key=test-key", None).await;
+    assert_eq!(result, "cached");
+    assert_eq!(backend.request_count(), 0);
+
+    let result = send_genesis_request(
+        state.clone(),
+        "/?api-key=test-key",
+        Some("max-age=0, no-cache"),
+    )
+    .await;
+    assert_eq!(result, "upstream");
+    assert_eq!(backend.request_count(), 1);
+
+    // The fresh answer replaced the cached one
+    let result = send_genesis_request(state.clone(), "/?api-key=test-key", None).await;
+    assert_eq!(result, "upstream");
+    assert_eq!(backend.request_count(), 1);
+
+    send_genesis_request(state, "/?api-key=test-key&fresh=true&x=1", None).await;
+    assert_eq!(backend.request_count(), 2);
+    // The bypass is the router's, so it isn't forwarded
+    assert_eq!(backend.request_paths()[1], "/?x=1");
+}
+
+#[tokio::test]
+async fn test_cache_status_header_when_backend_exposed() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"genesis","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(cache_config())),
+    );
+    let loaded = state.state.load_full();
+    state.state.store(Arc::new(RouterState {
+        proxy_config: ProxyConfig {
+            expose_backend_header: true,
+            ..loaded.proxy_config.clone()
+        },
+        ..(*loaded).clone()
+    }));
+
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let app = Router::new()
+            .route("/", post(proxy))
+            .with_state(state.clone())
+            .layer(middleware::from_fn(extract_rpc_method));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/?api-key=test-key")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","method":"getGenesisHash","id":1}"#,
+            ))
+            .unwrap();
+        let headers = app.oneshot(req).await.unwrap().headers().clone();
+        seen.push((
+            headers
+                .get("x-cache")
+                .map(|v| v.to_str().unwrap().to_string()),
+            headers
+                .get("x-served-by")
+                .map(|v| v.to_str().unwrap().to_string()),
+        ));
+    }
+
+    assert_eq!(
+        seen,
+        vec![
+            (Some("MISS".to_string()), Some("mock-backend".to_string())),
+            // A hit names no backend
+            (Some("HIT".to_string()), None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_warmed_method_is_cache_hit() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"genesis","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(CacheConfig {
+            warm_methods: vec!["getGenesisHash".to_string()],
+            ..cache_config()
+        })),
+    );
+
+    assert_eq!(warm::warm(&state).await, 1);
+    assert_eq!(backend.request_count(), 1);
+    let upstream: serde_json::Value = serde_json::from_str(&backend.request_bodies()[0]).unwrap();
+    assert_eq!(upstream["method"], "getGenesisHash");
+
+    // Empty params are the same call as none
+    for body in [
+        r#"{"jsonrpc":"2.0","method":"getGenesisHash","id":5}"#,
+        r#"{"jsonrpc":"2.0","method":"getGenesisHash","params":[],"id":6}"#,
+    ] {
+        let (status, response) = send(state.clone(), body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "genesis");
+    }
+    assert_eq!(backend.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_failed_warming_is_skipped() {
+    let backend = MockBackend::new().status(500).start().await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(CacheConfig {
+            warm_methods: vec!["getGenesisHash".to_string()],
+            ..cache_config()
+        })),
+    );
+
+    assert_eq!(warm::warm(&state).await, 0);
+    assert!(state.cache.is_empty());
+}
+
+#[tokio::test]
+async fn test_warming_sends_upstream_headers() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"genesis","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(CacheConfig {
+            warm_methods: vec!["getGenesisHash".to_string()],
+            ..cache_config()
+        })),
+    );
+    let loaded = state.state.load_full();
+    state.state.store(Arc::new(RouterState {
+        proxy_config: ProxyConfig {
+            upstream_user_agent: Some("sol-rpc-router".to_string()),
+            upstream_headers: HashMap::from([("x-provider-key".to_string(), "secret".to_string())]),
+            ..loaded.proxy_config.clone()
+        },
+        ..(*loaded).clone()
+    }));
+
+    assert_eq!(warm::warm(&state).await, 1);
+    let headers = &backend.request_headers()[0];
+    assert_eq!(headers["x-provider-key"], "secret");
+    assert_eq!(headers["user-agent"], "sol-rpc-router");
+}
+
+#[tokio::test]
+async fn test_warming_runs_concurrently_within_deadline() {
+    let backend = MockBackend::new()
+        .delay(Duration::from_secs(3))
+        .body(r#"{"jsonrpc":"2.0","result":"genesis","id":1}"#)
+        .start()
+        .await;
+    let state = make_state(
+        backend.url.clone(),
+        Arc::new(ResponseCache::new(CacheConfig {
+            warm_methods: vec!["getGenesisHash".to_string(), "getSlot".to_string()],
+            ..cache_config()
+        })),
+    );
+    let loaded = state.state.load_full();
+    state.state.store(Arc::new(RouterState {
+        proxy_config: ProxyConfig {
+            timeout_secs: 1,
+            ..loaded.proxy_config.clone()
+        },
+        ..(*loaded).clone()
+    }));
+
+    let start = std::time::Instant::now();
+    assert_eq!(warm::warm(&state).await, 0);
+    // Both methods share the one-second deadline rather than waiting in turn
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_eq!(backend.request_count(), 2);
+}
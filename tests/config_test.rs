@@ -1,6 +1,9 @@
 use std::io::Write;
 
-use sol_rpc_router::config::load_config;
+use sol_rpc_router::{
+    config::{load_config, MethodRoute},
+    error::ConfigError,
+};
 
 fn write_temp_config(name: &str, content: &str) -> String {
     let mut path = std::env::temp_dir();
@@ -41,6 +44,7 @@ fn test_load_config_file_not_found() {
     let path_str = path.to_str().unwrap();
 
     let err = load_config(path_str).unwrap_err();
+    assert!(matches!(err, ConfigError::NotFound(_)), "{:?}", err);
     assert!(
         err.to_string().contains("not found") || err.to_string().contains("No such file"),
         "Expected 'not found' in error: {}",
@@ -52,7 +56,8 @@ fn test_load_config_file_not_found() {
 fn test_load_config_invalid_toml() {
     let path = write_temp_config("invalid_toml", "this is not valid toml {{{{");
     let err = load_config(&path).unwrap_err();
-    // toml parse errors are descriptive enough; just make sure it's an error
+    // toml parse errors are descriptive enough; just make sure it's a parse error
+    assert!(matches!(err, ConfigError::Parse(_)), "{:?}", err);
     assert!(!err.to_string().is_empty());
 }
 
@@ -72,6 +77,7 @@ weight = 1
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::MissingRedisUrl), "{:?}", err);
     assert!(
         err.to_string().contains("Redis URL"),
         "Expected 'Redis URL' in error: {}",
@@ -91,6 +97,7 @@ backends = []
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::NoBackends), "{:?}", err);
     assert!(
         err.to_string().contains("At least one backend"),
         "Expected 'At least one backend' in error: {}",
@@ -119,6 +126,11 @@ weight = 1
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::DuplicateBackendLabels),
+        "{:?}",
+        err
+    );
     assert!(
         err.to_string().contains("Duplicate backend labels"),
         "Expected 'Duplicate backend labels' in error: {}",
@@ -142,8 +154,17 @@ weight = 0
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidBackend { ref label, .. } if label == "bad-backend"),
+        "{:?}",
+        err
+    );
     let msg = err.to_string();
-    assert!(msg.contains("weight 0"), "Expected 'weight 0' in error: {}", msg);
+    assert!(
+        msg.contains("weight 0"),
+        "Expected 'weight 0' in error: {}",
+        msg
+    );
     assert!(
         msg.contains("bad-backend"),
         "Expected backend name in error: {}",
@@ -193,6 +214,11 @@ timeout_secs = 0
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::OutOfRange { ref field, .. } if field == "proxy.timeout_secs"),
+        "{:?}",
+        err
+    );
     assert!(
         err.to_string().contains("timeout_secs"),
         "Expected 'timeout_secs' in error: {}",
@@ -219,6 +245,14 @@ getSlot = "nonexistent"
 "#,
     );
     let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            ConfigError::UnknownBackendLabel { ref label, .. } if label == "nonexistent"
+        ),
+        "{:?}",
+        err
+    );
     let msg = err.to_string();
     assert!(
         msg.contains("nonexistent"),
@@ -232,6 +266,46 @@ getSlot = "nonexistent"
     );
 }
 
+#[test]
+fn test_load_config_method_route_forms() {
+    let path = write_temp_config(
+        "method_route_forms",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "archival"
+url = "http://localhost:9000"
+weight = 1
+
+[method_routes]
+getSlot = "archival"
+getConfirmedBlock = { backend = "archival", strict = true }
+getBlock = ["archival", "bulk"]
+
+[[backends]]
+label = "bulk"
+url = "http://localhost:9001"
+weight = 1
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(
+        config.method_routes["getSlot"],
+        MethodRoute::from("archival")
+    );
+    assert_eq!(
+        config.method_routes["getConfirmedBlock"],
+        MethodRoute::strict("archival")
+    );
+    assert_eq!(
+        config.method_routes["getBlock"].backends,
+        vec!["archival".to_string(), "bulk".to_string()]
+    );
+}
+
 #[test]
 fn test_load_config_missing_metrics_port() {
     let path = write_temp_config(
@@ -268,7 +342,14 @@ weight = 1
     );
     let err = load_config(&path).unwrap_err();
     assert!(
-        err.to_string().contains("HTTP port and Metrics port must be different"),
+        matches!(
+            err,
+            ConfigError::PortConflict {
+                listener: "Metrics",
+                other: "HTTP",
+                ..
+            }
+        ),
         "Expected conflict error: {}",
         err
     );
@@ -291,8 +372,839 @@ weight = 1
     );
     let err = load_config(&path).unwrap_err();
     assert!(
-        err.to_string().contains("conflicts with WebSocket port"),
+        err.to_string().contains("conflicts with the WebSocket"),
         "Expected WS conflict error: {}",
         err
     );
 }
+
+#[test]
+fn test_load_config_admin_port_conflict() {
+    let path = write_temp_config(
+        "admin_port_conflict",
+        r#"
+port = 8080
+metrics_port = 9091
+admin_port = 8081
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("Admin port 8081"),
+        "Expected admin port conflict in error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_response_id_validation() {
+    let path = write_temp_config(
+        "response_id_validation",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+validate_response_id = true
+id_mismatch_action = "retry"
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert!(config.proxy.validate_response_id);
+    assert_eq!(
+        config.proxy.id_mismatch_action,
+        sol_rpc_router::config::IdMismatchAction::Retry
+    );
+    // timeout_secs keeps its default when only the new keys are set
+    assert_eq!(config.proxy.timeout_secs, 30);
+}
+
+#[test]
+fn test_load_config_retry_policy() {
+    let path = write_temp_config(
+        "retry_policy",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+retry_policy = "connect_and_5xx_and_jsonrpc"
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(
+        config.proxy.retry_policy,
+        sol_rpc_router::config::RetryPolicy::ConnectAnd5xxAndJsonRpc
+    );
+    assert_eq!(
+        sol_rpc_router::config::ProxyConfig::default().retry_policy,
+        sol_rpc_router::config::RetryPolicy::Never
+    );
+}
+
+#[test]
+fn test_load_config_soft_timeout_must_be_below_hard() {
+    let path = write_temp_config(
+        "soft_timeout",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy.method_timeouts]
+getProgramAccounts = { timeout_secs = 10, soft_timeout_secs = 10 }
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::OutOfRange { .. }), "{:?}", err);
+    assert!(
+        err.to_string().contains("getProgramAccounts.soft_timeout_secs"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_failure_status_codes() {
+    let path = write_temp_config(
+        "failure_status_codes",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+failure_status_codes = [429, 503]
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert!(config.proxy.failure_status_codes.is_failure(429));
+    assert!(!config.proxy.failure_status_codes.is_failure(500));
+}
+
+#[test]
+fn test_load_config_default_failure_status_codes() {
+    let path = write_temp_config(
+        "default_failure_status_codes",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert!(config.proxy.failure_status_codes.is_failure(502));
+    assert!(!config.proxy.failure_status_codes.is_failure(429));
+}
+
+#[test]
+fn test_load_config_invalid_failure_status_code() {
+    let path = write_temp_config(
+        "invalid_failure_status_code",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+failure_status_codes = [42]
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            ConfigError::OutOfRange { ref field, ref value, .. }
+                if field == "proxy.failure_status_codes" && value == "42"
+        ),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_invalid_backend_url() {
+    let path = write_temp_config(
+        "invalid_backend_url",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "localhost:9000/no-scheme"
+weight = 1
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidBackend { .. }), "{:?}", err);
+    assert!(
+        err.to_string().contains("invalid URL"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_tls_client_cert_without_key() {
+    let path = write_temp_config(
+        "tls_cert_without_key",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "https://localhost:9000"
+weight = 1
+tls_client_cert = "/etc/router/client.crt"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("tls_client_key"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_response_transform() {
+    let path = write_temp_config(
+        "response_transform",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[transform]
+getVersion.remove = ["feature-set"]
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(config.transform["getVersion"].remove, vec!["feature-set"]);
+}
+
+#[test]
+fn test_load_config_response_metadata() {
+    let config_with = |metadata: &str| {
+        format!(
+            r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy.response_metadata]
+{}
+"#,
+            metadata
+        )
+    };
+    let path = write_temp_config(
+        "response_metadata",
+        &config_with(r#"value = { region = "eu-west", gateway = 3 }"#),
+    );
+    let metadata = load_config(&path).unwrap().proxy.response_metadata.unwrap();
+    assert_eq!(metadata.field, "_router");
+    assert_eq!(metadata.value["region"], "eu-west");
+    assert_eq!(metadata.value["gateway"], 3);
+
+    let path = write_temp_config(
+        "response_metadata_empty_field",
+        &config_with(r#"field = """#),
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("response_metadata.field"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_trusted_proxies() {
+    let config_with = |proxies: &str| {
+        format!(
+            r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[listener]
+max_connections_per_ip_per_sec = 20
+trusted_proxies = {}
+"#,
+            proxies
+        )
+    };
+    let path = write_temp_config(
+        "trusted_proxies",
+        &config_with(r#"["10.0.0.0/8", "2001:db8::/32", "192.0.2.1"]"#),
+    );
+    let listener = load_config(&path).unwrap().listener;
+    assert_eq!(listener.max_connections_per_ip_per_sec, 20);
+    let proxies = &listener.trusted_proxies;
+    assert!(proxies[0].contains("10.1.2.3".parse().unwrap()));
+    assert!(!proxies[0].contains("11.0.0.1".parse().unwrap()));
+    assert!(proxies[1].contains("2001:db8::1".parse().unwrap()));
+    assert!(proxies[2].contains("192.0.2.1".parse().unwrap()));
+    assert!(!proxies[2].contains("192.0.2.2".parse().unwrap()));
+
+    let path = write_temp_config(
+        "trusted_proxies_invalid",
+        &config_with(r#"["10.0.0.0/33"]"#),
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("10.0.0.0/33"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_min_share() {
+    let config_with = |shares: (&str, &str)| {
+        format!(
+            r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 99
+min_share = {}
+
+[[backends]]
+label = "b2"
+url = "http://localhost:9001"
+weight = 1
+min_share = {}
+"#,
+            shares.0, shares.1
+        )
+    };
+    let path = write_temp_config("min_share", &config_with(("0", "0.05")));
+    let config = load_config(&path).unwrap();
+    assert_eq!(config.backends[1].min_share, 0.05);
+
+    let path = write_temp_config("min_share_out_of_range", &config_with(("0", "1.5")));
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidBackend { ref reason, .. } if reason.contains("min_share")),
+        "Unexpected error: {}",
+        err
+    );
+
+    let path = write_temp_config("min_share_over_total", &config_with(("0.6", "0.5")));
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::MinSharesExceedTraffic(_)),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_fallback_backend() {
+    let path = write_temp_config(
+        "fallback_backend",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[fallback_backend]
+label = "public"
+url = "https://api.mainnet-beta.solana.com"
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(config.fallback_backend.unwrap().label, "public");
+}
+
+#[test]
+fn test_load_config_fallback_backend_duplicate_label() {
+    let path = write_temp_config(
+        "fallback_backend_duplicate",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[fallback_backend]
+label = "b1"
+url = "https://api.mainnet-beta.solana.com"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidBackend { ref label, .. } if label == "b1"),
+        "{:?}",
+        err
+    );
+    assert!(
+        err.to_string().contains("fallback backend"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_idempotent_methods_opt_in() {
+    let path = write_temp_config(
+        "idempotent_methods",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+idempotent_methods = ["getSlot", "sendTransaction"]
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    let methods = &config.proxy.idempotent_methods;
+    assert!(methods.contains("sendTransaction"));
+    assert!(methods.contains("getSlot"));
+    // An explicit list replaces the defaults
+    assert!(!methods.contains("getBlock"));
+}
+
+#[test]
+fn test_load_config_rejects_hedging_non_idempotent_method() {
+    let path = write_temp_config(
+        "hedge_non_idempotent",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+hedge_methods = ["getSlot", "sendTransaction"]
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("'sendTransaction'"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_rejects_slot_scoped_method_not_cached() {
+    let path = write_temp_config(
+        "cache_slot_scoped",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[cache]
+ttl_ms = 1000
+methods = ["getGenesisHash"]
+slot_scoped_methods = ["getSlot"]
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("cache.slot_scoped_methods"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_rejects_warm_method_not_cached() {
+    let path = write_temp_config(
+        "cache_warm",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[cache]
+ttl_ms = 1000
+methods = ["getGenesisHash"]
+warm_methods = ["getEpochSchedule"]
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("cache.warm_methods"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_health_check_params() {
+    let path = write_temp_config(
+        "health_params",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[health_check]
+method = "getHealth"
+params = { commitment = "finalized" }
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(
+        config.health_check.params,
+        serde_json::json!({"commitment": "finalized"})
+    );
+
+    // Defaults to an empty array
+    let path = write_temp_config(
+        "health_params_default",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    assert_eq!(config.health_check.params, serde_json::json!([]));
+}
+
+#[test]
+fn test_load_config_rejects_scalar_health_check_params() {
+    let path = write_temp_config(
+        "health_params_scalar",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[health_check]
+params = "finalized"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("health_check.params"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_default_backend_unknown_label() {
+    let path = write_temp_config(
+        "default_backend_unknown",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+default_backend = "missing"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("default_backend"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_invalid_certs_need_env_override() {
+    let path = write_temp_config(
+        "invalid_certs_without_env",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "https://localhost:9000"
+weight = 1
+
+[upstream]
+danger_accept_invalid_certs = true
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("SOL_RPC_ROUTER_ALLOW_INVALID_CERTS=1"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_invalid_metric_group_pattern() {
+    let path = write_temp_config(
+        "metric_group_pattern",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[metric_groups]
+"get*Info" = "read"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("metric_groups pattern 'get*Info'"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_invalid_default_commitment() {
+    let path = write_temp_config(
+        "default_commitment",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+
+[proxy]
+default_commitment = "recent"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("proxy.default_commitment 'recent' is not a commitment level"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_invalid_force_scheme() {
+    let path = write_temp_config(
+        "invalid_force_scheme",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+force_scheme = "ftp"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidBackend { .. }), "{:?}", err);
+    assert!(err.to_string().contains("force_scheme"), "{}", err);
+}
+
+#[test]
+fn test_load_config_local_zone_must_match_a_backend() {
+    let path = write_temp_config(
+        "unknown_local_zone",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[proxy]
+local_zone = "us-east-1z"
+
+[[backends]]
+label = "b1"
+url = "http://localhost:9000"
+weight = 1
+zone = "us-east-1a"
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidValue { ref field, .. } if field == "proxy.local_zone"),
+        "{:?}",
+        err
+    );
+    assert!(err.to_string().contains("us-east-1z"), "{}", err);
+}
+
+#[test]
+fn test_load_config_read_write_split_unknown_label() {
+    let path = write_temp_config(
+        "read_write_split_unknown",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[proxy.read_write_split]
+write_backends = ["leader"]
+read_backends = ["replica"]
+
+[[backends]]
+label = "leader"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let err = load_config(&path).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            ConfigError::UnknownBackendLabel { ref field, ref label }
+                if field == "proxy.read_write_split.read_backends" && label == "replica"
+        ),
+        "{:?}",
+        err
+    );
+    assert!(
+        err.to_string()
+            .contains("read_backends references unknown backend label 'replica'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_load_config_read_write_split_defaults() {
+    let path = write_temp_config(
+        "read_write_split_defaults",
+        r#"
+port = 8080
+metrics_port = 9091
+redis_url = "redis://localhost"
+
+[proxy.read_write_split]
+write_backends = ["leader"]
+
+[[backends]]
+label = "leader"
+url = "http://localhost:9000"
+weight = 1
+"#,
+    );
+    let config = load_config(&path).unwrap();
+    let split = config.proxy.read_write_split.unwrap();
+    assert_eq!(split.write_methods, vec!["sendTransaction"]);
+    assert_eq!(split.group_for(Some("sendTransaction")), ["leader"]);
+    assert!(split.group_for(Some("getSlot")).is_empty());
+}
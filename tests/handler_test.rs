@@ -1,5 +1,8 @@
-use std::{collections::HashMap, sync::Arc};
-use std::sync::atomic::AtomicBool;
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 use arc_swap::ArcSwap;
 use axum::{
@@ -12,13 +15,27 @@ use axum::{
 use http_body_util::BodyExt;
 use hyper_tls::HttpsConnector;
 use hyper_util::client::legacy::Client;
+use rand::{rngs::StdRng, SeedableRng};
 use sol_rpc_router::{
-    config::{Backend, HealthCheckConfig},
-    handlers::{extract_rpc_method, health_endpoint, proxy, RpcMethod},
+    config::{
+        Backend, FailureStatusCodes, HealthCheckConfig, IdMismatchAction, KeystoreFailurePolicy,
+        MethodRoute, MethodTimeout, ProxyConfig, ResponseMetadata, ResponseTransform,
+        RestGatewayConfig, RetryPolicy,
+    },
+    error::{ConfigError, Error, KeyStoreError, ProxyError},
+    handlers::{
+        extract_rpc_method, health_endpoint, key_prefix, proxy, strip_api_key, strip_router_params,
+        track_metrics, version_endpoint, ClientKey, RpcMethod, API_KEY_HEADER,
+        ORIGINAL_USER_AGENT_HEADER,
+    },
     health::{BackendHealthStatus, HealthState},
     mock::MockKeyStore,
-    state::{AppState, RouterState, RuntimeBackend},
+    retry::{jittered_backoff, RetryThrottle},
+    routes::public_router,
+    state::{upstream_client, AppState, ClientOptions, RouterState, RuntimeBackend},
+    testing::MockBackend,
 };
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tower::ServiceExt; // for oneshot
 
 fn make_app_state(
@@ -31,35 +48,23 @@ fn make_app_state(
         backends,
         method_routes: HashMap::new(),
         health_state,
-        proxy_timeout_secs: 5,
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
         health_check_config: HealthCheckConfig::default(),
     };
 
-    Arc::new(AppState {
+    Arc::new(AppState::new(
         client,
         keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    })
-}
-
-async fn start_mock_backend() -> String {
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let addr = listener.local_addr().unwrap();
-
-    tokio::spawn(async move {
-        let app = Router::new().route(
-            "/",
-            post(|| async { "{\"jsonrpc\":\"2.0\",\"result\":\"ok\",\"id\":1}" }),
-        );
-        axum::serve(listener, app).await.unwrap();
-    });
-
-    format!("http://{}", addr)
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ))
 }
 
 #[tokio::test]
 async fn test_proxy_handler_success() {
-    let backend_url = start_mock_backend().await;
+    let backend_url = MockBackend::new().start().await.url;
 
     let https = HttpsConnector::new();
     let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
@@ -71,12 +76,10 @@ async fn test_proxy_handler_success() {
         url: backend_url.clone(),
         ws_url: None,
         weight: 100,
+        ..Default::default()
     };
 
-    let runtime_backend = RuntimeBackend {
-        config: backend,
-        healthy: Arc::new(AtomicBool::new(true)),
-    };
+    let runtime_backend = RuntimeBackend::new(backend).unwrap();
 
     let health_state = Arc::new(HealthState::new(vec!["mock-backend".to_string()]));
     let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
@@ -216,9 +219,38 @@ async fn test_proxy_keystore_internal_error() {
     assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
 
+#[tokio::test]
+async fn test_keystore_failure_policy() {
+    let backend = MockBackend::new().start().await;
+    for (policy, expected) in [
+        (
+            KeystoreFailurePolicy::FailClosed,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+        (KeystoreFailurePolicy::FailOpen, StatusCode::OK),
+    ] {
+        let keystore = test_keystore();
+        keystore.set_error("test-key", "Redis connection failed");
+        let state = Arc::new(
+            app_state_with(
+                keystore,
+                vec![test_backend("primary", &backend.url)],
+                HashMap::new(),
+                ProxyConfig::default(),
+            )
+            .with_keystore_failure(policy),
+        );
+
+        let (status, _) = send_method(state, "getSlot").await;
+        assert_eq!(status, expected, "{:?}", policy);
+    }
+    // Only the fail-open request reached the backend
+    assert_eq!(backend.request_count(), 1);
+}
+
 #[tokio::test]
 async fn test_proxy_no_healthy_backends() {
-    let backend_url = start_mock_backend().await;
+    let backend_url = MockBackend::new().start().await.url;
 
     let https = HttpsConnector::new();
     let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
@@ -230,12 +262,11 @@ async fn test_proxy_no_healthy_backends() {
         url: backend_url.clone(),
         ws_url: None,
         weight: 1,
+        ..Default::default()
     };
 
-    let runtime_backend = RuntimeBackend {
-        config: backend,
-        healthy: Arc::new(AtomicBool::new(false)), // Start unhealthy
-    };
+    let runtime_backend =
+        RuntimeBackend::with_health(backend, false, &ClientOptions::default()).unwrap();
 
     let health_state = Arc::new(HealthState::new(vec!["sick-backend".to_string()]));
     let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
@@ -256,6 +287,219 @@ async fn test_proxy_no_healthy_backends() {
     assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
 }
 
+// --- Query string handling tests ---
+
+#[test]
+fn test_strip_api_key_between_other_params() {
+    assert_eq!(
+        strip_api_key("foo=bar&api-key=x&baz=qux"),
+        "foo=bar&baz=qux"
+    );
+}
+
+#[test]
+fn test_strip_api_key_repeated_and_encoded_key() {
+    assert_eq!(
+        strip_api_key("api-key=a&foo=1&api%2Dkey=b&api-key"),
+        "foo=1"
+    );
+}
+
+#[test]
+fn test_strip_api_key_keeps_lookalike_params() {
+    // A value containing "api-key=" and a key that merely starts with it are not auth params
+    assert_eq!(
+        strip_api_key("redirect=%3Fapi-key%3Dx&api-key-hint=1&api-key=secret"),
+        "redirect=%3Fapi-key%3Dx&api-key-hint=1"
+    );
+}
+
+#[test]
+fn test_strip_api_key_preserves_encoded_values() {
+    assert_eq!(
+        strip_api_key("api-key=k&filter=a%20b%26c&q=x+y"),
+        "filter=a%20b%26c&q=x+y"
+    );
+}
+
+#[test]
+fn test_strip_router_params_removes_cache_bypass() {
+    assert_eq!(
+        strip_router_params("api-key=k&fresh=true&commitment=finalized&fresh=false"),
+        "commitment=finalized&fresh=false"
+    );
+}
+
+#[test]
+fn test_key_prefix_counts_characters() {
+    assert_eq!(key_prefix("abcdefgh"), "abcdef");
+    assert_eq!(key_prefix("abc"), "abc");
+    // Slicing six bytes would split the fourth character
+    assert_eq!(key_prefix("aéééééé"), "aééééé");
+}
+
+#[tokio::test]
+async fn test_proxy_rejects_multibyte_key_without_panicking() {
+    let state = make_retry_state("http://127.0.0.1:1".to_string(), ProxyConfig::default());
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=a%C3%A9%C3%A9%C3%A9%C3%A9")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_proxy_forwards_cleaned_query() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let app = Router::new().route(
+            "/",
+            post(|uri: axum::http::Uri| async move { uri.query().unwrap_or("").to_string() }),
+        );
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let runtime_backend = RuntimeBackend::new(Backend {
+        label: "echo".to_string(),
+        url: format!("http://{}", addr),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    let health_state = Arc::new(HealthState::new(vec!["echo".to_string()]));
+    let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?foo=bar&api-key=test-key&baz=a%20b")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        "foo=bar&baz=a%20b"
+    );
+}
+
+#[tokio::test]
+async fn test_query_api_key_rejected_when_disabled() {
+    // Echoes back whether the api key header reached the backend
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let app = Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap| async move {
+                format!("forwarded_key={}", headers.contains_key(API_KEY_HEADER))
+            }),
+        );
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let state = make_backends_state(
+        vec![test_backend("echo", &format!("http://{}", addr))],
+        HashMap::new(),
+        ProxyConfig {
+            timeout_secs: 5,
+            allow_query_api_key: false,
+            ..Default::default()
+        },
+    );
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let rpc_body = r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#;
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(rpc_body))
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8(body.to_vec())
+        .unwrap()
+        .contains("x-api-key header"));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("content-type", "application/json")
+        .header(API_KEY_HEADER, "test-key")
+        .body(Body::from(rpc_body))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        "forwarded_key=false"
+    );
+}
+
+#[tokio::test]
+async fn test_header_api_key_accepted_by_default() {
+    let backend_url = MockBackend::new().start().await.url;
+
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let runtime_backend = RuntimeBackend::new(Backend {
+        label: "mock-backend".to_string(),
+        url: backend_url,
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    let health_state = Arc::new(HealthState::new(vec!["mock-backend".to_string()]));
+    let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header(API_KEY_HEADER, "test-key")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
 // --- Health endpoint tests ---
 
 fn make_health_state(backends: &[Backend]) -> Arc<AppState> {
@@ -267,10 +511,7 @@ fn make_health_state(backends: &[Backend]) -> Arc<AppState> {
 
     let runtime_backends = backends
         .iter()
-        .map(|b| RuntimeBackend {
-            config: b.clone(),
-            healthy: Arc::new(AtomicBool::new(true)),
-        })
+        .map(|b| RuntimeBackend::new(b.clone()).unwrap())
         .collect();
 
     make_app_state(client, keystore, runtime_backends, health_state)
@@ -283,12 +524,14 @@ fn test_backends() -> Vec<Backend> {
             url: "http://a".to_string(),
             ws_url: None,
             weight: 1,
+            ..Default::default()
         },
         Backend {
             label: "b".to_string(),
             url: "http://b".to_string(),
             ws_url: None,
             weight: 1,
+            ..Default::default()
         },
     ]
 }
@@ -319,9 +562,15 @@ async fn test_health_endpoint_all_healthy() {
 async fn test_health_endpoint_mixed() {
     let state = make_health_state(&test_backends());
 
-    let mut unhealthy = BackendHealthStatus::default();
-    unhealthy.healthy = false;
-    state.state.load().health_state.update_status("b", unhealthy);
+    let unhealthy = BackendHealthStatus {
+        healthy: false,
+        ..Default::default()
+    };
+    state
+        .state
+        .load()
+        .health_state
+        .update_status("b", unhealthy);
 
     let app = Router::new()
         .route("/health", get(health_endpoint))
@@ -349,8 +598,10 @@ async fn test_health_endpoint_all_unhealthy() {
     let state = make_health_state(&test_backends());
     let loaded = state.state.load();
     for label in &["a", "b"] {
-        let mut unhealthy = BackendHealthStatus::default();
-        unhealthy.healthy = false;
+        let unhealthy = BackendHealthStatus {
+            healthy: false,
+            ..Default::default()
+        };
         loaded.health_state.update_status(label, unhealthy);
     }
 
@@ -369,6 +620,74 @@ async fn test_health_endpoint_all_unhealthy() {
     assert_eq!(json["overall_status"], "unhealthy");
 }
 
+#[tokio::test]
+async fn test_health_endpoint_reports_zones() {
+    let mut backends = test_backends();
+    backends[0].zone = Some("us-east-1a".to_string());
+    backends[1].zone = Some("us-east-1b".to_string());
+    let state = make_health_state(&backends);
+    let loaded = state.state.load_full();
+    state.state.store(Arc::new(RouterState {
+        proxy_config: ProxyConfig {
+            local_zone: Some("us-east-1a".to_string()),
+            ..loaded.proxy_config.clone()
+        },
+        ..(*loaded).clone()
+    }));
+    let unhealthy = BackendHealthStatus {
+        healthy: false,
+        ..Default::default()
+    };
+    loaded.health_state.update_status("b", unhealthy);
+
+    let app = Router::new()
+        .route("/health", get(health_endpoint))
+        .with_state(state);
+    let req = Request::builder()
+        .uri("/health")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["backends"][0]["zone"], "us-east-1a");
+    assert_eq!(
+        json["zones"]["us-east-1a"],
+        serde_json::json!({"healthy_backends": 1, "total_backends": 1, "local": true})
+    );
+    assert_eq!(
+        json["zones"]["us-east-1b"],
+        serde_json::json!({"healthy_backends": 0, "total_backends": 1, "local": false})
+    );
+}
+
+// --- Version endpoint tests ---
+
+#[tokio::test]
+async fn test_version_endpoint_reports_crate_version() {
+    let state = make_health_state(&test_backends());
+    let app = Router::new()
+        .route("/version", get(version_endpoint))
+        .with_state(state);
+
+    let req = Request::builder()
+        .uri("/version")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!json["git_sha"].as_str().unwrap().is_empty());
+    assert!(!json["build_time"].as_str().unwrap().is_empty());
+    assert_eq!(json["backend_count"], 2);
+    assert!(json["uptime_secs"].is_u64());
+}
+
 // --- extract_rpc_method middleware tests ---
 
 #[tokio::test]
@@ -448,3 +767,2269 @@ async fn test_extract_rpc_method_invalid_json() {
     let body = response.into_body().collect().await.unwrap().to_bytes();
     assert_eq!(String::from_utf8(body.to_vec()).unwrap(), "none");
 }
+
+#[tokio::test]
+async fn test_extract_rpc_method_does_not_buffer_whole_body() {
+    use futures_util::StreamExt;
+
+    let app = Router::new()
+        .route(
+            "/",
+            post(|req: Request<Body>| async move {
+                match req.extensions().get::<RpcMethod>() {
+                    Some(m) => m.0.clone(),
+                    None => "none".to_string(),
+                }
+            }),
+        )
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    // A large sendTransaction whose params never finish arriving: the method must be
+    // available without waiting for the rest of the body
+    let head = r#"{"jsonrpc":"2.0","id":1,"method":"sendTransaction","params":[""#;
+    let body = futures_util::stream::once(async move { Ok::<_, std::io::Error>(head) })
+        .chain(futures_util::stream::pending());
+    let req = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("content-type", "application/json")
+        .body(Body::from_stream(body))
+        .unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(1), app.oneshot(req))
+        .await
+        .expect("middleware waited for the whole body")
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8(body.to_vec()).unwrap(), "sendTransaction");
+}
+
+#[tokio::test]
+async fn test_extract_rpc_method_split_across_chunks() {
+    let app = Router::new()
+        .route(
+            "/",
+            post(|req: Request<Body>| async move {
+                let method = req.extensions().get::<RpcMethod>().unwrap().0.clone();
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                format!("{} {}", method, String::from_utf8(body.to_vec()).unwrap())
+            }),
+        )
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let chunks = [
+        r#"{"jsonrpc":"2.0","params":[{"en"#,
+        r#"coding":"base64"}],"me"#,
+        r#"thod":"getSl"#,
+        r#"ot","id":7}"#,
+    ];
+    let body = futures_util::stream::iter(chunks.map(Ok::<_, std::io::Error>));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("content-type", "application/json")
+        .body(Body::from_stream(body))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        format!("getSlot {}", chunks.concat())
+    );
+}
+
+// --- Response id validation tests ---
+
+fn prometheus_handle() -> &'static metrics_exporter_prometheus::PrometheusHandle {
+    static HANDLE: std::sync::OnceLock<metrics_exporter_prometheus::PrometheusHandle> =
+        std::sync::OnceLock::new();
+    HANDLE.get_or_init(|| {
+        metrics_exporter_prometheus::PrometheusBuilder::new()
+            .install_recorder()
+            .unwrap()
+    })
+}
+
+fn make_validating_state(
+    backends: Vec<(&str, String)>,
+    method_routes: HashMap<String, MethodRoute>,
+    action: IdMismatchAction,
+) -> Arc<AppState> {
+    make_backends_state(
+        backends
+            .iter()
+            .map(|(label, url)| test_backend(label, url))
+            .collect(),
+        method_routes,
+        ProxyConfig {
+            timeout_secs: 5,
+            validate_response_id: true,
+            id_mismatch_action: action,
+            ..Default::default()
+        },
+    )
+}
+
+async fn send_rpc(state: Arc<AppState>, id: u64) -> (StatusCode, String) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(format!(
+            r#"{{"jsonrpc":"2.0","method":"getSlot","id":{}}}"#,
+            id
+        )))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_proxy_id_mismatch_passes_through_and_counts() {
+    let handle = prometheus_handle();
+    let bad_url = MockBackend::new().result_with_id(999).start().await.url;
+    let state = make_validating_state(
+        vec![("mismatch-pass", bad_url)],
+        HashMap::new(),
+        IdMismatchAction::Pass,
+    );
+
+    let (status, body) = send_rpc(state, 7).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"id\":999"), "body: {}", body);
+
+    let rendered = handle.render();
+    assert!(
+        rendered.contains("rpc_response_id_mismatch_total{backend=\"mismatch-pass\"} 1"),
+        "metrics: {}",
+        rendered
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_id_match_is_not_counted() {
+    let handle = prometheus_handle();
+    let good_url = MockBackend::new().result_with_id(7).start().await.url;
+    let state = make_validating_state(
+        vec![("id-match", good_url)],
+        HashMap::new(),
+        IdMismatchAction::Pass,
+    );
+
+    let (status, body) = send_rpc(state, 7).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"id\":7"));
+    assert!(!handle
+        .render()
+        .contains("rpc_response_id_mismatch_total{backend=\"id-match\"}"));
+}
+
+#[tokio::test]
+async fn test_proxy_id_mismatch_retries_other_backend() {
+    let _ = prometheus_handle();
+    let bad_url = MockBackend::new().result_with_id(999).start().await.url;
+    let good_url = MockBackend::new().result_with_id(7).start().await.url;
+
+    // Pin the method to the misbehaving backend so it is always tried first
+    let mut method_routes = HashMap::new();
+    method_routes.insert("getSlot".to_string(), "mismatch-retry".into());
+
+    let state = make_validating_state(
+        vec![("mismatch-retry", bad_url), ("healthy-retry", good_url)],
+        method_routes,
+        IdMismatchAction::Retry,
+    );
+
+    let (status, body) = send_rpc(state, 7).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"id\":7"), "body: {}", body);
+}
+
+#[tokio::test]
+async fn test_proxy_id_mismatch_retry_without_alternative_returns_original() {
+    let bad_url = MockBackend::new().result_with_id(999).start().await.url;
+    let state = make_validating_state(
+        vec![("mismatch-only", bad_url)],
+        HashMap::new(),
+        IdMismatchAction::Retry,
+    );
+
+    let (status, body) = send_rpc(state, 7).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"id\":999"), "body: {}", body);
+}
+
+#[tokio::test]
+async fn test_proxy_passes_through_backend_errors() {
+    let backend = MockBackend::new()
+        .rpc_error(-32005, "Node is behind")
+        .fail_after(1)
+        .start()
+        .await;
+    let state = make_validating_state(
+        vec![("flaky", backend.url.clone())],
+        HashMap::new(),
+        IdMismatchAction::Pass,
+    );
+
+    // First request gets the JSON-RPC error body with a 200 status
+    let (status, body) = send_rpc(state.clone(), 1).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Node is behind"), "body: {}", body);
+
+    // After that the backend starts failing at the HTTP level
+    let (status, _) = send_rpc(state, 1).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(backend.request_count(), 2);
+}
+
+#[tokio::test]
+async fn test_proxy_relays_upstream_error_status_and_body() {
+    let error_body =
+        r#"{"jsonrpc":"2.0","error":{"code":-32429,"message":"monthly quota exceeded"},"id":1}"#;
+    let backend = MockBackend::new()
+        .status(503)
+        .body(error_body)
+        .start()
+        .await;
+    let state = make_validating_state(
+        vec![("quota", backend.url.clone())],
+        HashMap::new(),
+        IdMismatchAction::Pass,
+    );
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8(body.to_vec()).unwrap(), error_body);
+}
+
+#[tokio::test]
+async fn test_proxy_synthesizes_error_for_transport_failure() {
+    // Reserve a port, then close it so connections are refused
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let state = make_validating_state(
+        vec![("down", format!("http://{}", addr))],
+        HashMap::new(),
+        IdMismatchAction::Pass,
+    );
+
+    let (status, body) = send_rpc(state, 1).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(body.starts_with("Proxy error:"), "body: {}", body);
+}
+
+#[tokio::test]
+async fn test_proxy_appends_request_path_to_backend_base_path() {
+    let backend = MockBackend::new().start().await;
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let runtime_backend = RuntimeBackend::new(Backend {
+        label: "base-path".to_string(),
+        url: format!("{}/rpc/", backend.url),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    let health_state = Arc::new(HealthState::new(vec!["base-path".to_string()]));
+    let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .route("/*path", post(proxy))
+        .with_state(state);
+
+    for uri in ["/?api-key=test-key", "/v1/x?api-key=test-key&a=1"] {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+            .unwrap();
+        let response = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    assert_eq!(backend.request_paths(), vec!["/rpc", "/rpc/v1/x?a=1"]);
+}
+
+#[tokio::test]
+async fn test_proxy_merges_backend_url_query_with_client_query() {
+    let backend = MockBackend::new().start().await;
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let runtime_backend = RuntimeBackend::new(Backend {
+        label: "keyed".to_string(),
+        url: format!("{}/?api-key=PROVIDER_KEY", backend.url),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    let health_state = Arc::new(HealthState::new(vec!["keyed".to_string()]));
+    let state = make_app_state(client, keystore, vec![runtime_backend], health_state);
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .route("/*path", post(proxy))
+        .with_state(state);
+
+    for uri in [
+        "/?api-key=test-key",
+        "/v1?api-key=test-key&commitment=finalized",
+    ] {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+            .unwrap();
+        let response = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // The provider key survives and the client's own key is never forwarded
+    assert_eq!(
+        backend.request_paths(),
+        vec![
+            "/?api-key=PROVIDER_KEY",
+            "/v1?api-key=PROVIDER_KEY&commitment=finalized"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_applies_response_transform() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":{"solana-core":"2.1.0","feature-set":123},"id":1}"#)
+        .start()
+        .await;
+
+    let transforms = HashMap::from([(
+        "getVersion".to_string(),
+        ResponseTransform {
+            remove: vec!["feature-set".to_string()],
+        },
+    )]);
+    let state = Arc::new(
+        app_state_with(
+            test_keystore(),
+            vec![test_backend("mock-backend", &backend.url)],
+            HashMap::new(),
+            ProxyConfig {
+                timeout_secs: 5,
+                ..Default::default()
+            },
+        )
+        .with_transforms(transforms),
+    );
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let rpc = |method: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/?api-key=test-key")
+            .header("content-type", "application/json")
+            .body(Body::from(format!(
+                r#"{{"jsonrpc":"2.0","method":"{}","id":1}}"#,
+                method
+            )))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(rpc("getVersion")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["result"]["solana-core"], "2.1.0");
+    assert!(
+        json["result"].get("feature-set").is_none(),
+        "body: {}",
+        json
+    );
+
+    // Methods without a transform are relayed untouched
+    let response = app.oneshot(rpc("getSlot")).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body).contains("feature-set"));
+}
+
+fn region_metadata() -> ResponseMetadata {
+    ResponseMetadata {
+        value: serde_json::Map::from_iter([("region".to_string(), "eu-west".into())]),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_response_metadata_injected_when_enabled() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            response_metadata: Some(region_metadata()),
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_rpc(state, 1).await;
+    assert_eq!(status, StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["result"], "ok");
+    assert_eq!(json["_router"], serde_json::json!({"region": "eu-west"}));
+
+    // Off by default
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+    let (_, body) = send_rpc(state, 1).await;
+    assert_eq!(body, r#"{"jsonrpc":"2.0","result":"ok","id":1}"#);
+}
+
+#[tokio::test]
+async fn test_response_metadata_batch_and_non_json() {
+    let batch = MockBackend::new()
+        .body(r#"[{"jsonrpc":"2.0","result":1,"id":1},{"jsonrpc":"2.0","result":2,"id":2}]"#)
+        .start()
+        .await;
+    let config = ProxyConfig {
+        response_metadata: Some(ResponseMetadata {
+            field: "served_by".to_string(),
+            ..region_metadata()
+        }),
+        ..Default::default()
+    };
+    let state = make_retry_state(batch.url.clone(), config.clone());
+    let (_, body) = send_body(
+        state,
+        r#"[{"jsonrpc":"2.0","method":"getSlot","id":1},{"jsonrpc":"2.0","method":"getSlot","id":2}]"#,
+    )
+    .await;
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    for entry in json.as_array().unwrap() {
+        assert_eq!(entry["served_by"]["region"], "eu-west", "body: {}", body);
+    }
+
+    let plain = MockBackend::new().body("not json").start().await;
+    let state = make_retry_state(plain.url.clone(), config);
+    let (status, body) = send_rpc(state, 1).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "not json");
+}
+
+fn make_retry_state(backend_url: String, proxy_config: ProxyConfig) -> Arc<AppState> {
+    make_backends_state(
+        vec![test_backend("flaky", &backend_url)],
+        HashMap::new(),
+        proxy_config,
+    )
+}
+
+/// An evenly weighted backend with nothing else configured.
+fn test_backend(label: &str, url: &str) -> Backend {
+    Backend {
+        label: label.to_string(),
+        url: url.to_string(),
+        weight: 1,
+        ..Default::default()
+    }
+}
+
+/// A keystore holding `test-key`, owned by `tester`.
+fn test_keystore() -> Arc<MockKeyStore> {
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+    keystore
+}
+
+/// `backends`, all healthy, checked against `keystore`. Returned unwrapped so tests
+/// can add `with_*` options.
+fn app_state_with(
+    keystore: Arc<MockKeyStore>,
+    backends: Vec<Backend>,
+    method_routes: HashMap<String, MethodRoute>,
+    proxy_config: ProxyConfig,
+) -> AppState {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = RouterState {
+        health_state: Arc::new(HealthState::new(
+            backends.iter().map(|b| b.label.clone()).collect(),
+        )),
+        backends: backends
+            .into_iter()
+            .map(|b| RuntimeBackend::new(b).unwrap())
+            .collect(),
+        method_routes,
+        proxy_config,
+        health_check_config: HealthCheckConfig::default(),
+    };
+    AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    )
+}
+
+/// `backends`, all healthy, behind a keystore holding `test-key`.
+fn make_backends_state(
+    backends: Vec<Backend>,
+    method_routes: HashMap<String, MethodRoute>,
+    proxy_config: ProxyConfig,
+) -> Arc<AppState> {
+    Arc::new(app_state_with(
+        test_keystore(),
+        backends,
+        method_routes,
+        proxy_config,
+    ))
+}
+
+async fn send_method(state: Arc<AppState>, method: &str) -> (StatusCode, String) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","id":1}}"#,
+            method
+        )))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_proxy_retries_with_growing_backoff() {
+    let backend = MockBackend::new().fail_first(2).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_retries: 3,
+            retry_policy: RetryPolicy::ConnectAnd5xx,
+            retry_base_delay_ms: 100,
+            retry_max_delay_ms: 1000,
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"result\":\"ok\""), "body: {}", body);
+    assert_eq!(backend.request_count(), 3);
+
+    // First backoff is in [50, 100]ms, the second in [100, 200]ms
+    let times = backend.request_times();
+    let first_gap = times[1] - times[0];
+    let second_gap = times[2] - times[1];
+    assert!(first_gap >= Duration::from_millis(50), "{:?}", first_gap);
+    assert!(second_gap >= Duration::from_millis(100), "{:?}", second_gap);
+}
+
+#[test]
+fn test_jittered_backoff_doubles_with_upper_half_jitter() {
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..1000 {
+        for (retries, low, high) in [(0, 50, 100), (1, 100, 200), (2, 200, 400), (5, 500, 1000)] {
+            let delay = jittered_backoff(100, 1000, retries, &mut rng);
+            assert!(
+                (Duration::from_millis(low)..=Duration::from_millis(high)).contains(&delay),
+                "retry {}: {:?}",
+                retries,
+                delay
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_never_retries_send_transaction() {
+    let backend = MockBackend::new().fail_first(1).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_retries: 3,
+            retry_policy: RetryPolicy::ConnectAnd5xx,
+            retry_base_delay_ms: 10,
+            ..Default::default()
+        },
+    );
+
+    let (status, _) = send_method(state, "sendTransaction").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(backend.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_proxy_retry_respects_budget() {
+    let backend = MockBackend::new().status(503).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_retries: 10,
+            retry_policy: RetryPolicy::ConnectAnd5xx,
+            retry_base_delay_ms: 100,
+            retry_max_delay_ms: 100,
+            retry_budget_ms: 250,
+            ..Default::default()
+        },
+    );
+
+    let start = std::time::Instant::now();
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    // Backoffs of 50-100ms stop once 250ms have passed, well short of 10 retries
+    assert!(backend.request_count() < 10, "{}", backend.request_count());
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+/// Two backends with `getSlot` pinned to `first`, so a retry lands on `second`.
+fn make_failover_state(
+    first_url: &str,
+    second_url: &str,
+    proxy_config: ProxyConfig,
+) -> Arc<AppState> {
+    make_backends_state(
+        vec![
+            test_backend("first", first_url),
+            test_backend("second", second_url),
+        ],
+        HashMap::from([("getSlot".to_string(), "first".into())]),
+        proxy_config,
+    )
+}
+
+/// A single quick retry under `policy`.
+fn retry_once(policy: RetryPolicy) -> ProxyConfig {
+    ProxyConfig {
+        timeout_secs: 5,
+        max_retries: 1,
+        retry_base_delay_ms: 10,
+        retry_max_delay_ms: 10,
+        retry_policy: policy,
+        ..Default::default()
+    }
+}
+
+// Port 1 on loopback refuses connections immediately
+const UNREACHABLE_URL: &str = "http://127.0.0.1:1";
+
+#[tokio::test]
+async fn test_failure_status_codes_fail_over() {
+    let second = MockBackend::new().start().await;
+    let config = ProxyConfig {
+        failure_status_codes: FailureStatusCodes(vec![429, 500, 502, 503, 504]),
+        ..retry_once(RetryPolicy::ConnectAnd5xx)
+    };
+
+    // A configured 429 is the backend failing, so another backend is tried
+    let throttled = MockBackend::new().status(429).start().await;
+    let state = make_failover_state(&throttled.url, &second.url, config.clone());
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second.request_count(), 1);
+
+    // Any other 4xx is the client's problem and passed through
+    let rejecting = MockBackend::new().status(400).start().await;
+    let state = make_failover_state(&rejecting.url, &second.url, config);
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(second.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_policy_never() {
+    let second = MockBackend::new().start().await;
+    let state = make_failover_state(UNREACHABLE_URL, &second.url, retry_once(RetryPolicy::Never));
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    let first = MockBackend::new().status(503).start().await;
+    let state = make_failover_state(&first.url, &second.url, retry_once(RetryPolicy::Never));
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(second.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_retry_policy_connect_errors_only() {
+    let second = MockBackend::new().start().await;
+    let state = make_failover_state(
+        UNREACHABLE_URL,
+        &second.url,
+        retry_once(RetryPolicy::ConnectErrorsOnly),
+    );
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second.request_count(), 1);
+
+    // A backend that answered, even with a failure status, isn't retried
+    let first = MockBackend::new().status(503).start().await;
+    let state = make_failover_state(
+        &first.url,
+        &second.url,
+        retry_once(RetryPolicy::ConnectErrorsOnly),
+    );
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(second.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_policy_connect_and_5xx() {
+    let first = MockBackend::new().status(503).start().await;
+    let second = MockBackend::new().start().await;
+    let state = make_failover_state(
+        &first.url,
+        &second.url,
+        retry_once(RetryPolicy::ConnectAnd5xx),
+    );
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second.request_count(), 1);
+
+    // JSON-RPC errors are relayed
+    let first = MockBackend::new()
+        .rpc_error(-32005, "Node is behind")
+        .start()
+        .await;
+    let state = make_failover_state(
+        &first.url,
+        &second.url,
+        retry_once(RetryPolicy::ConnectAnd5xx),
+    );
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Node is behind"), "{}", body);
+    assert_eq!(second.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_policy_connect_and_5xx_and_jsonrpc() {
+    let first = MockBackend::new()
+        .rpc_error(-32005, "Node is behind")
+        .start()
+        .await;
+    let second = MockBackend::new().start().await;
+    let state = make_failover_state(
+        &first.url,
+        &second.url,
+        retry_once(RetryPolicy::ConnectAnd5xxAndJsonRpc),
+    );
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"result\":\"ok\""), "{}", body);
+    assert_eq!(first.request_count(), 1);
+    assert_eq!(second.request_count(), 1);
+}
+
+/// `getSlot` pinned to `slow` and hedged to `fast` after 50ms; `fast` takes at most
+/// `fast_max_concurrent` requests at once (0 = unlimited).
+fn make_hedge_state(slow_url: &str, fast_url: &str, fast_max_concurrent: usize) -> Arc<AppState> {
+    make_backends_state(
+        vec![
+            test_backend("slow", slow_url),
+            Backend {
+                max_concurrent_requests: fast_max_concurrent,
+                ..test_backend("fast", fast_url)
+            },
+        ],
+        // Pin the first attempt to the slow backend
+        HashMap::from([("getSlot".to_string(), "slow".into())]),
+        ProxyConfig {
+            timeout_secs: 5,
+            hedge_methods: vec!["getSlot".to_string()],
+            hedge_delay_ms: 50,
+            ..Default::default()
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_proxy_hedge_wins_over_slow_primary() {
+    let slow = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"slow","id":1}"#)
+        .delay(Duration::from_secs(2))
+        .start()
+        .await;
+    let fast = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"fast","id":1}"#)
+        .start()
+        .await;
+    let state = make_hedge_state(&slow.url, &fast.url, 0);
+
+    let start = std::time::Instant::now();
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"fast\""), "{}", body);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(slow.request_count(), 1);
+    assert_eq!(fast.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_proxy_hedge_failure_status_does_not_win() {
+    let slow = MockBackend::new()
+        .status(502)
+        .delay(Duration::from_millis(100))
+        .start()
+        .await;
+    let fast = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"fast","id":1}"#)
+        .delay(Duration::from_millis(200))
+        .start()
+        .await;
+    let state = make_hedge_state(&slow.url, &fast.url, 0);
+
+    // The primary's 502 arrives first but the hedge's answer is returned
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"fast\""), "{}", body);
+    assert_eq!(slow.request_count(), 1);
+    assert_eq!(fast.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_proxy_hedge_respects_backend_concurrency_limit() {
+    let slow = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":"slow","id":1}"#)
+        .delay(Duration::from_millis(300))
+        .start()
+        .await;
+    let fast = MockBackend::new().start().await;
+    let state = make_hedge_state(&slow.url, &fast.url, 1);
+
+    // The hedge backend's only slot is taken, so the primary answers alone
+    let loaded = state.state.load_full();
+    let _busy = loaded.backends[1].start_request().unwrap();
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"slow\""), "{}", body);
+    assert_eq!(fast.request_count(), 0);
+    assert_eq!(loaded.backends[1].in_flight.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn test_in_flight_request_held_until_response_body_read() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+    );
+    let loaded = state.state.load_full();
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(loaded.backends[0].in_flight.load(Ordering::Relaxed), 1);
+    response.into_body().collect().await.unwrap();
+    assert_eq!(loaded.backends[0].in_flight.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn test_strict_method_route_returns_503_when_backend_down() {
+    let archival = MockBackend::new().start().await;
+    let regular = MockBackend::new().start().await;
+
+    let state = make_backends_state(
+        vec![
+            test_backend("archival", &archival.url),
+            test_backend("regular", &regular.url),
+        ],
+        HashMap::from([
+            (
+                "getConfirmedBlock".to_string(),
+                MethodRoute::strict("archival"),
+            ),
+            ("getBlock".to_string(), "archival".into()),
+        ]),
+        ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+    );
+
+    let (status, _) = send_method(state.clone(), "getConfirmedBlock").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(archival.request_count(), 1);
+
+    state.state.load().backends[0]
+        .healthy
+        .store(false, Ordering::Relaxed);
+
+    // The strict route doesn't fall back to a backend that can't serve the method
+    let (status, _) = send_method(state.clone(), "getConfirmedBlock").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(regular.request_count(), 0);
+
+    // A regular route to the same backend still falls back
+    let (status, _) = send_method(state, "getBlock").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(regular.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_oversized_upstream_response_rejected() {
+    let large = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"result":"{}"}}"#,
+        "x".repeat(4096)
+    );
+    let backend = MockBackend::new().body(large).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_response_bytes: 1024,
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(
+        body.contains("exceeds max_response_bytes (1024)"),
+        "{}",
+        body
+    );
+
+    // Responses within the limit pass through
+    let small = MockBackend::new().start().await;
+    let state = make_retry_state(
+        small.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_response_bytes: 1024,
+            ..Default::default()
+        },
+    );
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn send_with_headers(state: Arc<AppState>, headers: &[(String, String)]) -> StatusCode {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let mut req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json");
+    for (name, value) in headers {
+        req = req.header(name, value);
+    }
+    let req = req
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    app.oneshot(req).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_proxy_rejects_too_many_headers() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_header_count: 8,
+            ..Default::default()
+        },
+    );
+
+    let headers: Vec<_> = (0..10)
+        .map(|i| (format!("x-extra-{}", i), "1".to_string()))
+        .collect();
+    let status = send_with_headers(state.clone(), &headers).await;
+    assert_eq!(status, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    assert_eq!(backend.request_count(), 0);
+
+    let status = send_with_headers(state, &headers[..2]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_proxy_rejects_oversized_headers() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_header_bytes: 1024,
+            ..Default::default()
+        },
+    );
+
+    let headers = vec![("x-large".to_string(), "a".repeat(2048))];
+    let status = send_with_headers(state, &headers).await;
+    assert_eq!(status, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    assert_eq!(backend.request_count(), 0);
+}
+
+async fn received_host(preserve_host: bool) -> (String, String) {
+    let backend = MockBackend::new().start().await;
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+    let state = make_app_state(
+        client,
+        keystore,
+        vec![RuntimeBackend::new(Backend {
+            label: "vhost".to_string(),
+            url: backend.url.clone(),
+            weight: 1,
+            preserve_host,
+            ..Default::default()
+        })
+        .unwrap()],
+        Arc::new(HealthState::new(vec!["vhost".to_string()])),
+    );
+
+    let app = Router::new().route("/", post(proxy)).with_state(state);
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("host", "rpc.example.com")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    (backend.request_hosts()[0].clone(), backend.addr.to_string())
+}
+
+#[tokio::test]
+async fn test_proxy_rewrites_host_by_default() {
+    let (host, backend_addr) = received_host(false).await;
+    assert_eq!(host, backend_addr);
+}
+
+#[tokio::test]
+async fn test_proxy_preserves_client_host() {
+    let (host, _) = received_host(true).await;
+    assert_eq!(host, "rpc.example.com");
+}
+
+#[tokio::test]
+async fn test_request_metrics_labelled_with_owner() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+    );
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state, track_metrics))
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The resolved key travels with the response for downstream middleware
+    let client_key = response.extensions().get::<ClientKey>().unwrap();
+    assert_eq!(client_key.info.owner, "tester");
+    assert_eq!(client_key.prefix, "test-k");
+
+    let rendered = handle.render();
+    let line = rendered
+        .lines()
+        .find(|l| l.starts_with("rpc_requests_total"))
+        .unwrap_or_else(|| panic!("no request counter in:\n{}", rendered));
+    assert!(line.contains(r#"owner="tester""#), "line: {}", line);
+    assert!(line.contains(r#"backend="flaky""#), "line: {}", line);
+}
+
+#[tokio::test]
+async fn test_exhausted_retry_budget_stops_retries() {
+    let backend = MockBackend::new().status(503).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            timeout_secs: 5,
+            max_retries: 3,
+            retry_policy: RetryPolicy::ConnectAnd5xx,
+            retry_base_delay_ms: 1,
+            retry_max_delay_ms: 1,
+            ..Default::default()
+        },
+    );
+    let throttle = Arc::new(RetryThrottle::new(2, 0.1));
+    let state = Arc::new((*state).clone().with_retry_throttle(throttle.clone()));
+
+    // Two tokens pay for two retries; the third is refused
+    let (status, _) = send_method(state.clone(), "getSlot").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(backend.request_count(), 3);
+    assert_eq!(throttle.tokens(), 0.0);
+
+    // With the budget exhausted, failures are returned without retrying
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(backend.request_count(), 4);
+}
+
+#[test]
+fn test_retry_budget_refilled_by_successes() {
+    let throttle = RetryThrottle::new(1, 0.5);
+    assert!(throttle.try_acquire());
+    assert!(!throttle.try_acquire());
+
+    throttle.record_success();
+    assert!(!throttle.try_acquire());
+    throttle.record_success();
+    assert!(throttle.try_acquire());
+
+    // Never refilled past the maximum
+    for _ in 0..10 {
+        throttle.record_success();
+    }
+    assert_eq!(throttle.tokens(), 1.0);
+}
+
+#[test]
+fn test_retry_budget_resize_keeps_tokens_within_new_max() {
+    let throttle = RetryThrottle::new(10, 0.1);
+    for _ in 0..4 {
+        assert!(throttle.try_acquire());
+    }
+
+    // Growing the bucket keeps the tokens left, which refill at the new ratio
+    throttle.resize(20, 1.0);
+    assert_eq!(throttle.tokens(), 6.0);
+    throttle.record_success();
+    assert_eq!(throttle.tokens(), 7.0);
+
+    // Shrinking it drops whatever no longer fits
+    throttle.resize(2, 1.0);
+    assert_eq!(throttle.tokens(), 2.0);
+    throttle.record_success();
+    assert_eq!(throttle.tokens(), 2.0);
+}
+
+async fn send_body(state: Arc<AppState>, body: &str) -> (StatusCode, String) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_proxy_rejects_batch_with_duplicate_ids() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    let (status, body) = send_body(
+        state,
+        r#"[{"jsonrpc":"2.0","method":"getSlot","id":1},{"jsonrpc":"2.0","method":"getHealth","id":1}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["error"]["code"], -32600);
+    assert!(json["id"].is_null());
+    assert_eq!(backend.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_proxy_forwards_mixed_request_notification_batch() {
+    let backend = MockBackend::new()
+        .body(r#"[{"jsonrpc":"2.0","result":1,"id":1}]"#)
+        .start()
+        .await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    let batch =
+        r#"[{"jsonrpc":"2.0","method":"getSlot","id":1},{"jsonrpc":"2.0","method":"getHealth"}]"#;
+    let (status, body) = send_body(state, batch).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, r#"[{"jsonrpc":"2.0","result":1,"id":1}]"#);
+    assert_eq!(backend.request_bodies(), vec![batch.to_string()]);
+}
+
+#[tokio::test]
+async fn test_proxy_failed_batch_gets_error_per_request() {
+    // Port 1 on loopback refuses connections immediately
+    let state = make_retry_state("http://127.0.0.1:1".to_string(), ProxyConfig::default());
+
+    let (status, body) = send_body(
+        state,
+        r#"[{"jsonrpc":"2.0","method":"getSlot","id":1},{"jsonrpc":"2.0","method":"getHealth"},{"jsonrpc":"2.0","method":"getBalance","id":"b"}]"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let entries = json.as_array().unwrap();
+    // The notification gets no entry
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["id"], 1);
+    assert_eq!(entries[1]["id"], "b");
+    assert_eq!(entries[0]["error"]["code"], -32603);
+    assert!(entries[0]["error"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Proxy error:"));
+}
+
+#[tokio::test]
+async fn test_proxy_routes_finalized_request_to_finalized_group() {
+    let fast = MockBackend::new().start().await;
+    let finalized = MockBackend::new().start().await;
+    let backend = |label: &str, url: &str, commitments: &[&str]| Backend {
+        commitments: commitments.iter().map(|c| c.to_string()).collect(),
+        ..test_backend(label, url)
+    };
+    let state = make_backends_state(
+        vec![
+            backend("fast", &fast.url, &["processed"]),
+            backend("archive", &finalized.url, &["finalized"]),
+        ],
+        HashMap::new(),
+        ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+    );
+
+    for _ in 0..10 {
+        let (status, _) = send_body(
+            state.clone(),
+            r#"{"jsonrpc":"2.0","method":"getBalance","params":["abc",{"commitment":"finalized"}],"id":1}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    assert_eq!(finalized.request_count(), 10);
+    assert_eq!(fast.request_count(), 0);
+
+    let (status, _) = send_body(
+        state,
+        r#"{"jsonrpc":"2.0","method":"getBalance","params":["abc",{"commitment":"processed"}],"id":1}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fast.request_count(), 1);
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+async fn send_gzip(state: Arc<AppState>, body: Vec<u8>) -> (StatusCode, String) {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+#[tokio::test]
+async fn test_extract_rpc_method_from_gzip_body() {
+    let app = Router::new()
+        .route(
+            "/",
+            post(|req: Request<Body>| async move {
+                let method = req.extensions().get::<RpcMethod>().unwrap().0.clone();
+                let encoding = req.headers().get("content-encoding").cloned();
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                assert!(encoding.is_none());
+                format!("{} {}", method, String::from_utf8(body.to_vec()).unwrap())
+            }),
+        )
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let json = r#"{"jsonrpc":"2.0","method":"sendTransaction","params":["AQID"],"id":1}"#;
+    let req = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(Body::from(gzip(json.as_bytes())))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        format!("sendTransaction {}", json)
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_forwards_gzip_body_decompressed_or_recompressed() {
+    let json = r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#;
+
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+    let (status, _) = send_gzip(state, gzip(json.as_bytes())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(backend.request_bodies(), vec![json.to_string()]);
+
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            recompress_request_bodies: true,
+            ..Default::default()
+        },
+    );
+    let (status, _) = send_gzip(state, gzip(json.as_bytes())).await;
+    assert_eq!(status, StatusCode::OK);
+    // gzip magic bytes, not the plain JSON
+    assert!(backend.request_bodies()[0].starts_with('\u{1f}'));
+}
+
+#[tokio::test]
+async fn test_proxy_rejects_gzip_bomb_and_corrupt_gzip() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    // 11 MB of zeros compresses to a few KB but exceeds the 10 MB body limit
+    let bomb = gzip(&vec![0u8; 11 * 1024 * 1024]);
+    assert!(bomb.len() < 100 * 1024);
+    let (status, _) = send_gzip(state.clone(), bomb).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, body) = send_gzip(state, b"definitely not gzip".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Invalid gzip"), "{}", body);
+    assert_eq!(backend.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_upstream_errors_categorized() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let send = |state: Arc<AppState>, key: &'static str| async move {
+        let app = Router::new()
+            .route("/", post(proxy))
+            .with_state(state.clone())
+            .layer(middleware::from_fn_with_state(state, track_metrics))
+            .layer(middleware::from_fn(extract_rpc_method));
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/?api-key={}", key))
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        let status = response.status();
+        // Drain the body so streaming classification runs
+        response.into_body().collect().await.unwrap();
+        status
+    };
+
+    let slow = MockBackend::new()
+        .delay(Duration::from_secs(3))
+        .start()
+        .await;
+    let state = make_retry_state(
+        slow.url.clone(),
+        ProxyConfig {
+            timeout_secs: 1,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        send(state.clone(), "test-key").await,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+    assert_eq!(send(state, "wrong-key").await, StatusCode::UNAUTHORIZED);
+
+    let erroring = MockBackend::new()
+        .rpc_error(-32005, "Node is behind")
+        .start()
+        .await;
+    let state = make_retry_state(erroring.url.clone(), ProxyConfig::default());
+    assert_eq!(send(state, "test-key").await, StatusCode::OK);
+
+    // A configured 429 is a backend failure, but not a 5xx one
+    let throttled = MockBackend::new().status(429).start().await;
+    let state = make_retry_state(
+        throttled.url.clone(),
+        ProxyConfig {
+            failure_status_codes: FailureStatusCodes(vec![429, 500, 502, 503, 504]),
+            max_retries: 0,
+            ..Default::default()
+        },
+    );
+    assert_eq!(send(state, "test-key").await, StatusCode::TOO_MANY_REQUESTS);
+
+    let rendered = handle.render();
+    let count = |category: &str| {
+        rendered
+            .lines()
+            .filter(|l| l.starts_with("rpc_upstream_errors_total"))
+            .filter(|l| l.contains(&format!(r#"category="{}""#, category)))
+            .count()
+    };
+    assert_eq!(count("timeout"), 1, "{}", rendered);
+    assert_eq!(count("auth"), 1, "{}", rendered);
+    assert_eq!(count("jsonrpc_error"), 1, "{}", rendered);
+    assert_eq!(count("upstream_4xx"), 1, "{}", rendered);
+    assert_eq!(count("upstream_5xx"), 0, "{}", rendered);
+    assert_eq!(count("connect_error"), 0, "{}", rendered);
+}
+
+#[tokio::test]
+async fn test_method_soft_timeout_reports_slow_request() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let backend = MockBackend::new()
+        .delay(Duration::from_millis(1300))
+        .start()
+        .await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            // The method's own hard timeout replaces this one
+            timeout_secs: 1,
+            method_timeouts: HashMap::from([(
+                "getProgramAccounts".to_string(),
+                MethodTimeout {
+                    timeout_secs: Some(3),
+                    soft_timeout_secs: Some(1),
+                },
+            )]),
+            ..Default::default()
+        },
+    );
+
+    // Between the soft and hard thresholds: slow, but answered
+    let (status, _) = send_method(state.clone(), "getProgramAccounts").await;
+    assert_eq!(status, StatusCode::OK);
+    let rendered = handle.render();
+    assert!(
+        rendered.contains(r#"rpc_slow_requests_total{rpc_method="getProgramAccounts"} 1"#),
+        "{}",
+        rendered
+    );
+
+    // Other methods keep the global timeout
+    let (status, _) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(!handle.render().contains(r#"rpc_method="getSlot""#));
+}
+
+#[tokio::test]
+async fn test_rate_limiter_rejects_over_limit_keys() {
+    let backend = MockBackend::new().start().await;
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 2);
+    // Keys are limited in process memory unless another limiter is configured
+    let state = Arc::new(app_state_with(
+        keystore,
+        vec![test_backend("limited", &backend.url)],
+        HashMap::new(),
+        ProxyConfig::default(),
+    ));
+
+    for _ in 0..2 {
+        let (status, _) = send_method(state.clone(), "getSlot").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body, "Rate limit exceeded");
+    assert_eq!(backend.request_count(), 2);
+}
+
+async fn send_rest(state: Arc<AppState>, uri: &str) -> (StatusCode, String) {
+    let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let resp = public_router(state, false).oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_rest_gateway_forwards_path_method_as_jsonrpc() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            rest_gateway: Some(RestGatewayConfig {
+                path_prefix: "/v1/".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_rest(state.clone(), "/v1/getSlot?api-key=test-key").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, r#"{"jsonrpc":"2.0","result":"ok","id":1}"#);
+    let upstream: serde_json::Value = serde_json::from_str(&backend.request_bodies()[0]).unwrap();
+    assert_eq!(
+        upstream,
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"})
+    );
+    assert_eq!(backend.request_paths(), vec!["/".to_string()]);
+
+    // Paths outside the prefix, or with more than one segment, aren't methods
+    let (status, _) = send_rest(state.clone(), "/getSlot?api-key=test-key").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send_rest(state, "/v1/a/b?api-key=test-key").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(backend.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_rest_gateway_unwraps_result() {
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":{"slot":42},"id":1}"#)
+        .start()
+        .await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            rest_gateway: Some(RestGatewayConfig {
+                unwrap_result: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_rest(state, "/getSlot?api-key=test-key").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, r#"{"slot":42}"#);
+}
+
+#[tokio::test]
+async fn test_rest_gateway_disabled_by_default() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    let (status, _) = send_rest(state, "/getSlot?api-key=test-key").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(backend.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_get_request_forwards_query_call_as_post() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            allow_get_requests: true,
+            ..Default::default()
+        },
+    );
+
+    let (status, body) = send_rest(state.clone(), "/?method=getSlot&api-key=test-key").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, r#"{"jsonrpc":"2.0","result":"ok","id":1}"#);
+    let upstream: serde_json::Value = serde_json::from_str(&backend.request_bodies()[0]).unwrap();
+    assert_eq!(
+        upstream,
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"})
+    );
+    assert_eq!(backend.request_paths(), vec!["/".to_string()]);
+
+    // params and id are JSON, percent-encoded
+    let (status, _) = send_rest(
+        state,
+        "/?method=getBalance&params=%5B%22abc%22%5D&id=7&api-key=test-key",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let upstream: serde_json::Value = serde_json::from_str(&backend.request_bodies()[1]).unwrap();
+    assert_eq!(
+        upstream,
+        serde_json::json!({"jsonrpc": "2.0", "id": 7, "method": "getBalance", "params": ["abc"]})
+    );
+}
+
+#[tokio::test]
+async fn test_get_request_rejects_non_idempotent_method() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            allow_get_requests: true,
+            ..Default::default()
+        },
+    );
+
+    let (status, body) =
+        send_rest(state.clone(), "/?method=sendTransaction&api-key=test-key").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert!(body.contains("POST"));
+    let (status, _) = send_rest(state.clone(), "/?method=getSlot&params=1&api-key=test-key").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send_rest(state, "/?api-key=test-key").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(backend.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_get_request_disabled_by_default() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    // Without the setting, GET / is only a WebSocket upgrade
+    let (status, _) = send_rest(state, "/?method=getSlot&api-key=test-key").await;
+    assert!(status.is_client_error());
+    assert_eq!(backend.request_count(), 0);
+}
+
+#[tokio::test]
+async fn test_upstream_user_agent_replaces_client_user_agent() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            upstream_user_agent: Some("sol-rpc-router/1.2.3".to_string()),
+            forward_original_user_agent: true,
+            upstream_headers: HashMap::from([("x-gateway-id".to_string(), "edge-1".to_string())]),
+            ..Default::default()
+        },
+    );
+    let app = Router::new().route("/", post(proxy)).with_state(state);
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .header("user-agent", "curl/8.0")
+        .header("x-gateway-id", "spoofed")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let headers = &backend.request_headers()[0];
+    assert_eq!(headers["user-agent"], "sol-rpc-router/1.2.3");
+    assert_eq!(headers[ORIGINAL_USER_AGENT_HEADER], "curl/8.0");
+    assert_eq!(headers["x-gateway-id"], "edge-1");
+}
+
+#[tokio::test]
+async fn test_normalize_id_forwards_integer_id_and_restores_original() {
+    let backend = MockBackend::new().echo_id().start().await;
+    let state = make_backends_state(
+        vec![Backend {
+            normalize_id: true,
+            ..test_backend("strict-ids", &backend.url)
+        }],
+        HashMap::new(),
+        ProxyConfig {
+            validate_response_id: true,
+            ..Default::default()
+        },
+    );
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            r#"{"jsonrpc":"2.0","id":"req-abc","method":"getSlot"}"#,
+        ))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["id"], "req-abc");
+    assert_eq!(body["result"], "ok");
+
+    let upstream: serde_json::Value = serde_json::from_str(&backend.request_bodies()[0]).unwrap();
+    assert!(upstream["id"].is_u64(), "upstream id: {}", upstream["id"]);
+    assert_eq!(upstream["method"], "getSlot");
+}
+
+#[tokio::test]
+async fn test_adaptive_weights_lower_weight_after_429() {
+    let backend = MockBackend::new().status(429).start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            adaptive_weights: true,
+            ..Default::default()
+        },
+    );
+
+    let (status, _) = send_method(state.clone(), "getSlot").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let app = Router::new()
+        .route("/health", get(health_endpoint))
+        .with_state(state);
+    let req = Request::builder()
+        .uri("/health")
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(health["backends"][0]["weight"], 1);
+    assert_eq!(health["backends"][0]["effective_weight"], 0.5);
+}
+
+#[tokio::test]
+async fn test_connections_recycled_after_max_lifetime() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+    let state = Arc::new(
+        (*state)
+            .clone()
+            .with_max_connection_lifetime(Duration::from_millis(200)),
+    );
+
+    for _ in 0..2 {
+        assert_eq!(
+            send_method(state.clone(), "getSlot").await.0,
+            StatusCode::OK
+        );
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    for _ in 0..2 {
+        assert_eq!(
+            send_method(state.clone(), "getSlot").await.0,
+            StatusCode::OK
+        );
+    }
+
+    // Reused within the lifetime, replaced once it has passed
+    let peers = backend.request_peers();
+    assert_eq!(peers[0], peers[1]);
+    assert_ne!(peers[1], peers[2]);
+    assert_eq!(peers[2], peers[3]);
+}
+
+#[tokio::test]
+async fn test_connection_lifetime_counts_from_connect() {
+    // Each response takes 300ms, so the connection is 300ms old once the first one
+    // arrives and 600ms old after the second, past its 400ms lifetime
+    let backend = MockBackend::new()
+        .delay(Duration::from_millis(300))
+        .start()
+        .await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+    let state = Arc::new(
+        (*state)
+            .clone()
+            .with_max_connection_lifetime(Duration::from_millis(400)),
+    );
+
+    for _ in 0..3 {
+        assert_eq!(
+            send_method(state.clone(), "getSlot").await.0,
+            StatusCode::OK
+        );
+    }
+
+    let peers = backend.request_peers();
+    assert_eq!(peers[0], peers[1]);
+    assert_ne!(peers[1], peers[2]);
+}
+
+/// HTTPS upstream presenting the self-signed test fixture certificate. Each connection
+/// gets one `getSlot`-style answer and is closed.
+async fn start_self_signed_backend() -> String {
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let cert = std::fs::read(format!("{}/client.crt", fixtures)).unwrap();
+    let key = std::fs::read(format!("{}/client.key", fixtures)).unwrap();
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).unwrap();
+    let acceptor =
+        tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(tcp).await else {
+                    return;
+                };
+                let mut request = vec![0; 4096];
+                let _ = tls.read(&mut request).await;
+                let body = r#"{"jsonrpc":"2.0","result":42,"id":1}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = tls.write_all(response.as_bytes()).await;
+                let _ = tls.shutdown().await;
+            });
+        }
+    });
+    format!("https://{}", addr)
+}
+
+#[tokio::test]
+async fn test_accept_invalid_certs_reaches_self_signed_backend() {
+    let backend_url = start_self_signed_backend().await;
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+    let make_state = |accept_invalid_certs| {
+        let backend = RuntimeBackend::new(Backend {
+            label: "self-signed".to_string(),
+            url: backend_url.clone(),
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        make_app_state(
+            upstream_client(accept_invalid_certs),
+            keystore.clone(),
+            vec![backend],
+            Arc::new(HealthState::new(vec!["self-signed".to_string()])),
+        )
+    };
+
+    let (status, _) = send_method(make_state(false), "getSlot").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    let (status, body) = send_method(make_state(true), "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("42"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_default_commitment_injected_when_missing() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            default_commitment: Some("confirmed".to_string()),
+            ..Default::default()
+        },
+    );
+
+    let requests = [
+        r#"{"jsonrpc":"2.0","method":"getAccountInfo","params":["Vote111111111111111111111111111111111111111"],"id":1}"#,
+        r#"{"jsonrpc":"2.0","method":"getAccountInfo","params":["Vote111111111111111111111111111111111111111",{"encoding":"base64"}],"id":2}"#,
+        r#"{"jsonrpc":"2.0","method":"getAccountInfo","params":["Vote111111111111111111111111111111111111111",{"commitment":"finalized"}],"id":3}"#,
+        r#"{"jsonrpc":"2.0","method":"getSlot","id":4}"#,
+        r#"{"jsonrpc":"2.0","method":"getHealth","id":5}"#,
+    ];
+    for body in requests {
+        let (status, _) = send_body(state.clone(), body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let forwarded: Vec<serde_json::Value> = backend
+        .request_bodies()
+        .iter()
+        .map(|b| serde_json::from_str(b).unwrap())
+        .collect();
+    assert_eq!(forwarded[0]["params"][1]["commitment"], "confirmed");
+    assert_eq!(forwarded[1]["params"][1]["commitment"], "confirmed");
+    assert_eq!(forwarded[1]["params"][1]["encoding"], "base64");
+    // The client's own choice is kept
+    assert_eq!(forwarded[2]["params"][1]["commitment"], "finalized");
+    assert_eq!(
+        forwarded[3]["params"],
+        serde_json::json!([{"commitment": "confirmed"}])
+    );
+    assert!(forwarded[4].get("params").is_none());
+}
+
+#[tokio::test]
+async fn test_empty_success_response_fails_over_when_validated() {
+    let first = MockBackend::new().body("").start().await;
+    let second = MockBackend::new().start().await;
+
+    // Passed through as is without validation
+    let state = make_failover_state(
+        &first.url,
+        &second.url,
+        retry_once(RetryPolicy::ConnectAnd5xx),
+    );
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.is_empty());
+    assert_eq!(second.request_count(), 0);
+
+    let validating = ProxyConfig {
+        validate_response_body: true,
+        ..retry_once(RetryPolicy::ConnectAnd5xx)
+    };
+    let state = make_failover_state(&first.url, &second.url, validating);
+    let (status, body) = send_method(state, "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("result"), "{}", body);
+    assert_eq!(second.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_truncated_json_rejected_without_failover_target() {
+    let first = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":{"val"#)
+        .start()
+        .await;
+    let state = make_backends_state(
+        vec![
+            test_backend("first", &first.url),
+            test_backend("second", UNREACHABLE_URL),
+        ],
+        HashMap::from([("sendTransaction".to_string(), "first".into())]),
+        ProxyConfig {
+            validate_response_body: true,
+            ..retry_once(RetryPolicy::ConnectAnd5xx)
+        },
+    );
+    let (status, body) = send_method(state, "sendTransaction").await;
+    // Not idempotent, so not retried
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(body.contains("isn't JSON"), "{}", body);
+    assert_eq!(first.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_owner_concurrency_limit_rejects_second_request() {
+    let backend = MockBackend::new()
+        .delay(Duration::from_millis(300))
+        .start()
+        .await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            max_concurrent_requests_per_owner: 1,
+            ..Default::default()
+        },
+    );
+
+    let first = tokio::spawn(send_method(state.clone(), "getSlot"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(state.owner_concurrency.in_flight("tester"), 1);
+    let (status, body) = send_method(state.clone(), "getSlot").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body, "Too many concurrent requests");
+
+    let (status, _) = first.await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(backend.request_count(), 1);
+
+    // The slot is released once the first request finishes
+    assert_eq!(state.owner_concurrency.in_flight("tester"), 0);
+    let (status, _) = send_method(state.clone(), "getSlot").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_key_concurrency_limit_released_on_upstream_failure() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+    // The key's own limit applies without a configured default
+    keystore.set_max_concurrent_requests("test-key", 1);
+    let backend = RuntimeBackend::new(Backend {
+        label: "down".to_string(),
+        url: UNREACHABLE_URL.to_string(),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    let state = make_app_state(
+        client,
+        keystore,
+        vec![backend],
+        Arc::new(HealthState::new(vec!["down".to_string()])),
+    );
+
+    for _ in 0..2 {
+        let (status, _) = send_method(state.clone(), "getSlot").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+    assert_eq!(state.owner_concurrency.key_in_flight("test-key"), 0);
+}
+
+#[tokio::test]
+async fn test_key_concurrency_limit_counted_apart_from_owner() {
+    let backend = MockBackend::new()
+        .delay(Duration::from_millis(300))
+        .start()
+        .await;
+    let keystore = test_keystore();
+    keystore.add_key("own-limit-key", "tester", 100);
+    keystore.set_max_concurrent_requests("own-limit-key", 1);
+    let state = Arc::new(app_state_with(
+        keystore,
+        vec![test_backend("slow", &backend.url)],
+        HashMap::new(),
+        ProxyConfig {
+            max_concurrent_requests_per_owner: 1,
+            ..Default::default()
+        },
+    ));
+    let send_as = |key: &'static str| {
+        let app = Router::new()
+            .route("/", post(proxy))
+            .with_state(state.clone())
+            .layer(middleware::from_fn(extract_rpc_method));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header(API_KEY_HEADER, key)
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+            .unwrap();
+        async move { app.oneshot(req).await.unwrap().status() }
+    };
+
+    // The key with its own limit doesn't use up the owner's single slot
+    let limited = tokio::spawn(send_as("own-limit-key"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(state.owner_concurrency.key_in_flight("own-limit-key"), 1);
+    assert_eq!(state.owner_concurrency.in_flight("tester"), 0);
+    assert_eq!(
+        send_as("own-limit-key").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(send_as("test-key").await, StatusCode::OK);
+    assert_eq!(limited.await.unwrap(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_owner_slot_held_until_response_body_read() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            max_concurrent_requests_per_owner: 1,
+            ..Default::default()
+        },
+    );
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state.clone())
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(state.owner_concurrency.in_flight("tester"), 1);
+    response.into_body().collect().await.unwrap();
+    assert_eq!(state.owner_concurrency.in_flight("tester"), 0);
+}
+
+async fn served_by(state: Arc<AppState>) -> Option<String> {
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .headers()
+        .get("x-served-by")
+        .map(|v| v.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn test_served_by_header_names_backend_when_enabled() {
+    let backend = MockBackend::new().start().await;
+
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+    assert_eq!(served_by(state).await, None);
+
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            expose_backend_header: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(served_by(state).await.as_deref(), Some("flaky"));
+}
+
+#[tokio::test]
+async fn test_selected_trailers_forwarded() {
+    let backend = MockBackend::new()
+        .trailer("grpc-status", "0")
+        .trailer("x-internal-node", "node-7")
+        .start()
+        .await;
+    let state = make_retry_state(
+        backend.url.clone(),
+        ProxyConfig {
+            forward_trailers: vec!["grpc-status".to_string()],
+            ..Default::default()
+        },
+    );
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["trailer"], "grpc-status");
+
+    let collected = response.into_body().collect().await.unwrap();
+    let trailers = collected.trailers().cloned().expect("no trailers relayed");
+    assert_eq!(trailers["grpc-status"], "0");
+    assert!(!trailers.contains_key("x-internal-node"));
+    assert_eq!(
+        collected.to_bytes(),
+        r#"{"jsonrpc":"2.0","result":"ok","id":1}"#
+    );
+}
+
+#[tokio::test]
+async fn test_trailers_not_requested_when_none_forwarded() {
+    let backend = MockBackend::new().start().await;
+    let state = make_retry_state(backend.url.clone(), ProxyConfig::default());
+
+    let app = Router::new()
+        .route("/", post(proxy))
+        .with_state(state)
+        .layer(middleware::from_fn(extract_rpc_method));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .header("te", "trailers")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("trailer"));
+    assert!(!backend.request_headers()[0].contains_key("te"));
+}
+
+#[test]
+fn test_errors_map_to_client_statuses() {
+    for (error, status) in [
+        (
+            Error::from(KeyStoreError::RateLimited),
+            StatusCode::TOO_MANY_REQUESTS,
+        ),
+        (
+            Error::from(KeyStoreError::Unavailable("down".to_string())),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+        (
+            Error::from(ProxyError::NoHealthyBackends),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            Error::from(ProxyError::Timeout(5)),
+            StatusCode::GATEWAY_TIMEOUT,
+        ),
+        (
+            Error::from(ConfigError::NoBackends),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ] {
+        assert_eq!(error.status_code(), status, "{}", error);
+    }
+}
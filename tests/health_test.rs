@@ -0,0 +1,457 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    config::{Backend, FailureStatusCodes, HealthCheckConfig, ProxyConfig, SecondaryCheck},
+    error::HealthCheckError,
+    health::{
+        health_check_client, run_health_checks, run_staggered_health_checks, startup_probe,
+        HealthState,
+    },
+    state::{RouterState, RuntimeBackend},
+    testing::{MockBackend, RunningMockBackend},
+};
+
+fn unreachable_router_state() -> RouterState {
+    // Port 1 on loopback refuses connections immediately
+    let backends: Vec<RuntimeBackend> = ["a", "b"]
+        .iter()
+        .map(|label| {
+            RuntimeBackend::new(Backend {
+                label: label.to_string(),
+                url: "http://127.0.0.1:1".to_string(),
+                ws_url: None,
+                weight: 1,
+                ..Default::default()
+            })
+            .unwrap()
+        })
+        .collect();
+
+    let health_state = Arc::new(HealthState::new(vec!["a".to_string(), "b".to_string()]));
+
+    RouterState {
+        backends,
+        method_routes: HashMap::new(),
+        health_state,
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig {
+            timeout_secs: 1,
+            ..HealthCheckConfig::default()
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_startup_probe_fails_when_all_unreachable() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = unreachable_router_state();
+
+    let err = startup_probe(&client, &router_state, true)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, HealthCheckError::NoneReachable(2)),
+        "Unexpected error: {}",
+        err
+    );
+
+    // Unreachable backends are taken out of rotation
+    for backend in &router_state.backends {
+        assert!(!backend.healthy.load(Ordering::Relaxed));
+    }
+}
+
+#[tokio::test]
+async fn test_startup_probe_tolerates_unreachable_when_not_required() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = unreachable_router_state();
+
+    let reachable = startup_probe(&client, &router_state, false).await.unwrap();
+    assert_eq!(reachable, 0);
+}
+
+#[tokio::test]
+async fn test_warmup_failures_not_counted_until_warmup_elapses() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let mut router_state = unreachable_router_state();
+    router_state
+        .health_check_config
+        .consecutive_failures_threshold = 1;
+
+    // Failures during warmup are recorded but leave the backend in rotation
+    run_health_checks(&client, &router_state, true).await;
+    for backend in &router_state.backends {
+        assert!(backend.healthy.load(Ordering::Relaxed));
+        let status = router_state
+            .health_state
+            .get_status(&backend.config.label)
+            .unwrap();
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.last_error.is_some());
+    }
+
+    // Once warmup is over the same failure trips the threshold
+    run_health_checks(&client, &router_state, false).await;
+    for backend in &router_state.backends {
+        assert!(!backend.healthy.load(Ordering::Relaxed));
+    }
+}
+
+fn status_router_state(url: String) -> RouterState {
+    RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "status".to_string(),
+            url,
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["status".to_string()])),
+        proxy_config: ProxyConfig {
+            failure_status_codes: FailureStatusCodes(vec![429, 500, 502, 503, 504]),
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig {
+            timeout_secs: 1,
+            consecutive_failures_threshold: 1,
+            ..HealthCheckConfig::default()
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_configured_failure_status_marks_backend_unhealthy() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = status_router_state(MockBackend::new().status(429).start().await.url);
+
+    run_health_checks(&client, &router_state, false).await;
+    assert!(!router_state.backends[0].healthy.load(Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn test_client_error_status_does_not_mark_backend_unhealthy() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = status_router_state(MockBackend::new().status(400).start().await.url);
+
+    run_health_checks(&client, &router_state, false).await;
+    assert!(router_state.backends[0].healthy.load(Ordering::Relaxed));
+    let status = router_state.health_state.get_status("status").unwrap();
+    assert_eq!(status.consecutive_failures, 0);
+}
+
+#[tokio::test]
+async fn test_health_check_sends_configured_params() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let backend = MockBackend::new().start().await;
+    let mut router_state = status_router_state(backend.url.clone());
+    router_state.health_check_config.method = "getHealth".to_string();
+    router_state.health_check_config.params = serde_json::json!({"commitment": "finalized"});
+
+    run_health_checks(&client, &router_state, false).await;
+    let bodies = backend.request_bodies();
+    assert_eq!(bodies.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+    assert_eq!(body["method"], "getHealth");
+    assert_eq!(
+        body["params"],
+        serde_json::json!({"commitment": "finalized"})
+    );
+}
+
+/// One backend per mock, labeled `b0`, `b1`, ...
+fn mock_router_state(
+    mocks: &[RunningMockBackend],
+    health_check_config: HealthCheckConfig,
+) -> RouterState {
+    let labels: Vec<String> = (0..mocks.len()).map(|i| format!("b{}", i)).collect();
+    RouterState {
+        backends: mocks
+            .iter()
+            .zip(&labels)
+            .map(|(mock, label)| {
+                RuntimeBackend::new(Backend {
+                    label: label.clone(),
+                    url: mock.url.clone(),
+                    weight: 1,
+                    ..Default::default()
+                })
+                .unwrap()
+            })
+            .collect(),
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(labels)),
+        proxy_config: ProxyConfig::default(),
+        health_check_config,
+    }
+}
+
+#[tokio::test]
+async fn test_health_checks_bounded_by_concurrency() {
+    let delay = Duration::from_millis(200);
+    let mut mocks = Vec::new();
+    for _ in 0..12 {
+        mocks.push(MockBackend::new().delay(delay).start().await);
+    }
+    let health_check_config = HealthCheckConfig {
+        concurrency: 3,
+        connect_timeout_ms: 500,
+        ..HealthCheckConfig::default()
+    };
+    let router_state = mock_router_state(&mocks, health_check_config);
+    let client = health_check_client(&router_state.health_check_config, false);
+
+    run_health_checks(&client, &router_state, false).await;
+
+    // Every backend was probed, and no more than 3 probes were ever in flight: each
+    // probe holds its permit for `delay`, so any window shorter than that saw at most
+    // 3 probes start
+    let starts: Vec<_> = mocks.iter().flat_map(|m| m.request_times()).collect();
+    assert_eq!(starts.len(), 12);
+    let window = delay * 3 / 4;
+    let max_in_flight = starts
+        .iter()
+        .map(|t| {
+            starts
+                .iter()
+                .filter(|s| **s >= *t && s.duration_since(*t) < window)
+                .count()
+        })
+        .max()
+        .unwrap();
+    assert_eq!(max_in_flight, 3);
+}
+
+#[tokio::test]
+async fn test_staggered_first_round_spreads_probes() {
+    let mut mocks = Vec::new();
+    for _ in 0..4 {
+        mocks.push(MockBackend::new().start().await);
+    }
+    let router_state = mock_router_state(&mocks, HealthCheckConfig::default());
+    let client = health_check_client(&router_state.health_check_config, false);
+
+    let spread = Duration::from_millis(400);
+    run_staggered_health_checks(&client, &router_state, false, spread).await;
+
+    // Probes start a quarter of the spread apart instead of together
+    let mut starts: Vec<_> = mocks.iter().flat_map(|m| m.request_times()).collect();
+    assert_eq!(starts.len(), 4);
+    starts.sort();
+    for pair in starts.windows(2) {
+        let gap = pair[1].duration_since(pair[0]);
+        assert!(gap >= Duration::from_millis(80), "probes {:?} apart", gap);
+    }
+    for backend in &router_state.backends {
+        assert!(backend.healthy.load(Ordering::Relaxed));
+    }
+}
+
+#[tokio::test]
+async fn test_webhook_notified_on_health_transition() {
+    let receiver = MockBackend::new().start().await;
+    let mut router_state = unreachable_router_state();
+    router_state.backends.truncate(1);
+    router_state
+        .health_check_config
+        .consecutive_failures_threshold = 2;
+    router_state.health_check_config.webhook_url = Some(receiver.url.clone());
+    let client = health_check_client(&router_state.health_check_config, false);
+
+    // The first failure doesn't change the backend's health
+    run_health_checks(&client, &router_state, false).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(receiver.request_count(), 0);
+
+    run_health_checks(&client, &router_state, false).await;
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    while receiver.request_count() == 0 && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let bodies = receiver.request_bodies();
+    assert_eq!(bodies.len(), 1);
+    let notification: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+    assert_eq!(notification["backend"], "a");
+    assert_eq!(notification["healthy"], false);
+    assert_eq!(notification["consecutive"], 2);
+    assert!(notification["last_error"].is_string());
+    assert!(notification["timestamp"].as_u64().unwrap() > 0);
+
+    // Staying unhealthy sends nothing more
+    run_health_checks(&client, &router_state, false).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(receiver.request_count(), 1);
+}
+
+#[test]
+fn test_webhook_notifications_rate_limited_while_flapping() {
+    let health_state = HealthState::new(vec!["a".to_string()]);
+    let interval = Duration::from_millis(200);
+
+    assert!(health_state.should_notify("a", false, true, interval));
+    // Recovering inside the interval is held back
+    assert!(!health_state.should_notify("a", true, true, interval));
+    std::thread::sleep(interval);
+    // ...and reported once it has passed, even without a new transition
+    assert!(health_state.should_notify("a", true, false, interval));
+    assert!(!health_state.should_notify("a", true, false, interval));
+}
+
+#[test]
+fn test_repeated_failure_logs_throttled() {
+    let health_state = HealthState::new(vec!["a".to_string()]);
+    let interval = Duration::from_millis(200);
+
+    assert_eq!(
+        health_state.should_log_failure("a", "timeout", false, interval),
+        Some(0)
+    );
+    assert_eq!(
+        health_state.should_log_failure("a", "timeout", false, interval),
+        None
+    );
+    assert_eq!(
+        health_state.should_log_failure("a", "timeout", false, interval),
+        None
+    );
+    // A health transition is always logged, with the repeats skipped before it
+    assert_eq!(
+        health_state.should_log_failure("a", "timeout", true, interval),
+        Some(2)
+    );
+    // So is a different error
+    assert_eq!(
+        health_state.should_log_failure("a", "refused", false, interval),
+        Some(0)
+    );
+    std::thread::sleep(interval);
+    assert_eq!(
+        health_state.should_log_failure("a", "refused", false, interval),
+        Some(0)
+    );
+
+    // After a passing check the next failure is new again
+    health_state.clear_logged_failure("a");
+    assert_eq!(
+        health_state.should_log_failure("a", "refused", false, interval),
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn test_last_success_time_kept_through_failures() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let backend = MockBackend::new()
+        .body(r#"{"jsonrpc":"2.0","result":100,"id":1}"#)
+        .fail_after(1)
+        .start()
+        .await;
+    let router_state = status_router_state(backend.url.clone());
+    assert!(router_state
+        .health_state
+        .get_status("status")
+        .unwrap()
+        .time_since_last_success()
+        .is_none());
+
+    run_health_checks(&client, &router_state, false).await;
+    let status = router_state.health_state.get_status("status").unwrap();
+    let after_success = status.time_since_last_success().unwrap();
+    assert!(after_success < Duration::from_secs(1));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    run_health_checks(&client, &router_state, false).await;
+    let failed = router_state.health_state.get_status("status").unwrap();
+    assert_eq!(failed.consecutive_failures, 1);
+    assert_eq!(failed.last_success_time, status.last_success_time);
+    assert!(failed.time_since_last_success().unwrap() >= after_success + Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_failed_secondary_check_marks_backend_unhealthy() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    // Live (2xx) but unable to serve reads: every call is a JSON-RPC error
+    let backend = MockBackend::new()
+        .rpc_error(-32005, "Node is behind")
+        .start()
+        .await;
+    let mut router_state = status_router_state(backend.url.clone());
+    router_state.health_check_config.method = "getHealth".to_string();
+
+    // The liveness check alone passes
+    run_health_checks(&client, &router_state, false).await;
+    assert!(router_state.backends[0].healthy.load(Ordering::Relaxed));
+
+    router_state.health_check_config.secondary_check = Some(SecondaryCheck::default());
+    run_health_checks(&client, &router_state, false).await;
+    assert!(!router_state.backends[0].healthy.load(Ordering::Relaxed));
+    let status = router_state.health_state.get_status("status").unwrap();
+    let last_error = status.last_error.unwrap();
+    assert!(
+        last_error.starts_with("Secondary check getLatestBlockhash failed"),
+        "{}",
+        last_error
+    );
+
+    let methods: Vec<String> = backend
+        .request_bodies()
+        .iter()
+        .map(|body| serde_json::from_str::<serde_json::Value>(body).unwrap()["method"].to_string())
+        .collect();
+    assert_eq!(
+        methods,
+        ["\"getHealth\"", "\"getHealth\"", "\"getLatestBlockhash\""]
+    );
+}
+
+#[tokio::test]
+async fn test_all_backends_unhealthy_gauge_set_and_cleared() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    // b0 drops its first connection and recovers after; b1 stays down
+    let mocks = vec![
+        MockBackend::new()
+            .body(r#"{"jsonrpc":"2.0","result":100,"id":1}"#)
+            .fail_first(1)
+            .start()
+            .await,
+        MockBackend::new().status(500).start().await,
+    ];
+    let health_check_config = HealthCheckConfig {
+        consecutive_failures_threshold: 1,
+        consecutive_successes_threshold: 1,
+        ..HealthCheckConfig::default()
+    };
+    let router_state = mock_router_state(&mocks, health_check_config);
+    let client = health_check_client(&router_state.health_check_config, false);
+
+    run_health_checks(&client, &router_state, false).await;
+    assert!(router_state.health_state.all_unhealthy());
+    assert!(handle.render().contains("rpc_all_backends_unhealthy 1"));
+
+    // One backend recovering clears the outage
+    run_health_checks(&client, &router_state, false).await;
+    assert!(router_state.backends[0].healthy.load(Ordering::Relaxed));
+    assert!(!router_state.health_state.all_unhealthy());
+    assert!(handle.render().contains("rpc_all_backends_unhealthy 0"));
+}
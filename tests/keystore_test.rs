@@ -1,4 +1,11 @@
-use sol_rpc_router::{keystore::KeyStore, mock::MockKeyStore};
+use std::time::Duration;
+
+use metrics_exporter_prometheus::PrometheusBuilder;
+use sol_rpc_router::{
+    error::KeyStoreError,
+    keystore::{KeyCache, KeyInfo, KeyStore, StaleKeyCache},
+    mock::MockKeyStore,
+};
 
 #[tokio::test]
 async fn test_validate_key_valid() {
@@ -45,7 +52,7 @@ async fn test_validate_key_rate_limit() {
 
     let result = store.validate_key("limited-key").await;
     assert!(result.is_err());
-    assert_eq!(result.err().unwrap(), "Rate limit exceeded");
+    assert_eq!(result.unwrap_err(), KeyStoreError::RateLimited);
 }
 
 #[tokio::test]
@@ -68,5 +75,86 @@ async fn test_validate_key_custom_error() {
 
     let result = store.validate_key("err-key").await;
     assert!(result.is_err());
-    assert_eq!(result.err().unwrap(), "Redis connection failed");
+    assert_eq!(
+        result.unwrap_err(),
+        KeyStoreError::Unavailable("Redis connection failed".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_stale_cache_serves_known_keys_while_store_errors() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let primary = MockKeyStore::new();
+    primary.add_key("seen-key", "owner1", 100);
+    primary.add_key("unseen-key", "owner2", 100);
+    let store = StaleKeyCache::new(primary.clone(), Duration::from_secs(3600));
+    assert!(store.validate_key("seen-key").await.unwrap().is_some());
+
+    primary.set_error("seen-key", "Redis connection failed");
+    primary.set_error("unseen-key", "Redis connection failed");
+
+    let info = store.validate_key("seen-key").await.unwrap().unwrap();
+    assert_eq!(info.owner, "owner1");
+    assert_eq!(
+        store.validate_key("unseen-key").await.unwrap_err(),
+        KeyStoreError::Unavailable("Redis connection failed".to_string())
+    );
+    assert!(handle
+        .render()
+        .contains("rpc_keystore_stale_validations_total 1"));
+}
+
+#[tokio::test]
+async fn test_stale_cache_forgets_keys_reported_invalid() {
+    let primary = MockKeyStore::new();
+    primary.add_key("revoked-key", "owner1", 100);
+    let store = StaleKeyCache::new(primary.clone(), Duration::from_secs(3600));
+    assert!(store.validate_key("revoked-key").await.unwrap().is_some());
+
+    primary.set_inactive("revoked-key");
+    assert!(store.validate_key("revoked-key").await.unwrap().is_none());
+
+    primary.set_error("revoked-key", "Redis connection failed");
+    assert!(store.validate_key("revoked-key").await.is_err());
+}
+
+#[tokio::test]
+async fn test_key_cache_counts_hits_and_misses() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let cache = KeyCache::new(Duration::from_secs(60));
+    assert!(cache.get("repeat-key").await.is_none());
+    cache
+        .insert(
+            "repeat-key",
+            Some(KeyInfo {
+                owner: "owner".to_string(),
+                rate_limit: 10,
+                ws_message_rate: None,
+                max_concurrent_requests: None,
+            }),
+        )
+        .await;
+    let cached = cache.get("repeat-key").await.unwrap().unwrap();
+    assert_eq!(cached.owner, "owner");
+
+    let rendered = handle.render();
+    assert!(
+        rendered.contains("rpc_keystore_cache_hits_total 1"),
+        "{}",
+        rendered
+    );
+    assert!(
+        rendered.contains("rpc_keystore_cache_misses_total 1"),
+        "{}",
+        rendered
+    );
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+    assert_eq!(stats.hit_ratio, Some(0.5));
 }
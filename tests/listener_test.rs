@@ -0,0 +1,335 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc},
+};
+
+use arc_swap::ArcSwap;
+use axum::{extract::ConnectInfo, routing::get, Router};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::StatusCode;
+use hyper_tls::HttpsConnector;
+use hyper_util::{
+    client::legacy::Client,
+    rt::{TokioExecutor, TokioIo},
+};
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ListenerConfig, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::public_router,
+    server::{bind_tcp, serve_tcp},
+    shutdown::shutdown_requested,
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::{MockBackend, RunningMockBackend},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+
+fn make_state(backend_url: String) -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "mock-backend".to_string(),
+            url: backend_url,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["mock-backend".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ))
+}
+
+#[tokio::test]
+async fn test_proxy_over_tuned_tcp_listener() {
+    let backend = MockBackend::new().start().await;
+    let state = make_state(backend.url.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let server = tokio::spawn(serve_tcp(
+        listener,
+        public_router(state, true),
+        ListenerConfig {
+            tcp_nodelay: true,
+            http1_keep_alive: true,
+            http2_keep_alive_interval_secs: 30,
+            ..Default::default()
+        },
+        shutdown_requested(shutdown_rx),
+    ));
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    // Two requests on one connection: keep-alive holds it open between them
+    for _ in 0..2 {
+        let req = hyper::Request::post("/?api-key=test-key")
+            .header("host", "localhost")
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from_static(
+                br#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#,
+            )))
+            .unwrap();
+        let resp = sender.send_request(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("\"result\":\"ok\""));
+    }
+    assert_eq!(backend.request_count(), 2);
+
+    drop(sender);
+    let _ = shutdown_tx.send(true);
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_tcp_listener_provides_connect_info() {
+    let app = Router::new().route(
+        "/",
+        get(|ConnectInfo(peer): ConnectInfo<SocketAddr>| async move { peer.to_string() }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_tcp(
+        listener,
+        app,
+        ListenerConfig::default(),
+        std::future::pending(),
+    ));
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let local_addr = stream.local_addr().unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    let req = hyper::Request::get("/")
+        .header("host", "localhost")
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let resp = sender.send_request(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8_lossy(&body), local_addr.to_string());
+}
+
+/// Opens a new connection and sends `GET /` with `headers`, returning `None` when the
+/// listener closed the connection instead of answering.
+async fn get_on_new_connection(addr: SocketAddr, headers: &[(&str, &str)]) -> Option<StatusCode> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .ok()?;
+    tokio::spawn(conn);
+
+    let mut req = hyper::Request::get("/").header("host", "localhost");
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let resp = sender
+        .send_request(req.body(Full::new(Bytes::new())).unwrap())
+        .await
+        .ok()?;
+    Some(resp.status())
+}
+
+async fn serve_limited(config: ListenerConfig) -> SocketAddr {
+    let app = Router::new().route("/", get(|| async { "ok" }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_tcp(listener, app, config, std::future::pending()));
+    addr
+}
+
+#[tokio::test]
+async fn test_rapid_connections_from_one_ip_are_dropped() {
+    let addr = serve_limited(ListenerConfig {
+        max_connections_per_ip_per_sec: 3,
+        ..Default::default()
+    })
+    .await;
+
+    let mut statuses = Vec::new();
+    for _ in 0..6 {
+        statuses.push(get_on_new_connection(addr, &[]).await);
+    }
+    assert_eq!(
+        statuses,
+        vec![
+            Some(StatusCode::OK),
+            Some(StatusCode::OK),
+            Some(StatusCode::OK),
+            None,
+            None,
+            None
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_trusted_proxy_limited_by_forwarded_client() {
+    let addr = serve_limited(ListenerConfig {
+        max_connections_per_ip_per_sec: 1,
+        trusted_proxies: vec!["127.0.0.0/8".parse().unwrap()],
+        ..Default::default()
+    })
+    .await;
+
+    // The proxy's own connections aren't limited
+    for _ in 0..3 {
+        assert_eq!(get_on_new_connection(addr, &[]).await, Some(StatusCode::OK));
+    }
+
+    // WebSocket upgrades count against the client named in X-Forwarded-For
+    let upgrade_from = |client: &'static str| {
+        [
+            ("upgrade", "websocket"),
+            ("connection", "upgrade"),
+            ("x-forwarded-for", client),
+        ]
+    };
+    let first = get_on_new_connection(addr, &upgrade_from("203.0.113.7")).await;
+    assert_eq!(first, Some(StatusCode::OK));
+    let second = get_on_new_connection(addr, &upgrade_from("203.0.113.7, 127.0.0.1")).await;
+    assert_eq!(second, Some(StatusCode::TOO_MANY_REQUESTS));
+    let other = get_on_new_connection(addr, &upgrade_from("203.0.113.8")).await;
+    assert_eq!(other, Some(StatusCode::OK));
+}
+
+const SLOT_REQUEST: &str = r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#;
+
+/// Serves the public router over TCP in front of a backend that takes 5s to answer.
+/// Returns the listener address, the backend and a way to read its in-flight count.
+async fn serve_slow_backend() -> (SocketAddr, RunningMockBackend, impl Fn() -> usize) {
+    let backend = MockBackend::new()
+        .delay(std::time::Duration::from_secs(5))
+        .start()
+        .await;
+    let state = make_state(backend.url.clone());
+    let loaded = state.state.load_full();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_tcp(
+        listener,
+        public_router(state, true),
+        ListenerConfig::default(),
+        std::future::pending(),
+    ));
+    let in_flight = move || loaded.backends[0].in_flight.load(Ordering::Relaxed);
+    (addr, backend, in_flight)
+}
+
+#[tokio::test]
+async fn test_client_disconnect_cancels_upstream_request() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+    let (addr, backend, in_flight) = serve_slow_backend().await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(
+            format!(
+                "POST /?api-key=test-key HTTP/1.1\r\nhost: router\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                SLOT_REQUEST.len(),
+                SLOT_REQUEST
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(backend.request_count(), 1);
+    assert_eq!(in_flight(), 1);
+
+    // The upstream request is dropped with the client, long before the backend answers
+    drop(client);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(in_flight(), 0);
+    assert!(handle
+        .render()
+        .contains(r#"rpc_client_disconnects_total{backend="mock-backend"} 1"#));
+}
+
+#[tokio::test]
+async fn test_http2_stream_reset_cancels_upstream_request() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+    let (addr, backend, in_flight) = serve_slow_backend().await;
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+    let req = hyper::Request::post("http://router/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from_static(SLOT_REQUEST.as_bytes())))
+        .unwrap();
+    let response = tokio::spawn(sender.send_request(req));
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(backend.request_count(), 1);
+    assert_eq!(in_flight(), 1);
+
+    // Abandoning the response resets the stream while the connection stays open
+    response.abort();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(in_flight(), 0);
+    assert!(!sender.is_closed());
+    assert!(handle
+        .render()
+        .contains(r#"rpc_client_disconnects_total{backend="mock-backend"} 1"#));
+}
+
+#[tokio::test]
+async fn test_bind_tcp_reports_port_in_use() {
+    let taken = bind_tcp(SocketAddr::from(([127, 0, 0, 1], 0)), "HTTP")
+        .await
+        .unwrap();
+    let addr = taken.local_addr().unwrap();
+
+    let err = bind_tcp(addr, "HTTP").await.unwrap_err();
+    assert_eq!(
+        err,
+        format!(
+            "HTTP port {} is already in use; is another instance running?",
+            addr.port()
+        )
+    );
+
+    // Free again once the first listener is gone
+    drop(taken);
+    assert!(bind_tcp(addr, "HTTP").await.is_ok());
+}
@@ -0,0 +1,392 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use axum::{body::Body, http::Request};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, LoggingConfig, ProxyConfig},
+    health::{run_health_checks, HealthState},
+    mock::MockKeyStore,
+    routes::public_router,
+    selector::SelectionContext,
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockBackend,
+};
+use tower::ServiceExt; // for oneshot
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Collects formatted log output so tests can assert on levels and messages.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+async fn make_state(backend_delay: Duration, logging: LoggingConfig) -> Arc<AppState> {
+    let backend = MockBackend::new().delay(backend_delay).start().await;
+
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "mock-backend".to_string(),
+            url: backend.url,
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["mock-backend".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(
+        AppState::new(
+            client,
+            keystore,
+            Arc::new(ArcSwap::from_pointee(router_state)),
+        )
+        .with_logging(logging),
+    )
+}
+
+/// Sends one proxied request with a thread-local subscriber installed and returns the logs.
+async fn proxy_and_capture_logs(state: Arc<AppState>) -> String {
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#))
+        .unwrap();
+    public_router(state, true).oneshot(req).await.unwrap();
+
+    logs.contents()
+}
+
+#[tokio::test]
+async fn test_slow_request_logs_at_warn() {
+    let state = make_state(
+        Duration::from_millis(100),
+        LoggingConfig {
+            slow_request_threshold_ms: 20,
+            fast_request_sample_rate: 0.0,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let line = logs
+        .lines()
+        .find(|l| l.contains("Slow request"))
+        .unwrap_or_else(|| panic!("no slow request line in logs:\n{}", logs));
+    assert!(line.contains("WARN"), "line: {}", line);
+    assert!(line.contains("rpc_method=getSlot"), "line: {}", line);
+    assert!(line.contains("backend=mock-backend"), "line: {}", line);
+}
+
+#[tokio::test]
+async fn test_unsampled_fast_request_logs_at_debug() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            slow_request_threshold_ms: 10_000,
+            fast_request_sample_rate: 0.0,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let line = logs
+        .lines()
+        .find(|l| l.contains("rpc_method=getSlot"))
+        .unwrap_or_else(|| panic!("no request line in logs:\n{}", logs));
+    assert!(line.contains("DEBUG"), "line: {}", line);
+    assert!(!logs.contains("Slow request"));
+}
+
+#[tokio::test]
+async fn test_sampled_request_logs_bodies() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            body_sample_rate: 1.0,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let request_line = logs
+        .lines()
+        .find(|l| l.contains("Sampled request body"))
+        .unwrap_or_else(|| panic!("no request body line in logs:\n{}", logs));
+    assert!(
+        request_line.contains(r#""method":"getSlot""#),
+        "line: {}",
+        request_line
+    );
+    assert!(logs.contains("Sampled response body"), "logs:\n{}", logs);
+    assert!(logs.contains(r#""result""#), "logs:\n{}", logs);
+    // The api-key query param never reaches the log
+    assert!(!logs.contains("test-key"), "logs:\n{}", logs);
+}
+
+#[tokio::test]
+async fn test_unsampled_request_never_logs_bodies() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            body_sample_rate: 0.0,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    assert!(!logs.contains("Sampled"), "logs:\n{}", logs);
+    assert!(!logs.contains("getSlot\""), "logs:\n{}", logs);
+}
+
+#[tokio::test]
+async fn test_logged_body_is_truncated() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            body_sample_rate: 1.0,
+            max_logged_body_bytes: 10,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    assert!(
+        logs.contains(r#"body={"jsonrpc"... (43 bytes total)"#),
+        "logs:\n{}",
+        logs
+    );
+}
+
+#[tokio::test]
+async fn test_selection_span_records_chosen_backend() {
+    let state = make_state(Duration::ZERO, LoggingConfig::default()).await;
+
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let ctx = SelectionContext {
+        rpc_method: Some("getSlot"),
+        ..Default::default()
+    };
+    let selection = state.select_backend_for(&ctx, &[]).unwrap();
+    assert_eq!(selection.label(), "mock-backend");
+
+    let logs = logs.contents();
+    let line = logs
+        .lines()
+        .find(|l| l.contains("select_backend{"))
+        .unwrap_or_else(|| panic!("no selection span in logs:\n{}", logs));
+    assert!(line.contains("rpc_method=\"getSlot\""), "line: {}", line);
+    assert!(
+        line.contains(r#"candidates="mock-backend=healthy""#),
+        "line: {}",
+        line
+    );
+    assert!(line.contains(r#"chosen="mock-backend""#), "line: {}", line);
+}
+
+#[tokio::test]
+async fn test_traced_owner_logs_verbosely() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            fast_request_sample_rate: 0.0,
+            traced_owners: vec!["tester".to_string()],
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let line = logs
+        .lines()
+        .find(|l| l.contains("rpc_method=getSlot"))
+        .unwrap_or_else(|| panic!("no request line in logs:\n{}", logs));
+    assert!(line.contains("INFO"), "line: {}", line);
+    assert!(line.contains("owner=tester status=200"), "line: {}", line);
+    assert!(logs.contains("Sampled request body"), "logs:\n{}", logs);
+    assert!(logs.contains("Sampled response body"), "logs:\n{}", logs);
+}
+
+#[tokio::test]
+async fn test_untraced_owner_logs_normally() {
+    let state = make_state(
+        Duration::ZERO,
+        LoggingConfig {
+            fast_request_sample_rate: 0.0,
+            traced_owners: vec!["someone-else".to_string()],
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let line = logs
+        .lines()
+        .find(|l| l.contains("rpc_method=getSlot"))
+        .unwrap_or_else(|| panic!("no request line in logs:\n{}", logs));
+    assert!(line.contains("DEBUG"), "line: {}", line);
+    assert!(!line.contains("owner="), "line: {}", line);
+    assert!(!logs.contains("Sampled request body"), "logs:\n{}", logs);
+}
+
+/// Value of `key=` in a log line, in milliseconds.
+fn field_ms(line: &str, key: &str) -> f64 {
+    line.split_whitespace()
+        .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+        .unwrap_or_else(|| panic!("no {} in line: {}", key, line))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_access_log_separates_upstream_time() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _metrics = metrics::set_default_local_recorder(&recorder);
+
+    let state = make_state(
+        Duration::from_millis(50),
+        LoggingConfig {
+            slow_request_threshold_ms: 10,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let logs = proxy_and_capture_logs(state).await;
+    let line = logs
+        .lines()
+        .find(|l| l.contains("Slow request"))
+        .unwrap_or_else(|| panic!("no access log line in logs:\n{}", logs));
+    let total = field_ms(line, "total_ms");
+    let upstream = field_ms(line, "upstream_ms");
+    assert!(upstream >= 50.0, "line: {}", line);
+    assert!(upstream <= total, "line: {}", line);
+
+    assert!(
+        handle.render().contains(
+            "rpc_router_overhead_seconds_count{rpc_method=\"getSlot\",backend=\"mock-backend\"} 1"
+        ),
+        "{}",
+        handle.render()
+    );
+}
+
+#[tokio::test]
+async fn test_repeated_health_check_failures_are_throttled() {
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // Port 1 on loopback refuses connections, failing every check the same way
+    let state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "down".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["down".to_string()])),
+        proxy_config: ProxyConfig::default(),
+        health_check_config: HealthCheckConfig {
+            timeout_secs: 1,
+            consecutive_failures_threshold: 3,
+            repeated_error_log_interval_secs: 60,
+            ..Default::default()
+        },
+    };
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(HttpsConnector::new());
+
+    for _ in 0..6 {
+        run_health_checks(&client, &state, false).await;
+        let status = state.health_state.get_status("down").unwrap();
+        assert!(status.last_error.is_some());
+    }
+    assert_eq!(
+        state
+            .health_state
+            .get_status("down")
+            .unwrap()
+            .consecutive_failures,
+        6
+    );
+
+    // Logged on the first failure and again when the third marks it unhealthy
+    let logs = logs.contents();
+    let failures: Vec<&str> = logs
+        .lines()
+        .filter(|l| l.contains("Health check failed for backend down"))
+        .collect();
+    assert_eq!(failures.len(), 2, "logs:\n{}", logs);
+    assert!(failures.iter().all(|l| l.contains("WARN")));
+    assert!(
+        failures[1].contains("(1 identical failures since last logged)"),
+        "logs:\n{}",
+        logs
+    );
+    assert_eq!(logs.matches("marked as UNHEALTHY").count(), 1);
+}
@@ -0,0 +1,41 @@
+use std::{sync::Arc, time::Duration};
+
+use sol_rpc_router::ratelimit::{Decision, InMemoryRateLimiter, RateLimiter};
+
+#[tokio::test]
+async fn test_in_memory_limiter_limits_per_window() {
+    let limiter: Arc<dyn RateLimiter> = Arc::new(InMemoryRateLimiter::new());
+
+    for _ in 0..3 {
+        assert_eq!(limiter.check("key-a", 3, 1).await.unwrap(), Decision::Allow);
+    }
+    assert_eq!(
+        limiter.check("key-a", 3, 1).await.unwrap(),
+        Decision::Limited
+    );
+    // Keys are counted separately
+    assert_eq!(limiter.check("key-b", 3, 1).await.unwrap(), Decision::Allow);
+
+    // The next window starts from zero
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(limiter.check("key-a", 3, 1).await.unwrap(), Decision::Allow);
+}
+
+#[tokio::test]
+async fn test_in_memory_limiter_cost_and_unlimited() {
+    let limiter: Arc<dyn RateLimiter> = Arc::new(InMemoryRateLimiter::new());
+
+    assert_eq!(
+        limiter.check("batch", 10, 8).await.unwrap(),
+        Decision::Allow
+    );
+    assert_eq!(
+        limiter.check("batch", 10, 3).await.unwrap(),
+        Decision::Limited
+    );
+
+    // A limit of 0 never limits
+    for _ in 0..100 {
+        assert_eq!(limiter.check("free", 0, 1).await.unwrap(), Decision::Allow);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use arc_swap::ArcSwap;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+    Router,
+};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::{admin_router, public_router},
+    state::{AppState, RouterState, RuntimeBackend},
+};
+use tower::ServiceExt; // for oneshot
+
+fn make_state() -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+
+    let backend = RuntimeBackend::new(Backend {
+        label: "a".to_string(),
+        url: "http://a".to_string(),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let router_state = RouterState {
+        backends: vec![backend],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["a".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ))
+}
+
+async fn get_status(app: Router, path: &str) -> StatusCode {
+    let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+    // log_requests expects the peer address that axum::serve normally provides
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    app.oneshot(req).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_admin_routes_absent_from_public_listener_when_split() {
+    let state = make_state();
+
+    for path in ["/health", "/readyz", "/version"] {
+        let status = get_status(public_router(state.clone(), false), path).await;
+        assert_ne!(status, StatusCode::OK, "{} must not be public", path);
+    }
+}
+
+#[tokio::test]
+async fn test_admin_routes_present_on_admin_listener() {
+    let state = make_state();
+
+    for path in ["/health", "/readyz", "/version"] {
+        let status = get_status(admin_router(state.clone()), path).await;
+        assert_eq!(
+            status,
+            StatusCode::OK,
+            "{} missing from admin listener",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_admin_routes_on_public_listener_without_admin_port() {
+    let state = make_state();
+
+    let status = get_status(public_router(state, true), "/health").await;
+    assert_eq!(status, StatusCode::OK);
+}
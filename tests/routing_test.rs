@@ -1,60 +1,89 @@
-use std::{collections::HashMap, sync::Arc};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::Ordering;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use arc_swap::ArcSwap;
 use hyper_tls::HttpsConnector;
 use hyper_util::client::legacy::Client;
 use sol_rpc_router::{
-    config::{Backend, HealthCheckConfig},
+    config::{Backend, HealthCheckConfig, ProxyConfig, ReadWriteSplit},
     health::{BackendHealthStatus, HealthState},
     mock::MockKeyStore,
+    selector::{
+        BackendSelector, Candidates, CheapestFirstSelector, ConsistentHashSelector,
+        SelectionContext, WeightedRandomSelector,
+    },
     state::{AppState, RouterState, RuntimeBackend},
 };
 
 fn create_test_state() -> AppState {
-    let https = HttpsConnector::new();
-    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
-    let keystore = Arc::new(MockKeyStore::new());
+    test_state_with(
+        test_backends(),
+        ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
+    )
+}
 
-    let backend_configs = vec![
+/// `primary` takes all the weight; `secondary` is configured but gets none.
+fn test_backends() -> Vec<Backend> {
+    vec![
         Backend {
             label: "primary".to_string(),
             url: "http://primary".to_string(),
             ws_url: None,
             weight: 100,
+            ..Default::default()
         },
         Backend {
             label: "secondary".to_string(),
             url: "http://secondary".to_string(),
             ws_url: None,
             weight: 0,
+            ..Default::default()
         },
-    ];
+    ]
+}
+
+/// State serving `backends`, all healthy, under `proxy_config`.
+fn test_state_with(backends: Vec<Backend>, proxy_config: ProxyConfig) -> AppState {
+    test_state_with_fallback(backends, None, proxy_config)
+}
+
+/// Like `test_state_with`, with `fallback` (if any) after the other backends.
+fn test_state_with_fallback(
+    backends: Vec<Backend>,
+    fallback: Option<Backend>,
+    proxy_config: ProxyConfig,
+) -> AppState {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
 
-    let backends = backend_configs
+    let backend_labels = backends
         .iter()
-        .map(|b| RuntimeBackend {
-            config: b.clone(),
-            healthy: Arc::new(AtomicBool::new(true)),
-        })
+        .chain(&fallback)
+        .map(|b| b.label.clone())
         .collect();
-
-    let backend_labels = backend_configs.iter().map(|b| b.label.clone()).collect();
     let health_state = Arc::new(HealthState::new(backend_labels));
 
     let router_state = RouterState {
-        backends,
+        backends: backends
+            .into_iter()
+            .map(|b| RuntimeBackend::new(b).unwrap())
+            .chain(fallback.map(|b| RuntimeBackend::new(b).unwrap().into_fallback()))
+            .collect(),
         method_routes: HashMap::new(),
         health_state,
-        proxy_timeout_secs: 10,
+        proxy_config,
         health_check_config: HealthCheckConfig::default(),
     };
 
-    AppState {
+    AppState::new(
         client,
         keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    }
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    )
 }
 
 #[test]
@@ -64,24 +93,22 @@ fn test_select_backend_weighted() {
     let keystore = Arc::new(MockKeyStore::new());
 
     let backends = vec![
-        RuntimeBackend {
-            config: Backend {
-                label: "primary".to_string(),
-                url: "http://primary".to_string(),
-                ws_url: None,
-                weight: 1,
-            },
-            healthy: Arc::new(AtomicBool::new(true)),
-        },
-        RuntimeBackend {
-            config: Backend {
-                label: "secondary".to_string(),
-                url: "http://secondary".to_string(),
-                ws_url: None,
-                weight: 1,
-            },
-            healthy: Arc::new(AtomicBool::new(true)),
-        },
+        RuntimeBackend::new(Backend {
+            label: "primary".to_string(),
+            url: "http://primary".to_string(),
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap(),
+        RuntimeBackend::new(Backend {
+            label: "secondary".to_string(),
+            url: "http://secondary".to_string(),
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap(),
     ];
 
     let health_state = Arc::new(HealthState::new(vec![
@@ -93,15 +120,18 @@ fn test_select_backend_weighted() {
         backends,
         method_routes: HashMap::new(),
         health_state,
-        proxy_timeout_secs: 10,
+        proxy_config: ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
         health_check_config: HealthCheckConfig::default(),
     };
 
-    let state = AppState {
+    let state = AppState::new(
         client,
         keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    };
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    );
 
     let iterations = 1000;
     let mut primary_count = 0;
@@ -121,6 +151,36 @@ fn test_select_backend_weighted() {
     assert!(secondary_count > 400);
 }
 
+#[test]
+fn test_weighted_selection_with_weights_near_u32_max() {
+    // Three weights whose sum overflows u32
+    let backends: Vec<RuntimeBackend> = ["huge-a", "huge-b", "huge-c"]
+        .iter()
+        .map(|label| {
+            RuntimeBackend::new(Backend {
+                label: label.to_string(),
+                url: format!("http://{}", label),
+                weight: u32::MAX,
+                ..Default::default()
+            })
+            .unwrap()
+        })
+        .collect();
+    let candidates = Candidates::new(&backends, &|_| true);
+
+    let mut counts = HashMap::new();
+    for _ in 0..3000 {
+        let chosen = WeightedRandomSelector
+            .select(&candidates, &SelectionContext::default())
+            .unwrap();
+        *counts.entry(chosen.config.label.clone()).or_insert(0) += 1;
+    }
+    for label in ["huge-a", "huge-b", "huge-c"] {
+        let count = counts.get(label).copied().unwrap_or(0);
+        assert!(count > 800, "{} selected {} times", label, count);
+    }
+}
+
 #[test]
 fn test_select_backend_method_override() {
     let https = HttpsConnector::new();
@@ -128,24 +188,22 @@ fn test_select_backend_method_override() {
     let keystore = Arc::new(MockKeyStore::new());
 
     let backends = vec![
-        RuntimeBackend {
-            config: Backend {
-                label: "primary".to_string(),
-                url: "http://primary".to_string(),
-                ws_url: None,
-                weight: 100,
-            },
-            healthy: Arc::new(AtomicBool::new(true)),
-        },
-        RuntimeBackend {
-            config: Backend {
-                label: "secondary".to_string(),
-                url: "http://secondary".to_string(),
-                ws_url: None,
-                weight: 0,
-            },
-            healthy: Arc::new(AtomicBool::new(true)),
-        },
+        RuntimeBackend::new(Backend {
+            label: "primary".to_string(),
+            url: "http://primary".to_string(),
+            ws_url: None,
+            weight: 100,
+            ..Default::default()
+        })
+        .unwrap(),
+        RuntimeBackend::new(Backend {
+            label: "secondary".to_string(),
+            url: "http://secondary".to_string(),
+            ws_url: None,
+            weight: 0,
+            ..Default::default()
+        })
+        .unwrap(),
     ];
 
     let health_state = Arc::new(HealthState::new(vec![
@@ -154,21 +212,24 @@ fn test_select_backend_method_override() {
     ]));
 
     let mut method_routes = HashMap::new();
-    method_routes.insert("eth_call".to_string(), "secondary".to_string());
+    method_routes.insert("eth_call".to_string(), "secondary".into());
 
     let router_state = RouterState {
         backends,
         method_routes,
         health_state,
-        proxy_timeout_secs: 10,
+        proxy_config: ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
         health_check_config: HealthCheckConfig::default(),
     };
 
-    let state = AppState {
+    let state = AppState::new(
         client,
         keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    };
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    );
 
     let (label, _) = state.select_backend(Some("eth_call")).unwrap();
     assert_eq!(label, "secondary");
@@ -178,6 +239,65 @@ fn test_select_backend_method_override() {
     assert_eq!(label, "primary");
 }
 
+#[test]
+fn test_ordered_method_route_fails_over_in_order() {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let labels = ["bulk", "archive-1", "archive-2"];
+    let backends = labels
+        .iter()
+        .map(|label| {
+            RuntimeBackend::new(Backend {
+                label: label.to_string(),
+                url: format!("http://{}", label),
+                weight: 1,
+                ..Default::default()
+            })
+            .unwrap()
+        })
+        .collect();
+    let router_state = RouterState {
+        backends,
+        method_routes: HashMap::from([("getBlock".to_string(), ["archive-1", "archive-2"].into())]),
+        health_state: Arc::new(HealthState::new(
+            labels.iter().map(|l| l.to_string()).collect(),
+        )),
+        proxy_config: ProxyConfig::default(),
+        health_check_config: HealthCheckConfig::default(),
+    };
+    let state = AppState::new(
+        client,
+        Arc::new(MockKeyStore::new()),
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    );
+    let backends = state.state.load_full();
+
+    for _ in 0..20 {
+        assert_eq!(
+            state.select_backend(Some("getBlock")).unwrap().0,
+            "archive-1"
+        );
+    }
+
+    backends.backends[1].healthy.store(false, Ordering::Relaxed);
+    for _ in 0..20 {
+        assert_eq!(
+            state.select_backend(Some("getBlock")).unwrap().0,
+            "archive-2"
+        );
+    }
+
+    // A retry that already failed on the second label leaves weighted selection
+    let ctx = SelectionContext {
+        rpc_method: Some("getBlock"),
+        ..Default::default()
+    };
+    let selection = state
+        .select_backend_for(&ctx, &["archive-2".to_string()])
+        .unwrap();
+    assert_eq!(selection.label(), "bulk");
+}
+
 #[test]
 fn test_select_backend_unhealthy_fallback() {
     let state = create_test_state();
@@ -187,8 +307,10 @@ fn test_select_backend_unhealthy_fallback() {
     loaded.backends[0].healthy.store(false, Ordering::Relaxed);
 
     // Also update health_state for consistency
-    let mut status = BackendHealthStatus::default();
-    status.healthy = false;
+    let status = BackendHealthStatus {
+        healthy: false,
+        ..Default::default()
+    };
     loaded.health_state.update_status("primary", status);
 
     let (label, _) = state.select_backend(None).unwrap();
@@ -209,49 +331,26 @@ fn test_select_backend_all_unhealthy() {
 // --- WebSocket backend selection tests ---
 
 fn create_ws_test_state() -> AppState {
-    let https = HttpsConnector::new();
-    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
-    let keystore = Arc::new(MockKeyStore::new());
-
-    let backend_configs = vec![
-        Backend {
-            label: "ws-a".to_string(),
-            url: "http://ws-a".to_string(),
-            ws_url: Some("ws://ws-a".to_string()),
-            weight: 1,
-        },
-        Backend {
-            label: "ws-b".to_string(),
-            url: "http://ws-b".to_string(),
-            ws_url: Some("ws://ws-b".to_string()),
-            weight: 1,
-        },
-    ];
-
-    let backends = backend_configs
-        .iter()
-        .map(|b| RuntimeBackend {
-            config: b.clone(),
-            healthy: Arc::new(AtomicBool::new(true)),
-        })
-        .collect();
-
-    let backend_labels = backend_configs.iter().map(|b| b.label.clone()).collect();
-    let health_state = Arc::new(HealthState::new(backend_labels));
+    create_capped_ws_test_state(0)
+}
 
-    let router_state = RouterState {
-        backends,
-        method_routes: HashMap::new(),
-        health_state,
-        proxy_timeout_secs: 10,
-        health_check_config: HealthCheckConfig::default(),
+/// Two WebSocket backends, each accepting at most `max_ws_connections` connections.
+fn create_capped_ws_test_state(max_ws_connections: usize) -> AppState {
+    let backend = |label: &str| Backend {
+        label: label.to_string(),
+        url: format!("http://{}", label),
+        ws_url: Some(format!("ws://{}", label)),
+        weight: 1,
+        max_ws_connections,
+        ..Default::default()
     };
-
-    AppState {
-        client,
-        keystore,
-        state: Arc::new(ArcSwap::from_pointee(router_state)),
-    }
+    test_state_with(
+        vec![backend("ws-a"), backend("ws-b")],
+        ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
+    )
 }
 
 #[test]
@@ -293,3 +392,855 @@ fn test_select_ws_backend_unhealthy_excluded() {
         assert_eq!(label, "ws-b");
     }
 }
+
+#[test]
+fn test_select_ws_backend_skips_backend_at_capacity() {
+    let state = create_capped_ws_test_state(1);
+    let loaded = state.state.load_full();
+
+    let held_a = loaded.backends[0].open_ws_connection().unwrap();
+    assert!(loaded.backends[0].open_ws_connection().is_none());
+    for _ in 0..100 {
+        let (label, _) = state.select_ws_backend().unwrap();
+        assert_eq!(label, "ws-b");
+    }
+
+    // With every backend full there is nothing to select
+    let held_b = loaded.backends[1].open_ws_connection().unwrap();
+    assert!(state.select_ws_backend().is_none());
+
+    // Closing a connection frees its slot
+    drop(held_a);
+    assert_eq!(state.select_ws_backend().unwrap().0, "ws-a");
+    drop(held_b);
+    assert_eq!(loaded.backends[1].ws_connections.load(Ordering::Relaxed), 0);
+}
+
+/// Two equally weighted backends, each taking at most `max_concurrent_requests`
+/// requests at once.
+fn create_capped_request_test_state(max_concurrent_requests: usize) -> AppState {
+    let backend = |label: &str| Backend {
+        label: label.to_string(),
+        url: format!("http://{}", label),
+        weight: 1,
+        max_concurrent_requests,
+        ..Default::default()
+    };
+    test_state_with(
+        vec![backend("node-a"), backend("node-b")],
+        ProxyConfig::default(),
+    )
+}
+
+#[test]
+fn test_select_backend_skips_saturated_backend() {
+    let state = create_capped_request_test_state(2);
+    let loaded = state.state.load_full();
+
+    let held_a: Vec<_> = (0..2)
+        .map(|_| loaded.backends[0].start_request().unwrap())
+        .collect();
+    assert!(loaded.backends[0].start_request().is_none());
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_eq!(label, "node-b");
+    }
+
+    // With every backend saturated there is nothing to select
+    let held_b: Vec<_> = (0..2)
+        .map(|_| loaded.backends[1].start_request().unwrap())
+        .collect();
+    assert!(state.select_backend(None).is_none());
+
+    // A finished request frees its slot
+    drop(held_a);
+    assert_eq!(state.select_backend(None).unwrap().0, "node-a");
+    drop(held_b);
+    assert_eq!(loaded.backends[1].in_flight.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_throttled_backend_share_drops_and_recovers() {
+    let state = create_capped_request_test_state(0);
+    let loaded = state.state.load_full();
+    let share_of_a = || {
+        let picks = (0..2000)
+            .filter(|_| state.select_backend(None).unwrap().0 == "node-a")
+            .count();
+        picks as f64 / 2000.0
+    };
+
+    // Three 429s leave node-a at 1/8 of its weight: about 11% of traffic
+    for _ in 0..3 {
+        loaded.backends[0]
+            .throttle
+            .record_429(Duration::from_millis(100));
+    }
+    assert_eq!(loaded.backends[0].throttle.level(), 3);
+    let throttled = share_of_a();
+    assert!(throttled < 0.2, "throttled share {}", throttled);
+
+    // One halving is undone per quiet cooldown
+    std::thread::sleep(Duration::from_millis(350));
+    assert_eq!(loaded.backends[0].throttle.level(), 0);
+    let recovered = share_of_a();
+    assert!(
+        (0.4..0.6).contains(&recovered),
+        "recovered share {}",
+        recovered
+    );
+}
+
+fn create_min_share_test_state(min_share: f64) -> AppState {
+    test_state_with(
+        vec![
+            Backend {
+                label: "busy".to_string(),
+                url: "http://busy".to_string(),
+                weight: 999,
+                ..Default::default()
+            },
+            Backend {
+                label: "standby".to_string(),
+                url: "http://standby".to_string(),
+                weight: 1,
+                min_share,
+                ..Default::default()
+            },
+        ],
+        ProxyConfig::default(),
+    )
+}
+
+#[test]
+fn test_min_share_guarantees_low_weight_backend_traffic() {
+    let share_of_standby = |state: &AppState| {
+        let picks = (0..5000)
+            .filter(|_| state.select_backend(None).unwrap().0 == "standby")
+            .count();
+        picks as f64 / 5000.0
+    };
+
+    // By weight alone the standby gets about 0.1% of requests
+    let without_floor = share_of_standby(&create_min_share_test_state(0.0));
+    assert!(
+        without_floor < 0.01,
+        "share without floor {}",
+        without_floor
+    );
+
+    let with_floor = share_of_standby(&create_min_share_test_state(0.05));
+    assert!(with_floor >= 0.05, "share with floor {}", with_floor);
+    // The floor is a minimum, not a reservation: the rest still follows weight
+    assert!(with_floor < 0.07, "share with floor {}", with_floor);
+}
+
+/// Custom strategy that always routes to the last candidate.
+struct LastBackendSelector;
+
+impl BackendSelector for LastBackendSelector {
+    fn select<'a>(
+        &self,
+        candidates: &Candidates<'a>,
+        _ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend> {
+        candidates.last()
+    }
+}
+
+#[test]
+fn test_custom_selector_picks_last_backend() {
+    let state = create_test_state().with_selector(Arc::new(LastBackendSelector));
+
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_eq!(label, "secondary");
+    }
+
+    // Candidates are still health-filtered before the selector runs
+    state.state.load().backends[1]
+        .healthy
+        .store(false, Ordering::Relaxed);
+    let (label, _) = state.select_backend(None).unwrap();
+    assert_eq!(label, "primary");
+}
+
+#[test]
+fn test_custom_selector_used_for_ws_backends() {
+    let state = create_ws_test_state().with_selector(Arc::new(LastBackendSelector));
+
+    for _ in 0..100 {
+        let (label, _) = state.select_ws_backend().unwrap();
+        assert_eq!(label, "ws-b");
+    }
+}
+
+/// Broken strategy that returns a backend it holds on to instead of a candidate.
+struct ForeignBackendSelector(&'static RuntimeBackend);
+
+impl BackendSelector for ForeignBackendSelector {
+    fn select<'a>(
+        &self,
+        _candidates: &Candidates<'a>,
+        _ctx: &SelectionContext<'_>,
+    ) -> Option<&'a RuntimeBackend> {
+        Some(self.0)
+    }
+}
+
+#[test]
+fn test_custom_selector_returning_non_candidate_selects_nothing() {
+    let foreign = Box::leak(Box::new(
+        RuntimeBackend::new(Backend {
+            label: "primary".to_string(),
+            url: "http://primary".to_string(),
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap(),
+    ));
+    let state = create_test_state().with_selector(Arc::new(ForeignBackendSelector(foreign)));
+
+    assert!(state.select_backend(None).is_none());
+}
+
+#[test]
+fn test_select_backend_for_exposes_index() {
+    let state = create_test_state();
+    state.state.load().backends[0]
+        .healthy
+        .store(false, Ordering::Relaxed);
+
+    let selection = state
+        .select_backend_for(&SelectionContext::default(), &[])
+        .unwrap();
+    assert_eq!(selection.index(), 1);
+    assert_eq!(selection.label(), "secondary");
+    assert_eq!(selection.url(), "http://secondary");
+    // Atomics are reachable without a second lookup by label
+    assert!(selection.backend().healthy.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_runtime_backend_upstream_uri() {
+    let backend = RuntimeBackend::new(Backend {
+        label: "b".to_string(),
+        url: "https://rpc.example.com:8443/base/".to_string(),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert_eq!(backend.host_header(), "rpc.example.com:8443");
+    assert_eq!(
+        backend.upstream_uri("/").unwrap().to_string(),
+        "https://rpc.example.com:8443/base"
+    );
+    assert_eq!(
+        backend.upstream_uri("/path?q=1").unwrap().to_string(),
+        "https://rpc.example.com:8443/base/path?q=1"
+    );
+
+    let bare = RuntimeBackend::new(Backend {
+        label: "bare".to_string(),
+        url: "http://primary".to_string(),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(bare.host_header(), "primary");
+    assert_eq!(
+        bare.upstream_uri("/").unwrap().to_string(),
+        "http://primary/"
+    );
+
+    let keyed = RuntimeBackend::new(Backend {
+        label: "keyed".to_string(),
+        url: "https://rpc.example.com/base?api-key=P&x=1".to_string(),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        keyed.upstream_uri("/").unwrap().to_string(),
+        "https://rpc.example.com/base?api-key=P&x=1"
+    );
+    // Client parameters are appended, except those the backend URL already sets
+    assert_eq!(
+        keyed.upstream_uri("/path?x=2&y=3").unwrap().to_string(),
+        "https://rpc.example.com/base/path?api-key=P&x=1&y=3"
+    );
+
+    // A key on a root URL survives both the bare `/` mapping and a client query
+    let root_keyed = RuntimeBackend::new(Backend {
+        label: "root-keyed".to_string(),
+        url: "https://rpc.example.com/?api-key=secret".to_string(),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        root_keyed.upstream_uri("/").unwrap().to_string(),
+        "https://rpc.example.com/?api-key=secret"
+    );
+    assert_eq!(
+        root_keyed
+            .upstream_uri("/?commitment=confirmed")
+            .unwrap()
+            .to_string(),
+        "https://rpc.example.com/?api-key=secret&commitment=confirmed"
+    );
+}
+
+#[test]
+fn test_runtime_backend_force_scheme_overrides_url() {
+    let backend = RuntimeBackend::new(Backend {
+        label: "tls".to_string(),
+        url: "http://rpc.internal:8899/base".to_string(),
+        weight: 1,
+        force_scheme: Some("https".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        backend.upstream_uri("/path").unwrap().to_string(),
+        "https://rpc.internal:8899/base/path"
+    );
+    assert_eq!(backend.host_header(), "rpc.internal:8899");
+    assert_eq!(
+        backend.config.effective_url(),
+        "https://rpc.internal:8899/base"
+    );
+}
+
+#[test]
+fn test_runtime_backend_rejects_url_without_host() {
+    let err = RuntimeBackend::new(Backend {
+        label: "bad".to_string(),
+        url: "/relative/path".to_string(),
+        ws_url: None,
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap_err();
+    assert!(err.contains("bad"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn test_runtime_backend_with_mtls_builds_own_client() {
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let mtls = RuntimeBackend::new(Backend {
+        label: "mtls".to_string(),
+        url: "https://rpc.example.com".to_string(),
+        weight: 1,
+        tls_client_cert: Some(format!("{}/client.crt", fixtures)),
+        tls_client_key: Some(format!("{}/client.key", fixtures)),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(mtls.client().is_some());
+    assert!(mtls.health_client().is_some());
+
+    let plain = RuntimeBackend::new(Backend {
+        label: "plain".to_string(),
+        url: "https://rpc.example.com".to_string(),
+        weight: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(plain.client().is_none());
+    assert!(plain.health_client().is_none());
+}
+
+#[test]
+fn test_runtime_backend_rejects_missing_tls_identity() {
+    let err = RuntimeBackend::new(Backend {
+        label: "mtls".to_string(),
+        url: "https://rpc.example.com".to_string(),
+        weight: 1,
+        tls_client_cert: Some("/nonexistent/client.crt".to_string()),
+        tls_client_key: Some("/nonexistent/client.key".to_string()),
+        ..Default::default()
+    })
+    .unwrap_err();
+    assert!(
+        err.contains("TLS client identity"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+fn create_fallback_test_state() -> AppState {
+    test_state_with_fallback(
+        test_backends(),
+        Some(Backend {
+            label: "public".to_string(),
+            url: "https://public-rpc".to_string(),
+            ws_url: Some("wss://public-rpc".to_string()),
+            weight: 1000,
+            ..Default::default()
+        }),
+        ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn test_fallback_not_selected_while_primaries_healthy() {
+    let state = create_fallback_test_state();
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_ne!(label, "public");
+    }
+}
+
+#[test]
+fn test_fallback_selected_when_all_primaries_unhealthy() {
+    let state = create_fallback_test_state();
+    let loaded = state.state.load();
+    for backend in loaded.backends.iter().filter(|b| !b.is_fallback()) {
+        backend.healthy.store(false, Ordering::Relaxed);
+    }
+
+    let (label, url) = state.select_backend(None).unwrap();
+    assert_eq!(label, "public");
+    assert_eq!(url, "https://public-rpc");
+
+    // The fallback is health checked like any other backend
+    loaded.backends[2].healthy.store(false, Ordering::Relaxed);
+    assert!(state.select_backend(None).is_none());
+}
+
+#[test]
+fn test_fallback_used_for_websocket_when_no_ws_backends() {
+    // The regular backends have no ws_url
+    let state = create_fallback_test_state();
+    let (label, url) = state.select_ws_backend().unwrap();
+    assert_eq!(label, "public");
+    assert_eq!(url, "wss://public-rpc");
+}
+
+#[test]
+fn test_idempotent_methods_default_classification() {
+    let state = create_test_state();
+    let loaded = state.state.load();
+
+    assert!(loaded.is_idempotent(Some("getSlot")));
+    assert!(loaded.is_idempotent(Some("getAccountInfo")));
+    assert!(!loaded.is_idempotent(Some("sendTransaction")));
+    assert!(!loaded.is_idempotent(Some("requestAirdrop")));
+    // Batches carry no single method and are never retried
+    assert!(!loaded.is_idempotent(None));
+}
+
+fn create_tiered_test_state() -> AppState {
+    let backend = |label: &str, tier: u32| Backend {
+        label: label.to_string(),
+        url: format!("https://{}", label),
+        ws_url: Some(format!("wss://{}", label)),
+        weight: 1,
+        tier,
+        ..Default::default()
+    };
+    test_state_with(
+        vec![
+            backend("premium-1", 0),
+            backend("premium-2", 0),
+            backend("cheap", 1),
+        ],
+        ProxyConfig {
+            timeout_secs: 10,
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn test_higher_tier_unused_while_lower_tier_healthy() {
+    let state = create_tiered_test_state();
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_ne!(label, "cheap");
+    }
+
+    // One premium backend down still leaves tier 0 with capacity
+    state.state.load().backends[0]
+        .healthy
+        .store(false, Ordering::Relaxed);
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_eq!(label, "premium-2");
+        let (label, _) = state.select_ws_backend().unwrap();
+        assert_eq!(label, "premium-2");
+    }
+}
+
+#[test]
+fn test_higher_tier_used_when_lower_tier_unhealthy() {
+    let state = create_tiered_test_state();
+    let loaded = state.state.load();
+    loaded.backends[0].healthy.store(false, Ordering::Relaxed);
+    loaded.backends[1].healthy.store(false, Ordering::Relaxed);
+
+    let (label, _) = state.select_backend(None).unwrap();
+    assert_eq!(label, "cheap");
+    let (label, _) = state.select_ws_backend().unwrap();
+    assert_eq!(label, "cheap");
+
+    // Recovery of tier 0 takes traffic back
+    loaded.backends[1].healthy.store(true, Ordering::Relaxed);
+    let (label, _) = state.select_backend(None).unwrap();
+    assert_eq!(label, "premium-2");
+}
+
+fn create_zoned_test_state() -> AppState {
+    let backend = |label: &str, zone: &str| Backend {
+        label: label.to_string(),
+        url: format!("https://{}", label),
+        ws_url: Some(format!("wss://{}", label)),
+        weight: 1,
+        zone: Some(zone.to_string()),
+        ..Default::default()
+    };
+    test_state_with(
+        vec![
+            backend("local", "us-east-1a"),
+            backend("remote-1", "us-east-1b"),
+            backend("remote-2", "us-east-1c"),
+        ],
+        ProxyConfig {
+            local_zone: Some("us-east-1a".to_string()),
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn test_cross_zone_backends_unused_while_local_zone_healthy() {
+    let state = create_zoned_test_state();
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_eq!(label, "local");
+        let (label, _) = state.select_ws_backend().unwrap();
+        assert_eq!(label, "local");
+    }
+}
+
+#[test]
+fn test_selection_spills_to_other_zones_when_local_zone_down() {
+    let state = create_zoned_test_state();
+    let loaded = state.state.load();
+    loaded.backends[0].healthy.store(false, Ordering::Relaxed);
+
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..200 {
+        let (label, _) = state.select_backend(None).unwrap();
+        seen.insert(label);
+    }
+    assert_eq!(
+        seen,
+        ["remote-1", "remote-2"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect()
+    );
+
+    // A local backend that already failed the request doesn't hold traffic back
+    loaded.backends[0].healthy.store(true, Ordering::Relaxed);
+    let selection = state
+        .select_backend_for(&SelectionContext::default(), &["local".to_string()])
+        .unwrap();
+    assert_ne!(selection.label(), "local");
+}
+
+fn create_split_test_state() -> AppState {
+    let backend = |label: &str| Backend {
+        label: label.to_string(),
+        url: format!("https://{}", label),
+        weight: 1,
+        ..Default::default()
+    };
+    test_state_with(
+        vec![
+            backend("leader-1"),
+            backend("leader-2"),
+            backend("replica-1"),
+            backend("replica-2"),
+        ],
+        ProxyConfig {
+            read_write_split: Some(ReadWriteSplit {
+                write_backends: vec!["leader-1".to_string(), "leader-2".to_string()],
+                read_backends: vec!["replica-1".to_string(), "replica-2".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn test_read_write_split_routes_writes_and_reads_to_their_groups() {
+    let state = create_split_test_state();
+    let mut writes = std::collections::HashSet::new();
+    let mut reads = std::collections::HashSet::new();
+    for _ in 0..200 {
+        writes.insert(state.select_backend(Some("sendTransaction")).unwrap().0);
+        reads.insert(state.select_backend(Some("getSlot")).unwrap().0);
+    }
+    let labels = |l: &[&str]| l.iter().map(|l| l.to_string()).collect();
+    // Each group is spread by weight among its own backends
+    assert_eq!(writes, labels(&["leader-1", "leader-2"]));
+    assert_eq!(reads, labels(&["replica-1", "replica-2"]));
+}
+
+#[test]
+fn test_read_write_split_fails_writes_when_write_group_down() {
+    let state = create_split_test_state();
+    let loaded = state.state.load();
+    loaded.backends[0].healthy.store(false, Ordering::Relaxed);
+    loaded.backends[1].healthy.store(false, Ordering::Relaxed);
+
+    // Writes never reach the read replicas unless spilling is enabled
+    assert!(state.select_backend(Some("sendTransaction")).is_none());
+    let (label, _) = state.select_backend(Some("getSlot")).unwrap();
+    assert!(label.starts_with("replica"), "{}", label);
+}
+
+#[test]
+fn test_read_write_split_spills_when_enabled() {
+    let state = create_split_test_state();
+    let loaded = state.state.load_full();
+    let mut proxy_config = loaded.proxy_config.clone();
+    proxy_config.read_write_split.as_mut().unwrap().spill_writes = true;
+    state.state.store(Arc::new(RouterState {
+        proxy_config,
+        ..(*loaded).clone()
+    }));
+    let loaded = state.state.load();
+    loaded.backends[0].healthy.store(false, Ordering::Relaxed);
+    loaded.backends[1].healthy.store(false, Ordering::Relaxed);
+
+    let (label, _) = state.select_backend(Some("sendTransaction")).unwrap();
+    assert!(label.starts_with("replica"), "{}", label);
+
+    // Reads spill onto the write group regardless
+    loaded.backends[1].healthy.store(true, Ordering::Relaxed);
+    loaded.backends[2].healthy.store(false, Ordering::Relaxed);
+    loaded.backends[3].healthy.store(false, Ordering::Relaxed);
+    let (label, _) = state.select_backend(Some("getSlot")).unwrap();
+    assert_eq!(label, "leader-2");
+}
+
+#[test]
+fn test_default_backend_preferred_while_healthy() {
+    let state = create_tiered_test_state();
+    let loaded = state.state.load_full();
+    let mut backends = loaded.backends.clone();
+    backends[2] = backends[2].clone().into_primary();
+    state.state.store(Arc::new(RouterState {
+        backends,
+        method_routes: HashMap::from([("getSlot".to_string(), "premium-1".into())]),
+        ..(*loaded).clone()
+    }));
+
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_eq!(label, "cheap");
+        let (label, _) = state.select_ws_backend().unwrap();
+        assert_eq!(label, "cheap");
+    }
+    // Method routes still take precedence
+    let (label, _) = state.select_backend(Some("getSlot")).unwrap();
+    assert_eq!(label, "premium-1");
+
+    // Weighted selection takes over while the default is down
+    state.state.load().backends[2]
+        .healthy
+        .store(false, Ordering::Relaxed);
+    for _ in 0..100 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert_ne!(label, "cheap");
+    }
+}
+
+#[test]
+fn test_commitment_group_falls_back_to_weighted_selection() {
+    let state = create_test_state();
+    let loaded = state.state.load_full();
+    let secondary = RuntimeBackend::new(Backend {
+        commitments: vec!["finalized".to_string()],
+        ..loaded.backends[1].config.clone()
+    })
+    .unwrap();
+    state.state.store(Arc::new(RouterState {
+        backends: vec![loaded.backends[0].clone(), secondary],
+        ..(*loaded).clone()
+    }));
+
+    let ctx = SelectionContext {
+        commitment: Some("finalized"),
+        ..Default::default()
+    };
+    // The zero-weight secondary is the only finalized backend
+    for _ in 0..10 {
+        let selection = state.select_backend_for(&ctx, &[]).unwrap();
+        assert_eq!(selection.label(), "secondary");
+    }
+
+    // Without a usable group backend, or without a commitment, weighted selection applies
+    let selection = state
+        .select_backend_for(&ctx, &["secondary".to_string()])
+        .unwrap();
+    assert_eq!(selection.label(), "primary");
+    let (label, _) = state.select_backend(None).unwrap();
+    assert_eq!(label, "primary");
+}
+
+#[test]
+fn test_consistent_hash_selector_affinity_and_minimal_remap() {
+    let backends: Vec<RuntimeBackend> = (0..4)
+        .map(|i| {
+            RuntimeBackend::new(Backend {
+                label: format!("node-{}", i),
+                url: format!("http://node-{}", i),
+                weight: 1,
+                ..Default::default()
+            })
+            .unwrap()
+        })
+        .collect();
+    let selector = ConsistentHashSelector;
+    let all = |_: &RuntimeBackend| true;
+    let without_node_3 = |b: &RuntimeBackend| b.config.label != "node-3";
+    let pick = |filter: &dyn Fn(&RuntimeBackend) -> bool, key: &str| {
+        let ctx = SelectionContext {
+            request_key: Some(key),
+            ..Default::default()
+        };
+        selector
+            .select(&Candidates::new(&backends, filter), &ctx)
+            .unwrap()
+            .config
+            .label
+            .clone()
+    };
+
+    // The same pubkey always lands on the same backend
+    let pubkey = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+    let first = pick(&all, pubkey);
+    for _ in 0..50 {
+        assert_eq!(pick(&all, pubkey), first);
+    }
+
+    let keys: Vec<String> = (0..2000).map(|i| format!("account-{}", i)).collect();
+    let mut per_backend: HashMap<String, usize> = HashMap::new();
+    let mut moved = 0;
+    for key in &keys {
+        let before = pick(&all, key);
+        let after = pick(&without_node_3, key);
+        if before != "node-3" {
+            // Keys of the remaining backends never move
+            assert_eq!(before, after);
+        } else {
+            moved += 1;
+        }
+        *per_backend.entry(before).or_default() += 1;
+    }
+    // Only node-3's share (about a quarter) is remapped, and load stays balanced
+    assert!(moved > 300 && moved < 700, "moved {} keys", moved);
+    for (label, count) in per_backend {
+        assert!(count > 300 && count < 700, "{} got {} keys", label, count);
+    }
+}
+
+#[test]
+fn test_app_state_consistent_hash_routes_same_key_together() {
+    let state = create_tiered_test_state().with_selector(Arc::new(ConsistentHashSelector));
+    let ctx = SelectionContext {
+        request_key: Some("SysvarC1ock11111111111111111111111111111111"),
+        ..Default::default()
+    };
+    let first = state
+        .select_backend_for(&ctx, &[])
+        .unwrap()
+        .label()
+        .to_string();
+    for _ in 0..20 {
+        assert_eq!(state.select_backend_for(&ctx, &[]).unwrap().label(), first);
+    }
+}
+
+#[test]
+fn test_method_route_to_missing_backend_counts_misconfiguration() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let state = create_test_state();
+    state.state.rcu(|current| {
+        let mut next = RouterState::clone(current);
+        next.method_routes = HashMap::from([("getBlock".to_string(), "removed".into())]);
+        next
+    });
+
+    // Non-strict routes still fall back to weighted selection
+    let (label, _) = state.select_backend(Some("getBlock")).unwrap();
+    assert_eq!(label, "primary");
+    assert!(handle
+        .render()
+        .contains(r#"rpc_route_misconfigured_total{rpc_method="getBlock",backend="removed"} 1"#));
+}
+
+/// Two cheap backends taking one request at a time each, and a pricier one.
+fn create_cost_test_state() -> AppState {
+    let backend = |label: &str, cost: u32, weight: u32| Backend {
+        label: label.to_string(),
+        url: format!("https://{}", label),
+        weight,
+        cost,
+        max_concurrent_requests: 1,
+        ..Default::default()
+    };
+    test_state_with(
+        vec![
+            backend("premium", 10, 100),
+            backend("cheap-1", 1, 1),
+            backend("cheap-2", 1, 1),
+        ],
+        ProxyConfig::default(),
+    )
+    .with_selector(Arc::new(CheapestFirstSelector))
+}
+
+#[test]
+fn test_cheapest_backends_take_all_light_traffic() {
+    let state = create_cost_test_state();
+    for _ in 0..200 {
+        let (label, _) = state.select_backend(None).unwrap();
+        assert!(label.starts_with("cheap-"), "{}", label);
+    }
+}
+
+#[test]
+fn test_cheapest_selection_spills_over_when_saturated() {
+    let state = create_cost_test_state();
+    let loaded = state.state.load_full();
+
+    let _held = loaded.backends[1].start_request().unwrap();
+    assert_eq!(state.select_backend(None).unwrap().0, "cheap-2");
+
+    // Every cheap backend busy: the pricier one takes the overflow
+    let held = loaded.backends[2].start_request().unwrap();
+    assert_eq!(state.select_backend(None).unwrap().0, "premium");
+    drop(held);
+    assert_eq!(state.select_backend(None).unwrap().0, "cheap-2");
+
+    // An unhealthy cheap backend is skipped the same way
+    loaded.backends[2].healthy.store(false, Ordering::Relaxed);
+    assert_eq!(state.select_backend(None).unwrap().0, "premium");
+}
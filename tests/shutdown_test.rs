@@ -0,0 +1,133 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::StatusCode;
+use hyper_tls::HttpsConnector;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::public_router,
+    shutdown::{begin_drain, shutdown_requested},
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockBackend,
+};
+use tokio::sync::watch;
+
+fn make_state(backend_url: String) -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "slow".to_string(),
+            url: backend_url,
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["slow".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ))
+}
+
+async fn get_status(client: &Client<HttpConnector, Full<Bytes>>, url: String) -> StatusCode {
+    let req = hyper::Request::get(url).body(Full::default()).unwrap();
+    client.request(req).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_readyz_fails_during_drain_while_in_flight_request_completes() {
+    let backend_url = MockBackend::new()
+        .delay(Duration::from_millis(500))
+        .start()
+        .await
+        .url;
+    let state = make_state(backend_url);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let app = public_router(state.clone(), true);
+    let server = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_requested(shutdown_rx))
+        .await
+        .unwrap();
+    });
+
+    let client: Client<HttpConnector, Full<Bytes>> =
+        Client::builder(TokioExecutor::new()).build_http();
+    assert_eq!(
+        get_status(&client, format!("http://{}/readyz", addr)).await,
+        StatusCode::OK
+    );
+
+    // Start a request that is still waiting on the backend when the signal arrives
+    let in_flight = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let req = hyper::Request::post(format!("http://{}/?api-key=test-key", addr))
+                .header("content-type", "application/json")
+                .body(Full::new(Bytes::from_static(
+                    br#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#,
+                )))
+                .unwrap();
+            let resp = client.request(req).await.unwrap();
+            let status = resp.status();
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let drain_state = state.clone();
+    tokio::spawn(async move {
+        begin_drain(&drain_state, Duration::from_millis(300)).await;
+        let _ = shutdown_tx.send(true);
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(
+        get_status(&client, format!("http://{}/readyz", addr)).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        get_status(&client, format!("http://{}/health", addr)).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    let (status, body) = in_flight.await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert!(String::from_utf8_lossy(&body).contains("\"result\":\"ok\""));
+
+    // Idle keep-alive connections must not hold the server open
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("server did not finish draining")
+        .unwrap();
+}
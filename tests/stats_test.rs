@@ -0,0 +1,328 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ProxyConfig},
+    error::KeyStoreError,
+    health::HealthState,
+    keystore::{KeyCache, KeyCacheStats, KeyInfo, KeyStore},
+    mock::MockKeyStore,
+    routes::public_router,
+    state::{AppState, RouterState, RuntimeBackend},
+    stats::{LatencyHistogram, LatencySnapshot, MetricGroups},
+    testing::MockBackend,
+};
+use tower::ServiceExt;
+
+#[test]
+fn test_empty_histogram_has_no_percentiles() {
+    let snapshot = LatencyHistogram::new().snapshot();
+    assert_eq!(
+        snapshot,
+        LatencySnapshot {
+            requests: 0,
+            p50_ms: None,
+            p95_ms: None,
+            p99_ms: None,
+        }
+    );
+}
+
+#[test]
+fn test_percentiles_within_bucket_resolution() {
+    let histogram = LatencyHistogram::new();
+    // 1..=100 ms, so P50 is 50 ms, P95 95 ms and P99 99 ms
+    for ms in 1..=100 {
+        histogram.record(Duration::from_millis(ms));
+    }
+
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.requests, 100);
+    for (actual, expected) in [
+        (snapshot.p50_ms, 50.0),
+        (snapshot.p95_ms, 95.0),
+        (snapshot.p99_ms, 99.0),
+    ] {
+        let actual = actual.unwrap();
+        assert!(
+            actual >= expected && actual <= expected * 1.2,
+            "{} not within 20% above {}",
+            actual,
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_latencies_beyond_last_bucket_are_counted() {
+    let histogram = LatencyHistogram::new();
+    histogram.record(Duration::from_secs(3600));
+
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.requests, 1);
+    assert!(snapshot.p99_ms.unwrap() >= 100_000.0);
+}
+
+fn make_state(backend_url: String) -> AppState {
+    make_state_with_cost(backend_url, 0)
+}
+
+fn make_state_with_cost(backend_url: String, cost: u32) -> AppState {
+    let keystore = MockKeyStore::new();
+    keystore.add_key("test-key", "tester", 100);
+    make_state_with_keystore(backend_url, cost, Arc::new(keystore))
+}
+
+fn make_state_with_keystore(
+    backend_url: String,
+    cost: u32,
+    keystore: Arc<dyn KeyStore>,
+) -> AppState {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "primary".to_string(),
+            url: backend_url,
+            weight: 1,
+            cost,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["primary".to_string()])),
+        proxy_config: ProxyConfig::default(),
+        health_check_config: HealthCheckConfig::default(),
+    };
+    AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    )
+}
+
+async fn send_method(state: Arc<AppState>, method: &str) {
+    let req = Request::builder()
+        .method("POST")
+        .uri("/?api-key=test-key")
+        .header("content-type", "application/json")
+        .body(Body::from(format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","id":1}}"#,
+            method
+        )))
+        .unwrap();
+    let resp = public_router(state, true).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+async fn get_stats(state: Arc<AppState>) -> serde_json::Value {
+    let req = Request::builder()
+        .uri("/stats")
+        .body(Body::empty())
+        .unwrap();
+    let resp = public_router(state, true).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_stats_endpoint_reports_ordered_percentiles() {
+    let backend = MockBackend::new().start().await;
+    let state = Arc::new(make_state(backend.url.clone()));
+
+    for _ in 0..5 {
+        send_method(state.clone(), "getSlot").await;
+    }
+
+    let stats = get_stats(state).await;
+    assert_eq!(stats["requests"], 5);
+    let p50 = stats["p50_ms"].as_f64().unwrap();
+    let p95 = stats["p95_ms"].as_f64().unwrap();
+    let p99 = stats["p99_ms"].as_f64().unwrap();
+    assert!(p50 > 0.0 && p50 <= p95 && p95 <= p99, "{}", stats);
+}
+
+#[test]
+fn test_metric_group_precedence() {
+    let groups = MetricGroups::new(&HashMap::from([
+        ("*Subscribe".to_string(), "subscription".to_string()),
+        ("get*".to_string(), "read".to_string()),
+        ("getProgram*".to_string(), "program_read".to_string()),
+        ("getAccountInfo".to_string(), "account_read".to_string()),
+    ]));
+
+    assert_eq!(groups.label("slotSubscribe"), "subscription");
+    assert_eq!(groups.label("getSlot"), "read");
+    assert_eq!(groups.label("getProgramAccounts"), "program_read");
+    assert_eq!(groups.label("getAccountInfo"), "account_read");
+    assert_eq!(groups.label("sendTransaction"), "sendTransaction");
+    assert_eq!(groups.group("Subscribe"), Some("subscription"));
+    assert_eq!(groups.group("sendTransaction"), None);
+}
+
+#[tokio::test]
+async fn test_subscribe_methods_recorded_under_group() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let backend = MockBackend::new().start().await;
+    let state = Arc::new(
+        make_state(backend.url.clone()).with_metric_groups(MetricGroups::new(&HashMap::from([(
+            "*Subscribe".to_string(),
+            "subscription".to_string(),
+        )]))),
+    );
+
+    send_method(state.clone(), "accountSubscribe").await;
+    send_method(state.clone(), "slotSubscribe").await;
+    send_method(state.clone(), "getSlot").await;
+
+    let rendered = handle.render();
+    let grouped = rendered
+        .lines()
+        .find(|l| l.starts_with("rpc_requests_total") && l.contains(r#"rpc_method="subscription""#))
+        .unwrap_or_else(|| panic!("no subscription series in:\n{}", rendered));
+    assert!(grouped.ends_with(" 2"), "{}", grouped);
+    assert!(rendered.contains(r#"rpc_method="getSlot""#));
+    assert!(!rendered.contains("accountSubscribe"));
+    assert!(!rendered.contains("slotSubscribe"));
+
+    let stats = get_stats(state).await;
+    assert_eq!(stats["requests"], 3);
+    assert_eq!(stats["groups"]["subscription"]["requests"], 2);
+}
+
+#[tokio::test]
+async fn test_stats_report_estimated_cost_per_backend() {
+    let backend = MockBackend::new().start().await;
+    let state = Arc::new(make_state_with_cost(backend.url.clone(), 5));
+
+    for _ in 0..3 {
+        send_method(state.clone(), "getSlot").await;
+    }
+
+    let stats = get_stats(state).await;
+    assert_eq!(stats["costs"]["primary"]["requests"], 3);
+    assert_eq!(stats["costs"]["primary"]["estimated_cost"], 15);
+}
+
+/// `MockKeyStore` behind a `KeyCache`, the way `RedisKeyStore` caches Redis lookups.
+struct CachedKeyStore {
+    cache: KeyCache,
+    inner: MockKeyStore,
+}
+
+#[async_trait]
+impl KeyStore for CachedKeyStore {
+    async fn validate_key(&self, key: &str) -> Result<Option<KeyInfo>, KeyStoreError> {
+        if let Some(info) = self.cache.get(key).await {
+            return Ok(info);
+        }
+        let info = self.inner.validate_key(key).await?;
+        self.cache.insert(key, info.clone()).await;
+        Ok(info)
+    }
+
+    fn cache_stats(&self) -> Option<KeyCacheStats> {
+        Some(self.cache.stats())
+    }
+}
+
+#[tokio::test]
+async fn test_stats_report_keystore_cache_hit_ratio() {
+    let backend = MockBackend::new().start().await;
+    let inner = MockKeyStore::new();
+    inner.add_key("test-key", "tester", 100);
+    let keystore = Arc::new(CachedKeyStore {
+        cache: KeyCache::new(Duration::from_secs(60)),
+        inner,
+    });
+    let state = Arc::new(make_state_with_keystore(backend.url.clone(), 0, keystore));
+
+    for _ in 0..4 {
+        send_method(state.clone(), "getSlot").await;
+    }
+
+    let stats = get_stats(state).await;
+    assert_eq!(stats["keystore_cache"]["hits"], 3);
+    assert_eq!(stats["keystore_cache"]["misses"], 1);
+    assert_eq!(stats["keystore_cache"]["hit_ratio"], 0.75);
+}
+
+#[tokio::test]
+async fn test_stats_omit_keystore_cache_without_one() {
+    let backend = MockBackend::new().start().await;
+    let state = Arc::new(make_state(backend.url.clone()));
+
+    let stats = get_stats(state).await;
+    assert!(stats.get("keystore_cache").is_none(), "{}", stats);
+}
+
+#[tokio::test]
+async fn test_upstream_queue_depth_rises_while_connecting() {
+    let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    // A backend that never accepts: once its one-slot backlog is taken, new connections
+    // hang in the handshake, so requests wait for a connection
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let _backlog = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+    let state = Arc::new(make_state(format!("http://{}", addr)));
+    let pending = tokio::spawn(send_method(state.clone(), "getSlot"));
+
+    let mut queued = 0;
+    for _ in 0..100 {
+        queued = get_stats(state.clone()).await["upstream_queue"]["primary"]
+            .as_u64()
+            .unwrap();
+        if queued > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(queued, 1);
+    assert!(handle
+        .render()
+        .contains(r#"rpc_upstream_queue_depth{backend="primary"} 1"#));
+
+    // A request given up on leaves the queue
+    pending.abort();
+    let _ = pending.await;
+    let stats = get_stats(state).await;
+    assert_eq!(stats["upstream_queue"]["primary"], 0);
+    assert!(handle
+        .render()
+        .contains(r#"rpc_upstream_queue_depth{backend="primary"} 0"#));
+    drop(listener);
+}
+
+#[tokio::test]
+async fn test_upstream_queue_empty_after_requests_complete() {
+    let backend = MockBackend::new()
+        .delay(Duration::from_millis(50))
+        .start()
+        .await;
+    let state = Arc::new(make_state(backend.url.clone()));
+
+    for _ in 0..3 {
+        send_method(state.clone(), "getSlot").await;
+    }
+
+    let stats = get_stats(state).await;
+    assert_eq!(stats["upstream_queue"]["primary"], 0);
+}
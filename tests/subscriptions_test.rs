@@ -0,0 +1,39 @@
+use sol_rpc_router::subscriptions::SubscriptionTracker;
+
+#[test]
+fn test_slot_subscribe_and_notification_tracked() {
+    let mut tracker = SubscriptionTracker::new();
+    tracker.client_frame(r#"{"jsonrpc":"2.0","id":1,"method":"slotSubscribe"}"#);
+    // Not active until the backend confirms it
+    assert_eq!(tracker.active(), 0);
+
+    tracker.backend_frame(r#"{"jsonrpc":"2.0","result":42,"id":1}"#);
+    tracker.backend_frame(
+        r#"{"jsonrpc":"2.0","method":"slotNotification","params":{"result":{"parent":1,"root":0,"slot":2},"subscription":42}}"#,
+    );
+    assert_eq!(tracker.active(), 1);
+    assert_eq!(tracker.method_of(42), Some("slotSubscribe"));
+    assert_eq!(tracker.notifications(), 1);
+
+    tracker.client_frame(r#"{"jsonrpc":"2.0","id":2,"method":"slotUnsubscribe","params":[42]}"#);
+    tracker.backend_frame(r#"{"jsonrpc":"2.0","result":true,"id":2}"#);
+    assert_eq!(tracker.active(), 0);
+}
+
+#[test]
+fn test_failed_subscribe_and_unparseable_frames_ignored() {
+    let mut tracker = SubscriptionTracker::new();
+    tracker.client_frame("not json");
+    tracker.client_frame(r#"[{"jsonrpc":"2.0","id":1,"method":"slotSubscribe"}]"#);
+    tracker
+        .client_frame(r#"{"jsonrpc":"2.0","id":"a","method":"accountSubscribe","params":["bad"]}"#);
+    tracker.backend_frame(
+        r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params"},"id":"a"}"#,
+    );
+    tracker.backend_frame("{");
+    assert_eq!(tracker.active(), 0);
+
+    // Responses to untracked ids don't create subscriptions
+    tracker.backend_frame(r#"{"jsonrpc":"2.0","result":7,"id":"b"}"#);
+    assert_eq!(tracker.active(), 0);
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::StatusCode;
+use hyper_tls::HttpsConnector;
+use hyper_util::{
+    client::legacy::Client,
+    rt::{TokioExecutor, TokioIo},
+};
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::public_router,
+    server::{bind_unix, serve_unix},
+    shutdown::shutdown_requested,
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockBackend,
+};
+use tokio::{net::UnixStream, sync::watch};
+
+fn make_state(backend_url: String) -> Arc<AppState> {
+    let https = HttpsConnector::new();
+    let client = Client::builder(TokioExecutor::new()).build(https);
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+
+    let router_state = RouterState {
+        backends: vec![RuntimeBackend::new(Backend {
+            label: "mock-backend".to_string(),
+            url: backend_url,
+            ws_url: None,
+            weight: 1,
+            ..Default::default()
+        })
+        .unwrap()],
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(vec!["mock-backend".to_string()])),
+        proxy_config: ProxyConfig {
+            timeout_secs: 5,
+            ..Default::default()
+        },
+        health_check_config: HealthCheckConfig::default(),
+    };
+
+    Arc::new(AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ))
+}
+
+#[tokio::test]
+async fn test_proxy_over_unix_socket() {
+    let backend = MockBackend::new().start().await;
+    let state = make_state(backend.url.clone());
+
+    let path =
+        std::env::temp_dir().join(format!("sol_rpc_router_test_{}.sock", std::process::id()));
+    let listener = bind_unix(&path).unwrap();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let server = tokio::spawn(serve_unix(
+        listener,
+        public_router(state, true),
+        shutdown_requested(shutdown_rx),
+    ));
+
+    let stream = UnixStream::connect(&path).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    let req = hyper::Request::post("/?api-key=test-key")
+        .header("host", "localhost")
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from_static(
+            br#"{"jsonrpc":"2.0","method":"getSlot","id":1}"#,
+        )))
+        .unwrap();
+    let resp = sender.send_request(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body).contains("\"result\":\"ok\""));
+    assert_eq!(backend.request_count(), 1);
+
+    drop(sender);
+    let _ = shutdown_tx.send(true);
+    server.await.unwrap().unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_bind_unix_replaces_only_sockets() {
+    let path = std::env::temp_dir().join(format!(
+        "sol_rpc_router_test_stale_{}.sock",
+        std::process::id()
+    ));
+
+    // A socket left behind by a previous run is replaced
+    drop(bind_unix(&path).unwrap());
+    assert!(path.exists());
+    drop(bind_unix(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    // A regular file at the path is kept and fails the bind
+    std::fs::write(&path, "not a socket").unwrap();
+    let err = bind_unix(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    std::fs::remove_file(&path).unwrap();
+}
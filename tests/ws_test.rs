@@ -0,0 +1,319 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use futures_util::{SinkExt, StreamExt};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use sol_rpc_router::{
+    config::{Backend, HealthCheckConfig, ProxyConfig},
+    health::HealthState,
+    mock::MockKeyStore,
+    routes::ws_router,
+    state::{AppState, RouterState, RuntimeBackend},
+    testing::MockWsBackend,
+};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::frame::coding::CloseCode, Error as WsError, Message},
+};
+
+fn test_keystore() -> Arc<MockKeyStore> {
+    let keystore = Arc::new(MockKeyStore::new());
+    keystore.add_key("test-key", "tester", 100);
+    keystore
+}
+
+/// Serves `ws_router` in front of `backend_ws_url`; returns the client URL.
+async fn start_router(
+    backend_ws_url: String,
+    keystore: Arc<MockKeyStore>,
+    ws_message_rate: u64,
+) -> String {
+    let proxy_config = ProxyConfig {
+        ws_message_rate,
+        ..Default::default()
+    };
+    start_router_with_config(backend_ws_url, keystore, proxy_config).await
+}
+
+async fn start_router_with_config(
+    backend_ws_url: String,
+    keystore: Arc<MockKeyStore>,
+    proxy_config: ProxyConfig,
+) -> String {
+    start_router_with_backends(&[backend_ws_url], keystore, proxy_config).await
+}
+
+/// Serves `ws_router` in front of equally weighted backends `ws-backend-0`, `ws-backend-1`,
+/// ... at `backend_ws_urls`.
+async fn start_router_with_backends(
+    backend_ws_urls: &[String],
+    keystore: Arc<MockKeyStore>,
+    proxy_config: ProxyConfig,
+) -> String {
+    let https = HttpsConnector::new();
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(https);
+    let labels: Vec<String> = if backend_ws_urls.len() == 1 {
+        vec!["ws-backend".to_string()]
+    } else {
+        (0..backend_ws_urls.len())
+            .map(|i| format!("ws-backend-{}", i))
+            .collect()
+    };
+    let router_state = RouterState {
+        backends: labels
+            .iter()
+            .zip(backend_ws_urls)
+            .map(|(label, ws_url)| {
+                RuntimeBackend::new(Backend {
+                    label: label.clone(),
+                    url: "http://127.0.0.1:1".to_string(),
+                    ws_url: Some(ws_url.clone()),
+                    weight: 1,
+                    ..Default::default()
+                })
+                .unwrap()
+            })
+            .collect(),
+        method_routes: HashMap::new(),
+        health_state: Arc::new(HealthState::new(labels)),
+        proxy_config,
+        health_check_config: HealthCheckConfig::default(),
+    };
+    let state = Arc::new(AppState::new(
+        client,
+        keystore,
+        Arc::new(ArcSwap::from_pointee(router_state)),
+    ));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, ws_router(state)).await.unwrap();
+    });
+    format!("ws://{}/?api-key=test-key", addr)
+}
+
+/// Sends `count` frames as fast as possible and returns the close code the router
+/// answered with, if it closed the connection.
+async fn flood(url: &str, count: usize) -> Option<CloseCode> {
+    let (mut ws, _) = connect_async(url).await.unwrap();
+    for i in 0..count {
+        let frame = format!(r#"{{"jsonrpc":"2.0","id":{},"method":"getSlot"}}"#, i);
+        if ws.send(Message::Text(frame)).await.is_err() {
+            break;
+        }
+    }
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while let Some(msg) = ws.next().await {
+            match msg {
+                Ok(Message::Close(frame)) => return frame.map(|f| f.code),
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+        None
+    })
+    .await
+    .unwrap_or(None)
+}
+
+#[tokio::test]
+async fn test_ws_connection_closed_when_message_rate_exceeded() {
+    let keystore = test_keystore();
+    let backend = MockWsBackend::new().start().await;
+    let url = start_router(backend.url, keystore, 5).await;
+
+    assert_eq!(flood(&url, 20).await, Some(CloseCode::Policy));
+}
+
+#[tokio::test]
+async fn test_ws_message_rate_overridden_per_key() {
+    let keystore = test_keystore();
+    keystore.set_ws_message_rate("test-key", 1000);
+    let backend = MockWsBackend::new().start().await;
+    let url = start_router(backend.url, keystore, 5).await;
+
+    // Within the key's own limit; the echo of every frame arrives and nothing closes
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+    for i in 0..20 {
+        let frame = format!(r#"{{"jsonrpc":"2.0","id":{},"method":"getSlot"}}"#, i);
+        ws.send(Message::Text(frame)).await.unwrap();
+    }
+    for _ in 0..20 {
+        let msg = tokio::time::timeout(Duration::from_secs(2), ws.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(msg.is_text(), "unexpected frame {:?}", msg);
+    }
+}
+
+#[tokio::test]
+async fn test_ws_relays_text_binary_and_ping() {
+    let backend = MockWsBackend::new().start().await;
+    let url = start_router(backend.url.clone(), test_keystore(), 0).await;
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+
+    let text = r#"{"jsonrpc":"2.0","id":1,"method":"slotSubscribe"}"#;
+    ws.send(Message::Text(text.to_string())).await.unwrap();
+    ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    ws.send(Message::Ping(b"hi".to_vec())).await.unwrap();
+
+    let (mut texts, mut binaries, mut pongs) = (Vec::new(), Vec::new(), Vec::new());
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while texts.is_empty() || binaries.is_empty() || pongs.is_empty() {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(t) => texts.push(t),
+                Message::Binary(b) => binaries.push(b),
+                Message::Pong(p) => pongs.push(p),
+                other => panic!("unexpected frame {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("frames were not relayed");
+
+    assert_eq!(texts, vec![text.to_string()]);
+    assert_eq!(binaries, vec![vec![1, 2, 3]]);
+    assert!(pongs.iter().all(|p| p == b"hi"));
+    // The ping itself reached the backend
+    assert!(backend.frames().contains(&Message::Ping(b"hi".to_vec())));
+}
+
+#[tokio::test]
+async fn test_ws_backend_close_propagates_to_client() {
+    let backend = MockWsBackend::new().close_after(1).start().await;
+    let url = start_router(backend.url.clone(), test_keystore(), 0).await;
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+
+    ws.send(Message::Text("hello".to_string())).await.unwrap();
+    let closed = tokio::time::timeout(Duration::from_secs(2), async {
+        while let Some(msg) = ws.next().await {
+            match msg {
+                Ok(Message::Close(_)) => return true,
+                Ok(_) => continue,
+                Err(_) => return false,
+            }
+        }
+        false
+    })
+    .await
+    .expect("client was not closed");
+    assert!(closed);
+}
+
+#[tokio::test]
+async fn test_ws_client_close_propagates_to_backend() {
+    let backend = MockWsBackend::new().start().await;
+    let url = start_router(backend.url.clone(), test_keystore(), 0).await;
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+
+    // Wait for the upstream connection to be established before closing
+    ws.send(Message::Text("hello".to_string())).await.unwrap();
+    assert!(ws.next().await.unwrap().unwrap().is_text());
+    ws.close(None).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while backend.close_count() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("backend never saw the close");
+}
+
+#[tokio::test]
+async fn test_ws_backend_connect_retried() {
+    let backend = MockWsBackend::new().fail_first(1).start().await;
+    let proxy_config = ProxyConfig {
+        ws_connect_retries: 2,
+        ws_connect_retry_base_delay_ms: 10,
+        ..Default::default()
+    };
+    let url = start_router_with_config(backend.url.clone(), test_keystore(), proxy_config).await;
+
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+    ws.send(Message::Text("hello".to_string())).await.unwrap();
+    let echoed = tokio::time::timeout(Duration::from_secs(2), ws.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(echoed, Message::Text("hello".to_string()));
+}
+
+#[tokio::test]
+async fn test_ws_upgrade_refused_when_backend_connect_fails() {
+    let backend = MockWsBackend::new().fail_first(1).start().await;
+    let url = start_router(backend.url.clone(), test_keystore(), 0).await;
+
+    match connect_async(&url).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 502),
+        other => panic!("expected 502, got {:?}", other.map(|(_, resp)| resp)),
+    }
+}
+
+#[tokio::test]
+async fn test_ws_upgrade_refused_when_headers_too_large() {
+    let backend = MockWsBackend::new().start().await;
+    let url = start_router_with_config(
+        backend.url.clone(),
+        test_keystore(),
+        ProxyConfig {
+            max_header_bytes: 64,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    match connect_async(&url).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 431),
+        other => panic!("expected 431, got {:?}", other.map(|(_, resp)| resp)),
+    }
+}
+
+#[tokio::test]
+async fn test_ws_backend_connect_times_out() {
+    // Accepts the connection but never answers the handshake
+    let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let silent_url = format!("ws://{}", silent.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = silent.accept().await {
+            held.push(stream);
+        }
+    });
+    let proxy_config = ProxyConfig {
+        timeout_secs: 1,
+        ..Default::default()
+    };
+    let url = start_router_with_config(silent_url, test_keystore(), proxy_config).await;
+
+    let started = std::time::Instant::now();
+    match connect_async(&url).await {
+        Err(WsError::Http(resp)) => assert_eq!(resp.status(), 502),
+        other => panic!("expected 502, got {:?}", other.map(|(_, resp)| resp)),
+    }
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_ws_connect_retry_skips_failed_backend() {
+    let backend = MockWsBackend::new().start().await;
+    let proxy_config = ProxyConfig {
+        ws_connect_retries: 1,
+        ws_connect_retry_base_delay_ms: 1,
+        ..Default::default()
+    };
+    let backends = ["ws://127.0.0.1:1".to_string(), backend.url.clone()];
+    let url = start_router_with_backends(&backends, test_keystore(), proxy_config).await;
+
+    // Whichever backend is picked first, the single retry goes to the other one
+    for _ in 0..10 {
+        let (mut ws, _) = connect_async(&url).await.unwrap();
+        ws.close(None).await.unwrap();
+    }
+}